@@ -37,6 +37,7 @@ mod channel;
 mod coprocessor;
 mod raftkv;
 mod serialization;
+mod split_check;
 mod writebatch;
 
 #[allow(dead_code)]
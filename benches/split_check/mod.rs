@@ -0,0 +1,143 @@
+// Copyright 2018 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use kvproto::metapb::Region;
+use rocksdb::{SeekKey, Writable, DB};
+use tempdir::TempDir;
+use test::Bencher;
+use tikv::raftstore::coprocessor::{KeyEntry, ObserverContext, SplitChecker, SplitCheckerHost};
+use tikv::raftstore::store::engine::{IterOption, Iterable};
+use tikv::storage::{ALL_CFS, CF_WRITE};
+use tikv::util::rocksdb::{get_cf_handle, new_engine};
+
+const ENTRY_COUNT: usize = 1 << 16;
+const BATCH_SIZE: usize = 1024;
+
+#[derive(Default)]
+struct CountChecker {
+    size: u64,
+}
+
+impl SplitChecker for CountChecker {
+    fn on_kv(&mut self, _: &mut ObserverContext, entry: &KeyEntry) -> bool {
+        self.size += entry.entry_size() as u64;
+        false
+    }
+
+    fn on_kvs(&mut self, _: &mut ObserverContext, entries: &[KeyEntry]) -> bool {
+        self.size += entries.iter().map(|e| e.entry_size() as u64).sum::<u64>();
+        false
+    }
+
+    fn split_key(&mut self) -> Option<Vec<u8>> {
+        None
+    }
+}
+
+fn gen_entries() -> Vec<KeyEntry> {
+    (0..ENTRY_COUNT)
+        .map(|i| KeyEntry::new(format!("{:08}", i).into_bytes(), 0, 64, CF_WRITE))
+        .collect()
+}
+
+fn new_host() -> SplitCheckerHost {
+    let mut host = SplitCheckerHost::new(true);
+    for _ in 0..3 {
+        host.add_checker(Box::new(CountChecker::default()));
+    }
+    host
+}
+
+#[bench]
+fn bench_split_check_on_kv(b: &mut Bencher) {
+    let region = Region::new();
+    let entries = gen_entries();
+    b.iter(|| {
+        let mut host = new_host();
+        for e in &entries {
+            host.on_kv(&region, e);
+        }
+        host
+    });
+}
+
+fn bench_split_check_batch(b: &mut Bencher, batch_size: usize) {
+    let region = Region::new();
+    let entries = gen_entries();
+    b.iter(|| {
+        let mut host = new_host();
+        for batch in entries.chunks(batch_size) {
+            host.on_kvs(&region, batch);
+        }
+        host
+    });
+}
+
+#[bench]
+fn bench_split_check_on_kvs(b: &mut Bencher) {
+    bench_split_check_batch(b, BATCH_SIZE)
+}
+
+#[bench]
+fn bench_split_check_on_kvs_batch_16(b: &mut Bencher) {
+    bench_split_check_batch(b, 16)
+}
+
+#[bench]
+fn bench_split_check_on_kvs_batch_256(b: &mut Bencher) {
+    bench_split_check_batch(b, 256)
+}
+
+#[bench]
+fn bench_split_check_on_kvs_batch_4096(b: &mut Bencher) {
+    bench_split_check_batch(b, 4096)
+}
+
+// Writes the entries to SST files, so they are read from files when scanned
+// without filling the block cache.
+fn new_flushed_engine() -> (TempDir, DB) {
+    let dir = TempDir::new("bench_split_check").unwrap();
+    let db = new_engine(dir.path().to_str().unwrap(), ALL_CFS, None).unwrap();
+    let handle = get_cf_handle(&db, CF_WRITE).unwrap();
+    for i in 0..ENTRY_COUNT {
+        let key = format!("z{:08}", i).into_bytes();
+        db.put_cf(handle, &key, &[0; 64]).unwrap();
+    }
+    db.flush_cf(handle, true).unwrap();
+    (dir, db)
+}
+
+fn bench_split_check_scan(b: &mut Bencher, readahead_size: usize) {
+    let (_dir, db) = new_flushed_engine();
+    b.iter(|| {
+        let iter_opt = IterOption::new(None, None, false).set_readahead_size(readahead_size);
+        let mut iter = db.new_iterator_cf(CF_WRITE, iter_opt).unwrap();
+        let mut size = 0;
+        let mut valid = iter.seek(SeekKey::Start);
+        while valid {
+            size += iter.key().len() + iter.value().len();
+            valid = iter.next();
+        }
+        size
+    });
+}
+
+#[bench]
+fn bench_split_check_scan_no_readahead(b: &mut Bencher) {
+    bench_split_check_scan(b, 0)
+}
+
+#[bench]
+fn bench_split_check_scan_readahead_2mb(b: &mut Bencher) {
+    bench_split_check_scan(b, 2 * 1024 * 1024)
+}
@@ -58,7 +58,7 @@ use tikv::config::{check_and_persist_critical_config, TiKvConfig};
 use tikv::coprocessor;
 use tikv::import::{ImportSSTService, SSTImporter};
 use tikv::pd::{PdClient, RpcClient};
-use tikv::raftstore::coprocessor::CoprocessorHost;
+use tikv::raftstore::coprocessor::{new_size_tracker_listener, CoprocessorHost, RegionSizeTracker};
 use tikv::raftstore::store::{self, new_compaction_listener, Engines, SnapManagerBuilder};
 use tikv::server::readpool::ReadPool;
 use tikv::server::resolve;
@@ -139,6 +139,13 @@ fn run_raft_server(pd_client: RpcClient, cfg: &TiKvConfig, security_mgr: Arc<Sec
     // Create kv engine, storage.
     let mut kv_db_opts = cfg.rocksdb.build_opt();
     kv_db_opts.add_event_listener(compaction_listener);
+    let size_tracker = if cfg.coprocessor.track_region_size {
+        let tracker = Arc::new(RegionSizeTracker::default());
+        kv_db_opts.add_event_listener(new_size_tracker_listener(Arc::clone(&tracker)));
+        Some(tracker)
+    } else {
+        None
+    };
     let kv_cfs_opts = cfg.rocksdb.build_cf_opts();
     let kv_engine = Arc::new(
         rocksdb_util::new_engine_opt(db_path.to_str().unwrap(), kv_db_opts, kv_cfs_opts)
@@ -190,7 +197,7 @@ fn run_raft_server(pd_client: RpcClient, cfg: &TiKvConfig, security_mgr: Arc<Sec
     let mut server = Server::new(
         &server_cfg,
         &security_mgr,
-        cfg.coprocessor.region_split_size.0 as usize,
+        cfg.coprocessor.region_split_size().0 as usize,
         storage.clone(),
         cop_read_pool,
         raft_router,
@@ -205,7 +212,11 @@ fn run_raft_server(pd_client: RpcClient, cfg: &TiKvConfig, security_mgr: Arc<Sec
     let mut node = Node::new(&mut event_loop, &server_cfg, &cfg.raft_store, pd_client);
 
     // Create CoprocessorHost.
-    let coprocessor_host = CoprocessorHost::new(cfg.coprocessor.clone(), node.get_sendch());
+    let coprocessor_host = CoprocessorHost::new_with_size_tracker(
+        cfg.coprocessor.clone(),
+        node.get_sendch(),
+        size_tracker,
+    );
 
     node.start(
         event_loop,
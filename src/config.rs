@@ -950,7 +950,7 @@ impl TiKvConfig {
         self.readpool.validate()?;
         self.storage.validate()?;
 
-        self.raft_store.region_split_check_diff = self.coprocessor.region_split_size / 16;
+        self.raft_store.region_split_check_diff = self.coprocessor.region_split_size() / 16;
         self.raft_store.raftdb_path = if self.raft_store.raftdb_path.is_empty() {
             config::canonicalize_sub_path(&self.storage.data_dir, "raft")?
         } else {
@@ -1011,12 +1011,12 @@ impl TiKvConfig {
                 "deprecated configuration, \
                  raftstore.region-split-size has been moved to coprocessor",
             );
-            if self.coprocessor.region_split_size == default_coprocessor.region_split_size {
+            if self.coprocessor.region_split_size.is_none() {
                 warn!(
                     "override coprocessor.region-split-size with raftstore.region-split-size, {:?}",
                     self.raft_store.region_split_size
                 );
-                self.coprocessor.region_split_size = self.raft_store.region_split_size;
+                self.coprocessor.region_split_size = Some(self.raft_store.region_split_size);
             }
             self.raft_store.region_split_size = default_raft_store.region_split_size;
         }
@@ -226,6 +226,13 @@ impl<T: PdClient> Runner<T> {
                 }
                 Err(e) => {
                     debug!("[region {}] failed to ask split: {:?}", region.get_id(), e);
+                    let ack = Msg::SplitAck {
+                        region_id: region.get_id(),
+                        applied: false,
+                    };
+                    if let Err(e) = ch.try_send(ack) {
+                        warn!("[region {}] failed to send split ack: {:?}", region.get_id(), e);
+                    }
                 }
             }
             Ok(())
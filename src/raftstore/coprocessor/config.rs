@@ -12,7 +12,10 @@
 // limitations under the License.
 
 use super::Result;
-use util::config::ReadableSize;
+use storage::{CfName, LARGE_CFS};
+use util::collections::HashMap;
+use util::config::{ReadableDuration, ReadableSize};
+use util::unescape;
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 #[serde(default)]
@@ -21,17 +24,163 @@ pub struct Config {
     /// When it is true, it will try to split a region with table prefix if
     /// that region crosses tables.
     pub split_region_on_table: bool,
+    /// When it is true, a region will also be split where the index
+    /// changes. It only takes effect when split_region_on_table is true.
+    pub split_region_on_index: bool,
+    /// When it is true, different versions of the same user key will
+    /// always be kept in the same region when splitting by size.
+    pub split_region_on_user_key: bool,
+    /// When it is not 0, a region will also be split where the first
+    /// split_region_on_prefix_len bytes of keys change, so regions are
+    /// aligned to fixed key intervals. 0 means disabled.
+    pub split_region_on_prefix_len: usize,
+    /// When it is not 0, the first split_region_on_hash_prefix_len bytes
+    /// of keys are taken as a hash prefix, whose space is divided into
+    /// region_hash_prefix_ranges ranges evenly. Regions are also split at
+    /// the boundaries of the ranges, so they are aligned to the ranges.
+    pub split_region_on_hash_prefix_len: usize,
+    pub region_hash_prefix_ranges: u64,
+    /// When it is true, a region won't be split between the keys locked
+    /// by the same transaction, the split key is moved after them instead.
+    pub split_region_avoid_locks: bool,
+    /// A region is never split inside any of the split_protected_ranges,
+    /// the split key is moved to the end of the range instead. Each range
+    /// is a pair of escaped start and end keys, as the keys are printed in
+    /// logs, an empty end key means the end of the key space.
+    pub split_protected_ranges: Vec<(String, String)>,
 
     /// When region [a, b) size meets region_max_size, it will be split
     /// into two region into [a, c), [c, b). And the size of [a, c) will
     /// be region_split_size (or a little bit smaller).
     pub region_max_size: ReadableSize,
-    pub region_split_size: ReadableSize,
+    /// None means it's derived from region_max_size, see
+    /// `Config::region_split_size`.
+    pub region_split_size: Option<ReadableSize>,
+    /// The split size of a region covering a whole table, which is usually
+    /// a new table. It's split more aggressively than the other regions,
+    /// and the max size is scaled accordingly. 0 means the same as
+    /// region_split_size.
+    pub region_initial_split_size: ReadableSize,
+    /// A region whose data is mostly written recently is split at
+    /// region_hot_split_size, so the hot range is spread out early. It's
+    /// judged by the timestamps recorded in the table properties. 0 means
+    /// disabled.
+    pub region_hot_split_size: ReadableSize,
+    /// A split key is discarded if the region after it would be smaller
+    /// than region_min_split_size.
+    pub region_min_split_size: ReadableSize,
+    /// A region is split by size only after region_min_split_keys keys
+    /// from its start or the last split key, so a single huge value doesn't
+    /// make a lopsided split. 0 means no limit.
+    pub region_min_split_keys: u64,
+    /// When it is true, only the size of values is counted when splitting
+    /// a region by size, so regions with large values are kept smaller
+    /// than the ones with large keys.
+    pub region_split_on_value_size: bool,
+    /// The size of the entries of a CF is scaled by its ratio in
+    /// region_cf_compression_ratios when splitting a region by size, which
+    /// is the size on disk divided by the logical size, so a compressed CF
+    /// takes more logical bytes to be split. CFs not in it aren't scaled.
+    pub region_cf_compression_ratios: HashMap<String, f64>,
+    /// When any CF of a region exceeds region_max_cf_size, the region will
+    /// be split even if its total size is under region_max_size. 0 means
+    /// no limit.
+    pub region_max_cf_size: ReadableSize,
     /// When the number of keys in region [a,d) meets the region_max_keys,
     /// it will be split into two regions [a,c),[c,d). And the keys of [a,c)
     /// will be region_split_keys.
     pub region_max_keys: u64,
     pub region_split_keys: u64,
+    /// When it is true, the keys split check scans a region from its end,
+    /// so the region after the split key gets region_split_keys keys. It
+    /// balances regions with many deleted keys at the front.
+    pub region_split_keys_reversed: bool,
+    /// When it is not 0, a region will also be split every
+    /// region_split_distinct_keys user keys once it has 1.5 times as many.
+    /// The versions of a key are counted only once. 0 means disabled.
+    pub region_split_distinct_keys: u64,
+    /// When it is not 0, a region will also be split once its keys span
+    /// more than region_max_key_span. Keys are compared as big-endian
+    /// numbers of their first 8 bytes, which suits sparse binary keys.
+    pub region_max_key_span: u64,
+    /// The number of buckets in every region_split_size of a region, the
+    /// bucket boundaries are reported after a split check scan. 0 means
+    /// no buckets.
+    pub region_bucket_count: u64,
+    /// The max number of split keys found by one split check, the scan
+    /// stops after the last one and the rest of the region is split by the
+    /// following checks. 0 means no limit, the whole region is scanned.
+    pub max_split_keys_per_check: u64,
+    /// When it is false, the approximate region size found by the split
+    /// check is not reported to raftstore, the split check still works.
+    pub report_region_size: bool,
+    /// When it is true, the approximate size of each CF of the region is
+    /// also reported along with the region size.
+    pub report_region_cf_sizes: bool,
+    /// When it is true, the lengths of the largest key and value seen by the
+    /// split check scan of a region are reported.
+    pub report_region_max_entry: bool,
+    /// When it is true, the number of entries of each CF scanned by the
+    /// split check of a region is reported.
+    pub report_region_cf_entries: bool,
+    /// When it is true, the scanned size and keys of a region are reported
+    /// after every split check scan of it.
+    pub report_split_check_result: bool,
+    /// When it is true, whether a region needs to be scanned by split check
+    /// is decided by the size estimated from the SST index entries, instead
+    /// of the size read from the table properties. It's faster when a lot
+    /// of SST files overlap with a region, but less accurate.
+    pub fast_size_estimate: bool,
+    /// When it is true, the size of a region in SST files is kept up to
+    /// date by the flushes and compactions of the kv engine after it's read
+    /// from the table properties once, until the region's epoch changes.
+    pub track_region_size: bool,
+    /// When it is true, the automatic split checks of a region whose
+    /// approximate size is near zero are skipped, none of the checkers
+    /// scans it.
+    pub skip_empty_region: bool,
+    /// When it is true, the size of a region is only checked on the leader
+    /// peer, which is the only one acting on splits. Set it to false to let
+    /// followers compute the split keys in advance.
+    pub split_check_leader_only: bool,
+    /// When the approximate size of a region differs from the size found
+    /// by scanning it by more than region_size_divergence_ratio times, the
+    /// scanned size is reported instead. 0 means never correct the size.
+    pub region_size_divergence_ratio: f64,
+    /// When the old versions are more than region_compact_garbage_ratio of
+    /// the MVCC versions of a region, the automatic split checks ask to
+    /// compact the region instead of splitting it. 0 means always split.
+    pub region_compact_garbage_ratio: f64,
+    /// The max bytes that split check can read from disk in one second,
+    /// 0 means no limit.
+    pub split_check_bytes_per_sec: ReadableSize,
+    /// A region is not checked again by the periodical split checks within
+    /// split_check_interval after the last check. 0 means no limit.
+    pub split_check_interval: ReadableDuration,
+    /// Number of entries read by split check before feeding them to the
+    /// checkers, whether the check is stopped is also checked once a batch.
+    pub split_check_scan_batch_size: usize,
+    /// Size of read-ahead of the iterators scanning regions for split
+    /// check, it reduces the stalls on cold regions whose blocks are not
+    /// cached. 0 means RocksDB's default.
+    pub split_check_readahead_size: ReadableSize,
+    /// When it is true and a region needs to be split mostly because of
+    /// the data in memtables, the memtables are flushed so the size
+    /// properties of the region become accurate.
+    pub split_check_flush_memtable: bool,
+    /// The max bytes an automatic split check scans before the rest of the
+    /// region is left to another task, so a huge region doesn't block the
+    /// checks of other regions. 0 means the region is scanned in one task.
+    pub split_check_chunk_size: ReadableSize,
+    /// The periodical split checks are delayed by a random duration up to
+    /// split_check_jitter, so the checks of all the regions don't run at
+    /// the same time after restart. 0 means no delay.
+    pub split_check_jitter: ReadableDuration,
+    /// When the moving average of the durations of split checks exceeds
+    /// split_check_overload_duration, the automatic checks use the
+    /// approximate policy instead of scanning regions until it drops. 0
+    /// means always scan.
+    pub split_check_overload_duration: ReadableDuration,
 }
 
 /// Default region split size.
@@ -44,23 +193,116 @@ impl Default for Config {
         let split_size = ReadableSize::mb(SPLIT_SIZE_MB);
         Config {
             split_region_on_table: true,
-            region_split_size: split_size,
+            split_region_on_index: false,
+            split_region_on_user_key: false,
+            split_region_on_prefix_len: 0,
+            split_region_on_hash_prefix_len: 0,
+            region_hash_prefix_ranges: 0,
+            split_region_avoid_locks: false,
+            split_protected_ranges: vec![],
+            region_split_size: None,
             region_max_size: split_size / 2 * 3,
+            region_initial_split_size: ReadableSize(0),
+            region_hot_split_size: ReadableSize(0),
+            region_min_split_size: ReadableSize(0),
+            region_min_split_keys: 0,
+            region_split_on_value_size: false,
+            region_cf_compression_ratios: HashMap::default(),
+            region_max_cf_size: ReadableSize(0),
             region_split_keys: SPLIT_KEYS,
             region_max_keys: SPLIT_KEYS / 2 * 3,
+            region_split_keys_reversed: false,
+            region_split_distinct_keys: 0,
+            region_max_key_span: 0,
+            region_bucket_count: 0,
+            max_split_keys_per_check: 0,
+            report_region_size: true,
+            report_region_cf_sizes: false,
+            report_region_max_entry: false,
+            report_region_cf_entries: false,
+            report_split_check_result: true,
+            fast_size_estimate: false,
+            track_region_size: false,
+            skip_empty_region: false,
+            split_check_leader_only: true,
+            region_size_divergence_ratio: 0.0,
+            region_compact_garbage_ratio: 0.0,
+            split_check_bytes_per_sec: ReadableSize(0),
+            split_check_interval: ReadableDuration::secs(0),
+            split_check_scan_batch_size: 1024,
+            split_check_readahead_size: ReadableSize(0),
+            split_check_flush_memtable: false,
+            split_check_chunk_size: ReadableSize(0),
+            split_check_jitter: ReadableDuration::secs(0),
+            split_check_overload_duration: ReadableDuration::secs(0),
         }
     }
 }
 
 impl Config {
+    /// The split size of regions. If it's not set, it's derived from
+    /// region_max_size.
+    pub fn region_split_size(&self) -> ReadableSize {
+        self.region_split_size.unwrap_or_else(|| self.region_max_size / 3 * 2)
+    }
+
     pub fn validate(&self) -> Result<()> {
-        if self.region_max_size.0 < self.region_split_size.0 {
+        let split_size = self.region_split_size();
+        if self.region_max_size.0 < split_size.0 {
             return Err(box_err!(
                 "region max size {} must >= split size {}",
                 self.region_max_size.0,
-                self.region_split_size.0
+                split_size.0
+            ));
+        }
+        if self.region_initial_split_size.0 > split_size.0 {
+            return Err(box_err!(
+                "region initial split size {} must <= split size {}",
+                self.region_initial_split_size.0,
+                split_size.0
+            ));
+        }
+        if self.region_hot_split_size.0 > split_size.0 {
+            return Err(box_err!(
+                "region hot split size {} must <= split size {}",
+                self.region_hot_split_size.0,
+                split_size.0
+            ));
+        }
+        if self.region_min_split_size.0 > split_size.0 {
+            return Err(box_err!(
+                "region min split size {} must <= split size {}",
+                self.region_min_split_size.0,
+                split_size.0
+            ));
+        }
+        if self.region_size_divergence_ratio != 0.0 && self.region_size_divergence_ratio < 1.0 {
+            return Err(box_err!(
+                "region size divergence ratio {} must >= 1 or be 0",
+                self.region_size_divergence_ratio
+            ));
+        }
+        if self.region_compact_garbage_ratio < 0.0 || self.region_compact_garbage_ratio >= 1.0 {
+            return Err(box_err!(
+                "region compact garbage ratio {} must be in [0, 1)",
+                self.region_compact_garbage_ratio
+            ));
+        }
+        if self.split_region_on_hash_prefix_len > 8 {
+            return Err(box_err!(
+                "hash prefix len {} must <= 8",
+                self.split_region_on_hash_prefix_len
+            ));
+        }
+        if self.split_region_on_hash_prefix_len > 0 && self.region_hash_prefix_ranges < 2 {
+            return Err(box_err!(
+                "region hash prefix ranges {} must >= 2",
+                self.region_hash_prefix_ranges
             ));
         }
+        if self.split_check_scan_batch_size == 0 {
+            return Err(box_err!("split check scan batch size must > 0"));
+        }
         if self.region_max_keys < self.region_split_keys {
             return Err(box_err!(
                 "region max keys {} must >= split keys {}",
@@ -68,8 +310,49 @@ impl Config {
                 self.region_split_keys
             ));
         }
+        for (cf, ratio) in &self.region_cf_compression_ratios {
+            if !LARGE_CFS.iter().any(|c| *c == cf.as_str()) {
+                return Err(box_err!("compression ratio of cf {} is not supported", cf));
+            }
+            if *ratio <= 0.0 || *ratio > 1.0 {
+                return Err(box_err!(
+                    "compression ratio {} of cf {} must be in (0, 1]",
+                    ratio,
+                    cf
+                ));
+            }
+        }
+        for (start_key, end_key) in self.protected_ranges() {
+            if !end_key.is_empty() && start_key >= end_key {
+                return Err(box_err!(
+                    "split protected range start key {:?} must < end key {:?}",
+                    start_key,
+                    end_key
+                ));
+            }
+        }
         Ok(())
     }
+
+    /// Get the unescaped start and end keys of split_protected_ranges.
+    pub fn protected_ranges(&self) -> Vec<(Vec<u8>, Vec<u8>)> {
+        self.split_protected_ranges
+            .iter()
+            .map(|&(ref start_key, ref end_key)| (unescape(start_key), unescape(end_key)))
+            .collect()
+    }
+
+    /// Get region_cf_compression_ratios keyed by the names of the CFs.
+    pub fn cf_compression_ratios(&self) -> HashMap<CfName, f64> {
+        LARGE_CFS
+            .iter()
+            .filter_map(|cf| {
+                self.region_cf_compression_ratios
+                    .get(*cf)
+                    .map(|ratio| (*cf, *ratio))
+            })
+            .collect()
+    }
 }
 
 #[cfg(test)]
@@ -83,12 +366,80 @@ mod tests {
 
         cfg = Config::default();
         cfg.region_max_size = ReadableSize(10);
-        cfg.region_split_size = ReadableSize(20);
+        cfg.region_split_size = Some(ReadableSize(20));
+        let err = cfg.validate().unwrap_err();
+        assert!(format!("{}", err).contains("must >= split size"));
+
+        // The split size is derived from the max size if it's not set.
+        cfg = Config::default();
+        cfg.region_max_size = ReadableSize::mb(30);
+        cfg.validate().unwrap();
+        assert_eq!(cfg.region_split_size(), ReadableSize::mb(20));
+
+        // An explicit split size is kept even if it equals the default one.
+        cfg = Config::default();
+        cfg.region_max_size = ReadableSize::mb(300);
+        cfg.region_split_size = Some(ReadableSize::mb(SPLIT_SIZE_MB));
+        cfg.validate().unwrap();
+        assert_eq!(cfg.region_split_size(), ReadableSize::mb(SPLIT_SIZE_MB));
+
+        cfg = Config::default();
+        cfg.region_initial_split_size = ReadableSize(cfg.region_split_size().0 + 1);
+        assert!(cfg.validate().is_err());
+
+        cfg = Config::default();
+        cfg.region_hot_split_size = ReadableSize(cfg.region_split_size().0 + 1);
+        assert!(cfg.validate().is_err());
+
+        cfg = Config::default();
+        cfg.region_min_split_size = ReadableSize(cfg.region_split_size().0 + 1);
+        assert!(cfg.validate().is_err());
+
+        cfg = Config::default();
+        cfg.region_size_divergence_ratio = 0.5;
+        assert!(cfg.validate().is_err());
+
+        cfg = Config::default();
+        cfg.region_compact_garbage_ratio = 1.0;
         assert!(cfg.validate().is_err());
 
+        cfg = Config::default();
+        cfg.split_check_scan_batch_size = 0;
+        assert!(cfg.validate().is_err());
+
+        cfg = Config::default();
+        cfg.split_region_on_hash_prefix_len = 9;
+        cfg.region_hash_prefix_ranges = 16;
+        assert!(cfg.validate().is_err());
+
+        cfg = Config::default();
+        cfg.split_region_on_hash_prefix_len = 1;
+        assert!(cfg.validate().is_err());
+        cfg.region_hash_prefix_ranges = 16;
+        cfg.validate().unwrap();
+
         cfg = Config::default();
         cfg.region_max_keys = 10;
         cfg.region_split_keys = 20;
         assert!(cfg.validate().is_err());
+
+        cfg = Config::default();
+        cfg.split_protected_ranges = vec![("b".to_owned(), "a".to_owned())];
+        assert!(cfg.validate().is_err());
+        cfg.split_protected_ranges = vec![("a".to_owned(), "\\x62".to_owned())];
+        cfg.validate().unwrap();
+        assert_eq!(cfg.protected_ranges(), vec![(b"a".to_vec(), b"b".to_vec())]);
+        cfg.split_protected_ranges = vec![("a".to_owned(), "".to_owned())];
+        cfg.validate().unwrap();
+
+        cfg = Config::default();
+        cfg.region_cf_compression_ratios.insert("raft".to_owned(), 0.5);
+        assert!(cfg.validate().is_err());
+        cfg = Config::default();
+        cfg.region_cf_compression_ratios.insert("write".to_owned(), 1.5);
+        assert!(cfg.validate().is_err());
+        cfg.region_cf_compression_ratios.insert("write".to_owned(), 0.5);
+        cfg.validate().unwrap();
+        assert_eq!(cfg.cf_compression_ratios().get("write"), Some(&0.5));
     }
 }
@@ -11,12 +11,16 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
 use rocksdb::DB;
 
 use kvproto::metapb::Region;
 use kvproto::raft_cmdpb::{RaftCmdRequest, RaftCmdResponse};
 
 use raftstore::store::msg::Msg;
+use util::collections::HashMap;
 use util::transport::{RetryableSendCh, Sender};
 
 use super::*;
@@ -24,6 +28,8 @@ use super::*;
 struct Entry<T> {
     priority: u32,
     observer: T,
+    // The observer is skipped while it's false.
+    enabled: Arc<AtomicBool>,
 }
 
 // TODO: change it to Send + Clone.
@@ -39,15 +45,21 @@ pub struct Registry {
     query_observers: Vec<Entry<BoxQueryObserver>>,
     split_check_observers: Vec<Entry<BoxSplitCheckObserver>>,
     role_observers: Vec<Entry<BoxRoleObserver>>,
+    // The enable flags of the split check observers registered with names.
+    split_check_toggles: HashMap<String, Arc<AtomicBool>>,
     // TODO: add endpoint
 }
 
 macro_rules! push {
     ($p:expr, $t:ident, $vec:expr) => {
+        push!($p, $t, $vec, Arc::new(AtomicBool::new(true)))
+    };
+    ($p:expr, $t:ident, $vec:expr, $enabled:expr) => {
         $t.start();
         let e = Entry {
             priority: $p,
             observer: $t,
+            enabled: $enabled,
         };
         let vec = &mut $vec;
         vec.push(e);
@@ -64,10 +76,45 @@ impl Registry {
         push!(priority, qo, self.query_observers);
     }
 
+    /// Observers with smaller priority add their checkers to the split
+    /// checker host first, and observers with the same priority keep the
+    /// order of registration. The split keys found by the first checker
+    /// in this order win.
     pub fn register_split_check_observer(&mut self, priority: u32, sco: BoxSplitCheckObserver) {
         push!(priority, sco, self.split_check_observers);
     }
 
+    /// Register a split check observer that can be disabled and enabled at
+    /// runtime by `name`, it's enabled at first. Observers registered with
+    /// the same name share the same flag.
+    pub fn register_named_split_check_observer(
+        &mut self,
+        priority: u32,
+        name: &str,
+        sco: BoxSplitCheckObserver,
+    ) {
+        if self.split_check_toggles.contains_key(name) {
+            warn!(
+                "split check observer {} is registered more than once, they share the same flag",
+                name
+            );
+        }
+        let enabled = Arc::clone(
+            self.split_check_toggles
+                .entry(name.to_owned())
+                .or_insert_with(|| Arc::new(AtomicBool::new(true))),
+        );
+        push!(priority, sco, self.split_check_observers, enabled);
+    }
+
+    /// Get the priorities of the split check observers in the order they run.
+    pub fn split_check_observer_priorities(&self) -> Vec<u32> {
+        self.split_check_observers
+            .iter()
+            .map(|e| e.priority)
+            .collect()
+    }
+
     pub fn register_role_observer(&mut self, priority: u32, ro: BoxRoleObserver) {
         push!(priority, ro, self.role_observers);
     }
@@ -81,7 +128,7 @@ macro_rules! try_loop_ob {
     };
 }
 
-/// A macro that loops over all observers and returns early when bypass is set.
+/// A macro that loops over all enabled observers and returns early when bypass is set.
 ///
 /// Using a macro so we don't need to write tests for every observers.
 macro_rules! loop_ob {
@@ -103,6 +150,9 @@ macro_rules! loop_ob {
     (_imp $res_type:tt, $r:expr, $obs:expr, $hook:ident, $($args:tt)*) => {{
         let mut ctx = ObserverContext::new($r);
         for o in $obs {
+            if !o.enabled.load(Ordering::SeqCst) {
+                continue;
+            }
             loop_ob!(_exec $res_type, o.observer, $hook, &mut ctx, $($args)*);
             if ctx.bypass {
                 break;
@@ -121,31 +171,187 @@ macro_rules! loop_ob {
 #[derive(Default)]
 pub struct CoprocessorHost {
     pub registry: Registry,
+    pub cfg: Config,
+    // The `[start, end]` key ranges being ingested, with their reference counts.
+    ingest_ranges: Mutex<Vec<(Vec<u8>, Vec<u8>, usize)>>,
 }
 
 impl CoprocessorHost {
     pub fn new<C: Sender<Msg> + Send + Sync + 'static>(
         cfg: Config,
         ch: RetryableSendCh<Msg, C>,
+    ) -> CoprocessorHost {
+        CoprocessorHost::new_with_size_tracker(cfg, ch, None)
+    }
+
+    /// Create a host whose size check observer gets the size of regions from
+    /// `size_tracker`, which should be updated by the listener of the engine.
+    pub fn new_with_size_tracker<C: Sender<Msg> + Send + Sync + 'static>(
+        cfg: Config,
+        ch: RetryableSendCh<Msg, C>,
+        size_tracker: Option<Arc<RegionSizeTracker>>,
     ) -> CoprocessorHost {
         let mut registry = Registry::default();
-        let split_size_check_observer =
-            SizeCheckObserver::new(cfg.region_max_size.0, cfg.region_split_size.0, ch.clone());
-        registry.register_split_check_observer(200, Box::new(split_size_check_observer));
+        let split_size_check_observer = SizeCheckObserver::new(
+            cfg.region_max_size.0,
+            cfg.region_split_size().0,
+            cfg.region_min_split_size.0,
+            cfg.region_max_cf_size.0,
+            cfg.region_bucket_count,
+            cfg.split_region_on_user_key,
+            ch.clone(),
+        ).with_report_region_size(cfg.report_region_size)
+            .with_report_cf_sizes(cfg.report_region_cf_sizes)
+            .with_flush_memtable(cfg.split_check_flush_memtable)
+            .with_max_split_keys(cfg.max_split_keys_per_check)
+            .with_min_split_keys(cfg.region_min_split_keys)
+            .with_count_value_only(cfg.region_split_on_value_size)
+            .with_compression_ratios(cfg.cf_compression_ratios())
+            .with_fast_size_estimate(cfg.fast_size_estimate)
+            .with_skip_empty_region(cfg.skip_empty_region)
+            .with_leader_only(cfg.split_check_leader_only)
+            .with_protected_ranges(cfg.protected_ranges())
+            .with_initial_split_size(cfg.region_initial_split_size.0)
+            .with_size_tracker(size_tracker);
+        registry.register_named_split_check_observer(
+            200,
+            "size",
+            Box::new(split_size_check_observer),
+        );
 
         let split_keys_check_observer =
-            KeysCheckObserver::new(cfg.region_max_keys, cfg.region_split_keys, ch);
-        registry.register_split_check_observer(200, Box::new(split_keys_check_observer));
+            KeysCheckObserver::new(cfg.region_max_keys, cfg.region_split_keys, ch)
+                .with_reversed(cfg.region_split_keys_reversed);
+        registry.register_named_split_check_observer(
+            200,
+            "keys",
+            Box::new(split_keys_check_observer),
+        );
+        if cfg.region_hot_split_size.0 > 0 {
+            // It lowers the thresholds of SizeCheckObserver, so it runs before it.
+            let hot_range_observer = HotRangeObserver::new(cfg.region_hot_split_size.0);
+            registry.register_named_split_check_observer(150, "hot", Box::new(hot_range_observer));
+        }
 
         // TableCheckObserver has higher priority than SizeCheckObserver.
-        registry.register_split_check_observer(
+        registry.register_named_split_check_observer(
             100,
+            "half",
             Box::new(HalfCheckObserver::new(cfg.region_max_size.0)),
         );
         if cfg.split_region_on_table {
-            registry.register_split_check_observer(400, Box::new(TableCheckObserver::default()));
+            let table_check_observer =
+                TableCheckObserver::new(cfg.split_region_on_index, cfg.region_min_split_size.0);
+            registry.register_named_split_check_observer(
+                400,
+                "table",
+                Box::new(table_check_observer),
+            );
+        }
+        if cfg.split_region_on_prefix_len > 0 {
+            let interval_check_observer = IntervalCheckObserver::new(
+                cfg.split_region_on_prefix_len,
+                cfg.region_min_split_size.0,
+            );
+            registry.register_named_split_check_observer(
+                400,
+                "interval",
+                Box::new(interval_check_observer),
+            );
+        }
+        if cfg.split_region_on_hash_prefix_len > 0 {
+            let hash_prefix_check_observer = HashPrefixCheckObserver::new(
+                cfg.split_region_on_hash_prefix_len,
+                cfg.region_hash_prefix_ranges,
+            );
+            registry.register_named_split_check_observer(
+                400,
+                "hash_prefix",
+                Box::new(hash_prefix_check_observer),
+            );
+        }
+        if cfg.region_max_key_span > 0 {
+            let span_check_observer = SpanCheckObserver::new(cfg.region_max_key_span);
+            registry.register_named_split_check_observer(
+                400,
+                "span",
+                Box::new(span_check_observer),
+            );
+        }
+        if cfg.region_split_distinct_keys > 0 {
+            let distinct_keys_check_observer =
+                DistinctKeysCheckObserver::new(cfg.region_split_distinct_keys);
+            registry.register_named_split_check_observer(
+                200,
+                "distinct_keys",
+                Box::new(distinct_keys_check_observer),
+            );
+        }
+        if cfg.split_region_avoid_locks {
+            // It only adjusts the split keys found by the other checkers.
+            registry.register_named_split_check_observer(500, "lock", Box::new(LockCheckObserver));
+        }
+        CoprocessorHost {
+            registry,
+            cfg,
+            ingest_ranges: Mutex::new(vec![]),
+        }
+    }
+
+    /// Register the key range `[start_key, end_key]` of an SST file being
+    /// ingested, the regions overlapping with it are not split until it's
+    /// unregistered. The keys are in the same form as region keys.
+    pub fn register_ingest_range(&self, start_key: &[u8], end_key: &[u8]) {
+        let mut ranges = self.ingest_ranges.lock().unwrap();
+        for r in ranges.iter_mut() {
+            if r.0.as_slice() == start_key && r.1.as_slice() == end_key {
+                r.2 += 1;
+                return;
+            }
+        }
+        ranges.push((start_key.to_vec(), end_key.to_vec(), 1));
+    }
+
+    /// Unregister the key range registered by `register_ingest_range`.
+    pub fn unregister_ingest_range(&self, start_key: &[u8], end_key: &[u8]) {
+        let mut ranges = self.ingest_ranges.lock().unwrap();
+        for r in ranges.iter_mut() {
+            if r.0.as_slice() == start_key && r.1.as_slice() == end_key {
+                r.2 -= 1;
+            }
+        }
+        ranges.retain(|r| r.2 > 0);
+    }
+
+    /// Disable or enable the split check observer registered with `name` at
+    /// runtime, a disabled observer adds no checker to the following checks.
+    pub fn set_split_check_observer_enabled(&self, name: &str, enabled: bool) -> Result<()> {
+        match self.registry.split_check_toggles.get(name) {
+            Some(flag) => {
+                flag.store(enabled, Ordering::SeqCst);
+                info!(
+                    "split check observer {} is {}",
+                    name,
+                    if enabled { "enabled" } else { "disabled" }
+                );
+                Ok(())
+            }
+            None => Err(box_err!("split check observer {} not found", name)),
         }
-        CoprocessorHost { registry }
+    }
+
+    /// Get the ingest ranges overlapping with the region.
+    fn overlapped_ingest_ranges(&self, region: &Region) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let (region_start, region_end) = (region.get_start_key(), region.get_end_key());
+        let ranges = self.ingest_ranges.lock().unwrap();
+        ranges
+            .iter()
+            .filter(|&&(ref start, ref end, _)| {
+                (region_end.is_empty() || start.as_slice() < region_end)
+                    && end.as_slice() >= region_start
+            })
+            .map(|&(ref start, ref end, _)| (start.clone(), end.clone()))
+            .collect()
     }
 
     /// Call all prepose hooks until bypass is set to true.
@@ -215,16 +421,65 @@ impl CoprocessorHost {
         region: &Region,
         engine: &DB,
         auto_split: bool,
+        check_ctx: SplitCheckContext,
     ) -> SplitCheckerHost {
         let mut host = SplitCheckerHost::new(auto_split);
+        let mut ctx = ObserverContext::new(region);
+        ctx.region_state = check_ctx.region_state;
+        ctx.is_leader = check_ctx.is_leader;
+        ctx.resumed_size = check_ctx.resumed_size;
+        ctx.custom_policy = check_ctx.custom_policy;
+        ctx.ingest_ranges = check_ctx.ingest_ranges;
+        ctx.ingest_ranges.extend(self.overlapped_ingest_ranges(region));
+        ctx.size_thresholds = self
+            .registry
+            .split_check_observers
+            .iter()
+            .filter(|entry| entry.enabled.load(Ordering::SeqCst))
+            .filter_map(|entry| entry.observer.override_thresholds(&ctx))
+            .next();
+        for entry in &self.registry.split_check_observers {
+            if !entry.enabled.load(Ordering::SeqCst) {
+                continue;
+            }
+            entry.observer.add_checker(&mut ctx, &mut host, engine);
+            if ctx.bypass {
+                break;
+            }
+        }
+        if let Some(size) = ctx.reported_size {
+            for entry in &self.registry.split_check_observers {
+                if !entry.enabled.load(Ordering::SeqCst) {
+                    continue;
+                }
+                entry.observer.on_region_size_reported(&ctx, size);
+            }
+        }
+        host
+    }
+
+    pub fn on_split_check_scan_complete(
+        &self,
+        region: &Region,
+        scanned_size: u64,
+        found_split: bool,
+    ) {
         loop_ob!(
             region,
             &self.registry.split_check_observers,
-            add_checker,
-            &mut host,
-            engine
+            on_scan_complete,
+            scanned_size,
+            found_split
+        );
+    }
+
+    pub fn on_split_emitted(&self, region: &Region, split_keys: &[Vec<u8>]) {
+        loop_ob!(
+            region,
+            &self.registry.split_check_observers,
+            on_split_emitted,
+            split_keys
         );
-        host
     }
 
     pub fn on_role_change(&self, region: &Region, role: StateRole) {
@@ -255,6 +510,12 @@ mod test {
     use kvproto::raft_cmdpb::{
         AdminRequest, AdminResponse, RaftCmdRequest, RaftCmdResponse, Request, Response,
     };
+    use raftstore::store::Msg;
+    use rocksdb::DB;
+    use storage::ALL_CFS;
+    use tempdir::TempDir;
+    use util::rocksdb::new_engine;
+    use util::transport::RetryableSendCh;
 
     #[derive(Clone, Default)]
     struct TestCoprocessor {
@@ -428,4 +689,97 @@ mod test {
             assert_all!(&[&ob1.called, &ob2.called], &[0, base_score + 1]);
         }
     }
+
+    #[test]
+    fn test_split_check_observer_priorities() {
+        let (tx, _rx) = mpsc::sync_channel::<Msg>(10);
+        let ch = RetryableSendCh::new(tx, "test-split-check");
+        let host = CoprocessorHost::new(Config::default(), ch);
+        // half, size, keys and table.
+        assert_eq!(
+            host.registry.split_check_observer_priorities(),
+            vec![100, 200, 200, 400]
+        );
+    }
+
+    struct NoopChecker;
+
+    impl SplitChecker for NoopChecker {
+        fn split_key(&mut self) -> Option<Vec<u8>> {
+            None
+        }
+    }
+
+    struct NoopObserver;
+
+    impl Coprocessor for NoopObserver {}
+
+    impl SplitCheckObserver for NoopObserver {
+        fn add_checker(&self, _: &mut ObserverContext, host: &mut SplitCheckerHost, _: &DB) {
+            host.add_checker(Box::new(NoopChecker));
+        }
+    }
+
+    #[test]
+    fn test_toggle_split_check_observer() {
+        let path = TempDir::new("test-toggle-split-check-observer").unwrap();
+        let engine = new_engine(path.path().to_str().unwrap(), ALL_CFS, None).unwrap();
+        let mut host = CoprocessorHost::default();
+        host.registry
+            .register_named_split_check_observer(1, "noop", Box::new(NoopObserver));
+        let region = Region::new();
+        let skip = |host: &CoprocessorHost| {
+            host.new_split_checker_host(&region, &engine, true, SplitCheckContext::default()).skip()
+        };
+
+        assert!(!skip(&host));
+        host.set_split_check_observer_enabled("noop", false).unwrap();
+        assert!(skip(&host));
+        host.set_split_check_observer_enabled("noop", true).unwrap();
+        assert!(!skip(&host));
+        assert!(host.set_split_check_observer_enabled("none", false).is_err());
+    }
+
+    #[derive(Clone, Default)]
+    struct CountingObserver {
+        scan_completed: Arc<AtomicUsize>,
+        split_emitted: Arc<AtomicUsize>,
+    }
+
+    impl Coprocessor for CountingObserver {}
+
+    impl SplitCheckObserver for CountingObserver {
+        fn add_checker(&self, _: &mut ObserverContext, _: &mut SplitCheckerHost, _: &DB) {}
+
+        fn on_scan_complete(&self, _: &ObserverContext, _: u64, _: bool) {
+            self.scan_completed.fetch_add(1, Ordering::SeqCst);
+        }
+
+        fn on_split_emitted(&self, _: &ObserverContext, _: &[Vec<u8>]) {
+            self.split_emitted.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn test_disabled_split_check_observer_hooks() {
+        let mut host = CoprocessorHost::default();
+        let ob = CountingObserver::default();
+        host.registry
+            .register_named_split_check_observer(1, "counting", Box::new(ob.clone()));
+        // Observers registered with the same name share the flag.
+        host.registry
+            .register_named_split_check_observer(2, "counting", Box::new(ob.clone()));
+        let region = Region::new();
+
+        host.on_split_check_scan_complete(&region, 10, false);
+        host.on_split_emitted(&region, &[b"k".to_vec()]);
+        assert_eq!(ob.scan_completed.load(Ordering::SeqCst), 2);
+        assert_eq!(ob.split_emitted.load(Ordering::SeqCst), 2);
+
+        host.set_split_check_observer_enabled("counting", false).unwrap();
+        host.on_split_check_scan_complete(&region, 10, false);
+        host.on_split_emitted(&region, &[b"k".to_vec()]);
+        assert_eq!(ob.scan_completed.load(Ordering::SeqCst), 2);
+        assert_eq!(ob.split_emitted.load(Ordering::SeqCst), 2);
+    }
 }
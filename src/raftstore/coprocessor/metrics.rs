@@ -11,17 +11,71 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use prometheus::{exponential_buckets, Histogram};
+use std::cmp;
+use std::sync::Mutex;
+
+use prometheus::{exponential_buckets, Histogram, IntCounterVec};
+
+// Number of buckets of the region size histogram in every 10x range.
+const REGION_SIZE_BUCKETS_PER_DECADE: usize = 10;
+
+/// Get the buckets of the region size histogram, they span from 0.1x to 10x
+/// of `region_max_size`.
+pub fn region_size_buckets(region_max_size: u64) -> Vec<f64> {
+    let start = cmp::max(region_max_size, 10) as f64 / 10.0;
+    let factor = 10f64.powf(1.0 / REGION_SIZE_BUCKETS_PER_DECADE as f64);
+    exponential_buckets(start, factor, REGION_SIZE_BUCKETS_PER_DECADE * 2 + 1).unwrap()
+}
+
+/// Get the histogram of approximate region size. It's registered by the
+/// first call, the buckets are decided by its `region_max_size`.
+pub fn region_size_histogram(region_max_size: u64) -> Histogram {
+    let mut histogram = REGION_SIZE_HISTOGRAM.lock().unwrap();
+    histogram
+        .get_or_insert_with(|| {
+            register_histogram!(
+                "tikv_raftstore_region_size",
+                "Bucketed histogram of approximate region size.",
+                region_size_buckets(region_max_size)
+            ).unwrap()
+        })
+        .clone()
+}
 
 lazy_static! {
-    pub static ref REGION_SIZE_HISTOGRAM: Histogram = register_histogram!(
-        "tikv_raftstore_region_size",
-        "Bucketed histogram of approximate region size.",
-        exponential_buckets(4096.0, 2.0, 20).unwrap()
-    ).unwrap();
+    // Registered once the max size of regions is known.
+    static ref REGION_SIZE_HISTOGRAM: Mutex<Option<Histogram>> = Mutex::new(None);
     pub static ref REGION_KEYS_HISTOGRAM: Histogram = register_histogram!(
         "tikv_raftstore_region_keys",
         "Bucketed histogram of approximate region keys.",
         exponential_buckets(1.0, 2.0, 30).unwrap()
     ).unwrap();
+    pub static ref REGION_SIZE_CACHE_COUNTER_VEC: IntCounterVec = register_int_counter_vec!(
+        "tikv_raftstore_region_size_cache_total",
+        "Total number of approximate region size cache lookups.",
+        &["type"]
+    ).unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_region_size_buckets() {
+        for &max_size in &[96 * 1024 * 1024, 10 * 1024 * 1024 * 1024] {
+            let buckets = region_size_buckets(max_size);
+            assert_eq!(buckets.len(), 21);
+            let max_size = max_size as f64;
+            assert!((buckets[0] - max_size / 10.0).abs() < 1.0);
+            assert!((buckets[10] / max_size - 1.0).abs() < 1e-6);
+            assert!((buckets[20] / max_size - 10.0).abs() < 1e-6);
+        }
+        // The buckets scale with the max size.
+        let small = region_size_buckets(1024 * 1024);
+        let large = region_size_buckets(1024 * 1024 * 1024);
+        for (s, l) in small.iter().zip(&large) {
+            assert!((l / s - 1024.0).abs() < 1e-6);
+        }
+    }
 }
@@ -16,6 +16,7 @@ use kvproto::raft_cmdpb::{AdminRequest, AdminResponse, Request, Response};
 use protobuf::RepeatedField;
 use raft::StateRole;
 use rocksdb::DB;
+use storage::{CfName, LARGE_CFS};
 
 pub mod config;
 pub mod dispatcher;
@@ -23,13 +24,19 @@ mod error;
 mod metrics;
 mod split_check;
 pub mod split_observer;
+#[cfg(test)]
+pub mod test_util;
 
 pub use self::config::Config;
 pub use self::dispatcher::{CoprocessorHost, Registry};
 pub use self::error::{Error, Result};
 pub use self::split_check::{
-    HalfCheckObserver, Host as SplitCheckerHost, KeysCheckObserver, SizeCheckObserver,
-    TableCheckObserver,
+    decay_weight, new_size_tracker_listener, CombineMode, CombinedChecker,
+    DistinctKeysCheckObserver, GapChecker, HalfCheckObserver, HashPrefixCheckObserver,
+    HashPrefixChecker, Host as SplitCheckerHost, HotRangeObserver, IntervalCheckObserver,
+    KeysCheckObserver, LoadAwareSplitObserver, LockCheckObserver, QuantileChecker,
+    RegionSizeTracker, SizeCheckObserver, SpanCheckObserver, TableCheckObserver, WeightFn,
+    WeightedChecker,
 };
 
 pub use raftstore::store::KeyEntry;
@@ -41,11 +48,64 @@ pub trait Coprocessor {
     fn stop(&self) {}
 }
 
+/// State of a region known by raftstore when the hooks are called.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RegionState {
+    Normal,
+    Merging,
+    Splitting,
+}
+
+/// The context of a split check given by its caller, see the fields of the
+/// same names in `ObserverContext`. The `ingest_ranges` are in addition to
+/// the ones registered in `CoprocessorHost`.
+#[derive(Clone, Debug)]
+pub struct SplitCheckContext {
+    pub region_state: RegionState,
+    pub is_leader: bool,
+    pub resumed_size: u64,
+    pub custom_policy: Option<u32>,
+    pub ingest_ranges: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+impl Default for SplitCheckContext {
+    fn default() -> SplitCheckContext {
+        SplitCheckContext {
+            region_state: RegionState::Normal,
+            is_leader: true,
+            resumed_size: 0,
+            custom_policy: None,
+            ingest_ranges: vec![],
+        }
+    }
+}
+
 /// Context of observer.
 pub struct ObserverContext<'a> {
     region: &'a Region,
     /// Whether to bypass following observer hook.
     pub bypass: bool,
+    /// A hint of the region's state, it's `Normal` if unknown.
+    pub region_state: RegionState,
+    /// Whether the local peer is the leader of the region when the split
+    /// check is scheduled, it's true if unknown.
+    pub is_leader: bool,
+    /// The size already scanned by the previous tasks if a split check is
+    /// resumed from the middle of the region, otherwise it's 0.
+    pub resumed_size: u64,
+    /// The `(max_size, split_size)` of the region overriding the ones in the
+    /// config, it's set by `SplitCheckObserver::override_thresholds`, or by
+    /// the observers adding checkers before the size checker.
+    pub size_thresholds: Option<(u64, u64)>,
+    /// The approximate size of the region sent to raftstore by the
+    /// observers, it's passed to `SplitCheckObserver::on_region_size_reported`.
+    pub reported_size: Option<u64>,
+    /// The policy defined by observers of the split check task, it's set by
+    /// `SplitCheckTask::with_custom_policy`.
+    pub custom_policy: Option<u32>,
+    /// The `[start, end]` key ranges of the SST files being ingested that
+    /// overlap with the region, splitting the region may break the ingestion.
+    pub ingest_ranges: Vec<(Vec<u8>, Vec<u8>)>,
 }
 
 impl<'a> ObserverContext<'a> {
@@ -53,10 +113,17 @@ impl<'a> ObserverContext<'a> {
         ObserverContext {
             region,
             bypass: false,
+            region_state: RegionState::Normal,
+            is_leader: true,
+            resumed_size: 0,
+            size_thresholds: None,
+            reported_size: None,
+            custom_policy: None,
+            ingest_ranges: vec![],
         }
     }
 
-    pub fn region(&self) -> &Region {
+    pub fn region(&self) -> &'a Region {
         self.region
     }
 }
@@ -93,6 +160,29 @@ pub trait QueryObserver: Coprocessor {
     fn post_apply_query(&self, _: &mut ObserverContext, _: &mut RepeatedField<Response>) {}
 }
 
+/// The name of the split checkers that don't tell their names, they are
+/// never deduplicated.
+pub const UNKNOWN_CHECKER: &str = "unknown";
+
+/// Describes a split checker and its thresholds, for introspection.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CheckerInfo {
+    pub name: &'static str,
+    pub max_size: Option<u64>,
+    pub split_size: Option<u64>,
+    pub max_keys: Option<u64>,
+    pub split_keys: Option<u64>,
+}
+
+impl CheckerInfo {
+    pub fn new(name: &'static str) -> CheckerInfo {
+        CheckerInfo {
+            name,
+            ..Default::default()
+        }
+    }
+}
+
 /// SplitChecker is invoked during a split check scan, and decides to use
 /// which keys to split a region.
 pub trait SplitChecker {
@@ -103,18 +193,118 @@ pub trait SplitChecker {
         false
     }
 
-    /// Get the desired split keys.
+    /// Hook to call for a batch of kvs scanned during split, the kvs are
+    /// in order and follow the ones of the last call.
+    ///
+    /// Return true to abort scan early.
+    fn on_kvs(&mut self, ctx: &mut ObserverContext, entries: &[KeyEntry]) -> bool {
+        for entry in entries {
+            if self.on_kv(ctx, entry) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Get the desired split key.
     fn split_key(&mut self) -> Option<Vec<u8>>;
 
+    /// The CFs whose kvs should be passed to `on_kv`.
+    fn interested_cfs(&self) -> &[CfName] {
+        LARGE_CFS
+    }
+
+    /// Whether the kvs should be passed to `on_kv` in descending order, from
+    /// the end of the region to the start. The split key is still the first
+    /// key of the right region.
+    fn reversed(&self) -> bool {
+        false
+    }
+
+    /// Get all the desired split keys in ascending order.
+    ///
+    /// A region that is many times larger than the threshold can be split
+    /// at several points after a single scan.
+    fn split_keys(&mut self) -> Vec<Vec<u8>> {
+        self.split_key().into_iter().collect()
+    }
+
+    /// Get the boundary keys of the buckets in the region, which are
+    /// evenly spaced by size, in ascending order.
+    fn buckets(&mut self) -> Vec<Vec<u8>> {
+        vec![]
+    }
+
+    /// Get the number of regions the scanned part of the region would
+    /// become if it's fully split, None if the checker can't tell.
+    fn estimated_splits(&self) -> Option<u64> {
+        None
+    }
+
     /// Get approximate split keys without scan.
     fn approximate_split_key(&self, _: &Region, _: &DB) -> Result<Option<Vec<u8>>> {
         Ok(None)
     }
+
+    /// Hook to call before splitting at `key`, which is a data key like
+    /// the ones passed to `on_kv`.
+    ///
+    /// Return false to veto the split.
+    fn approve_split(&self, _: &ObserverContext, _: &[u8]) -> bool {
+        true
+    }
+
+    /// Hook to call before splitting at `key`, it can move the split key
+    /// to a better place.
+    ///
+    /// Return the key to split at instead, or None to veto the split.
+    fn adjust_split_key(&self, _: &ObserverContext, key: Vec<u8>) -> Option<Vec<u8>> {
+        Some(key)
+    }
+
+    /// Describe the checker, it's only used for introspection.
+    fn describe(&self) -> CheckerInfo {
+        CheckerInfo::new(UNKNOWN_CHECKER)
+    }
+
+    /// The name of the checker, checkers of the same name do the same work,
+    /// so only the first one added to a host is kept.
+    fn name(&self) -> &'static str {
+        self.describe().name
+    }
 }
 
 pub trait SplitCheckObserver: Coprocessor {
     /// Add a checker for a split scan.
     fn add_checker(&self, _: &mut ObserverContext, &mut SplitCheckerHost, _: &DB);
+
+    /// Hook to call before adding checkers, it can override the size
+    /// thresholds of the region, e.g. to split a known hot table smaller.
+    ///
+    /// Return the `(max_size, split_size)` of the region, the first one
+    /// returned by the observers wins. `split_size` must not be larger
+    /// than `max_size`.
+    fn override_thresholds(&self, _: &ObserverContext) -> Option<(u64, u64)> {
+        None
+    }
+
+    /// Hook to call when a split check scan of the region finishes.
+    ///
+    /// `scanned_size` is the total size of the scanned kvs, which may be
+    /// far larger than the approximate size if there are many tombstones.
+    fn on_scan_complete(&self, _: &ObserverContext, _scanned_size: u64, _found_split: bool) {}
+
+    /// Hook to call right after the split keys of the region are sent to
+    /// raftstore, it can be used to prefetch the blocks around the split
+    /// keys for the new regions.
+    ///
+    /// The split keys are data keys, which start with "z".
+    fn on_split_emitted(&self, _: &ObserverContext, _split_keys: &[Vec<u8>]) {}
+
+    /// Hook to call after the checkers are added if the approximate size of
+    /// the region has been sent to raftstore, it can be used to forward the
+    /// size to an external metrics sink.
+    fn on_region_size_reported(&self, _: &ObserverContext, _size: u64) {}
 }
 
 pub trait RoleObserver: Coprocessor {
@@ -0,0 +1,189 @@
+// Copyright 2018 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use kvproto::metapb::Region;
+use rocksdb::DB;
+use storage::{CfName, DATA_CFS};
+
+use super::super::error::Result;
+use super::super::{CheckerInfo, KeyEntry, ObserverContext, SplitChecker, UNKNOWN_CHECKER};
+
+/// How the results of the checkers in a `CombinedChecker` are combined.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CombineMode {
+    /// Split when all the checkers find a split key, at the last one of
+    /// their split keys.
+    And,
+    /// Split when any checker finds a split key, at the first one of their
+    /// split keys.
+    Or,
+}
+
+/// Combines several checkers into one, so a region can be split by
+/// multiple conditions in a single host.
+pub struct CombinedChecker {
+    checkers: Vec<Box<SplitChecker>>,
+    mode: CombineMode,
+    // Whether each checker has aborted the scan.
+    finished: Vec<bool>,
+    cfs: Vec<CfName>,
+}
+
+impl CombinedChecker {
+    pub fn new(checkers: Vec<Box<SplitChecker>>, mode: CombineMode) -> CombinedChecker {
+        let cfs = DATA_CFS
+            .iter()
+            .filter(|cf| checkers.iter().any(|c| c.interested_cfs().contains(*cf)))
+            .cloned()
+            .collect();
+        let finished = vec![false; checkers.len()];
+        CombinedChecker {
+            checkers,
+            mode,
+            finished,
+            cfs,
+        }
+    }
+
+    fn done(&self) -> bool {
+        match self.mode {
+            CombineMode::And => self.finished.iter().all(|f| *f),
+            CombineMode::Or => self.finished.iter().any(|f| *f),
+        }
+    }
+
+    fn combine(&self, keys: Vec<Option<Vec<u8>>>) -> Option<Vec<u8>> {
+        match self.mode {
+            CombineMode::And => if keys.iter().all(|k| k.is_some()) {
+                keys.into_iter().filter_map(|k| k).max()
+            } else {
+                None
+            },
+            CombineMode::Or => keys.into_iter().filter_map(|k| k).min(),
+        }
+    }
+}
+
+impl SplitChecker for CombinedChecker {
+    fn on_kv(&mut self, ctx: &mut ObserverContext, entry: &KeyEntry) -> bool {
+        for (checker, finished) in self.checkers.iter_mut().zip(&mut self.finished) {
+            if !*finished {
+                *finished = checker.on_kv(ctx, entry);
+            }
+        }
+        self.done()
+    }
+
+    fn on_kvs(&mut self, ctx: &mut ObserverContext, entries: &[KeyEntry]) -> bool {
+        for (checker, finished) in self.checkers.iter_mut().zip(&mut self.finished) {
+            if !*finished {
+                *finished = checker.on_kvs(ctx, entries);
+            }
+        }
+        self.done()
+    }
+
+    fn split_key(&mut self) -> Option<Vec<u8>> {
+        let keys = self.checkers.iter_mut().map(|c| c.split_key()).collect();
+        self.combine(keys)
+    }
+
+    fn interested_cfs(&self) -> &[CfName] {
+        &self.cfs
+    }
+
+    fn approximate_split_key(&self, region: &Region, engine: &DB) -> Result<Option<Vec<u8>>> {
+        let mut keys = Vec::with_capacity(self.checkers.len());
+        for checker in &self.checkers {
+            keys.push(checker.approximate_split_key(region, engine)?);
+        }
+        Ok(self.combine(keys))
+    }
+
+    fn approve_split(&self, ctx: &ObserverContext, key: &[u8]) -> bool {
+        self.checkers.iter().all(|c| c.approve_split(ctx, key))
+    }
+
+    fn adjust_split_key(&self, ctx: &ObserverContext, key: Vec<u8>) -> Option<Vec<u8>> {
+        let mut key = key;
+        for checker in &self.checkers {
+            key = checker.adjust_split_key(ctx, key)?;
+        }
+        Some(key)
+    }
+
+    fn describe(&self) -> CheckerInfo {
+        CheckerInfo::new("combined")
+    }
+
+    // Combined checkers differ by the checkers they combine.
+    fn name(&self) -> &'static str {
+        UNKNOWN_CHECKER
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use storage::CF_WRITE;
+
+    use super::super::keys::Checker as KeysChecker;
+    use super::super::size::Checker as SizeChecker;
+    use super::*;
+
+    fn new_checker(max_size: u64, split_size: u64, mode: CombineMode) -> CombinedChecker {
+        let checkers: Vec<Box<SplitChecker>> = vec![
+            Box::new(SizeChecker::new(max_size, split_size, 0, None)),
+            Box::new(KeysChecker::new(5, 3)),
+        ];
+        CombinedChecker::new(checkers, mode)
+    }
+
+    fn check(mut checker: CombinedChecker) -> (usize, Option<Vec<u8>>) {
+        let region = Region::default();
+        let mut ctx = ObserverContext::new(&region);
+        // 20 entries of 10 bytes.
+        let mut scanned = 0;
+        for i in 0..20 {
+            let entry = KeyEntry::new(format!("{:04}", i).into_bytes(), 0, 6, CF_WRITE);
+            scanned += 1;
+            if checker.on_kv(&mut ctx, &entry) {
+                break;
+            }
+        }
+        (scanned, checker.split_key())
+    }
+
+    #[test]
+    fn test_combined_checker_or() {
+        // The keys checker aborts the scan after 6 keys, and splits at the
+        // 4th key.
+        let checker = new_checker(150, 100, CombineMode::Or);
+        assert_eq!(check(checker), (6, Some(b"0003".to_vec())));
+
+        // The earliest split key is used.
+        let checker = new_checker(30, 20, CombineMode::Or);
+        assert_eq!(check(checker), (6, Some(b"0002".to_vec())));
+    }
+
+    #[test]
+    fn test_combined_checker_and() {
+        // The scan goes on until both checkers finish, the size checker
+        // splits at the 11th key.
+        let checker = new_checker(150, 100, CombineMode::And);
+        assert_eq!(check(checker), (20, Some(b"0010".to_vec())));
+
+        // No split if the size checker doesn't find a split key.
+        let checker = new_checker(1000, 500, CombineMode::And);
+        assert_eq!(check(checker), (20, None));
+    }
+}
@@ -0,0 +1,195 @@
+// Copyright 2018 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::mem;
+
+use rocksdb::DB;
+
+use raftstore::store::util;
+use storage::types::Key;
+use storage::{CfName, CF_WRITE};
+
+use super::super::{
+    CheckerInfo, Coprocessor, KeyEntry, ObserverContext, SplitCheckObserver, SplitChecker,
+};
+use super::Host;
+
+/// Splits the region by the number of distinct user keys in the write CF,
+/// so the versions of a key don't count as more rows. The split keys are
+/// user keys, all the versions of a key stay in the same region.
+pub struct Checker {
+    max_keys: u64,
+    split_keys: u64,
+    // Number of distinct user keys scanned so far.
+    total_keys: u64,
+    // Number of distinct user keys since the last split key.
+    current_keys: u64,
+    last_user_key: Vec<u8>,
+    split_keys_found: Vec<Vec<u8>>,
+}
+
+impl Checker {
+    /// The region is split every `split_keys` distinct keys once it has
+    /// more than `max_keys` distinct keys.
+    pub fn new(max_keys: u64, split_keys: u64) -> Checker {
+        Checker {
+            max_keys,
+            split_keys,
+            total_keys: 0,
+            current_keys: 0,
+            last_user_key: vec![],
+            split_keys_found: vec![],
+        }
+    }
+}
+
+impl SplitChecker for Checker {
+    fn on_kv(&mut self, _: &mut ObserverContext, entry: &KeyEntry) -> bool {
+        if entry.cf() != CF_WRITE {
+            return false;
+        }
+        let user_key = Key::truncate_ts_for(entry.key()).unwrap_or(entry.key());
+        if user_key == self.last_user_key.as_slice() {
+            return false;
+        }
+        self.total_keys += 1;
+        self.current_keys += 1;
+        if self.current_keys > self.split_keys {
+            self.split_keys_found.push(user_key.to_vec());
+            self.current_keys = 1;
+        }
+        self.last_user_key.clear();
+        self.last_user_key.extend_from_slice(user_key);
+        false
+    }
+
+    fn split_key(&mut self) -> Option<Vec<u8>> {
+        self.split_keys().into_iter().next()
+    }
+
+    fn split_keys(&mut self) -> Vec<Vec<u8>> {
+        let split_keys = mem::replace(&mut self.split_keys_found, vec![]);
+        if self.total_keys > self.max_keys {
+            split_keys
+        } else {
+            vec![]
+        }
+    }
+
+    fn interested_cfs(&self) -> &[CfName] {
+        &[CF_WRITE]
+    }
+
+    fn describe(&self) -> CheckerInfo {
+        CheckerInfo {
+            max_keys: Some(self.max_keys),
+            split_keys: Some(self.split_keys),
+            ..CheckerInfo::new("distinct_keys")
+        }
+    }
+}
+
+pub struct DistinctKeysCheckObserver {
+    max_keys: u64,
+    split_keys: u64,
+}
+
+impl DistinctKeysCheckObserver {
+    pub fn new(split_keys: u64) -> DistinctKeysCheckObserver {
+        DistinctKeysCheckObserver {
+            max_keys: split_keys / 2 * 3,
+            split_keys,
+        }
+    }
+}
+
+impl Coprocessor for DistinctKeysCheckObserver {}
+
+impl SplitCheckObserver for DistinctKeysCheckObserver {
+    fn add_checker(&self, ctx: &mut ObserverContext, host: &mut Host, engine: &DB) {
+        let region = ctx.region();
+        // The approximate keys count all the versions, there can't be more
+        // distinct keys than it.
+        match util::get_region_approximate_keys(engine, region) {
+            Ok(keys) if keys <= self.max_keys => return,
+            Ok(_) => {}
+            Err(e) => warn!(
+                "[region {}] failed to get approximate keys: {}",
+                region.get_id(),
+                e
+            ),
+        }
+        host.add_checker(Box::new(Checker::new(self.max_keys, self.split_keys)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use kvproto::metapb::Region;
+
+    use raftstore::store::keys;
+    use storage::CF_DEFAULT;
+
+    use super::*;
+
+    fn user_key(i: u64) -> Vec<u8> {
+        keys::data_key(Key::from_raw(format!("k{:04}", i).as_bytes()).encoded())
+    }
+
+    fn version_key(i: u64, ts: u64) -> Vec<u8> {
+        keys::data_key(
+            Key::from_raw(format!("k{:04}", i).as_bytes())
+                .append_ts(ts)
+                .encoded(),
+        )
+    }
+
+    #[test]
+    fn test_distinct_keys_checker() {
+        let region = Region::default();
+        let mut ctx = ObserverContext::new(&region);
+
+        // 20 keys with 10 versions each, only the keys count.
+        let mut checker = Checker::new(15, 10);
+        for i in 0..20 {
+            for ts in (1..11).rev() {
+                let entry = KeyEntry::new(version_key(i, ts), 0, 10, CF_WRITE);
+                checker.on_kv(&mut ctx, &entry);
+            }
+            let entry = KeyEntry::new(version_key(i, 1), 0, 10, CF_DEFAULT);
+            checker.on_kv(&mut ctx, &entry);
+        }
+        assert_eq!(checker.split_keys(), vec![user_key(10)]);
+
+        // 200 versions are not enough to split.
+        let mut checker = Checker::new(15, 10);
+        for i in 0..10 {
+            for ts in (1..21).rev() {
+                let entry = KeyEntry::new(version_key(i, ts), 0, 10, CF_WRITE);
+                checker.on_kv(&mut ctx, &entry);
+            }
+        }
+        assert!(checker.split_keys().is_empty());
+
+        // Split every 10 distinct keys.
+        let mut checker = Checker::new(15, 10);
+        for i in 0..35 {
+            for ts in (1..3).rev() {
+                let entry = KeyEntry::new(version_key(i, ts), 0, 10, CF_WRITE);
+                checker.on_kv(&mut ctx, &entry);
+            }
+        }
+        let expected = vec![user_key(10), user_key(20), user_key(30)];
+        assert_eq!(checker.split_keys(), expected);
+    }
+}
@@ -0,0 +1,135 @@
+// Copyright 2018 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::cmp;
+
+use raftstore::store::keys;
+
+use super::super::{CheckerInfo, KeyEntry, ObserverContext, SplitChecker};
+use super::span::{key_to_number, number_to_key, SPAN_KEY_WIDTH};
+
+/// Splits the region at the middle of the largest gap between consecutive
+/// keys, so both regions have the most room to grow in a sparse key space.
+/// Keys are compared as fixed-width numbers like `span::Checker`.
+pub struct Checker {
+    width: usize,
+    min_size: u64,
+    last_number: Option<u64>,
+    scanned_size: u64,
+    // The gaps larger than all the gaps before them, with their middle
+    // numbers and the size before them. Sizes are ascending, so the
+    // largest gap with enough data after it is the last one that fits.
+    records: Vec<(u64, u64, u64)>,
+}
+
+impl Checker {
+    /// Both regions after split must have at least `min_size` bytes.
+    pub fn new(width: usize, min_size: u64) -> Checker {
+        Checker {
+            width: cmp::min(width, SPAN_KEY_WIDTH),
+            min_size,
+            last_number: None,
+            scanned_size: 0,
+            records: vec![],
+        }
+    }
+}
+
+impl SplitChecker for Checker {
+    fn on_kv(&mut self, _: &mut ObserverContext, entry: &KeyEntry) -> bool {
+        let number = key_to_number(keys::origin_key(entry.key()), self.width);
+        if let Some(last) = self.last_number {
+            let gap = number.saturating_sub(last);
+            let largest = self.records.last().map_or(true, |&(g, _, _)| gap > g);
+            // The middle must differ from both ends of the gap.
+            if gap >= 2 && self.scanned_size >= self.min_size && largest {
+                self.records.push((gap, last + gap / 2, self.scanned_size));
+            }
+        }
+        self.last_number = Some(number);
+        self.scanned_size += entry.entry_size() as u64;
+        false
+    }
+
+    fn split_key(&mut self) -> Option<Vec<u8>> {
+        let max_left_size = self.scanned_size.checked_sub(self.min_size)?;
+        self.records
+            .iter()
+            .rev()
+            .find(|&&(_, _, left_size)| left_size <= max_left_size)
+            .map(|&(_, middle, _)| keys::data_key(&number_to_key(middle, self.width)))
+    }
+
+    fn describe(&self) -> CheckerInfo {
+        CheckerInfo::new("gap")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use kvproto::metapb::Region;
+
+    use storage::CF_WRITE;
+
+    use super::*;
+
+    fn check(checker: &mut Checker, numbers: &[u64]) -> Option<Vec<u8>> {
+        let region = Region::default();
+        let mut ctx = ObserverContext::new(&region);
+        for n in numbers {
+            let key = keys::data_key(&number_to_key(*n, SPAN_KEY_WIDTH));
+            checker.on_kv(&mut ctx, &KeyEntry::new(key, 0, 2, CF_WRITE));
+        }
+        checker.split_key()
+    }
+
+    fn split_at(n: u64) -> Option<Vec<u8>> {
+        Some(keys::data_key(&number_to_key(n, SPAN_KEY_WIDTH)))
+    }
+
+    #[test]
+    fn test_number_to_key() {
+        assert_eq!(number_to_key(0x0102, 2), vec![1, 2]);
+        assert_eq!(number_to_key(0x0102, 4), vec![0, 0, 1, 2]);
+        assert_eq!(key_to_number(&number_to_key(1 << 40, 8), 8), 1 << 40);
+    }
+
+    #[test]
+    fn test_gap_checker() {
+        // Two clusters of keys, the split lands in the middle of the gap.
+        let mut numbers: Vec<_> = (0..10).collect();
+        numbers.extend(1000..1010);
+        let mut checker = Checker::new(8, 0);
+        assert_eq!(check(&mut checker, &numbers), split_at(504));
+        let mut checker = Checker::new(8, 100);
+        assert_eq!(check(&mut checker, &numbers), split_at(504));
+
+        // Every entry is 11 bytes. The largest gap leaves too little data
+        // on the right, the largest one of the rest is chosen.
+        let numbers = [0, 1, 2, 10, 11, 12, 13, 14, 15, 1000];
+        let mut checker = Checker::new(8, 0);
+        assert_eq!(check(&mut checker, &numbers), split_at(507));
+        let mut checker = Checker::new(8, 20);
+        assert_eq!(check(&mut checker, &numbers), split_at(6));
+        let mut checker = Checker::new(8, 40);
+        assert_eq!(check(&mut checker, &numbers), None);
+        // The largest gap leaves too little data on the left.
+        let numbers = [0, 1000, 1001, 1002, 1010, 1011, 1012];
+        let mut checker = Checker::new(8, 20);
+        assert_eq!(check(&mut checker, &numbers), split_at(1006));
+
+        // Consecutive keys have no room for a split key between them.
+        let mut checker = Checker::new(8, 0);
+        assert_eq!(check(&mut checker, &[0, 1, 2, 3]), None);
+    }
+}
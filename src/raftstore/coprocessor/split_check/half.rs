@@ -11,12 +11,16 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::u64;
+
 use rocksdb::DB;
 
 use util::config::ReadableSize;
 
 use super::super::error::Result;
-use super::super::{Coprocessor, KeyEntry, ObserverContext, SplitCheckObserver, SplitChecker};
+use super::super::{
+    CheckerInfo, Coprocessor, KeyEntry, ObserverContext, SplitCheckObserver, SplitChecker,
+};
 use super::Host;
 use kvproto::metapb::Region;
 use raftstore::store::util as raftstore_util;
@@ -26,7 +30,10 @@ const BUCKET_SIZE_LIMIT_MB: u64 = 512;
 
 pub struct Checker {
     buckets: Vec<Vec<u8>>,
+    // Size of data before each bucket.
+    offsets: Vec<u64>,
     cur_bucket_size: u64,
+    current_size: u64,
     each_bucket_size: u64,
 }
 
@@ -35,7 +42,9 @@ impl Checker {
         Checker {
             each_bucket_size,
             cur_bucket_size: 0,
+            current_size: 0,
             buckets: vec![],
+            offsets: vec![],
         }
     }
 }
@@ -44,14 +53,34 @@ impl SplitChecker for Checker {
     fn on_kv(&mut self, _: &mut ObserverContext, entry: &KeyEntry) -> bool {
         if self.buckets.is_empty() || self.cur_bucket_size >= self.each_bucket_size {
             self.buckets.push(entry.key().to_vec());
+            self.offsets.push(self.current_size);
             self.cur_bucket_size = 0;
         }
-        self.cur_bucket_size += entry.entry_size() as u64;
+        let size = entry.entry_size() as u64;
+        self.cur_bucket_size += size;
+        self.current_size += size;
         false
     }
 
+    /// Get the start key of the bucket which is closest to the middle
+    /// of the region by size.
     fn split_key(&mut self) -> Option<Vec<u8>> {
-        let mid = self.buckets.len() / 2;
+        let half = self.current_size / 2;
+        let (mut mid, mut min_diff) = (0, u64::MAX);
+        // The first bucket starts at the start of the region, skip it.
+        for (i, &offset) in self.offsets.iter().enumerate().skip(1) {
+            let diff = if offset > half {
+                offset - half
+            } else {
+                half - offset
+            };
+            // Offsets are ascending, the diff won't be smaller any more.
+            if diff >= min_diff {
+                break;
+            }
+            min_diff = diff;
+            mid = i;
+        }
         if mid == 0 {
             None
         } else {
@@ -64,6 +93,10 @@ impl SplitChecker for Checker {
             engine, region
         )))
     }
+
+    fn describe(&self) -> CheckerInfo {
+        CheckerInfo::new("half")
+    }
 }
 
 pub struct HalfCheckObserver {
@@ -120,6 +153,25 @@ mod tests {
     use super::*;
     use raftstore::coprocessor::{Config, CoprocessorHost};
 
+    #[test]
+    fn test_checker_split_at_size_median() {
+        let region = Region::default();
+        let mut ctx = ObserverContext::new(&region);
+        let mut checker = Checker::new(1);
+        // The first key is much larger than the others.
+        for i in 0..10 {
+            let key = format!("k{}", i).into_bytes();
+            let value_size = if i == 0 { 100 } else { 4 };
+            checker.on_kv(&mut ctx, &KeyEntry::new(key, 0, value_size, CF_DEFAULT));
+        }
+        assert_eq!(checker.split_key(), Some(b"k1".to_vec()));
+
+        // Not enough data to be split.
+        let mut checker = Checker::new(1);
+        checker.on_kv(&mut ctx, &KeyEntry::new(b"k0".to_vec(), 0, 4, CF_DEFAULT));
+        assert_eq!(checker.split_key(), None);
+    }
+
     #[test]
     fn test_split_check() {
         let path = TempDir::new("test-raftstore").unwrap();
@@ -167,12 +219,12 @@ mod tests {
             CheckPolicy::SCAN,
         ));
         let split_key = Key::from_raw(b"0005");
-        must_split_at(&rx, &region, split_key.encoded());
+        must_split_at(&rx, &region, vec![split_key.encoded().clone()]);
         runnable.run(SplitCheckTask::new(
             region.clone(),
             false,
             CheckPolicy::APPROXIMATE,
         ));
-        must_split_at(&rx, &region, split_key.encoded());
+        must_split_at(&rx, &region, vec![split_key.encoded().clone()]);
     }
 }
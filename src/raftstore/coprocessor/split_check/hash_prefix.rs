@@ -0,0 +1,240 @@
+// Copyright 2018 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::cmp;
+use std::mem;
+
+use raftstore::store::keys;
+use rocksdb::DB;
+
+use super::super::{
+    CheckerInfo, Coprocessor, KeyEntry, ObserverContext, SplitCheckObserver, SplitChecker,
+};
+use super::span::{key_to_number, number_to_key, SPAN_KEY_WIDTH};
+use super::Host;
+
+/// Gets the prefixes dividing the space of `prefix_len` bytes prefixes into
+/// `count` ranges of the same width, in ascending order.
+fn boundaries(prefix_len: usize, count: u64) -> Vec<u64> {
+    let space = 1u128 << (prefix_len * 8);
+    let mut boundaries: Vec<_> = (1..count)
+        .map(|i| (space * u128::from(i) / u128::from(count)) as u64)
+        .filter(|b| *b > 0)
+        .collect();
+    // There are fewer prefixes than ranges.
+    boundaries.dedup();
+    boundaries
+}
+
+/// Splits the region at the boundaries of the ranges of hash prefixes, so
+/// regions are aligned to the ranges when the keys are spread by a hash
+/// prefix. The first `prefix_len` bytes of keys are the hash prefix, and
+/// the space of prefixes is divided into `count` ranges evenly.
+pub struct Checker {
+    prefix_len: usize,
+    boundaries: Vec<u64>,
+    // The index of the first boundary that the scanned keys haven't reached.
+    next_boundary: usize,
+    first_key: bool,
+    split_keys: Vec<Vec<u8>>,
+}
+
+impl Checker {
+    pub fn new(prefix_len: usize, count: u64) -> Checker {
+        let prefix_len = cmp::min(prefix_len, SPAN_KEY_WIDTH);
+        Checker {
+            prefix_len,
+            boundaries: boundaries(prefix_len, count),
+            next_boundary: 0,
+            first_key: true,
+            split_keys: vec![],
+        }
+    }
+}
+
+impl SplitChecker for Checker {
+    fn on_kv(&mut self, _: &mut ObserverContext, entry: &KeyEntry) -> bool {
+        let prefix = key_to_number(keys::origin_key(entry.key()), self.prefix_len);
+        // Only the last boundary is kept if the key jumps over several, the
+        // ranges between them have no keys.
+        let mut crossed = None;
+        while self.next_boundary < self.boundaries.len()
+            && self.boundaries[self.next_boundary] <= prefix
+        {
+            crossed = Some(self.boundaries[self.next_boundary]);
+            self.next_boundary += 1;
+        }
+        // Never split before the first key, otherwise the left region would
+        // be empty.
+        if let Some(boundary) = crossed {
+            if !self.first_key {
+                let key = number_to_key(boundary, self.prefix_len);
+                self.split_keys.push(keys::data_key(&key));
+            }
+        }
+        self.first_key = false;
+        false
+    }
+
+    fn split_key(&mut self) -> Option<Vec<u8>> {
+        self.split_keys().into_iter().next()
+    }
+
+    fn split_keys(&mut self) -> Vec<Vec<u8>> {
+        mem::replace(&mut self.split_keys, vec![])
+    }
+
+    fn describe(&self) -> CheckerInfo {
+        CheckerInfo::new("hash_prefix")
+    }
+}
+
+pub struct HashPrefixCheckObserver {
+    prefix_len: usize,
+    count: u64,
+}
+
+impl HashPrefixCheckObserver {
+    pub fn new(prefix_len: usize, count: u64) -> HashPrefixCheckObserver {
+        HashPrefixCheckObserver {
+            prefix_len: cmp::min(prefix_len, SPAN_KEY_WIDTH),
+            count,
+        }
+    }
+
+    // Whether any boundary of the ranges is inside the key range, which
+    // needs to be split.
+    fn has_boundary_in(&self, start_key: &[u8], end_key: &[u8]) -> bool {
+        boundaries(self.prefix_len, self.count)
+            .into_iter()
+            .map(|b| number_to_key(b, self.prefix_len))
+            .any(|key| key.as_slice() > start_key && key.as_slice() < end_key)
+    }
+}
+
+impl Coprocessor for HashPrefixCheckObserver {}
+
+impl SplitCheckObserver for HashPrefixCheckObserver {
+    fn add_checker(&self, ctx: &mut ObserverContext, host: &mut Host, _: &DB) {
+        let region = ctx.region();
+        let (start_key, end_key) = (region.get_start_key(), region.get_end_key());
+        // An empty end key means the end of the key space.
+        if !end_key.is_empty() && !self.has_boundary_in(start_key, end_key) {
+            // Region is in a single range, skip for saving IO.
+            return;
+        }
+        host.add_checker(Box::new(Checker::new(self.prefix_len, self.count)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use kvproto::metapb::Region;
+    use tempdir::TempDir;
+
+    use storage::{ALL_CFS, CF_WRITE};
+    use util::rocksdb::new_engine;
+
+    use super::*;
+
+    fn check(checker: &mut Checker, origin_keys: &[Vec<u8>]) -> Vec<Vec<u8>> {
+        let region = Region::default();
+        let mut ctx = ObserverContext::new(&region);
+        for key in origin_keys {
+            let entry = KeyEntry::new(keys::data_key(key), 0, 10, CF_WRITE);
+            checker.on_kv(&mut ctx, &entry);
+        }
+        checker.split_keys()
+    }
+
+    #[test]
+    fn test_boundaries() {
+        assert_eq!(boundaries(1, 4), vec![64, 128, 192]);
+        assert_eq!(boundaries(2, 2), vec![1 << 15]);
+        assert_eq!(boundaries(8, 2), vec![1 << 63]);
+        assert!(boundaries(1, 1).is_empty());
+        // Not enough prefixes for so many ranges.
+        assert_eq!(boundaries(1, 1024).len(), 255);
+    }
+
+    #[test]
+    fn test_hash_prefix_checker() {
+        // Keys are spread by a 2 bytes hash prefix.
+        let mut origin_keys: Vec<_> = (0..1000u64)
+            .map(|i| {
+                let hash = ((i * 2_654_435_761) & 0xffff_ffff) >> 16;
+                let mut key = number_to_key(hash, 2);
+                key.extend_from_slice(format!("k{:04}", i).as_bytes());
+                key
+            })
+            .collect();
+        origin_keys.sort();
+        let mut checker = Checker::new(2, 4);
+        let split_keys = check(&mut checker, &origin_keys);
+        let expected: Vec<_> = [0x4000, 0x8000, 0xc000]
+            .iter()
+            .map(|b| keys::data_key(&number_to_key(*b, 2)))
+            .collect();
+        assert_eq!(split_keys, expected);
+
+        // The keys are partitioned evenly by the prefix ranges.
+        let mut bounds = vec![0];
+        for split_key in &split_keys {
+            let origin_key = keys::origin_key(split_key);
+            let count = origin_keys
+                .iter()
+                .take_while(|k| k.as_slice() < origin_key)
+                .count();
+            bounds.push(count);
+        }
+        bounds.push(origin_keys.len());
+        for w in bounds.windows(2) {
+            let count = w[1] - w[0];
+            assert!(count > 225 && count < 275, "{:?}", bounds);
+        }
+
+        // The first key is past some boundaries, and the keys jump over
+        // several boundaries at once.
+        let mut checker = Checker::new(1, 8);
+        let origin_keys = vec![vec![70], vec![80], vec![200, 1], vec![250]];
+        let expected = vec![keys::data_key(&[192]), keys::data_key(&[224])];
+        assert_eq!(check(&mut checker, &origin_keys), expected);
+
+        // All the keys are in one range.
+        let mut checker = Checker::new(1, 4);
+        assert!(check(&mut checker, &[vec![65], vec![100], vec![127, 255]]).is_empty());
+    }
+
+    #[test]
+    fn test_hash_prefix_check_observer() {
+        let path = TempDir::new("test-hash-prefix-check-observer").unwrap();
+        let engine = new_engine(path.path().to_str().unwrap(), ALL_CFS, None).unwrap();
+        let observer = HashPrefixCheckObserver::new(1, 4);
+        let check = |start_key: &[u8], end_key: &[u8]| {
+            let mut region = Region::default();
+            region.set_start_key(start_key.to_vec());
+            region.set_end_key(end_key.to_vec());
+            let mut ctx = ObserverContext::new(&region);
+            let mut host = Host::new(true);
+            observer.add_checker(&mut ctx, &mut host, &engine);
+            host.skip()
+        };
+
+        // The region in a single range is skipped.
+        assert!(check(&[64], &[127, 255]));
+        assert!(check(&[64, 1], &[128]));
+        assert!(!check(&[64], &[128, 0]));
+        assert!(!check(&[64], b""));
+        assert!(!check(b"", &[64, 1]));
+    }
+}
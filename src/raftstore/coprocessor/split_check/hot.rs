@@ -0,0 +1,207 @@
+// Copyright 2018 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use rocksdb::DB;
+
+use raftstore::store::util;
+
+use super::super::{Coprocessor, ObserverContext, RegionState, SplitCheckObserver};
+use super::Host;
+
+// A region is hot if at least this ratio of its data is written recently.
+const HOT_WRITE_RATIO: f64 = 0.5;
+
+/// Splits the regions written recently into smaller ones, so the hot range
+/// is spread out before it's overloaded. The recent writes are found by the
+/// timestamps recorded by `RangePropertiesCollector` at compaction.
+///
+/// It only lowers the size thresholds of hot regions, the split keys are
+/// still found by the size checker with all its options, so it must be
+/// registered before `SizeCheckObserver`.
+pub struct HotRangeObserver {
+    max_size: u64,
+    split_size: u64,
+}
+
+impl HotRangeObserver {
+    pub fn new(split_size: u64) -> HotRangeObserver {
+        HotRangeObserver {
+            max_size: split_size / 2 * 3,
+            split_size,
+        }
+    }
+}
+
+impl Coprocessor for HotRangeObserver {}
+
+impl SplitCheckObserver for HotRangeObserver {
+    fn add_checker(&self, ctx: &mut ObserverContext, _: &mut Host, engine: &DB) {
+        let region = ctx.region();
+        if ctx.region_state == RegionState::Merging || ctx.size_thresholds.is_some() {
+            return;
+        }
+        let region_size = match util::get_region_approximate_size(engine, region) {
+            Ok(size) => size,
+            Err(e) => {
+                warn!(
+                    "[region {}] failed to get approximate size: {}",
+                    region.get_id(),
+                    e
+                );
+                return;
+            }
+        };
+        if region_size < self.max_size {
+            return;
+        }
+        let ratio = match util::get_region_recent_write_ratio(engine, region) {
+            Ok(ratio) => ratio,
+            Err(e) => {
+                warn!(
+                    "[region {}] failed to get recent write ratio: {}",
+                    region.get_id(),
+                    e
+                );
+                return;
+            }
+        };
+        if ratio < HOT_WRITE_RATIO {
+            return;
+        }
+        info!(
+            "[region {}] {:.2} of size {} is written recently, split it by {}",
+            region.get_id(),
+            ratio,
+            region_size,
+            self.split_size
+        );
+        ctx.size_thresholds = Some((self.max_size, self.split_size));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use kvproto::metapb::Region;
+    use rocksdb::{ColumnFamilyOptions, DBOptions, Writable};
+    use tempdir::TempDir;
+
+    use raftstore::coprocessor::{Config, CoprocessorHost, SplitCheckContext};
+    use raftstore::store::{keys, KeyEntry};
+    use storage::{Key, ALL_CFS, CF_WRITE};
+    use util::config::ReadableSize;
+    use util::properties::RangePropertiesCollectorFactory;
+    use util::rocksdb::{get_cf_handle, new_engine_opt, CFOptions};
+    use util::time::time_now_sec;
+    use util::transport::RetryableSendCh;
+
+    use super::*;
+
+    fn new_test_engine(path: &TempDir) -> DB {
+        let path_str = path.path().to_str().unwrap();
+        let mut cf_opts = ColumnFamilyOptions::new();
+        let f = Box::new(RangePropertiesCollectorFactory::default());
+        cf_opts.add_table_properties_collector_factory("tikv.range-collector", f);
+        let cfs_opts = ALL_CFS
+            .iter()
+            .map(|cf| CFOptions::new(cf, cf_opts.clone()))
+            .collect();
+        new_engine_opt(path_str, DBOptions::new(), cfs_opts).unwrap()
+    }
+
+    // Commit 10 keys from `start` at `hours_ago`, in TSO timestamps.
+    fn put_keys(engine: &DB, start: u64, hours_ago: u64) {
+        let write_cf = get_cf_handle(engine, CF_WRITE).unwrap();
+        let ts = ((time_now_sec() - hours_ago * 3600) * 1000) << 18;
+        for i in start..start + 10 {
+            let key = Key::from_raw(format!("{:04}", i).as_bytes()).append_ts(ts);
+            engine
+                .put_cf(write_cf, &keys::data_key(key.encoded()), &[0; 256])
+                .unwrap();
+        }
+        engine.flush_cf(write_cf, true).unwrap();
+    }
+
+    #[test]
+    fn test_hot_range_observer() {
+        let path = TempDir::new("test-hot-range-observer").unwrap();
+        let engine = new_test_engine(&path);
+
+        let mut region = Region::new();
+        region.set_id(1);
+        let observer = HotRangeObserver::new(1024);
+        let thresholds = || {
+            let mut ctx = ObserverContext::new(&region);
+            let mut host = Host::new(true);
+            observer.add_checker(&mut ctx, &mut host, &engine);
+            assert!(host.skip());
+            ctx.size_thresholds
+        };
+
+        // The region is too small.
+        assert_eq!(thresholds(), None);
+        // The data is written long ago.
+        put_keys(&engine, 0, 24);
+        assert_eq!(thresholds(), None);
+        // Most of the data is written recently.
+        put_keys(&engine, 10, 0);
+        put_keys(&engine, 20, 0);
+        assert_eq!(thresholds(), Some((1536, 1024)));
+    }
+
+    #[test]
+    fn test_hot_range_with_protected_ranges() {
+        let path = TempDir::new("test-hot-range-protected-ranges").unwrap();
+        let engine = new_test_engine(&path);
+        for start in &[0, 10, 20] {
+            put_keys(&engine, *start, 0);
+        }
+
+        let mut region = Region::new();
+        region.set_id(1);
+        let split_keys = |protected_ranges: Vec<(String, String)>| {
+            let (tx, _rx) = mpsc::sync_channel(100);
+            let ch = RetryableSendCh::new(tx, "test-split");
+            let mut cfg = Config::default();
+            cfg.split_region_on_table = false;
+            cfg.region_hot_split_size = ReadableSize(1024);
+            cfg.split_protected_ranges = protected_ranges;
+            let coprocessor = CoprocessorHost::new(cfg, ch);
+            let mut host = coprocessor.new_split_checker_host(
+                &region,
+                &engine,
+                true,
+                SplitCheckContext::default(),
+            );
+            // The only size checker is the configured one with the thresholds
+            // of the hot region.
+            let sizes: Vec<_> = host
+                .checkers()
+                .into_iter()
+                .filter(|c| c.name == "size")
+                .map(|c| (c.max_size.unwrap(), c.split_size.unwrap()))
+                .collect();
+            assert_eq!(sizes, vec![(1536, 1024)]);
+            for i in 0..30 {
+                let key = keys::data_key(format!("{:04}", i).as_bytes());
+                host.on_kv(&region, &KeyEntry::new(key, 0, 256, CF_WRITE));
+            }
+            host.split_keys()
+        };
+
+        assert!(!split_keys(vec![]).is_empty());
+        // The hot region is never split inside a protected range.
+        assert!(split_keys(vec![("".to_owned(), "".to_owned())]).is_empty());
+    }
+}
@@ -0,0 +1,196 @@
+// Copyright 2018 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::cmp;
+use std::mem;
+
+use raftstore::store::keys;
+use rocksdb::DB;
+
+use super::super::{
+    CheckerInfo, Coprocessor, KeyEntry, ObserverContext, SplitCheckObserver, SplitChecker,
+};
+use super::size::KeyPrefixFn;
+use super::Host;
+
+/// Splits the region whenever the prefix of keys changes, so regions are
+/// aligned to fixed key intervals, like the hours of time series keys.
+pub struct Checker {
+    // Extracts the prefix of a key, without the data prefix.
+    prefix_fn: KeyPrefixFn,
+    // A segment smaller than this is merged with the next one.
+    min_size: u64,
+    current_size: u64,
+    last_prefix: Option<Vec<u8>>,
+    split_keys: Vec<Vec<u8>>,
+}
+
+impl Checker {
+    pub fn new<F>(prefix_fn: F, min_size: u64) -> Checker
+    where
+        F: Fn(&[u8]) -> &[u8] + 'static,
+    {
+        Checker {
+            prefix_fn: Box::new(prefix_fn),
+            min_size,
+            current_size: 0,
+            last_prefix: None,
+            split_keys: vec![],
+        }
+    }
+
+    /// Split when the first `prefix_len` bytes of keys change.
+    pub fn with_prefix_len(prefix_len: usize, min_size: u64) -> Checker {
+        Checker::new(move |key| &key[..cmp::min(prefix_len, key.len())], min_size)
+    }
+}
+
+impl SplitChecker for Checker {
+    fn on_kv(&mut self, _: &mut ObserverContext, entry: &KeyEntry) -> bool {
+        let prefix_changed = {
+            let prefix = (self.prefix_fn)(keys::origin_key(entry.key()));
+            let changed = match self.last_prefix {
+                Some(ref last) => last.as_slice() != prefix,
+                None => false,
+            };
+            if changed || self.last_prefix.is_none() {
+                self.last_prefix = Some(prefix.to_vec());
+            }
+            changed
+        };
+        if prefix_changed && self.current_size >= self.min_size {
+            self.split_keys.push(entry.key().to_vec());
+            self.current_size = 0;
+        }
+        self.current_size += entry.entry_size() as u64;
+        false
+    }
+
+    fn split_key(&mut self) -> Option<Vec<u8>> {
+        self.split_keys().into_iter().next()
+    }
+
+    fn split_keys(&mut self) -> Vec<Vec<u8>> {
+        mem::replace(&mut self.split_keys, vec![])
+    }
+
+    fn describe(&self) -> CheckerInfo {
+        CheckerInfo::new("interval")
+    }
+}
+
+pub struct IntervalCheckObserver {
+    prefix_len: usize,
+    min_size: u64,
+}
+
+impl IntervalCheckObserver {
+    pub fn new(prefix_len: usize, min_size: u64) -> IntervalCheckObserver {
+        IntervalCheckObserver {
+            prefix_len,
+            min_size,
+        }
+    }
+
+    fn prefix<'a>(&self, key: &'a [u8]) -> &'a [u8] {
+        &key[..cmp::min(self.prefix_len, key.len())]
+    }
+}
+
+impl Coprocessor for IntervalCheckObserver {}
+
+impl SplitCheckObserver for IntervalCheckObserver {
+    fn add_checker(&self, ctx: &mut ObserverContext, host: &mut Host, _: &DB) {
+        let region = ctx.region();
+        let (start_key, end_key) = (region.get_start_key(), region.get_end_key());
+        // An empty end key means the end of the key space.
+        if !end_key.is_empty()
+            && start_key.len() >= self.prefix_len
+            && self.prefix(start_key) == self.prefix(end_key)
+        {
+            // Region is inside an interval, skip for saving IO.
+            return;
+        }
+        host.add_checker(Box::new(Checker::with_prefix_len(
+            self.prefix_len,
+            self.min_size,
+        )));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use kvproto::metapb::Region;
+    use tempdir::TempDir;
+
+    use storage::{ALL_CFS, CF_WRITE};
+    use util::rocksdb::new_engine;
+
+    use super::*;
+
+    fn hour_key(hour: u64, i: u64) -> Vec<u8> {
+        keys::data_key(format!("h{:02}_{:04}", hour, i).as_bytes())
+    }
+
+    fn check(checker: &mut Checker, hours: u64, keys_per_hour: u64) -> Vec<Vec<u8>> {
+        let region = Region::default();
+        let mut ctx = ObserverContext::new(&region);
+        for hour in 0..hours {
+            for i in 0..keys_per_hour {
+                let entry = KeyEntry::new(hour_key(hour, i), 0, 10, CF_WRITE);
+                checker.on_kv(&mut ctx, &entry);
+            }
+        }
+        checker.split_keys()
+    }
+
+    #[test]
+    fn test_interval_checker() {
+        // "hNN" is the hour prefix, every key is 9 + 10 bytes.
+        let mut checker = Checker::with_prefix_len(3, 0);
+        let expected: Vec<_> = (1..4).map(|h| hour_key(h, 0)).collect();
+        assert_eq!(check(&mut checker, 4, 5), expected);
+
+        // Small intervals are merged with the next ones.
+        let mut checker = Checker::with_prefix_len(3, 150);
+        let expected = vec![hour_key(2, 0)];
+        assert_eq!(check(&mut checker, 4, 5), expected);
+
+        // The prefix can be extracted by a closure.
+        let mut checker = Checker::new(|key| &key[..2], 0);
+        let expected = vec![hour_key(10, 0)];
+        assert_eq!(check(&mut checker, 12, 1), expected);
+    }
+
+    #[test]
+    fn test_interval_check_observer() {
+        let path = TempDir::new("test-interval-check-observer").unwrap();
+        let engine = new_engine(path.path().to_str().unwrap(), ALL_CFS, None).unwrap();
+        let observer = IntervalCheckObserver::new(3, 0);
+        let check = |start_key: &[u8], end_key: &[u8]| {
+            let mut region = Region::default();
+            region.set_start_key(start_key.to_vec());
+            region.set_end_key(end_key.to_vec());
+            let mut ctx = ObserverContext::new(&region);
+            let mut host = Host::new(true);
+            observer.add_checker(&mut ctx, &mut host, &engine);
+            host.skip()
+        };
+
+        // The region inside an hour is skipped.
+        assert!(check(b"h01_0001", b"h01_0005"));
+        assert!(!check(b"h01_0001", b"h02_0005"));
+        assert!(!check(b"h01_0001", b""));
+        assert!(!check(b"", b"h01_0005"));
+    }
+}
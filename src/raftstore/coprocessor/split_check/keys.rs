@@ -11,19 +11,26 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::time::Duration;
+
 use raftstore::store::{util, Msg};
 use rocksdb::DB;
 use util::transport::{RetryableSendCh, Sender};
 
 use super::super::metrics::*;
-use super::super::{Coprocessor, KeyEntry, ObserverContext, SplitCheckObserver, SplitChecker};
-use super::Host;
+use super::super::{
+    CheckerInfo, Coprocessor, KeyEntry, ObserverContext, SplitCheckObserver, SplitChecker,
+};
+use super::{Host, SEND_TIMEOUT_MS};
 
 pub struct Checker {
     max_keys: u64,
     split_keys: u64,
     current_keys: u64,
     split_key: Option<Vec<u8>>,
+    // Scan from the end of the region, so the right region gets
+    // `split_keys` keys instead of the left one.
+    reversed: bool,
 }
 
 impl Checker {
@@ -33,21 +40,71 @@ impl Checker {
             split_keys,
             current_keys: 0,
             split_key: None,
+            reversed: false,
+        }
+    }
+
+    /// Scan the region in reverse order and choose the split key relative to
+    /// the end key, which balances the regions with many tombstones at the
+    /// front.
+    pub fn with_reversed(mut self, reversed: bool) -> Checker {
+        self.reversed = reversed;
+        self
+    }
+
+    /// Whether the current key should be the split key. In reverse order the
+    /// split key is the last key of the right region, so it's recorded one
+    /// key earlier.
+    fn reach_split_keys(&self) -> bool {
+        if self.reversed {
+            self.current_keys >= self.split_keys
+        } else {
+            self.current_keys > self.split_keys
         }
     }
 }
 
+#[cfg(test)]
+impl Checker {
+    /// The number of commit versions scanned so far.
+    pub fn current_keys(&self) -> u64 {
+        self.current_keys
+    }
+
+    /// The split key found so far, it's only returned by `split_key` when
+    /// the region exceeds the max keys.
+    pub fn pending_split_key(&self) -> Option<&[u8]> {
+        self.split_key.as_ref().map(|k| k.as_slice())
+    }
+}
+
 impl SplitChecker for Checker {
     fn on_kv(&mut self, _: &mut ObserverContext, key: &KeyEntry) -> bool {
         if key.is_commit_version() {
             self.current_keys += 1;
         }
-        if self.current_keys > self.split_keys && self.split_key.is_none() {
+        if self.reach_split_keys() && self.split_key.is_none() {
             self.split_key = Some(key.key().to_vec());
         }
         self.current_keys > self.max_keys
     }
 
+    fn on_kvs(&mut self, _: &mut ObserverContext, entries: &[KeyEntry]) -> bool {
+        for entry in entries {
+            if !entry.is_commit_version() {
+                continue;
+            }
+            self.current_keys += 1;
+            if self.reach_split_keys() && self.split_key.is_none() {
+                self.split_key = Some(entry.key().to_vec());
+            }
+            if self.current_keys > self.max_keys {
+                return true;
+            }
+        }
+        false
+    }
+
     fn split_key(&mut self) -> Option<Vec<u8>> {
         if self.current_keys > self.max_keys {
             self.split_key.take()
@@ -55,11 +112,24 @@ impl SplitChecker for Checker {
             None
         }
     }
+
+    fn reversed(&self) -> bool {
+        self.reversed
+    }
+
+    fn describe(&self) -> CheckerInfo {
+        CheckerInfo {
+            max_keys: Some(self.max_keys),
+            split_keys: Some(self.split_keys),
+            ..CheckerInfo::new("keys")
+        }
+    }
 }
 
 pub struct KeysCheckObserver<C> {
     region_max_keys: u64,
     split_keys: u64,
+    reversed: bool,
     ch: RetryableSendCh<Msg, C>,
 }
 
@@ -72,9 +142,20 @@ impl<C: Sender<Msg>> KeysCheckObserver<C> {
         KeysCheckObserver {
             region_max_keys,
             split_keys,
+            reversed: false,
             ch,
         }
     }
+
+    /// Whether the checkers scan regions in reverse order.
+    pub fn with_reversed(mut self, reversed: bool) -> KeysCheckObserver<C> {
+        self.reversed = reversed;
+        self
+    }
+
+    fn new_checker(&self) -> Box<Checker> {
+        Box::new(Checker::new(self.region_max_keys, self.split_keys).with_reversed(self.reversed))
+    }
 }
 
 impl<C> Coprocessor for KeysCheckObserver<C> {}
@@ -91,10 +172,7 @@ impl<C: Sender<Msg> + Send> SplitCheckObserver for KeysCheckObserver<C> {
                     region_id, e
                 );
                 // Need to check keys.
-                host.add_checker(Box::new(Checker::new(
-                    self.region_max_keys,
-                    self.split_keys,
-                )));
+                host.add_checker(self.new_checker());
                 return;
             }
         };
@@ -103,7 +181,8 @@ impl<C: Sender<Msg> + Send> SplitCheckObserver for KeysCheckObserver<C> {
             region_id,
             keys: region_keys,
         };
-        if let Err(e) = self.ch.try_send(res) {
+        let timeout = Duration::from_millis(SEND_TIMEOUT_MS);
+        if let Err(e) = self.ch.try_send_timeout(res, timeout) {
             warn!(
                 "[region {}] failed to send approximate region keys: {}",
                 region_id, e
@@ -119,10 +198,7 @@ impl<C: Sender<Msg> + Send> SplitCheckObserver for KeysCheckObserver<C> {
                 self.region_max_keys
             );
             // Need to check keys.
-            host.add_checker(Box::new(Checker::new(
-                self.region_max_keys,
-                self.split_keys,
-            )));
+            host.add_checker(self.new_checker());
         } else {
             // Does not need to check keys.
             debug!(
@@ -147,7 +223,7 @@ mod tests {
     use rocksdb::{ColumnFamilyOptions, DBOptions};
     use tempdir::TempDir;
 
-    use raftstore::store::{keys, Msg, SplitCheckRunner, SplitCheckTask};
+    use raftstore::store::{keys, KeyEntry, Msg, SplitCheckRunner, SplitCheckTask};
     use storage::mvcc::{Write, WriteType};
     use storage::{Key, ALL_CFS, CF_DEFAULT, CF_WRITE};
     use util::properties::RangePropertiesCollectorFactory;
@@ -155,9 +231,10 @@ mod tests {
     use util::transport::RetryableSendCh;
     use util::worker::Runnable;
 
-    use raftstore::coprocessor::{Config, CoprocessorHost};
+    use raftstore::coprocessor::{Config, CoprocessorHost, ObserverContext, SplitChecker};
 
     use super::super::size::tests::must_split_at;
+    use super::Checker;
 
     #[test]
     fn test_split_check() {
@@ -238,10 +315,118 @@ mod tests {
         }
 
         runnable.run(SplitCheckTask::new(region.clone(), true, CheckPolicy::SCAN));
-        must_split_at(&rx, &region, Key::from_raw(b"0080").append_ts(2).encoded());
+        must_split_at(
+            &rx,
+            &region,
+            vec![Key::from_raw(b"0080").append_ts(2).take_encoded()],
+        );
 
         drop(rx);
         // It should be safe even the result can't be sent back.
         runnable.run(SplitCheckTask::new(region, true, CheckPolicy::SCAN));
     }
+
+    #[test]
+    fn test_split_check_reversed() {
+        let path = TempDir::new("test-split-check-reversed").unwrap();
+        let path_str = path.path().to_str().unwrap();
+        let mut cf_opts = ColumnFamilyOptions::new();
+        let f = Box::new(RangePropertiesCollectorFactory::default());
+        cf_opts.add_table_properties_collector_factory("tikv.range-properties-collector", f);
+        let cfs_opts = ALL_CFS
+            .iter()
+            .map(|cf| CFOptions::new(cf, cf_opts.clone()))
+            .collect();
+        let engine = Arc::new(new_engine_opt(path_str, DBOptions::new(), cfs_opts).unwrap());
+
+        let write_cf = engine.cf_handle(CF_WRITE).unwrap();
+        for i in 0..150 {
+            let key = keys::data_key(
+                Key::from_raw(format!("{:04}", i).as_bytes())
+                    .append_ts(2)
+                    .encoded(),
+            );
+            let write_value = Write::new(WriteType::Put, 0, None).to_bytes();
+            engine.put_cf(write_cf, &key, &write_value).unwrap();
+        }
+        engine.flush_cf(write_cf, true).unwrap();
+
+        let mut region = Region::new();
+        region.set_id(1);
+        region.mut_peers().push(Peer::new());
+
+        let split_keys = |reversed: bool| {
+            let (tx, rx) = mpsc::sync_channel(100);
+            let ch = RetryableSendCh::new(tx, "test-split");
+            let mut cfg = Config::default();
+            cfg.region_max_keys = 100;
+            cfg.region_split_keys = 80;
+            cfg.region_split_keys_reversed = reversed;
+            let mut runnable = SplitCheckRunner::new(
+                Arc::clone(&engine),
+                ch.clone(),
+                Arc::new(CoprocessorHost::new(cfg, ch.clone())),
+            );
+            runnable.run(SplitCheckTask::new(region.clone(), true, CheckPolicy::SCAN));
+            loop {
+                match rx.try_recv() {
+                    Ok(Msg::SplitRegion { split_keys, .. }) => return split_keys,
+                    Ok(_) => continue,
+                    others => panic!("expect split region, but got {:?}", others),
+                }
+            }
+        };
+
+        // The left region gets 80 keys in forward order.
+        assert_eq!(
+            split_keys(false),
+            vec![Key::from_raw(b"0080").append_ts(2).take_encoded()]
+        );
+        // And the right region gets 80 keys in reverse order.
+        assert_eq!(
+            split_keys(true),
+            vec![Key::from_raw(b"0070").append_ts(2).take_encoded()]
+        );
+    }
+
+    #[test]
+    fn test_checker_with_same_max_and_split_keys() {
+        let mut checker = Checker::new(3, 3);
+        let region = Region::default();
+        let mut ctx = ObserverContext::new(&region);
+        for i in 0..4 {
+            let data = KeyEntry::new(format!("{:04}", i).into_bytes(), 0, 4, CF_WRITE);
+            if checker.on_kv(&mut ctx, &data) {
+                break;
+            }
+        }
+
+        assert_eq!(checker.split_key(), Some(b"0003".to_vec()));
+    }
+
+    #[test]
+    fn test_checker_on_kvs() {
+        let region = Region::default();
+        let mut ctx = ObserverContext::new(&region);
+        let mut entries = vec![];
+        for i in 0..20 {
+            let key = format!("{:04}", i).into_bytes();
+            entries.push(KeyEntry::new(key.clone(), 0, 4, CF_DEFAULT));
+            entries.push(KeyEntry::new(key, 0, 4, CF_WRITE));
+        }
+
+        // Feeding the kvs in batches gives the same split key.
+        let mut checker = Checker::new(10, 6);
+        assert!(!checker.on_kvs(&mut ctx, &entries[..7]));
+        assert!(checker.on_kvs(&mut ctx, &entries[7..]));
+        let mut exp_checker = Checker::new(10, 6);
+        for e in &entries {
+            if exp_checker.on_kv(&mut ctx, e) {
+                break;
+            }
+        }
+        assert_eq!(checker.pending_split_key(), exp_checker.pending_split_key());
+        assert_eq!(checker.current_keys(), exp_checker.current_keys());
+        assert_eq!(checker.split_key(), exp_checker.split_key());
+    }
 }
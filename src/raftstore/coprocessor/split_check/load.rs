@@ -0,0 +1,107 @@
+// Copyright 2018 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use rocksdb::DB;
+
+use super::super::{Coprocessor, ObserverContext, SplitCheckObserver};
+use super::Host;
+
+/// Defers automatic splits while the write load is high, splitting during
+/// a write burst adds latency. It should be registered before the other
+/// split check observers.
+pub struct LoadAwareSplitObserver {
+    // Updated by the caller, like the bytes written per second.
+    write_load: Arc<AtomicUsize>,
+    max_write_load: usize,
+}
+
+impl LoadAwareSplitObserver {
+    pub fn new(write_load: Arc<AtomicUsize>, max_write_load: usize) -> LoadAwareSplitObserver {
+        LoadAwareSplitObserver {
+            write_load,
+            max_write_load,
+        }
+    }
+}
+
+impl Coprocessor for LoadAwareSplitObserver {}
+
+impl SplitCheckObserver for LoadAwareSplitObserver {
+    fn add_checker(&self, ctx: &mut ObserverContext, host: &mut Host, _: &DB) {
+        // Splits requested explicitly are never deferred.
+        if !host.auto_split() {
+            return;
+        }
+        let write_load = self.write_load.load(Ordering::Relaxed);
+        if write_load <= self.max_write_load {
+            return;
+        }
+        debug!(
+            "[region {}] write load {} > {}, skip split check",
+            ctx.region().get_id(),
+            write_load,
+            self.max_write_load
+        );
+        // Following observers won't add any checker.
+        ctx.bypass = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use kvproto::metapb::Region;
+    use tempdir::TempDir;
+
+    use raftstore::coprocessor::{Config, CoprocessorHost, SplitCheckContext};
+    use storage::ALL_CFS;
+    use util::rocksdb::new_engine;
+    use util::transport::RetryableSendCh;
+
+    use super::*;
+
+    #[test]
+    fn test_load_aware_split_observer() {
+        let path = TempDir::new("test-load-aware-split-observer").unwrap();
+        let engine = new_engine(path.path().to_str().unwrap(), ALL_CFS, None).unwrap();
+        let mut region = Region::new();
+        region.set_id(1);
+
+        let (tx, _rx) = mpsc::sync_channel(100);
+        let ch = RetryableSendCh::new(tx, "test-split");
+        let mut cfg = Config::default();
+        cfg.split_region_on_table = false;
+        let mut coprocessor = CoprocessorHost::new(cfg, ch);
+        let write_load = Arc::new(AtomicUsize::new(0));
+        let observer = LoadAwareSplitObserver::new(Arc::clone(&write_load), 100);
+        coprocessor
+            .registry
+            .register_split_check_observer(1, Box::new(observer));
+        let skip = |auto_split| {
+            coprocessor
+                .new_split_checker_host(&region, &engine, auto_split, SplitCheckContext::default())
+                .skip()
+        };
+
+        // The half checker is added for manual splits.
+        assert!(!skip(false));
+        write_load.store(1000, Ordering::Relaxed);
+        assert!(skip(true));
+        // Splits requested explicitly are not deferred.
+        assert!(!skip(false));
+    }
+}
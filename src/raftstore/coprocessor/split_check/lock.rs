@@ -0,0 +1,205 @@
+// Copyright 2018 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use rocksdb::DB;
+
+use raftstore::store::engine::Iterable;
+use raftstore::store::keys;
+use storage::mvcc::Lock;
+use storage::types::Key;
+use storage::CF_LOCK;
+use util::collections::HashMap;
+use util::escape;
+
+use super::super::{
+    CheckerInfo, Coprocessor, KeyEntry, ObserverContext, SplitCheckObserver, SplitChecker,
+};
+use super::Host;
+
+/// Moves the split keys out of the transactions, so the locks of a
+/// transaction are always kept in the same region.
+pub struct Checker {
+    // Locked data keys in ascending order, with the start_ts of their locks.
+    locks: Vec<(Vec<u8>, u64)>,
+    // The first scanned user key after each of the locked keys.
+    next_keys: Vec<Option<Vec<u8>>>,
+    // The first and the last locks of each transaction, by position.
+    txns: HashMap<u64, (usize, usize)>,
+    // The first lock whose next key is not scanned yet.
+    cursor: usize,
+}
+
+impl Checker {
+    pub fn new(locks: Vec<(Vec<u8>, u64)>) -> Checker {
+        let mut txns = HashMap::default();
+        for (pos, &(_, ts)) in locks.iter().enumerate() {
+            txns.entry(ts).or_insert((pos, pos)).1 = pos;
+        }
+        let next_keys = vec![None; locks.len()];
+        Checker {
+            locks,
+            next_keys,
+            txns,
+            cursor: 0,
+        }
+    }
+
+    // Get the last lock of the transactions that would be split at `key`.
+    fn last_split_lock(&self, key: &[u8]) -> Option<usize> {
+        self.txns
+            .values()
+            .filter(|&&(first, last)| {
+                self.locks[first].0.as_slice() < key && self.locks[last].0.as_slice() >= key
+            })
+            .map(|&(_, last)| last)
+            .max()
+    }
+}
+
+impl SplitChecker for Checker {
+    fn on_kv(&mut self, _: &mut ObserverContext, entry: &KeyEntry) -> bool {
+        let user_key = Key::truncate_ts_for(entry.key()).unwrap_or(entry.key());
+        while self.cursor < self.locks.len() && self.locks[self.cursor].0.as_slice() < user_key {
+            self.next_keys[self.cursor] = Some(user_key.to_vec());
+            self.cursor += 1;
+        }
+        false
+    }
+
+    fn split_key(&mut self) -> Option<Vec<u8>> {
+        None
+    }
+
+    fn adjust_split_key(&self, _: &ObserverContext, key: Vec<u8>) -> Option<Vec<u8>> {
+        let mut key = key;
+        while let Some(last) = self.last_split_lock(&key) {
+            // Split before the next user key, the locked key is kept in the
+            // left region with all its versions.
+            match self.next_keys[last] {
+                Some(ref next_key) => key = next_key.clone(),
+                None => return None,
+            }
+        }
+        Some(key)
+    }
+
+    fn describe(&self) -> CheckerInfo {
+        CheckerInfo::new("lock")
+    }
+}
+
+/// Adds a lock checker if the region has any lock, it should be registered
+/// after the other split check observers.
+pub struct LockCheckObserver;
+
+impl Coprocessor for LockCheckObserver {}
+
+impl SplitCheckObserver for LockCheckObserver {
+    fn add_checker(&self, ctx: &mut ObserverContext, host: &mut Host, engine: &DB) {
+        // No need to scan the locks if the region won't be scanned.
+        if host.skip() {
+            return;
+        }
+        let region = ctx.region();
+        let mut locks = vec![];
+        let res = engine.scan_cf(
+            CF_LOCK,
+            &keys::enc_start_key(region),
+            &keys::enc_end_key(region),
+            false,
+            |key, value| {
+                let lock = box_try!(Lock::parse(value));
+                locks.push((key.to_vec(), lock.ts));
+                Ok(true)
+            },
+        );
+        if let Err(e) = res {
+            warn!(
+                "[region {}] failed to scan locks, split anyway: {}",
+                region.get_id(),
+                e
+            );
+            return;
+        }
+        if locks.is_empty() {
+            return;
+        }
+        debug!(
+            "[region {}] found {} locks, the first is at {}",
+            region.get_id(),
+            locks.len(),
+            escape(&locks[0].0)
+        );
+        host.add_checker(Box::new(Checker::new(locks)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use kvproto::metapb::Region;
+    use tempdir::TempDir;
+
+    use storage::mvcc::LockType;
+    use storage::{ALL_CFS, CF_WRITE};
+    use util::rocksdb::new_engine;
+
+    use super::super::size::Checker as SizeChecker;
+    use super::*;
+
+    fn lock_key(i: u64) -> Vec<u8> {
+        keys::data_key(Key::from_raw(format!("k{:02}", i).as_bytes()).encoded())
+    }
+
+    fn write_key(i: u64) -> Vec<u8> {
+        let key = Key::from_raw(format!("k{:02}", i).as_bytes()).append_ts(5);
+        keys::data_key(key.encoded())
+    }
+
+    #[test]
+    fn test_lock_check_observer() {
+        let path = TempDir::new("test-lock-check-observer").unwrap();
+        let engine = new_engine(path.path().to_str().unwrap(), ALL_CFS, None).unwrap();
+        let lock_cf = engine.cf_handle(CF_LOCK).unwrap();
+        // Transaction 10 locks k03 and k05, transaction 20 locks k04 and k06,
+        // transaction 30 locks k08 and k09.
+        for &(i, ts) in &[(3, 10), (4, 20), (5, 10), (6, 20), (8, 30), (9, 30)] {
+            let lock = Lock::new(LockType::Put, lock_key(i), ts, 0, None);
+            engine.put_cf(lock_cf, &lock_key(i), &lock.to_bytes()).unwrap();
+        }
+
+        let region = Region::default();
+        let mut ctx = ObserverContext::new(&region);
+        let mut host = Host::new(true);
+        // No lock checker is added if nothing will be scanned.
+        LockCheckObserver.add_checker(&mut ctx, &mut host, &engine);
+        assert!(host.skip());
+        host.add_checker(Box::new(SizeChecker::new(1000, 500, 0, None)));
+        LockCheckObserver.add_checker(&mut ctx, &mut host, &engine);
+        assert_eq!(host.checkers().len(), 2);
+
+        for i in 0..10 {
+            let entry = KeyEntry::new(write_key(i), 0, 10, CF_WRITE);
+            host.on_kv(&region, &entry);
+        }
+        let adjust = |key| host.adjust_split_key(&region, key);
+        // Keys outside of the transactions are kept.
+        assert_eq!(adjust(write_key(1)), Some(write_key(1)));
+        assert_eq!(adjust(lock_key(3)), Some(lock_key(3)));
+        assert_eq!(adjust(write_key(7)), Some(write_key(7)));
+        // The split key is moved after both transaction 10 and 20.
+        assert_eq!(adjust(write_key(3)), Some(lock_key(7)));
+        assert_eq!(adjust(write_key(5)), Some(lock_key(7)));
+        // Vetoed if there is no key after the transaction.
+        assert_eq!(adjust(lock_key(9)), None);
+    }
+}
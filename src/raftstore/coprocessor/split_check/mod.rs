@@ -11,21 +11,48 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+mod combined;
+mod distinct_keys;
+mod gap;
 mod half;
+mod hash_prefix;
+mod hot;
+mod interval;
 mod keys;
+mod load;
+mod lock;
+mod quantile;
 mod size;
+mod span;
 mod table;
+mod weighted;
 
 use rocksdb::DB;
+use storage::{CfName, DATA_CFS};
 
 use super::error::Result;
-use super::{KeyEntry, ObserverContext, SplitChecker};
+use super::{CheckerInfo, KeyEntry, ObserverContext, SplitChecker, UNKNOWN_CHECKER};
 use kvproto::metapb::Region;
 
+pub use self::combined::{CombineMode, CombinedChecker};
+pub use self::distinct_keys::DistinctKeysCheckObserver;
+pub use self::gap::Checker as GapChecker;
 pub use self::half::HalfCheckObserver;
+pub use self::hash_prefix::{Checker as HashPrefixChecker, HashPrefixCheckObserver};
+pub use self::hot::HotRangeObserver;
+pub use self::interval::IntervalCheckObserver;
 pub use self::keys::KeysCheckObserver;
-pub use self::size::SizeCheckObserver;
+pub use self::load::LoadAwareSplitObserver;
+pub use self::lock::LockCheckObserver;
+pub use self::quantile::Checker as QuantileChecker;
+pub use self::size::{new_size_tracker_listener, RegionSizeTracker, SizeCheckObserver};
+pub use self::span::SpanCheckObserver;
 pub use self::table::TableCheckObserver;
+pub use self::weighted::{decay_weight, Checker as WeightedChecker, WeightFn};
+
+// Max time to wait for sending the approximate statistics to raftstore
+// when the channel is full.
+const SEND_TIMEOUT_MS: u64 = 100;
 
 #[derive(Default)]
 pub struct Host {
@@ -51,28 +78,111 @@ impl Host {
         self.checkers.is_empty()
     }
 
-    /// Hook to call for every check during split.
+    /// Get the CFs that any of the checkers scanning in ascending order is
+    /// interested in, the CFs only needed by the reversed scan are excluded.
+    pub fn interested_cfs(&self) -> Vec<CfName> {
+        DATA_CFS
+            .iter()
+            .filter(|cf| {
+                self.checkers
+                    .iter()
+                    .any(|checker| !checker.reversed() && checker.interested_cfs().contains(*cf))
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Get the CFs that any of the checkers scanning in reverse order is
+    /// interested in, it's empty if no checker scans in reverse order.
+    pub fn reversed_cfs(&self) -> Vec<CfName> {
+        DATA_CFS
+            .iter()
+            .filter(|cf| {
+                self.checkers
+                    .iter()
+                    .any(|checker| checker.reversed() && checker.interested_cfs().contains(*cf))
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Hook to call for every check during split, checkers scanning in
+    /// reverse order or not interested in the CF of the entry are skipped.
     ///
     /// Return true means abort early.
     pub fn on_kv(&mut self, region: &Region, entry: &KeyEntry) -> bool {
         let mut ob_ctx = ObserverContext::new(region);
-        for checker in &mut self.checkers {
-            if checker.on_kv(&mut ob_ctx, entry) {
+        for checker in self.checkers.iter_mut().filter(|c| !c.reversed()) {
+            if checker.interested_cfs().contains(&entry.cf()) && checker.on_kv(&mut ob_ctx, entry)
+            {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Hook to call for a batch of kvs in order during split, checkers
+    /// scanning in reverse order are skipped.
+    ///
+    /// Return true means abort early.
+    pub fn on_kvs(&mut self, region: &Region, entries: &[KeyEntry]) -> bool {
+        let mut ob_ctx = ObserverContext::new(region);
+        for checker in self.checkers.iter_mut().filter(|c| !c.reversed()) {
+            if feed_kvs(checker.as_mut(), &mut ob_ctx, entries) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Hook to call for a batch of kvs in descending order during split,
+    /// only checkers scanning in reverse order are called.
+    ///
+    /// Return true means abort early.
+    pub fn on_kvs_reversed(&mut self, region: &Region, entries: &[KeyEntry]) -> bool {
+        let mut ob_ctx = ObserverContext::new(region);
+        for checker in self.checkers.iter_mut().filter(|c| c.reversed()) {
+            if feed_kvs(checker.as_mut(), &mut ob_ctx, entries) {
                 return true;
             }
         }
         false
     }
 
-    pub fn split_key(mut self) -> Option<Vec<u8>> {
+    /// Get the split keys of the first checker that has found any.
+    pub fn split_keys(&mut self) -> Vec<Vec<u8>> {
+        for checker in &mut self.checkers {
+            let keys = checker.split_keys();
+            if !keys.is_empty() {
+                return keys;
+            }
+        }
+        vec![]
+    }
+
+    /// Get the bucket boundaries of the first checker that has found any.
+    pub fn buckets(&mut self) -> Vec<Vec<u8>> {
+        for checker in &mut self.checkers {
+            let keys = checker.buckets();
+            if !keys.is_empty() {
+                return keys;
+            }
+        }
+        vec![]
+    }
+
+    /// Get the estimated number of regions after split of the first checker
+    /// that can tell, 0 if none of them can.
+    pub fn estimated_splits(&self) -> u64 {
         self.checkers
-            .drain(..)
-            .flat_map(|mut c| c.split_key())
+            .iter()
+            .filter_map(|checker| checker.estimated_splits())
             .next()
+            .unwrap_or(0)
     }
 
     pub fn approximate_split_key(
-        mut self,
+        &mut self,
         region: &Region,
         engine: &DB,
     ) -> Result<Option<Vec<u8>>> {
@@ -85,8 +195,132 @@ impl Host {
         Ok(None)
     }
 
-    #[inline]
+    /// Whether all the checkers approve to split the region at `key`.
+    pub fn approve_split(&self, region: &Region, key: &[u8]) -> bool {
+        let ob_ctx = ObserverContext::new(region);
+        self.checkers
+            .iter()
+            .all(|checker| checker.approve_split(&ob_ctx, key))
+    }
+
+    /// Let all the checkers adjust the split key `key` in order, None means
+    /// any of them vetoes the split.
+    pub fn adjust_split_key(&self, region: &Region, key: Vec<u8>) -> Option<Vec<u8>> {
+        let ob_ctx = ObserverContext::new(region);
+        let mut key = key;
+        for checker in &self.checkers {
+            key = checker.adjust_split_key(&ob_ctx, key)?;
+        }
+        Some(key)
+    }
+
+    /// Add the checker unless a checker of the same name has been added,
+    /// the scan would run the same logic twice.
     pub fn add_checker(&mut self, checker: Box<SplitChecker>) {
+        let name = checker.name();
+        if name != UNKNOWN_CHECKER && self.checkers.iter().any(|c| c.name() == name) {
+            warn!("split checker {} is added more than once, skip it", name);
+            return;
+        }
         self.checkers.push(checker);
     }
+
+    /// Describe the added checkers in order.
+    pub fn checkers(&self) -> Vec<CheckerInfo> {
+        self.checkers.iter().map(|c| c.describe()).collect()
+    }
+}
+
+/// Feed the entries of the CFs that the checker is interested in, all the
+/// CFs are scanned together, so each checker only picks its own entries.
+fn feed_kvs(checker: &mut SplitChecker, ctx: &mut ObserverContext, entries: &[KeyEntry]) -> bool {
+    let cfs = checker.interested_cfs();
+    if entries.iter().all(|e| cfs.contains(&e.cf())) {
+        return checker.on_kvs(ctx, entries);
+    }
+    for entry in entries.iter().filter(|e| cfs.contains(&e.cf())) {
+        if checker.on_kv(ctx, entry) {
+            return true;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use raftstore::coprocessor::test_util::CfChecker;
+    use storage::{CF_DEFAULT, CF_LOCK, CF_WRITE};
+
+    use super::keys::Checker as KeysChecker;
+    use super::size::Checker as SizeChecker;
+    use super::*;
+
+    #[test]
+    fn test_host_shared_scan() {
+        let region = Region::default();
+        let mut host = Host::new(true);
+        let write_fed: Arc<Mutex<Vec<CfName>>> = Arc::default();
+        let lock_fed: Arc<Mutex<Vec<CfName>>> = Arc::default();
+        host.add_checker(Box::new(CfChecker {
+            cfs: &[CF_WRITE],
+            fed: Arc::clone(&write_fed),
+        }));
+        host.add_checker(Box::new(CfChecker {
+            cfs: &[CF_LOCK],
+            fed: Arc::clone(&lock_fed),
+        }));
+        // A single scan of both CFs is enough.
+        assert_eq!(host.interested_cfs(), vec![CF_LOCK, CF_WRITE]);
+
+        let entries: Vec<_> = [CF_WRITE, CF_LOCK, CF_WRITE, CF_DEFAULT]
+            .iter()
+            .enumerate()
+            .map(|(i, cf)| KeyEntry::new(vec![i as u8], 0, 1, *cf))
+            .collect();
+        assert!(!host.on_kvs(&region, &entries));
+        assert!(!host.on_kv(&region, &KeyEntry::new(vec![4], 0, 1, CF_LOCK)));
+        assert_eq!(*write_fed.lock().unwrap(), vec![CF_WRITE, CF_WRITE]);
+        assert_eq!(*lock_fed.lock().unwrap(), vec![CF_LOCK, CF_LOCK]);
+    }
+
+    #[test]
+    fn test_host_reversed_cfs() {
+        let mut host = Host::new(true);
+        host.add_checker(Box::new(CfChecker {
+            cfs: &[CF_LOCK],
+            fed: Arc::default(),
+        }));
+        host.add_checker(Box::new(KeysChecker::new(100, 50).with_reversed(true)));
+        // The CFs only needed by the reversed scan are not scanned forward.
+        assert_eq!(host.interested_cfs(), vec![CF_LOCK]);
+        assert_eq!(host.reversed_cfs(), vec![CF_DEFAULT, CF_WRITE]);
+    }
+
+    #[test]
+    fn test_host_dedupe_checkers() {
+        let region = Region::default();
+        let mut host = Host::new(true);
+        host.add_checker(Box::new(SizeChecker::new(100, 50, 0, None)));
+        // Only the first size checker runs.
+        host.add_checker(Box::new(SizeChecker::new(1000, 500, 0, None)));
+        assert_eq!(host.checkers().len(), 1);
+        assert_eq!(host.checkers()[0].max_size, Some(100));
+        // Every entry is 10 bytes.
+        for i in 0..20 {
+            host.on_kv(&region, &KeyEntry::new(vec![i], 0, 9, CF_WRITE));
+        }
+        assert_eq!(host.split_keys(), vec![vec![5], vec![10]]);
+
+        // Checkers without names are never deduplicated.
+        let fed: Arc<Mutex<Vec<CfName>>> = Arc::default();
+        for _ in 0..2 {
+            host.add_checker(Box::new(CfChecker {
+                cfs: &[CF_WRITE],
+                fed: Arc::clone(&fed),
+            }));
+        }
+        assert_eq!(host.checkers().len(), 3);
+    }
 }
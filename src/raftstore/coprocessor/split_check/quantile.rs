@@ -0,0 +1,159 @@
+// Copyright 2018 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::cmp;
+use std::mem;
+
+use util::config::ReadableSize;
+
+use super::super::{CheckerInfo, KeyEntry, ObserverContext, SplitChecker};
+
+const BUCKET_NUMBER_LIMIT: u64 = 1024;
+const BUCKET_SIZE_LIMIT_MB: u64 = 512;
+
+fn distance(a: u64, b: u64) -> u64 {
+    if a > b {
+        a - b
+    } else {
+        b - a
+    }
+}
+
+/// Splits the region into `count` parts of roughly equal size in a single
+/// scan, it's a generalized half checker. The split keys are the start keys
+/// of the buckets closest to the quantiles of the region by size.
+pub struct Checker {
+    count: u64,
+    buckets: Vec<Vec<u8>>,
+    // Size of data before each bucket.
+    offsets: Vec<u64>,
+    cur_bucket_size: u64,
+    current_size: u64,
+    each_bucket_size: u64,
+}
+
+impl Checker {
+    /// `region_size_limit` decides the size of buckets, the more accurate the
+    /// closer it is to the size of the region.
+    pub fn new(count: u64, region_size_limit: u64) -> Checker {
+        let bucket_size_limit = ReadableSize::mb(BUCKET_SIZE_LIMIT_MB).0;
+        let each_bucket_size = cmp::min(
+            cmp::max(region_size_limit / BUCKET_NUMBER_LIMIT, 1),
+            bucket_size_limit,
+        );
+        Checker {
+            count,
+            buckets: vec![],
+            offsets: vec![],
+            cur_bucket_size: 0,
+            current_size: 0,
+            each_bucket_size,
+        }
+    }
+}
+
+impl SplitChecker for Checker {
+    fn on_kv(&mut self, _: &mut ObserverContext, entry: &KeyEntry) -> bool {
+        if self.buckets.is_empty() || self.cur_bucket_size >= self.each_bucket_size {
+            self.buckets.push(entry.key().to_vec());
+            self.offsets.push(self.current_size);
+            self.cur_bucket_size = 0;
+        }
+        let size = entry.entry_size() as u64;
+        self.cur_bucket_size += size;
+        self.current_size += size;
+        false
+    }
+
+    fn split_key(&mut self) -> Option<Vec<u8>> {
+        self.split_keys().into_iter().next()
+    }
+
+    /// Get the start keys of the buckets closest to the quantiles, fewer
+    /// keys are returned if there are not enough buckets.
+    fn split_keys(&mut self) -> Vec<Vec<u8>> {
+        let mut indexes = Vec::with_capacity(self.count as usize);
+        // The first bucket starts at the start of the region, skip it.
+        let mut i = 1;
+        for j in 1..self.count {
+            if i >= self.offsets.len() {
+                break;
+            }
+            let quantile = self.current_size * j / self.count;
+            // Offsets are ascending, move on while the next one is closer.
+            while i + 1 < self.offsets.len()
+                && distance(self.offsets[i + 1], quantile) <= distance(self.offsets[i], quantile)
+            {
+                i += 1;
+            }
+            if indexes.last() != Some(&i) {
+                indexes.push(i);
+            }
+        }
+        let mut buckets = mem::replace(&mut self.buckets, vec![]);
+        indexes
+            .into_iter()
+            .map(|i| mem::replace(&mut buckets[i], vec![]))
+            .collect()
+    }
+
+    fn estimated_splits(&self) -> Option<u64> {
+        Some(self.count)
+    }
+
+    fn describe(&self) -> CheckerInfo {
+        CheckerInfo::new("quantile")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use kvproto::metapb::Region;
+
+    use storage::CF_DEFAULT;
+
+    use super::*;
+
+    fn key(i: usize) -> Vec<u8> {
+        format!("k{:04}", i).into_bytes()
+    }
+
+    #[test]
+    fn test_quantile_checker() {
+        let region = Region::default();
+        let mut ctx = ObserverContext::new(&region);
+        let mut checker = Checker::new(4, 1024);
+        for i in 0..100 {
+            checker.on_kv(&mut ctx, &KeyEntry::new(key(i), 0, 5, CF_DEFAULT));
+        }
+        assert_eq!(checker.split_keys(), vec![key(25), key(50), key(75)]);
+
+        // The first key is as large as the others in total.
+        let mut checker = Checker::new(2, 1024);
+        for i in 0..10 {
+            let value_size = if i == 0 { 90 } else { 5 };
+            checker.on_kv(&mut ctx, &KeyEntry::new(key(i), 0, value_size, CF_DEFAULT));
+        }
+        assert_eq!(checker.split_keys(), vec![key(1)]);
+
+        // Not enough data to be split into 4 parts.
+        let mut checker = Checker::new(4, 1024);
+        for i in 0..2 {
+            checker.on_kv(&mut ctx, &KeyEntry::new(key(i), 0, 5, CF_DEFAULT));
+        }
+        assert_eq!(checker.split_keys(), vec![key(1)]);
+        let mut checker = Checker::new(4, 1024);
+        checker.on_kv(&mut ctx, &KeyEntry::new(key(0), 0, 5, CF_DEFAULT));
+        assert!(checker.split_keys().is_empty());
+    }
+}
@@ -11,54 +11,696 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use raftstore::store::{util, Msg};
+use std::cmp;
+use std::collections::BTreeMap;
+use std::collections::Bound::{Excluded, Unbounded};
+use std::mem;
+use std::result::Result as StdResult;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use kvproto::metapb::Region;
+use prometheus::Histogram;
+use raftstore::store::util::ApproximateSizeError;
+use raftstore::store::{keys, util, Msg};
 use rocksdb::DB;
+use storage::{CfName, Key, ALL_CFS, LARGE_CFS};
+use util::collections::{HashMap, HashSet};
+use util::rocksdb::{SstChangeListener, SstChangedEvent};
 use util::transport::{RetryableSendCh, Sender};
 
+use super::super::error::Result;
 use super::super::metrics::*;
-use super::super::{Coprocessor, KeyEntry, ObserverContext, SplitCheckObserver, SplitChecker};
-use super::Host;
+use super::super::{
+    CheckerInfo, Coprocessor, KeyEntry, ObserverContext, RegionState, SplitCheckObserver,
+    SplitChecker,
+};
+use super::table::is_whole_table_region;
+use super::{Host, SEND_TIMEOUT_MS};
+
+// A region smaller than it holds at most a few tiny kvs, it's treated as
+// empty.
+const EMPTY_REGION_SIZE: u64 = 64;
+
+/// Extracts the logical prefix of a key, keys sharing the same prefix
+/// should never be split into different regions.
+pub type KeyPrefixFn = Box<Fn(&[u8]) -> &[u8]>;
 
 pub struct Checker {
     max_size: u64,
     split_size: u64,
+    min_split_region_size: u64,
+    // Size of all the scanned entries.
+    scanned_size: u64,
+    // Size of the segment following the last recorded split key.
     current_size: u64,
-    split_key: Option<Vec<u8>>,
+    // Size of the segment preceding the last recorded split key.
+    last_segment_size: u64,
+    // Number of keys in the segment following the last recorded split key.
+    current_keys: u64,
+    // A segment must have at least this many keys before it can be split.
+    min_keys_before_split: u64,
+    // Only count the size of values, keys are ignored.
+    count_value_only: bool,
+    // The size of entries of a CF is scaled by its compression ratio.
+    compression_ratios: HashMap<CfName, f64>,
+    split_keys: Vec<Vec<u8>>,
+    // Max number of split keys recorded by a scan, 0 means no limit.
+    max_split_keys: u64,
+    key_prefix_fn: Option<KeyPrefixFn>,
+    last_key: Vec<u8>,
+    // Limits of a single CF, 0 means no limit.
+    max_cf_size: u64,
+    split_cf_size: u64,
+    // Sizes of each CF in the current and the last segment.
+    cf_sizes: HashMap<CfName, u64>,
+    last_cf_sizes: HashMap<CfName, u64>,
+    // Size of a bucket, 0 means buckets are not recorded.
+    bucket_size: u64,
+    bucket_current_size: u64,
+    bucket_keys: Vec<Vec<u8>>,
+    // Data key ranges the region must not be split inside.
+    protected_ranges: Vec<(Vec<u8>, Vec<u8>)>,
 }
 
 impl Checker {
-    pub fn new(max_size: u64, split_size: u64) -> Checker {
+    pub fn new(
+        max_size: u64,
+        split_size: u64,
+        min_split_region_size: u64,
+        key_prefix_fn: Option<KeyPrefixFn>,
+    ) -> Checker {
+        debug_assert!(
+            split_size <= max_size,
+            "split size {} must <= max size {}",
+            split_size,
+            max_size
+        );
         Checker {
             max_size,
             split_size,
+            min_split_region_size,
+            scanned_size: 0,
             current_size: 0,
-            split_key: None,
+            last_segment_size: 0,
+            current_keys: 0,
+            min_keys_before_split: 0,
+            count_value_only: false,
+            compression_ratios: HashMap::default(),
+            split_keys: vec![],
+            max_split_keys: 0,
+            key_prefix_fn,
+            last_key: vec![],
+            max_cf_size: 0,
+            split_cf_size: 0,
+            cf_sizes: HashMap::default(),
+            last_cf_sizes: HashMap::default(),
+            bucket_size: 0,
+            bucket_current_size: 0,
+            bucket_keys: vec![],
+            protected_ranges: vec![],
+        }
+    }
+
+    /// Also record the boundaries of `bucket_count` buckets per
+    /// `split_size`, 0 means no buckets.
+    pub fn with_bucket_count(mut self, bucket_count: u64) -> Checker {
+        if bucket_count > 0 {
+            self.bucket_size = cmp::max(self.split_size / bucket_count, 1);
+        }
+        self
+    }
+
+    /// Only split after at least `min_keys` keys in a segment, so a single
+    /// huge value doesn't make a lopsided split.
+    pub fn with_min_keys_before_split(mut self, min_keys: u64) -> Checker {
+        self.min_keys_before_split = min_keys;
+        self
+    }
+
+    /// Start counting from `size`, which has been scanned by a previous
+    /// check of the region that didn't find any split key.
+    pub fn with_current_size(mut self, size: u64) -> Checker {
+        self.scanned_size = size;
+        self.current_size = size;
+        self
+    }
+
+    /// Record at most `max_split_keys` split keys, the scan stops after the
+    /// last one and the rest of the region is left to the following checks.
+    /// 0 means no limit.
+    pub fn with_max_split_keys(mut self, max_split_keys: u64) -> Checker {
+        self.max_split_keys = max_split_keys;
+        self
+    }
+
+    /// Only count the size of values, so regions with large values are
+    /// kept smaller than the ones with large keys.
+    pub fn with_count_value_only(mut self, count_value_only: bool) -> Checker {
+        self.count_value_only = count_value_only;
+        self
+    }
+
+    /// Scale the size of entries by the compression ratio of their CFs, which
+    /// is the size on disk divided by the logical size, so a compressed CF
+    /// takes more logical bytes to be split. CFs not in `ratios` aren't scaled.
+    pub fn with_compression_ratios(mut self, ratios: HashMap<CfName, f64>) -> Checker {
+        self.compression_ratios = ratios;
+        self
+    }
+
+    /// Also split the region when any CF exceeds `max_cf_size`. The size
+    /// of each CF after split keeps the same ratio as the total size.
+    pub fn with_max_cf_size(mut self, max_cf_size: u64) -> Checker {
+        self.max_cf_size = max_cf_size;
+        if self.max_size > 0 {
+            self.split_cf_size =
+                (max_cf_size as f64 * self.split_size as f64 / self.max_size as f64) as u64;
+        }
+        self
+    }
+
+    /// Never split inside any of the data key `ranges`, the split is deferred
+    /// to the first key out of the range. An empty end key means the end of
+    /// the key space.
+    pub fn with_protected_ranges(mut self, ranges: Vec<(Vec<u8>, Vec<u8>)>) -> Checker {
+        self.protected_ranges = ranges;
+        self
+    }
+
+    /// Whether splitting at `key` would separate the keys of a protected
+    /// range, a range can still start or end at `key`.
+    fn in_protected_range(&self, key: &[u8]) -> bool {
+        self.protected_ranges.iter().any(|&(ref start_key, ref end_key)| {
+            key > start_key.as_slice() && (end_key.is_empty() || key < end_key.as_slice())
+        })
+    }
+
+    /// Whether all the CFs of the last two segments fit in `max_cf_size`.
+    fn fit_max_cf_size(&self) -> bool {
+        self.max_cf_size == 0 || self.cf_sizes.iter().all(|(cf, size)| {
+            self.last_cf_sizes.get(cf).cloned().unwrap_or(0) + size <= self.max_cf_size
+        })
+    }
+
+    /// Whether the scan can stop, i.e. no more split keys or buckets are
+    /// needed. The last split key must not be dropped by `split_keys` as a
+    /// small tail, so the segment after it must be large enough.
+    fn is_finished(&self) -> bool {
+        self.bucket_size == 0
+            && self.max_split_keys > 0
+            && self.split_keys.len() as u64 >= self.max_split_keys
+            && self.last_segment_size + self.current_size > self.max_size
+            && self.current_size >= self.min_split_region_size
+    }
+
+    /// Whether a region can start at `key`, i.e. `key` doesn't share
+    /// the prefix with the key before it.
+    fn at_prefix_boundary(&self, key: &[u8]) -> bool {
+        match self.key_prefix_fn {
+            Some(ref f) => self.last_key.is_empty() || f(&self.last_key) != f(key),
+            None => true,
         }
     }
 }
 
+#[cfg(test)]
+impl Checker {
+    /// The size of the segment following the last recorded split key.
+    pub fn current_size(&self) -> u64 {
+        self.current_size
+    }
+
+    /// The last recorded split key, it may still be dropped when the split
+    /// keys are taken.
+    pub fn pending_split_key(&self) -> Option<&[u8]> {
+        self.split_keys.last().map(|k| k.as_slice())
+    }
+}
+
 impl SplitChecker for Checker {
     fn on_kv(&mut self, _: &mut ObserverContext, entry: &KeyEntry) -> bool {
-        self.current_size += entry.entry_size() as u64;
-        if self.current_size > self.split_size && self.split_key.is_none() {
-            self.split_key = Some(entry.key().to_vec());
+        let mut size = if self.count_value_only {
+            entry.value_size() as u64
+        } else {
+            entry.entry_size() as u64
+        };
+        if let Some(ratio) = self.compression_ratios.get(entry.cf()) {
+            size = (size as f64 * ratio) as u64;
+        }
+        if self.bucket_size > 0 {
+            // A new bucket starts at the key once the last one is full.
+            if self.bucket_current_size >= self.bucket_size {
+                self.bucket_keys.push(entry.key().to_vec());
+                self.bucket_current_size = 0;
+            }
+            self.bucket_current_size += size;
+        }
+        self.scanned_size += size;
+        self.current_size += size;
+        self.current_keys += 1;
+        let cf_size = {
+            let cf_size = self.cf_sizes.entry(entry.cf()).or_insert(0);
+            *cf_size += size;
+            *cf_size
+        };
+        let exceed_cf_size = self.max_cf_size > 0 && cf_size > self.split_cf_size;
+        // The size is added before the split is decided, so the key crossing
+        // the split size is the split key. Even if max_size equals split_size,
+        // the segment before it exceeds the max size with this key, so it's
+        // never dropped as a small tail by `split_keys`. The split key is
+        // recorded before `is_finished` is checked, so the same key can both
+        // be the split key and stop the scan.
+        let exceed_split_size = self.current_size > self.split_size;
+        // Keep counting the size, but only split at a prefix boundary. And never
+        // split at the first key, otherwise the left region would be empty.
+        if (exceed_split_size || exceed_cf_size)
+            && self.current_size > size
+            && self.current_keys > self.min_keys_before_split
+            && (self.max_split_keys == 0 || (self.split_keys.len() as u64) < self.max_split_keys)
+            && self.at_prefix_boundary(entry.key())
+            && !self.in_protected_range(entry.key())
+        {
+            self.split_keys.push(entry.key().to_vec());
+            // The entry at the split key belongs to the next segment.
+            self.last_segment_size = self.current_size - size;
+            self.current_size = size;
+            self.current_keys = 1;
+            if self.max_cf_size > 0 {
+                let mut cf_sizes = HashMap::default();
+                cf_sizes.insert(entry.cf(), size);
+                self.last_cf_sizes = mem::replace(&mut self.cf_sizes, cf_sizes);
+                *self.last_cf_sizes.get_mut(entry.cf()).unwrap() -= size;
+            }
         }
-        // should consider max_size may equal to split_size
-        self.current_size > self.max_size
+        if self.key_prefix_fn.is_some() {
+            self.last_key.clear();
+            self.last_key.extend_from_slice(entry.key());
+        }
+        self.is_finished()
     }
 
     fn split_key(&mut self) -> Option<Vec<u8>> {
-        if self.current_size > self.max_size {
-            self.split_key.take()
-        } else {
-            None
+        self.split_keys().into_iter().next()
+    }
+
+    fn split_keys(&mut self) -> Vec<Vec<u8>> {
+        // Don't leave a small region at the tail, the last two segments are
+        // kept together if they don't exceed the max size, or the last one
+        // is too small to be a region.
+        if (self.last_segment_size + self.current_size <= self.max_size
+            && self.fit_max_cf_size())
+            || self.current_size < self.min_split_region_size
+        {
+            self.split_keys.pop();
+        }
+        mem::replace(&mut self.split_keys, vec![])
+    }
+
+    fn buckets(&mut self) -> Vec<Vec<u8>> {
+        mem::replace(&mut self.bucket_keys, vec![])
+    }
+
+    fn estimated_splits(&self) -> Option<u64> {
+        if self.split_size == 0 {
+            return None;
+        }
+        Some(self.scanned_size / self.split_size)
+    }
+
+    fn approximate_split_key(&self, region: &Region, engine: &DB) -> Result<Option<Vec<u8>>> {
+        Ok(box_try!(util::get_region_approximate_split_key(
+            engine,
+            region,
+            self.split_size
+        )))
+    }
+
+    fn describe(&self) -> CheckerInfo {
+        CheckerInfo {
+            max_size: Some(self.max_size),
+            split_size: Some(self.split_size),
+            ..CheckerInfo::new("size")
+        }
+    }
+}
+
+/// Strips the MVCC timestamp of a key, so all versions of a user key stay
+/// in the same region.
+fn user_key_prefix(key: &[u8]) -> &[u8] {
+    Key::truncate_ts_for(key).unwrap_or(key)
+}
+
+/// Get the approximate size by `f`, it's tried once more if the error may be
+/// transient, like a disk hiccup.
+fn retry_transient<F>(region_id: u64, mut f: F) -> StdResult<u64, ApproximateSizeError>
+where
+    F: FnMut() -> StdResult<u64, ApproximateSizeError>,
+{
+    match f() {
+        Err(ref e) if e.is_transient() => {
+            warn!(
+                "[region {}] failed to get approximate size, retry: {}",
+                region_id, e
+            );
+            f()
+        }
+        res => res,
+    }
+}
+
+// Max number of CFs of regions whose approximate sizes are cached.
+const SIZE_CACHE_CAPACITY: usize = 8192;
+
+struct SizeCacheEntry {
+    version: u64,
+    // The data keys of the region.
+    start_key: Vec<u8>,
+    end_key: Vec<u8>,
+    // The size of the region in each SST file keyed by its path, which is
+    // kept up to date by the changes of SST files.
+    files: HashMap<String, u64>,
+    sst_size: u64,
+    last_access: u64,
+}
+
+#[derive(Default)]
+struct SizeCacheInner {
+    entries: HashMap<(u64, CfName), SizeCacheEntry>,
+    // The cached regions of each CF keyed by their end keys, the cached
+    // ranges of a CF never overlap.
+    ranges: HashMap<CfName, BTreeMap<Vec<u8>, u64>>,
+    // The cached regions whose sizes are tracked in each SST file.
+    file_regions: HashMap<String, HashSet<(u64, CfName)>>,
+    // Increased on every change of the SST files of a CF, a size read before
+    // the change isn't cached.
+    seqs: HashMap<CfName, u64>,
+    // Increased on every lookup, used to evict the least recently used entry.
+    tick: u64,
+}
+
+impl SizeCacheInner {
+    fn seq(&self, cf: CfName) -> u64 {
+        self.seqs.get(cf).cloned().unwrap_or(0)
+    }
+
+    // Get the ids of the cached regions of the CF overlapping with the data
+    // key range `[start_key, end_key)`.
+    fn overlapped_regions(&self, cf: CfName, start_key: &[u8], end_key: &[u8]) -> Vec<u64> {
+        let ranges = match self.ranges.get(cf) {
+            Some(ranges) => ranges,
+            None => return vec![],
+        };
+        let mut ids = vec![];
+        for (_, id) in ranges.range::<[u8], _>((Excluded(start_key), Unbounded)) {
+            match self.entries.get(&(*id, cf)) {
+                Some(e) if e.start_key.as_slice() < end_key => ids.push(*id),
+                _ => break,
+            }
+        }
+        ids
+    }
+
+    fn insert(&mut self, capacity: usize, key: (u64, CfName), entry: SizeCacheEntry) {
+        self.remove(&key);
+        // The regions overlapping with it must be stale.
+        for id in self.overlapped_regions(key.1, &entry.start_key, &entry.end_key) {
+            self.remove(&(id, key.1));
+        }
+        if self.entries.len() >= capacity {
+            let lru = self
+                .entries
+                .iter()
+                .min_by_key(|&(_, e)| e.last_access)
+                .map(|(k, _)| *k);
+            if let Some(k) = lru {
+                self.remove(&k);
+            }
+        }
+        self.ranges
+            .entry(key.1)
+            .or_insert_with(BTreeMap::new)
+            .insert(entry.end_key.clone(), key.0);
+        for file in entry.files.keys() {
+            self.file_regions
+                .entry(file.clone())
+                .or_insert_with(HashSet::default)
+                .insert(key);
+        }
+        self.entries.insert(key, entry);
+    }
+
+    fn remove(&mut self, key: &(u64, CfName)) {
+        let entry = match self.entries.remove(key) {
+            Some(e) => e,
+            None => return,
+        };
+        if let Some(ranges) = self.ranges.get_mut(key.1) {
+            if ranges.get(&entry.end_key) == Some(&key.0) {
+                ranges.remove(&entry.end_key);
+            }
+        }
+        for file in entry.files.keys() {
+            let empty = match self.file_regions.get_mut(file) {
+                Some(regions) => {
+                    regions.remove(key);
+                    regions.is_empty()
+                }
+                None => false,
+            };
+            if empty {
+                self.file_regions.remove(file);
+            }
+        }
+    }
+}
+
+/// Caches the approximate size of regions in SST files, which is read from
+/// table properties and can be slow. A cached size is kept up to date by the
+/// changes of SST files passed to `on_sst_changed`, until the region's
+/// version changes.
+pub struct ApproximateSizeCache {
+    capacity: usize,
+    inner: Mutex<SizeCacheInner>,
+}
+
+impl ApproximateSizeCache {
+    pub fn new(capacity: usize) -> ApproximateSizeCache {
+        ApproximateSizeCache {
+            capacity,
+            inner: Mutex::new(SizeCacheInner::default()),
+        }
+    }
+
+    /// Get the approximate size of the region in the CF, the size in
+    /// memtables is never cached.
+    pub fn get_region_approximate_size_cf(
+        &self,
+        engine: &DB,
+        cf: CfName,
+        region: &Region,
+    ) -> StdResult<u64, ApproximateSizeError> {
+        let (cached, seq) = self.get_sst_size(cf, region);
+        let sst_size = match cached {
+            Some(size) => {
+                REGION_SIZE_CACHE_COUNTER_VEC
+                    .with_label_values(&["hit"])
+                    .inc();
+                size
+            }
+            None => {
+                REGION_SIZE_CACHE_COUNTER_VEC
+                    .with_label_values(&["miss"])
+                    .inc();
+                let sizes = util::get_region_approximate_sst_sizes_cf(engine, cf, region)?;
+                let size = sizes.iter().map(|&(_, size)| size).sum();
+                self.insert(cf, region, sizes.into_iter().collect(), size, seq);
+                size
+            }
+        };
+        let memtable_size = util::get_region_approximate_memtable_size_cf(engine, cf, region)?;
+        Ok(sst_size + memtable_size)
+    }
+
+    // Get the cached size of the region in SST files, and the sequence of
+    // the changes of the CF, which should be passed to `insert` later.
+    fn get_sst_size(&self, cf: CfName, region: &Region) -> (Option<u64>, u64) {
+        let key = (region.get_id(), cf);
+        let version = region.get_region_epoch().get_version();
+        let mut inner = self.inner.lock().unwrap();
+        inner.tick += 1;
+        let tick = inner.tick;
+        let seq = inner.seq(cf);
+        match inner.entries.get_mut(&key) {
+            Some(ref mut e) if e.version == version => {
+                e.last_access = tick;
+                return (Some(e.sst_size), seq);
+            }
+            Some(_) => {}
+            None => return (None, seq),
+        }
+        inner.remove(&key);
+        (None, seq)
+    }
+
+    fn insert(
+        &self,
+        cf: CfName,
+        region: &Region,
+        files: HashMap<String, u64>,
+        sst_size: u64,
+        seq: u64,
+    ) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.seq(cf) != seq {
+            // The SST files changed while the size was being read, the size
+            // may or may not include the changes.
+            return;
+        }
+        let entry = SizeCacheEntry {
+            version: region.get_region_epoch().get_version(),
+            start_key: keys::enc_start_key(region),
+            end_key: keys::enc_end_key(region),
+            files,
+            sst_size,
+            last_access: inner.tick,
+        };
+        inner.insert(self.capacity, (region.get_id(), cf), entry);
+    }
+
+    // Update the tracked sizes of the CF by the changed SST files.
+    fn on_sst_changed(&self, event: SstChangedEvent) {
+        let cf = match ALL_CFS.iter().find(|cf| **cf == event.cf) {
+            Some(cf) => *cf,
+            None => return,
+        };
+        let mut guard = self.inner.lock().unwrap();
+        let inner = &mut *guard;
+        *inner.seqs.entry(cf).or_insert(0) += 1;
+        let (added, deleted) = match event.files {
+            Some(files) => files,
+            None => {
+                // The sizes can't be updated, read them again.
+                let keys: Vec<_> = inner
+                    .entries
+                    .keys()
+                    .filter(|&&(_, c)| c == cf)
+                    .cloned()
+                    .collect();
+                for key in keys {
+                    inner.remove(&key);
+                }
+                return;
+            }
+        };
+        // A file moved to another level is both deleted and added.
+        for file in &deleted {
+            for key in inner.file_regions.remove(file).unwrap_or_default() {
+                if let Some(e) = inner.entries.get_mut(&key) {
+                    if let Some(size) = e.files.remove(file) {
+                        e.sst_size -= size;
+                    }
+                }
+            }
         }
+        for (file, props) in added {
+            let (start_key, end_key) = {
+                let mut offset_keys = props.offsets.keys();
+                match (offset_keys.next(), offset_keys.next_back()) {
+                    (Some(first), last) => {
+                        // The smallest key greater than the last key.
+                        let mut end_key = last.unwrap_or(first).clone();
+                        end_key.push(0);
+                        (first.clone(), end_key)
+                    }
+                    (None, _) => continue,
+                }
+            };
+            for id in inner.overlapped_regions(cf, &start_key, &end_key) {
+                let key = (id, cf);
+                let e = inner.entries.get_mut(&key).unwrap();
+                let size = props.get_approximate_size_in_range(&e.start_key, &e.end_key);
+                if size > 0 && !e.files.contains_key(&file) {
+                    e.files.insert(file.clone(), size);
+                    e.sst_size += size;
+                    inner
+                        .file_regions
+                        .entry(file.clone())
+                        .or_insert_with(HashSet::default)
+                        .insert(key);
+                }
+            }
+        }
+    }
+}
+
+/// Tracks the approximate size of regions in SST files. The size of a region
+/// is read from the table properties once, then it's updated by the SST
+/// files added and deleted by flushes and compactions, which are sent by the
+/// listener created by `new_size_tracker_listener`. The size is read again
+/// after the region's version changes.
+pub struct RegionSizeTracker {
+    cache: ApproximateSizeCache,
+}
+
+impl RegionSizeTracker {
+    pub fn new(capacity: usize) -> RegionSizeTracker {
+        RegionSizeTracker {
+            cache: ApproximateSizeCache::new(capacity),
+        }
+    }
+
+    /// Get the approximate size of the region in the CF, the size in SST
+    /// files is only read from the table properties if it's not tracked.
+    pub fn get_region_approximate_size_cf(
+        &self,
+        engine: &DB,
+        cf: CfName,
+        region: &Region,
+    ) -> StdResult<u64, ApproximateSizeError> {
+        self.cache.get_region_approximate_size_cf(engine, cf, region)
+    }
+
+    /// Update the tracked sizes of the CF by the changed SST files.
+    pub fn on_sst_changed(&self, event: SstChangedEvent) {
+        self.cache.on_sst_changed(event)
+    }
+}
+
+impl Default for RegionSizeTracker {
+    fn default() -> RegionSizeTracker {
+        RegionSizeTracker::new(SIZE_CACHE_CAPACITY)
     }
 }
 
+/// Create a listener of the kv engine, which keeps the sizes tracked by
+/// `tracker` up to date.
+pub fn new_size_tracker_listener(tracker: Arc<RegionSizeTracker>) -> SstChangeListener {
+    SstChangeListener::new(Box::new(move |event| tracker.on_sst_changed(event)))
+}
+
 pub struct SizeCheckObserver<C> {
     region_max_size: u64,
     split_size: u64,
+    // Split size of regions of new tables, 0 means the same as split_size.
+    initial_split_size: u64,
+    min_split_region_size: u64,
+    min_split_keys: u64,
+    count_value_only: bool,
+    compression_ratios: HashMap<CfName, f64>,
+    max_cf_size: u64,
+    bucket_count: u64,
+    split_on_user_key: bool,
+    report_region_size: bool,
+    report_cf_sizes: bool,
+    flush_memtable: bool,
+    max_split_keys: u64,
+    fast_size_estimate: bool,
+    skip_empty_region: bool,
+    leader_only: bool,
+    protected_ranges: Vec<(Vec<u8>, Vec<u8>)>,
+    size_tracker: Option<Arc<RegionSizeTracker>>,
+    size_histogram: Histogram,
     ch: RetryableSendCh<Msg, C>,
 }
 
@@ -66,14 +708,259 @@ impl<C: Sender<Msg>> SizeCheckObserver<C> {
     pub fn new(
         region_max_size: u64,
         split_size: u64,
+        min_split_region_size: u64,
+        max_cf_size: u64,
+        bucket_count: u64,
+        split_on_user_key: bool,
         ch: RetryableSendCh<Msg, C>,
     ) -> SizeCheckObserver<C> {
         SizeCheckObserver {
             region_max_size,
             split_size,
+            initial_split_size: 0,
+            min_split_region_size,
+            min_split_keys: 0,
+            count_value_only: false,
+            compression_ratios: HashMap::default(),
+            max_cf_size,
+            bucket_count,
+            split_on_user_key,
+            report_region_size: true,
+            report_cf_sizes: false,
+            flush_memtable: false,
+            max_split_keys: 0,
+            fast_size_estimate: false,
+            skip_empty_region: false,
+            leader_only: true,
+            protected_ranges: vec![],
+            size_tracker: None,
+            size_histogram: region_size_histogram(region_max_size),
             ch,
         }
     }
+
+    /// Whether to send the approximate region size to raftstore, it can be
+    /// turned off if the size is reported to PD in another way.
+    pub fn with_report_region_size(mut self, report: bool) -> SizeCheckObserver<C> {
+        self.report_region_size = report;
+        self
+    }
+
+    /// Whether to also send the approximate size of each CF to raftstore
+    /// when the region size is reported.
+    pub fn with_report_cf_sizes(mut self, report: bool) -> SizeCheckObserver<C> {
+        self.report_cf_sizes = report;
+        self
+    }
+
+    /// Whether to flush the memtables if the region exceeds the max size only
+    /// with the data in memtables.
+    pub fn with_flush_memtable(mut self, flush: bool) -> SizeCheckObserver<C> {
+        self.flush_memtable = flush;
+        self
+    }
+
+    /// Only split after at least `min_keys` keys in a segment, 0 means no
+    /// limit.
+    pub fn with_min_split_keys(mut self, min_keys: u64) -> SizeCheckObserver<C> {
+        self.min_split_keys = min_keys;
+        self
+    }
+
+    /// Only count the size of values when scanning regions.
+    pub fn with_count_value_only(mut self, count_value_only: bool) -> SizeCheckObserver<C> {
+        self.count_value_only = count_value_only;
+        self
+    }
+
+    /// Scale the size of entries by the compression ratio of their CFs.
+    pub fn with_compression_ratios(
+        mut self,
+        ratios: HashMap<CfName, f64>,
+    ) -> SizeCheckObserver<C> {
+        self.compression_ratios = ratios;
+        self
+    }
+
+    /// Limit the number of split keys found by one check, 0 means no limit.
+    pub fn with_max_split_keys(mut self, max_split_keys: u64) -> SizeCheckObserver<C> {
+        self.max_split_keys = max_split_keys;
+        self
+    }
+
+    /// Whether to estimate the size of regions from the SST index entries
+    /// instead of reading the table properties.
+    pub fn with_fast_size_estimate(mut self, fast: bool) -> SizeCheckObserver<C> {
+        self.fast_size_estimate = fast;
+        self
+    }
+
+    /// Whether to skip the automatic checks of the regions whose approximate
+    /// size is near zero, the following observers don't add checkers either.
+    pub fn with_skip_empty_region(mut self, skip: bool) -> SizeCheckObserver<C> {
+        self.skip_empty_region = skip;
+        self
+    }
+
+    /// Whether to skip the regions whose local peers are not leaders, it's
+    /// true by default.
+    pub fn with_leader_only(mut self, leader_only: bool) -> SizeCheckObserver<C> {
+        self.leader_only = leader_only;
+        self
+    }
+
+    /// Never split inside any of the `ranges` of origin keys.
+    pub fn with_protected_ranges(
+        mut self,
+        ranges: Vec<(Vec<u8>, Vec<u8>)>,
+    ) -> SizeCheckObserver<C> {
+        self.protected_ranges = ranges;
+        self
+    }
+
+    /// Split the regions covering a whole table at `initial_split_size`, so
+    /// a new table is spread out early. The max size keeps the same ratio to
+    /// the split size. 0 means no difference.
+    pub fn with_initial_split_size(mut self, size: u64) -> SizeCheckObserver<C> {
+        self.initial_split_size = size;
+        self
+    }
+
+    /// Get the size of regions in SST files from `tracker` instead of the
+    /// table properties, it's only up to date if the listener of the tracker
+    /// is added to the engine.
+    pub fn with_size_tracker(
+        mut self,
+        tracker: Option<Arc<RegionSizeTracker>>,
+    ) -> SizeCheckObserver<C> {
+        self.size_tracker = tracker;
+        self
+    }
+
+    /// Get the max size and the split size of the region.
+    fn thresholds(&self, region: &Region) -> (u64, u64) {
+        if self.initial_split_size == 0 || self.split_size == 0 || !is_whole_table_region(region) {
+            return (self.region_max_size, self.split_size);
+        }
+        let ratio = self.region_max_size as f64 / self.split_size as f64;
+        let max_size = (self.initial_split_size as f64 * ratio) as u64;
+        (max_size, self.initial_split_size)
+    }
+
+    fn get_region_approximate_size_cf(
+        &self,
+        engine: &DB,
+        cf: CfName,
+        region: &Region,
+    ) -> StdResult<u64, ApproximateSizeError> {
+        retry_transient(region.get_id(), || {
+            if self.fast_size_estimate {
+                return util::get_region_approximate_size_fast_cf(engine, cf, region);
+            }
+            if let Some(ref tracker) = self.size_tracker {
+                return tracker.get_region_approximate_size_cf(engine, cf, region);
+            }
+            util::get_region_approximate_size_cf(engine, cf, region)
+                .map_err(|e| ApproximateSizeError::Io(e.to_string()))
+        })
+    }
+
+    /// Get the size of the region excluding the data deleted by `DeleteRange`
+    /// at its edges, `region_size` is returned if it fails.
+    fn get_region_live_size(&self, engine: &DB, region: &Region, region_size: u64) -> u64 {
+        let mut live_size = 0;
+        for cf in LARGE_CFS {
+            match util::get_region_approximate_live_size_cf(engine, cf, region) {
+                Ok(size) => live_size += size,
+                Err(e) => {
+                    warn!(
+                        "[region {}] failed to get approximate live size: {}",
+                        region.get_id(),
+                        e
+                    );
+                    return region_size;
+                }
+            }
+        }
+        cmp::min(live_size, region_size)
+    }
+
+    /// Ask raftstore to flush the memtables if the size in SST files alone
+    /// doesn't reach the max size.
+    fn maybe_flush_memtable(
+        &self,
+        engine: &DB,
+        region: &Region,
+        region_size: u64,
+        max_size: u64,
+    ) {
+        let region_id = region.get_id();
+        let mut memtable_size = 0;
+        for cf in LARGE_CFS {
+            match util::get_region_approximate_memtable_size_cf(engine, cf, region) {
+                Ok(size) => memtable_size += size,
+                Err(e) => {
+                    warn!(
+                        "[region {}] failed to get approximate memtable size: {}",
+                        region_id, e
+                    );
+                    return;
+                }
+            }
+        }
+        if region_size.saturating_sub(memtable_size) >= max_size {
+            return;
+        }
+        info!(
+            "[region {}] approximate size {} in memtables is too large, request to flush",
+            region_id, memtable_size
+        );
+        let timeout = Duration::from_millis(SEND_TIMEOUT_MS);
+        if let Err(e) = self
+            .ch
+            .try_send_timeout(Msg::FlushRegion { region_id }, timeout)
+        {
+            warn!("[region {}] failed to send flush request: {}", region_id, e);
+        }
+    }
+
+    fn new_checker(&self, max_size: u64, split_size: u64, resumed_size: u64) -> Box<Checker> {
+        let key_prefix_fn: Option<KeyPrefixFn> = if self.split_on_user_key {
+            Some(Box::new(user_key_prefix))
+        } else {
+            None
+        };
+        let checker = Checker::new(
+            max_size,
+            split_size,
+            self.min_split_region_size,
+            key_prefix_fn,
+        );
+        // The checker sees data keys, an empty end key stays empty.
+        let protected_ranges = self
+            .protected_ranges
+            .iter()
+            .map(|&(ref start_key, ref end_key)| {
+                let end_key = if end_key.is_empty() {
+                    vec![]
+                } else {
+                    keys::data_key(end_key)
+                };
+                (keys::data_key(start_key), end_key)
+            })
+            .collect();
+        Box::new(
+            checker
+                .with_protected_ranges(protected_ranges)
+                .with_max_cf_size(self.max_cf_size)
+                .with_bucket_count(self.bucket_count)
+                .with_max_split_keys(self.max_split_keys)
+                .with_min_keys_before_split(self.min_split_keys)
+                .with_count_value_only(self.count_value_only)
+                .with_compression_ratios(self.compression_ratios.clone())
+                .with_current_size(resumed_size),
+        )
+    }
 }
 
 impl<C> Coprocessor for SizeCheckObserver<C> {}
@@ -82,53 +969,127 @@ impl<C: Sender<Msg> + Send> SplitCheckObserver for SizeCheckObserver<C> {
     fn add_checker(&self, ctx: &mut ObserverContext, host: &mut Host, engine: &DB) {
         let region = ctx.region();
         let region_id = region.get_id();
-        let region_size = match util::get_region_approximate_size(engine, region) {
-            Ok(size) => size,
-            Err(e) => {
-                warn!(
-                    "[region {}] failed to get approximate stat: {}",
+        if ctx.region_state == RegionState::Merging {
+            // The region will be gone after merge, no need to split it.
+            debug!("[region {}] is merging, skip size check", region_id);
+            return;
+        }
+        if self.leader_only && !ctx.is_leader {
+            debug!("[region {}] is not leader, skip size check", region_id);
+            return;
+        }
+        if !ctx.ingest_ranges.is_empty() {
+            // The ingested SST files must be in the region.
+            info!("[region {}] is being ingested, skip size check", region_id);
+            return;
+        }
+        let (max_size, split_size) = ctx
+            .size_thresholds
+            .unwrap_or_else(|| self.thresholds(region));
+        let mut cf_sizes = Vec::with_capacity(LARGE_CFS.len());
+        for cf in LARGE_CFS {
+            match self.get_region_approximate_size_cf(engine, cf, region) {
+                Ok(size) => cf_sizes.push((*cf, size)),
+                Err(e) => {
+                    match e {
+                        ApproximateSizeError::NoProperties => info!(
+                            "[region {}] has no size properties in cf {}",
+                            region_id, cf
+                        ),
+                        e => warn!(
+                            "[region {}] failed to get approximate stat: {}",
+                            region_id, e
+                        ),
+                    }
+                    // Need to check size.
+                    host.add_checker(self.new_checker(max_size, split_size, ctx.resumed_size));
+                    return;
+                }
+            }
+        }
+        let region_size: u64 = cf_sizes.iter().map(|&(_, size)| size).sum();
+
+        if self.report_region_size {
+            let res = Msg::RegionApproximateSize {
+                region_id,
+                size: region_size,
+            };
+            let timeout = Duration::from_millis(SEND_TIMEOUT_MS);
+            match self.ch.try_send_timeout(res, timeout) {
+                Ok(()) => ctx.reported_size = Some(region_size),
+                Err(e) => warn!(
+                    "[region {}] failed to send approximate region size: {}",
                     region_id, e
-                );
-                // Need to check size.
-                host.add_checker(Box::new(Checker::new(
-                    self.region_max_size,
-                    self.split_size,
-                )));
-                return;
+                ),
             }
-        };
+            if self.report_cf_sizes {
+                let sizes = cf_sizes
+                    .iter()
+                    .map(|&(cf, size)| (cf.to_owned(), size))
+                    .collect();
+                let res = Msg::RegionApproximateSizePerCf { region_id, sizes };
+                if let Err(e) = self.ch.try_send_timeout(res, timeout) {
+                    warn!(
+                        "[region {}] failed to send approximate cf sizes: {}",
+                        region_id, e
+                    );
+                }
+            }
+        }
 
-        let res = Msg::RegionApproximateSize {
-            region_id,
-            size: region_size,
-        };
-        if let Err(e) = self.ch.try_send(res) {
-            warn!(
-                "[region {}] failed to send approximate region size: {}",
-                region_id, e
+        self.size_histogram.observe(region_size as f64);
+        if self.skip_empty_region && host.auto_split() && region_size < EMPTY_REGION_SIZE {
+            info!(
+                "[region {}] treat_empty_region: approximate size {}, skip split check",
+                region_id, region_size
             );
+            return;
         }
-
-        REGION_SIZE_HISTOGRAM.observe(region_size as f64);
-        if region_size >= self.region_max_size {
+        // The data deleted by `DeleteRange` is still counted in the approximate
+        // size before it's compacted, so exclude it before scanning the region.
+        let region_size = if region_size >= max_size {
+            let live_size = self.get_region_live_size(engine, region, region_size);
+            if live_size < region_size {
+                debug!(
+                    "[region {}] approximate size {} excluding deleted ranges is {}",
+                    region_id, region_size, live_size
+                );
+            }
+            live_size
+        } else {
+            region_size
+        };
+        if region_size >= max_size {
             info!(
                 "[region {}] approximate size {} >= {}, need to do split check",
                 region.get_id(),
                 region_size,
-                self.region_max_size
+                max_size
             );
+            if self.flush_memtable {
+                self.maybe_flush_memtable(engine, region, region_size, max_size);
+            }
             // Need to check size.
-            host.add_checker(Box::new(Checker::new(
-                self.region_max_size,
-                self.split_size,
-            )));
+            host.add_checker(self.new_checker(max_size, split_size, ctx.resumed_size));
+        } else if let Some(&(cf, cf_size)) = cf_sizes
+            .iter()
+            .find(|&&(_, size)| self.max_cf_size > 0 && size >= self.max_cf_size)
+        {
+            info!(
+                "[region {}] approximate size {} of cf {} >= {}, need to do split check",
+                region.get_id(),
+                cf_size,
+                cf,
+                self.max_cf_size
+            );
+            host.add_checker(self.new_checker(max_size, split_size, ctx.resumed_size));
         } else {
             // Does not need to check size.
             debug!(
                 "[region {}] approximate size {} < {}, does not need to do split check",
                 region.get_id(),
                 region_size,
-                self.region_max_size
+                max_size
             );
         }
     }
@@ -136,42 +1097,66 @@ impl<C: Sender<Msg> + Send> SplitCheckObserver for SizeCheckObserver<C> {
 
 #[cfg(test)]
 pub mod tests {
+    use std::result::Result as StdResult;
     use std::sync::mpsc;
-    use std::sync::Arc;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
 
     use kvproto::metapb::Peer;
     use kvproto::metapb::Region;
     use kvproto::pdpb::CheckPolicy;
     use rocksdb::Writable;
-    use rocksdb::{ColumnFamilyOptions, DBOptions};
+    use rocksdb::{ColumnFamilyOptions, DBOptions, WriteBatch, DB};
     use tempdir::TempDir;
 
-    use super::Checker;
-    use raftstore::coprocessor::{Config, CoprocessorHost, ObserverContext, SplitChecker};
-    use raftstore::store::{keys, KeyEntry, Msg, SplitCheckRunner, SplitCheckTask};
-    use storage::{ALL_CFS, CF_WRITE};
+    use super::super::Host;
+    use super::{
+        new_size_tracker_listener, retry_transient, user_key_prefix, ApproximateSizeCache, Checker,
+        RegionSizeTracker, SizeCheckObserver,
+    };
+    use coprocessor::codec::table;
+    use raftstore::coprocessor::{
+        Config, Coprocessor, CoprocessorHost, ObserverContext, RegionState, SplitCheckContext,
+        SplitCheckObserver, SplitChecker,
+    };
+    use raftstore::store::util::{get_region_approximate_size_cf, ApproximateSizeError};
+    use raftstore::store::{
+        keys, split_keys_of_entries, KeyEntry, Msg, SplitCheckRunner, SplitCheckTask,
+        VecEntryIterator,
+    };
+    use storage::{Key, ALL_CFS, CF_DEFAULT, CF_WRITE};
+    use util::codec;
+    use util::collections::HashMap;
     use util::config::ReadableSize;
-    use util::properties::RangePropertiesCollectorFactory;
-    use util::rocksdb::{new_engine_opt, CFOptions};
+    use util::properties::{RangeOffsets, RangeProperties, RangePropertiesCollectorFactory};
+    use util::rocksdb::{new_engine, new_engine_opt, CFOptions, SstChangedEvent};
     use util::transport::RetryableSendCh;
     use util::worker::Runnable;
 
-    pub fn must_split_at(rx: &mpsc::Receiver<Msg>, exp_region: &Region, exp_split_key: &[u8]) {
+    pub fn must_split_at(
+        rx: &mpsc::Receiver<Msg>,
+        exp_region: &Region,
+        exp_split_keys: Vec<Vec<u8>>,
+    ) {
         loop {
             match rx.try_recv() {
                 Ok(Msg::RegionApproximateSize { region_id, .. })
-                | Ok(Msg::RegionApproximateKeys { region_id, .. }) => {
+                | Ok(Msg::RegionApproximateKeys { region_id, .. })
+                | Ok(Msg::SplitCheckResult { region_id, .. })
+                | Ok(Msg::RegionMaxEntry { region_id, .. })
+                | Ok(Msg::SplitCheckCfStats { region_id, .. }) => {
                     assert_eq!(region_id, exp_region.get_id());
                 }
                 Ok(Msg::SplitRegion {
                     region_id,
                     region_epoch,
-                    split_key,
+                    split_keys,
                     ..
                 }) => {
                     assert_eq!(region_id, exp_region.get_id());
                     assert_eq!(&region_epoch, exp_region.get_region_epoch());
-                    assert_eq!(split_key, exp_split_key);
+                    assert_eq!(split_keys, exp_split_keys);
                     break;
                 }
                 others => panic!("expect split check result, but got {:?}", others),
@@ -179,6 +1164,21 @@ pub mod tests {
         }
     }
 
+    #[test]
+    fn test_checker_in_memory() {
+        let region = Region::default();
+        let mut host = Host::new(true);
+        host.add_checker(Box::new(Checker::new(100, 60, 0, None)));
+        // Every entry is 10 bytes, no engine is needed to find the split keys.
+        let entries = (0..20)
+            .map(|i| KeyEntry::new(vec![b'z', i], 0, 8, CF_DEFAULT))
+            .collect();
+        let mut iter = VecEntryIterator::new(entries);
+        let split_keys = split_keys_of_entries(&mut host, &region, &mut iter, 4).unwrap();
+        // The last two segments fit in the max size, so they are kept together.
+        assert_eq!(split_keys, vec![vec![b'z', 6], vec![b'z', 12]]);
+    }
+
     #[test]
     fn test_split_check() {
         let path = TempDir::new("test-raftstore").unwrap();
@@ -206,7 +1206,7 @@ pub mod tests {
         let ch = RetryableSendCh::new(tx, "test-split");
         let mut cfg = Config::default();
         cfg.region_max_size = ReadableSize(100);
-        cfg.region_split_size = ReadableSize(60);
+        cfg.region_split_size = Some(ReadableSize(60));
 
         let mut runnable = SplitCheckRunner::new(
             Arc::clone(&engine),
@@ -239,9 +1239,9 @@ pub mod tests {
         engine.flush(true).unwrap();
 
         runnable.run(SplitCheckTask::new(region.clone(), true, CheckPolicy::SCAN));
-        must_split_at(&rx, &region, b"0006");
+        must_split_at(&rx, &region, vec![b"0006".to_vec()]);
 
-        // So split key will be z0003
+        // So split keys will be z0003 and z0006
         for i in 0..6 {
             let s = keys::data_key(format!("{:04}", i).as_bytes());
             for cf in ALL_CFS {
@@ -255,24 +1255,1398 @@ pub mod tests {
         }
 
         runnable.run(SplitCheckTask::new(region.clone(), true, CheckPolicy::SCAN));
-        must_split_at(&rx, &region, b"0003");
+        must_split_at(&rx, &region, vec![b"0003".to_vec(), b"0006".to_vec()]);
+
+        // Only keys in the range are checked.
+        let task = SplitCheckTask::new(region.clone(), true, CheckPolicy::SCAN)
+            .with_range(b"0001".to_vec(), vec![]);
+        runnable.run(task);
+        loop {
+            match rx.try_recv() {
+                Ok(Msg::SplitCheckResult {
+                    scanned_size,
+                    scanned_keys,
+                    found_split,
+                    ..
+                }) => {
+                    // 0001~0005 are in both default and write cf.
+                    assert_eq!(scanned_keys, 15);
+                    assert_eq!(scanned_size, 150);
+                    assert!(found_split);
+                    break;
+                }
+                Ok(_) => continue,
+                others => panic!("expect split check result, but got {:?}", others),
+            }
+        }
+        must_split_at(&rx, &region, vec![b"0004".to_vec()]);
+
         drop(rx);
         // It should be safe even the result can't be sent back.
         runnable.run(SplitCheckTask::new(region, true, CheckPolicy::SCAN));
     }
 
     #[test]
-    fn test_checker_with_same_max_and_split_size() {
-        let mut checker = Checker::new(24, 24);
-        let region = Region::default();
-        let mut ctx = ObserverContext::new(&region);
-        loop {
-            let data = KeyEntry::new(b"xxxx".to_vec(), 0, 4, CF_WRITE);
-            if checker.on_kv(&mut ctx, &data) {
-                break;
-            }
-        }
+    fn test_split_check_with_max_cf_size() {
+        let path = TempDir::new("test-raftstore").unwrap();
+        let path_str = path.path().to_str().unwrap();
+        let db_opts = DBOptions::new();
+        let mut cf_opts = ColumnFamilyOptions::new();
+        let f = Box::new(RangePropertiesCollectorFactory::default());
+        cf_opts.add_table_properties_collector_factory("tikv.range-collector", f);
+
+        let cfs_opts = ALL_CFS
+            .iter()
+            .map(|cf| CFOptions::new(cf, cf_opts.clone()))
+            .collect();
+        let engine = Arc::new(new_engine_opt(path_str, db_opts, cfs_opts).unwrap());
+
+        let mut region = Region::new();
+        region.set_id(1);
+        region.mut_peers().push(Peer::new());
+        region.mut_region_epoch().set_version(2);
+        region.mut_region_epoch().set_conf_ver(5);
+
+        let (tx, rx) = mpsc::sync_channel(100);
+        let ch = RetryableSendCh::new(tx, "test-split");
+        let mut cfg = Config::default();
+        cfg.region_max_size = ReadableSize(300);
+        cfg.region_split_size = Some(ReadableSize(200));
+        cfg.region_max_cf_size = ReadableSize(150);
+
+        let mut runnable = SplitCheckRunner::new(
+            Arc::clone(&engine),
+            ch.clone(),
+            Arc::new(CoprocessorHost::new(cfg, ch.clone())),
+        );
+
+        // All data is in write cf, 200 bytes in total.
+        let write_cf = engine.cf_handle(CF_WRITE).unwrap();
+        for i in 0..20 {
+            let s = keys::data_key(format!("{:04}", i).as_bytes());
+            engine.put_cf(write_cf, &s, &s).unwrap();
+        }
+        engine.flush_cf(write_cf, true).unwrap();
+
+        // The total size doesn't exceed the max size, but the write cf does.
+        runnable.run(SplitCheckTask::new(region.clone(), true, CheckPolicy::SCAN));
+        must_split_at(&rx, &region, vec![b"0010".to_vec()]);
+    }
+
+    #[test]
+    fn test_approximate_split_key() {
+        let path = TempDir::new("test-raftstore").unwrap();
+        let path_str = path.path().to_str().unwrap();
+        let db_opts = DBOptions::new();
+        let mut cf_opts = ColumnFamilyOptions::new();
+        let f = Box::new(RangePropertiesCollectorFactory::default());
+        cf_opts.add_table_properties_collector_factory("tikv.range-collector", f);
+
+        let cfs_opts = ALL_CFS
+            .iter()
+            .map(|cf| CFOptions::new(cf, cf_opts.clone()))
+            .collect();
+        let engine = Arc::new(new_engine_opt(path_str, db_opts, cfs_opts).unwrap());
+
+        let mut region = Region::new();
+        region.set_id(1);
+        region.mut_peers().push(Peer::new());
+        region.mut_region_epoch().set_version(2);
+        region.mut_region_epoch().set_conf_ver(5);
+
+        let (tx, rx) = mpsc::sync_channel(100);
+        let ch = RetryableSendCh::new(tx, "test-split");
+        let mut cfg = Config::default();
+        cfg.region_max_size = ReadableSize(150);
+        cfg.region_split_size = Some(ReadableSize(100));
+
+        let mut runnable = SplitCheckRunner::new(
+            Arc::clone(&engine),
+            ch.clone(),
+            Arc::new(CoprocessorHost::new(cfg, ch.clone())),
+        );
+
+        let cf_handle = engine.cf_handle(CF_DEFAULT).unwrap();
+        let mut split_candidates = vec![];
+        for i in 0..20 {
+            let s = keys::data_key(format!("{:04}", i).as_bytes());
+            engine.put_cf(cf_handle, &s, &[0; 10]).unwrap();
+            // Flush for every key so that every key is a property block.
+            engine.flush_cf(cf_handle, true).unwrap();
+            split_candidates.push(keys::origin_key(&s).to_vec());
+        }
+
+        let mut split_key_pos = |region: &Region, policy| {
+            runnable.run(SplitCheckTask::new(region.clone(), true, policy));
+            loop {
+                match rx.try_recv() {
+                    Ok(Msg::RegionApproximateSize { .. })
+                    | Ok(Msg::RegionApproximateKeys { .. })
+                    | Ok(Msg::SplitCheckResult { .. })
+                    | Ok(Msg::RegionMaxEntry { .. })
+                    | Ok(Msg::SplitCheckCfStats { .. }) => continue,
+                    Ok(Msg::SplitRegion { split_keys, .. }) => {
+                        return split_candidates
+                            .iter()
+                            .position(|k| *k == split_keys[0])
+                            .unwrap();
+                    }
+                    others => panic!("expect split check result, but got {:?}", others),
+                }
+            }
+        };
+
+        let exact = split_key_pos(&region, CheckPolicy::SCAN);
+        // Change the epoch, otherwise the same split keys are not sent again.
+        region.mut_region_epoch().set_version(3);
+        let approximate = split_key_pos(&region, CheckPolicy::APPROXIMATE);
+        assert!(
+            exact <= approximate + 1 && approximate <= exact + 1,
+            "exact {}, approximate {}",
+            exact,
+            approximate
+        );
+    }
+
+    #[test]
+    fn test_approximate_size_cache() {
+        let path = TempDir::new("test-raftstore").unwrap();
+        let path_str = path.path().to_str().unwrap();
+        let db_opts = DBOptions::new();
+        let mut cf_opts = ColumnFamilyOptions::new();
+        let f = Box::new(RangePropertiesCollectorFactory::default());
+        cf_opts.add_table_properties_collector_factory("tikv.range-collector", f);
+        let cfs_opts = ALL_CFS
+            .iter()
+            .map(|cf| CFOptions::new(cf, cf_opts.clone()))
+            .collect();
+        let engine = new_engine_opt(path_str, db_opts, cfs_opts).unwrap();
+        let cf_handle = engine.cf_handle(CF_DEFAULT).unwrap();
+
+        let mut region = Region::new();
+        region.set_id(1);
+        region.set_end_key(b"0100".to_vec());
+        let mut region2 = Region::new();
+        region2.set_id(2);
+        region2.set_start_key(b"0100".to_vec());
+
+        let cache = ApproximateSizeCache::new(1);
+        let check_size = |region: &Region| {
+            let size = cache
+                .get_region_approximate_size_cf(&engine, CF_DEFAULT, region)
+                .unwrap();
+            let exp_size = get_region_approximate_size_cf(&engine, CF_DEFAULT, region).unwrap();
+            assert_eq!(size, exp_size);
+        };
+
+        for i in 0..10 {
+            let s = keys::data_key(format!("{:04}", i).as_bytes());
+            engine.put_cf(cf_handle, &s, &s).unwrap();
+        }
+        engine.flush_cf(cf_handle, true).unwrap();
+        check_size(&region);
+        check_size(&region);
+        assert_eq!(cache.inner.lock().unwrap().entries.len(), 1);
+
+        // Data in memtables is always counted.
+        for i in 10..20 {
+            let s = keys::data_key(format!("{:04}", i).as_bytes());
+            engine.put_cf(cf_handle, &s, &s).unwrap();
+        }
+        check_size(&region);
+
+        // The least recently used entry is evicted.
+        check_size(&region2);
+        {
+            let inner = cache.inner.lock().unwrap();
+            assert_eq!(inner.entries.len(), 1);
+            assert!(inner.entries.contains_key(&(2, CF_DEFAULT)));
+        }
+    }
+
+    #[test]
+    fn test_region_size_tracker() {
+        let path = TempDir::new("test-raftstore").unwrap();
+        let path_str = path.path().to_str().unwrap();
+        let tracker = Arc::new(RegionSizeTracker::default());
+        let mut db_opts = DBOptions::new();
+        db_opts.add_event_listener(new_size_tracker_listener(Arc::clone(&tracker)));
+        let mut cf_opts = ColumnFamilyOptions::new();
+        let f = Box::new(RangePropertiesCollectorFactory::default());
+        cf_opts.add_table_properties_collector_factory("tikv.range-collector", f);
+        let cfs_opts = ALL_CFS
+            .iter()
+            .map(|cf| CFOptions::new(cf, cf_opts.clone()))
+            .collect();
+        let engine = new_engine_opt(path_str, db_opts, cfs_opts).unwrap();
+        let cf_handle = engine.cf_handle(CF_DEFAULT).unwrap();
+
+        let mut region = Region::new();
+        region.set_id(1);
+        region.set_end_key(b"0100".to_vec());
+        let (tx, _rx) = mpsc::sync_channel(100);
+        let ch = RetryableSendCh::new(tx, "test-split");
+        let observer = SizeCheckObserver::new(100, 60, 0, 0, 0, false, ch)
+            .with_size_tracker(Some(Arc::clone(&tracker)));
+
+        let put_and_flush = |start: u64, end: u64| {
+            for i in start..end {
+                let s = keys::data_key(format!("{:04}", i).as_bytes());
+                engine.put_cf(cf_handle, &s, &s).unwrap();
+            }
+            engine.flush_cf(cf_handle, true).unwrap();
+        };
+        // The listener may be called after the flush returns.
+        let must_track = |region: &Region, exp_size: u64| {
+            for _ in 0..100 {
+                if tracker.cache.get_sst_size(CF_DEFAULT, region).0 == Some(exp_size) {
+                    return;
+                }
+                thread::sleep(Duration::from_millis(10));
+            }
+            panic!(
+                "expect tracked size {}, got {:?}",
+                exp_size,
+                tracker.cache.get_sst_size(CF_DEFAULT, region).0
+            );
+        };
+        let must_receive_changes = |exp_seq: u64| {
+            for _ in 0..100 {
+                if tracker.cache.inner.lock().unwrap().seq(CF_DEFAULT) >= exp_seq {
+                    return;
+                }
+                thread::sleep(Duration::from_millis(10));
+            }
+            panic!("expect {} changes of SST files", exp_seq);
+        };
+
+        // The size is read from the table properties at first. It isn't
+        // tracked if the flush is received while it's being read.
+        put_and_flush(0, 10);
+        must_receive_changes(1);
+        assert_eq!(tracker.cache.get_sst_size(CF_DEFAULT, &region).0, None);
+        let size = observer
+            .get_region_approximate_size_cf(&engine, CF_DEFAULT, &region)
+            .unwrap();
+        let exp_size = get_region_approximate_size_cf(&engine, CF_DEFAULT, &region).unwrap();
+        assert_eq!(size, exp_size);
+        must_track(&region, exp_size);
+
+        // The flushed file is added to the tracked size, the keys out of the
+        // region don't count.
+        put_and_flush(10, 20);
+        put_and_flush(200, 210);
+        let exp_size = get_region_approximate_size_cf(&engine, CF_DEFAULT, &region).unwrap();
+        assert!(exp_size > size);
+        must_track(&region, exp_size);
+
+        // The compacted files are replaced by the output files.
+        engine.compact_range_cf(cf_handle, None, None);
+        let exp_size = get_region_approximate_size_cf(&engine, CF_DEFAULT, &region).unwrap();
+        must_track(&region, exp_size);
+
+        // The observer uses the tracked size.
+        tracker
+            .cache
+            .inner
+            .lock()
+            .unwrap()
+            .entries
+            .get_mut(&(1, CF_DEFAULT))
+            .unwrap()
+            .sst_size = 1;
+        let size = observer
+            .get_region_approximate_size_cf(&engine, CF_DEFAULT, &region)
+            .unwrap();
+        assert_eq!(size, 1);
+
+        // The size is read again after the epoch changes.
+        region.mut_region_epoch().set_version(2);
+        assert_eq!(tracker.cache.get_sst_size(CF_DEFAULT, &region).0, None);
+        let size = observer
+            .get_region_approximate_size_cf(&engine, CF_DEFAULT, &region)
+            .unwrap();
+        assert_eq!(size, exp_size);
+        must_track(&region, exp_size);
+    }
+
+    #[test]
+    fn test_region_size_tracker_ranges() {
+        let tracker = RegionSizeTracker::default();
+        let new_region = |id: u64, start_key: &[u8], end_key: &[u8]| {
+            let mut region = Region::new();
+            region.set_id(id);
+            region.set_start_key(start_key.to_vec());
+            region.set_end_key(end_key.to_vec());
+            region
+        };
+        let track = |region: &Region, size: u64| {
+            let (cached, seq) = tracker.cache.get_sst_size(CF_DEFAULT, region);
+            assert_eq!(cached, None);
+            let files = map!{ "1.sst".to_owned() => size };
+            tracker.cache.insert(CF_DEFAULT, region, files, size, seq);
+        };
+        let tracked_size = |region: &Region| tracker.cache.get_sst_size(CF_DEFAULT, region).0;
+
+        let (region1, region2) = (new_region(1, b"", b"b"), new_region(2, b"b", b""));
+        track(&region1, 10);
+        track(&region2, 20);
+        assert_eq!(tracked_size(&region1), Some(10));
+        assert_eq!(tracked_size(&region2), Some(20));
+
+        // A new file is only added to the regions overlapping with it.
+        let new_props = |offsets: &[(&[u8], u64)]| {
+            let mut props = RangeProperties::default();
+            for &(key, size) in offsets {
+                let offsets = RangeOffsets { size, keys: 1 };
+                props.offsets.insert(key.to_vec(), offsets);
+            }
+            props
+        };
+        let props = new_props(&[(b"za", 5), (b"zb", 8), (b"zc", 12)]);
+        tracker.on_sst_changed(SstChangedEvent {
+            cf: CF_DEFAULT.to_owned(),
+            files: Some((vec![("2.sst".to_owned(), props)], vec![])),
+        });
+        assert_eq!(tracked_size(&region1), Some(18));
+        assert_eq!(tracked_size(&region2), Some(24));
+        let props = new_props(&[(b"zc", 6)]);
+        tracker.on_sst_changed(SstChangedEvent {
+            cf: CF_DEFAULT.to_owned(),
+            files: Some((vec![("3.sst".to_owned(), props)], vec!["1.sst".to_owned()])),
+        });
+        assert_eq!(tracked_size(&region1), Some(8));
+        assert_eq!(tracked_size(&region2), Some(10));
+
+        // The regions overlapping with a new one are stale.
+        let region3 = new_region(3, b"a", b"c");
+        track(&region3, 30);
+        assert_eq!(tracked_size(&region3), Some(30));
+        assert_eq!(tracked_size(&region1), None);
+        assert_eq!(tracked_size(&region2), None);
+
+        // The size isn't tracked if the SST files changed while it's read.
+        let (cached, seq) = tracker.cache.get_sst_size(CF_DEFAULT, &region1);
+        assert_eq!(cached, None);
+        tracker.on_sst_changed(SstChangedEvent {
+            cf: CF_DEFAULT.to_owned(),
+            files: Some((vec![], vec![])),
+        });
+        let files = HashMap::default();
+        tracker.cache.insert(CF_DEFAULT, &region1, files, 10, seq);
+        assert_eq!(tracked_size(&region1), None);
+        // The changes of the other CFs don't matter.
+        let (_, seq) = tracker.cache.get_sst_size(CF_DEFAULT, &region1);
+        tracker.on_sst_changed(SstChangedEvent {
+            cf: CF_WRITE.to_owned(),
+            files: None,
+        });
+        let files = HashMap::default();
+        tracker.cache.insert(CF_DEFAULT, &region1, files, 10, seq);
+        assert_eq!(tracked_size(&region1), Some(10));
+    }
+
+    #[test]
+    fn test_skip_merging_region() {
+        let path = TempDir::new("test-raftstore").unwrap();
+        let path_str = path.path().to_str().unwrap();
+        let db_opts = DBOptions::new();
+        let mut cf_opts = ColumnFamilyOptions::new();
+        let f = Box::new(RangePropertiesCollectorFactory::default());
+        cf_opts.add_table_properties_collector_factory("tikv.range-collector", f);
+        let cfs_opts = ALL_CFS
+            .iter()
+            .map(|cf| CFOptions::new(cf, cf_opts.clone()))
+            .collect();
+        let engine = new_engine_opt(path_str, db_opts, cfs_opts).unwrap();
+        let cf_handle = engine.cf_handle(CF_DEFAULT).unwrap();
+        for i in 0..20 {
+            let s = keys::data_key(format!("{:04}", i).as_bytes());
+            engine.put_cf(cf_handle, &s, &s).unwrap();
+        }
+        engine.flush_cf(cf_handle, true).unwrap();
+
+        let mut region = Region::new();
+        region.set_id(1);
+        let (tx, _rx) = mpsc::sync_channel(100);
+        let ch = RetryableSendCh::new(tx, "test-split");
+        let observer = SizeCheckObserver::new(100, 60, 0, 0, 0, false, ch);
+
+        let mut ctx = ObserverContext::new(&region);
+        let mut host = Host::new(true);
+        observer.add_checker(&mut ctx, &mut host, &engine);
+        assert!(!host.skip());
+
+        ctx.region_state = RegionState::Merging;
+        let mut host = Host::new(true);
+        observer.add_checker(&mut ctx, &mut host, &engine);
+        assert!(host.skip());
+    }
+
+    #[test]
+    fn test_skip_follower_region() {
+        let path = TempDir::new("test-raftstore").unwrap();
+        let path_str = path.path().to_str().unwrap();
+        let db_opts = DBOptions::new();
+        let mut cf_opts = ColumnFamilyOptions::new();
+        let f = Box::new(RangePropertiesCollectorFactory::default());
+        cf_opts.add_table_properties_collector_factory("tikv.range-collector", f);
+        let cfs_opts = ALL_CFS
+            .iter()
+            .map(|cf| CFOptions::new(cf, cf_opts.clone()))
+            .collect();
+        let engine = new_engine_opt(path_str, db_opts, cfs_opts).unwrap();
+        let cf_handle = engine.cf_handle(CF_DEFAULT).unwrap();
+        for i in 0..20 {
+            let s = keys::data_key(format!("{:04}", i).as_bytes());
+            engine.put_cf(cf_handle, &s, &s).unwrap();
+        }
+        engine.flush_cf(cf_handle, true).unwrap();
+
+        let mut region = Region::new();
+        region.set_id(1);
+        let (tx, _rx) = mpsc::sync_channel(100);
+        let ch = RetryableSendCh::new(tx, "test-split");
+        let observer = SizeCheckObserver::new(100, 60, 0, 0, 0, false, ch.clone());
+
+        let mut ctx = ObserverContext::new(&region);
+        ctx.is_leader = false;
+        let mut host = Host::new(true);
+        observer.add_checker(&mut ctx, &mut host, &engine);
+        assert!(host.skip());
+
+        // Followers are checked too if it's not leader only.
+        let observer = SizeCheckObserver::new(100, 60, 0, 0, 0, false, ch).with_leader_only(false);
+        let mut host = Host::new(true);
+        observer.add_checker(&mut ctx, &mut host, &engine);
+        assert!(!host.skip());
+    }
+
+    // Always adds a checker, to tell whether the following observers run.
+    struct AlwaysCheckObserver;
+
+    impl Coprocessor for AlwaysCheckObserver {}
+
+    impl SplitCheckObserver for AlwaysCheckObserver {
+        fn add_checker(&self, _: &mut ObserverContext, host: &mut Host, _: &DB) {
+            host.add_checker(Box::new(Checker::new(100, 60, 0, None)));
+        }
+    }
+
+    #[test]
+    fn test_skip_empty_region() {
+        let path = TempDir::new("test-skip-empty-region").unwrap();
+        let engine = new_engine(path.path().to_str().unwrap(), ALL_CFS, None).unwrap();
+        let mut region = Region::new();
+        region.set_id(1);
+
+        let (tx, _rx) = mpsc::sync_channel(100);
+        let ch = RetryableSendCh::new(tx, "test-split");
+        // The max sizes of the size checkers added to the host.
+        let max_sizes = |skip_empty_region: bool, auto_split: bool| {
+            let mut cfg = Config::default();
+            cfg.split_region_on_table = false;
+            cfg.skip_empty_region = skip_empty_region;
+            // Any region is large enough to be checked by its size.
+            cfg.region_max_size = ReadableSize(0);
+            let mut coprocessor = CoprocessorHost::new(cfg, ch.clone());
+            coprocessor
+                .registry
+                .register_split_check_observer(300, Box::new(AlwaysCheckObserver));
+            coprocessor
+                .new_split_checker_host(&region, &engine, auto_split, SplitCheckContext::default())
+                .checkers()
+                .into_iter()
+                .filter(|c| c.name == "size")
+                .map(|c| c.max_size.unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        // The empty region is not checked by its size, the following
+        // observers still add their checkers.
+        assert_eq!(max_sizes(true, true), vec![100]);
+        assert_eq!(max_sizes(false, true), vec![0]);
+        // Unless the split is requested explicitly.
+        assert_eq!(max_sizes(true, false), vec![0]);
+
+        let cf_handle = engine.cf_handle(CF_DEFAULT).unwrap();
+        for i in 0..100 {
+            let s = keys::data_key(format!("{:04}", i).as_bytes());
+            engine.put_cf(cf_handle, &s, &s).unwrap();
+        }
+        engine.flush_cf(cf_handle, true).unwrap();
+        assert_eq!(max_sizes(true, true), vec![0]);
+    }
+
+    // Overrides the thresholds of the regions in `[start_key, end_key)`.
+    struct HotRangeObserver {
+        start_key: Vec<u8>,
+        end_key: Vec<u8>,
+        thresholds: (u64, u64),
+    }
+
+    impl Coprocessor for HotRangeObserver {}
+
+    impl SplitCheckObserver for HotRangeObserver {
+        fn add_checker(&self, _: &mut ObserverContext, _: &mut Host, _: &DB) {}
+
+        fn override_thresholds(&self, ctx: &ObserverContext) -> Option<(u64, u64)> {
+            let region = ctx.region();
+            if region.get_start_key() >= self.start_key.as_slice()
+                && !region.get_end_key().is_empty()
+                && region.get_end_key() <= self.end_key.as_slice()
+            {
+                Some(self.thresholds)
+            } else {
+                None
+            }
+        }
+    }
+
+    #[test]
+    fn test_override_thresholds() {
+        let path = TempDir::new("test-raftstore").unwrap();
+        let path_str = path.path().to_str().unwrap();
+        let db_opts = DBOptions::new();
+        let mut cf_opts = ColumnFamilyOptions::new();
+        let f = Box::new(RangePropertiesCollectorFactory::default());
+        cf_opts.add_table_properties_collector_factory("tikv.range-collector", f);
+        let cfs_opts = ALL_CFS
+            .iter()
+            .map(|cf| CFOptions::new(cf, cf_opts.clone()))
+            .collect();
+        let engine = new_engine_opt(path_str, db_opts, cfs_opts).unwrap();
+        let cf_handle = engine.cf_handle(CF_DEFAULT).unwrap();
+        // 20 kvs of 10 bytes.
+        for i in 0..20 {
+            let s = keys::data_key(format!("{:04}", i).as_bytes());
+            engine.put_cf(cf_handle, &s, &s).unwrap();
+        }
+        engine.flush_cf(cf_handle, true).unwrap();
+
+        let (tx, _rx) = mpsc::sync_channel(100);
+        let ch = RetryableSendCh::new(tx, "test-split");
+        let mut cfg = Config::default();
+        cfg.split_region_on_table = false;
+        cfg.region_max_size = ReadableSize(1000);
+        cfg.region_split_size = Some(ReadableSize(600));
+        let mut coprocessor = CoprocessorHost::new(cfg, ch);
+        let observer = HotRangeObserver {
+            start_key: b"0000".to_vec(),
+            end_key: b"0020".to_vec(),
+            thresholds: (100, 60),
+        };
+        coprocessor
+            .registry
+            .register_split_check_observer(300, Box::new(observer));
+
+        let size_thresholds = |region: &Region| {
+            let host = coprocessor.new_split_checker_host(
+                region,
+                &engine,
+                true,
+                SplitCheckContext::default(),
+            );
+            host.checkers()
+                .into_iter()
+                .find(|c| c.name == "size")
+                .map(|c| (c.max_size.unwrap(), c.split_size.unwrap()))
+        };
+
+        // The region is too small to be checked with the global thresholds.
+        let mut region = Region::new();
+        region.set_id(1);
+        assert_eq!(size_thresholds(&region), None);
+
+        // But a region in the hot range is checked with smaller ones.
+        region.set_id(2);
+        region.set_start_key(b"0000".to_vec());
+        region.set_end_key(b"0020".to_vec());
+        assert_eq!(size_thresholds(&region), Some((100, 60)));
+    }
+
+    #[test]
+    fn test_initial_split_size() {
+        let path = TempDir::new("test-raftstore").unwrap();
+        let path_str = path.path().to_str().unwrap();
+        let db_opts = DBOptions::new();
+        let mut cf_opts = ColumnFamilyOptions::new();
+        let f = Box::new(RangePropertiesCollectorFactory::default());
+        cf_opts.add_table_properties_collector_factory("tikv.range-collector", f);
+        let cfs_opts = ALL_CFS
+            .iter()
+            .map(|cf| CFOptions::new(cf, cf_opts.clone()))
+            .collect();
+        let engine = new_engine_opt(path_str, db_opts, cfs_opts).unwrap();
+        let cf_handle = engine.cf_handle(CF_DEFAULT).unwrap();
+        // 20 rows of table 1.
+        for i in 0..20 {
+            let key = Key::from_raw(&table::encode_row_key(1, i));
+            let s = keys::data_key(key.encoded());
+            engine.put_cf(cf_handle, &s, b"v").unwrap();
+        }
+        engine.flush_cf(cf_handle, true).unwrap();
+
+        let (tx, _rx) = mpsc::sync_channel(100);
+        let ch = RetryableSendCh::new(tx, "test-split");
+        let mut cfg = Config::default();
+        cfg.split_region_on_table = false;
+        cfg.region_max_size = ReadableSize(10000);
+        cfg.region_split_size = Some(ReadableSize(6000));
+        cfg.region_initial_split_size = ReadableSize(60);
+        let coprocessor = CoprocessorHost::new(cfg, ch);
+
+        let size_thresholds = |region: &Region| {
+            let host = coprocessor.new_split_checker_host(
+                region,
+                &engine,
+                true,
+                SplitCheckContext::default(),
+            );
+            host.checkers()
+                .into_iter()
+                .find(|c| c.name == "size")
+                .map(|c| (c.max_size.unwrap(), c.split_size.unwrap()))
+        };
+        let table_prefix = |table_id| {
+            let key = table::encode_row_key(table_id, 0);
+            Key::from_raw(&key[..table::TABLE_PREFIX_KEY_LEN]).take_encoded()
+        };
+
+        // The region of the new table uses the initial split size.
+        let mut region = Region::new();
+        region.set_id(1);
+        region.set_start_key(table_prefix(1));
+        region.set_end_key(table_prefix(2));
+        assert_eq!(size_thresholds(&region), Some((100, 60)));
+
+        // The region split from it is too small for the steady split size.
+        region.set_id(2);
+        region.set_start_key(Key::from_raw(&table::encode_row_key(1, 5)).take_encoded());
+        assert_eq!(size_thresholds(&region), None);
+    }
+
+    #[test]
+    fn test_skip_ingesting_region() {
+        let path = TempDir::new("test-raftstore").unwrap();
+        let path_str = path.path().to_str().unwrap();
+        let db_opts = DBOptions::new();
+        let mut cf_opts = ColumnFamilyOptions::new();
+        let f = Box::new(RangePropertiesCollectorFactory::default());
+        cf_opts.add_table_properties_collector_factory("tikv.range-collector", f);
+        let cfs_opts = ALL_CFS
+            .iter()
+            .map(|cf| CFOptions::new(cf, cf_opts.clone()))
+            .collect();
+        let engine = new_engine_opt(path_str, db_opts, cfs_opts).unwrap();
+        let cf_handle = engine.cf_handle(CF_DEFAULT).unwrap();
+        // 20 kvs of 10 bytes.
+        for i in 0..20 {
+            let s = keys::data_key(format!("{:04}", i).as_bytes());
+            engine.put_cf(cf_handle, &s, &s).unwrap();
+        }
+        engine.flush_cf(cf_handle, true).unwrap();
+
+        let (tx, _rx) = mpsc::sync_channel(100);
+        let ch = RetryableSendCh::new(tx, "test-split");
+        let mut cfg = Config::default();
+        cfg.split_region_on_table = false;
+        cfg.region_max_size = ReadableSize(100);
+        cfg.region_split_size = Some(ReadableSize(60));
+        let coprocessor = CoprocessorHost::new(cfg, ch);
+
+        let mut region = Region::new();
+        region.set_id(1);
+        region.set_start_key(b"0000".to_vec());
+        region.set_end_key(b"0020".to_vec());
+        let size_checked = || {
+            let host = coprocessor.new_split_checker_host(
+                &region,
+                &engine,
+                true,
+                SplitCheckContext::default(),
+            );
+            host.checkers().into_iter().any(|c| c.name == "size")
+        };
+        assert!(size_checked());
+
+        // The range after the region.
+        coprocessor.register_ingest_range(b"0020", b"0030");
+        assert!(size_checked());
+        coprocessor.register_ingest_range(b"0010", b"0030");
+        assert!(!size_checked());
+        coprocessor.unregister_ingest_range(b"0010", b"0030");
+        assert!(size_checked());
+    }
+
+    #[test]
+    fn test_skip_deleted_ranges() {
+        let path = TempDir::new("test-raftstore").unwrap();
+        let path_str = path.path().to_str().unwrap();
+        let db_opts = DBOptions::new();
+        let mut cf_opts = ColumnFamilyOptions::new();
+        let f = Box::new(RangePropertiesCollectorFactory::default());
+        cf_opts.add_table_properties_collector_factory("tikv.range-collector", f);
+        let cfs_opts = ALL_CFS
+            .iter()
+            .map(|cf| CFOptions::new(cf, cf_opts.clone()))
+            .collect();
+        let engine = new_engine_opt(path_str, db_opts, cfs_opts).unwrap();
+        let cf_handle = engine.cf_handle(CF_DEFAULT).unwrap();
+        // 100 kvs of 100 bytes in two SST files, the sizes are recorded
+        // per file as the files are small.
+        for i in 0..100 {
+            let s = keys::data_key(format!("{:04}", i).as_bytes());
+            engine.put_cf(cf_handle, &s, &[0; 95]).unwrap();
+            if i == 89 {
+                engine.flush_cf(cf_handle, true).unwrap();
+            }
+        }
+        engine.flush_cf(cf_handle, true).unwrap();
+
+        let mut region = Region::new();
+        region.set_id(1);
+        let (tx, _rx) = mpsc::sync_channel(100);
+        let ch = RetryableSendCh::new(tx, "test-split");
+        let observer = SizeCheckObserver::new(5000, 3000, 0, 0, 0, false, ch);
+        let size_checked = || {
+            let mut ctx = ObserverContext::new(&region);
+            let mut host = Host::new(true);
+            observer.add_checker(&mut ctx, &mut host, &engine);
+            host.checkers().into_iter().any(|c| c.name == "size")
+        };
+        assert!(size_checked());
+
+        // Delete the first 90 kvs, they are still in the SST files.
+        let wb = WriteBatch::new();
+        let start = keys::data_key(b"0000");
+        let end = keys::data_key(b"0090");
+        wb.delete_range_cf(cf_handle, &start, &end).unwrap();
+        engine.write(wb).unwrap();
+        let region_size = get_region_approximate_size_cf(&engine, CF_DEFAULT, &region).unwrap();
+        assert!(region_size >= 5000, "{}", region_size);
+        assert!(!size_checked());
+    }
+
+    #[test]
+    fn test_flush_memtable() {
+        let path = TempDir::new("test-raftstore").unwrap();
+        let path_str = path.path().to_str().unwrap();
+        let db_opts = DBOptions::new();
+        let mut cf_opts = ColumnFamilyOptions::new();
+        let f = Box::new(RangePropertiesCollectorFactory::default());
+        cf_opts.add_table_properties_collector_factory("tikv.range-collector", f);
+        let cfs_opts = ALL_CFS
+            .iter()
+            .map(|cf| CFOptions::new(cf, cf_opts.clone()))
+            .collect();
+        let engine = new_engine_opt(path_str, db_opts, cfs_opts).unwrap();
+        // Only write to the memtable.
+        let cf_handle = engine.cf_handle(CF_DEFAULT).unwrap();
+        for i in 0..1000 {
+            let s = keys::data_key(format!("{:04}", i).as_bytes());
+            engine.put_cf(cf_handle, &s, &[0; 1024]).unwrap();
+        }
+
+        let mut region = Region::new();
+        region.set_id(1);
+        let (tx, rx) = mpsc::sync_channel(100);
+        let ch = RetryableSendCh::new(tx, "test-split");
+        let max_size = ReadableSize::kb(100).0;
+        let observer = SizeCheckObserver::new(max_size, max_size / 3 * 2, 0, 0, 0, false, ch)
+            .with_report_region_size(false)
+            .with_flush_memtable(true);
+
+        // The region is still splittable with the size in the memtable.
+        let mut ctx = ObserverContext::new(&region);
+        let mut host = Host::new(true);
+        observer.add_checker(&mut ctx, &mut host, &engine);
+        assert!(!host.skip());
+        match rx.try_recv() {
+            Ok(Msg::FlushRegion { region_id }) => assert_eq!(region_id, 1),
+            others => panic!("expect flush region, but got {:?}", others),
+        }
+
+        // No need to flush if the size in SST files is large enough.
+        engine.flush_cf(cf_handle, true).unwrap();
+        let mut host = Host::new(true);
+        observer.add_checker(&mut ctx, &mut host, &engine);
+        assert!(!host.skip());
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_not_report_region_size() {
+        let path = TempDir::new("test-raftstore").unwrap();
+        let path_str = path.path().to_str().unwrap();
+        let db_opts = DBOptions::new();
+        let mut cf_opts = ColumnFamilyOptions::new();
+        let f = Box::new(RangePropertiesCollectorFactory::default());
+        cf_opts.add_table_properties_collector_factory("tikv.range-collector", f);
+        let cfs_opts = ALL_CFS
+            .iter()
+            .map(|cf| CFOptions::new(cf, cf_opts.clone()))
+            .collect();
+        let engine = new_engine_opt(path_str, db_opts, cfs_opts).unwrap();
+        let cf_handle = engine.cf_handle(CF_DEFAULT).unwrap();
+        for i in 0..20 {
+            let s = keys::data_key(format!("{:04}", i).as_bytes());
+            engine.put_cf(cf_handle, &s, &s).unwrap();
+        }
+        engine.flush_cf(cf_handle, true).unwrap();
+
+        let mut region = Region::new();
+        region.set_id(1);
+        let (tx, rx) = mpsc::sync_channel(100);
+        let ch = RetryableSendCh::new(tx, "test-split");
+        let observer =
+            SizeCheckObserver::new(100, 60, 0, 0, 0, false, ch).with_report_region_size(false);
+
+        // The split check still works without reporting the size.
+        let mut ctx = ObserverContext::new(&region);
+        let mut host = Host::new(true);
+        observer.add_checker(&mut ctx, &mut host, &engine);
+        assert!(!host.skip());
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_report_cf_sizes() {
+        let path = TempDir::new("test-raftstore").unwrap();
+        let path_str = path.path().to_str().unwrap();
+        let db_opts = DBOptions::new();
+        let mut cf_opts = ColumnFamilyOptions::new();
+        let f = Box::new(RangePropertiesCollectorFactory::default());
+        cf_opts.add_table_properties_collector_factory("tikv.range-collector", f);
+        let cfs_opts = ALL_CFS
+            .iter()
+            .map(|cf| CFOptions::new(cf, cf_opts.clone()))
+            .collect();
+        let engine = new_engine_opt(path_str, db_opts, cfs_opts).unwrap();
+        for cf in &[CF_DEFAULT, CF_WRITE] {
+            let cf_handle = engine.cf_handle(cf).unwrap();
+            for i in 0..20 {
+                let s = keys::data_key(format!("{:04}", i).as_bytes());
+                engine.put_cf(cf_handle, &s, &s).unwrap();
+            }
+            engine.flush_cf(cf_handle, true).unwrap();
+        }
+
+        let mut region = Region::new();
+        region.set_id(1);
+        let (tx, rx) = mpsc::sync_channel(100);
+        let ch = RetryableSendCh::new(tx, "test-split");
+        let observer =
+            SizeCheckObserver::new(100, 60, 0, 0, 0, false, ch).with_report_cf_sizes(true);
+        let mut ctx = ObserverContext::new(&region);
+        let mut host = Host::new(true);
+        observer.add_checker(&mut ctx, &mut host, &engine);
+
+        let (mut region_size, mut cf_sizes) = (None, None);
+        loop {
+            match rx.try_recv() {
+                Ok(Msg::RegionApproximateSize { size, .. }) => region_size = Some(size),
+                Ok(Msg::RegionApproximateSizePerCf { sizes, .. }) => cf_sizes = Some(sizes),
+                Ok(_) => continue,
+                Err(_) => break,
+            }
+        }
+        let (region_size, cf_sizes) = (region_size.unwrap(), cf_sizes.unwrap());
+        assert!(cf_sizes[CF_DEFAULT] > 0);
+        assert!(cf_sizes[CF_WRITE] > 0);
+        // The sizes of CFs add up to the size of the region.
+        assert_eq!(cf_sizes.values().sum::<u64>(), region_size);
+    }
+
+    // Records the region sizes reported to raftstore.
+    #[derive(Default)]
+    struct SizeRecordObserver {
+        sizes: Arc<Mutex<Vec<(u64, u64)>>>,
+    }
+
+    impl Coprocessor for SizeRecordObserver {}
+
+    impl SplitCheckObserver for SizeRecordObserver {
+        fn add_checker(&self, _: &mut ObserverContext, _: &mut Host, _: &DB) {}
+
+        fn on_region_size_reported(&self, ctx: &ObserverContext, size: u64) {
+            let region_id = ctx.region().get_id();
+            self.sizes.lock().unwrap().push((region_id, size));
+        }
+    }
+
+    #[test]
+    fn test_region_size_reported() {
+        let path = TempDir::new("test-raftstore").unwrap();
+        let path_str = path.path().to_str().unwrap();
+        let db_opts = DBOptions::new();
+        let mut cf_opts = ColumnFamilyOptions::new();
+        let f = Box::new(RangePropertiesCollectorFactory::default());
+        cf_opts.add_table_properties_collector_factory("tikv.range-collector", f);
+        let cfs_opts = ALL_CFS
+            .iter()
+            .map(|cf| CFOptions::new(cf, cf_opts.clone()))
+            .collect();
+        let engine = new_engine_opt(path_str, db_opts, cfs_opts).unwrap();
+        let cf_handle = engine.cf_handle(CF_DEFAULT).unwrap();
+        for i in 0..20 {
+            let s = keys::data_key(format!("{:04}", i).as_bytes());
+            engine.put_cf(cf_handle, &s, &s).unwrap();
+        }
+        engine.flush_cf(cf_handle, true).unwrap();
+
+        let mut region = Region::new();
+        region.set_id(1);
+        let new_coprocessor = |cfg: Config| {
+            let (tx, rx) = mpsc::sync_channel(100);
+            let ch = RetryableSendCh::new(tx, "test-split");
+            let mut coprocessor = CoprocessorHost::new(cfg, ch);
+            let observer = SizeRecordObserver::default();
+            let sizes = Arc::clone(&observer.sizes);
+            coprocessor
+                .registry
+                .register_split_check_observer(1, Box::new(observer));
+            (coprocessor, rx, sizes)
+        };
+
+        let mut cfg = Config::default();
+        cfg.split_region_on_table = false;
+        let (coprocessor, rx, sizes) = new_coprocessor(cfg.clone());
+        coprocessor.new_split_checker_host(&region, &engine, true, SplitCheckContext::default());
+        let reported_size = loop {
+            match rx.try_recv() {
+                Ok(Msg::RegionApproximateSize { size, .. }) => break size,
+                Ok(_) => continue,
+                others => panic!("expect approximate size, but got {:?}", others),
+            }
+        };
+        assert_eq!(*sizes.lock().unwrap(), vec![(1, reported_size)]);
+
+        // Not called if the size is not reported.
+        cfg.report_region_size = false;
+        let (coprocessor, _rx, sizes) = new_coprocessor(cfg);
+        coprocessor.new_split_checker_host(&region, &engine, true, SplitCheckContext::default());
+        assert!(sizes.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_checker_estimated_splits() {
+        let region = Region::default();
+        let mut ctx = ObserverContext::new(&region);
+        let mut checker = Checker::new(150, 100, 0, None);
+        assert_eq!(checker.estimated_splits(), Some(0));
+        // 350 bytes, 3.5 times of the split size.
+        for i in 0..35 {
+            let entry = KeyEntry::new(format!("{:04}", i).into_bytes(), 0, 6, CF_WRITE);
+            checker.on_kv(&mut ctx, &entry);
+        }
+        assert_eq!(checker.estimated_splits(), Some(3));
+
+        // The size scanned by the previous checks counts.
+        let checker = Checker::new(150, 100, 0, None).with_current_size(250);
+        assert_eq!(checker.estimated_splits(), Some(2));
+    }
+
+    #[test]
+    fn test_checker_with_huge_first_entry() {
+        let region = Region::default();
+        let mut ctx = ObserverContext::new(&region);
+        let mut checker = Checker::new(100, 60, 0, None);
+        checker.on_kv(&mut ctx, &KeyEntry::new(b"0000".to_vec(), 0, 200, CF_WRITE));
+        assert!(checker.split_keys().is_empty());
+
+        // The next key can still be a split key.
+        let mut checker = Checker::new(100, 60, 0, None);
+        checker.on_kv(&mut ctx, &KeyEntry::new(b"0000".to_vec(), 0, 200, CF_WRITE));
+        checker.on_kv(&mut ctx, &KeyEntry::new(b"0001".to_vec(), 0, 200, CF_WRITE));
+        assert_eq!(checker.split_keys(), vec![b"0001".to_vec()]);
+    }
+
+    #[test]
+    fn test_checker_buckets() {
+        let region = Region::default();
+        let mut ctx = ObserverContext::new(&region);
+        let gen_entry = |i: u64| KeyEntry::new(format!("{:04}", i).into_bytes(), 0, 6, CF_WRITE);
+
+        // 100 entries of 10 bytes, 5 buckets every 100 bytes.
+        let mut checker = Checker::new(150, 100, 0, None).with_bucket_count(5);
+        for i in 0..100 {
+            checker.on_kv(&mut ctx, &gen_entry(i));
+        }
+        let buckets = checker.buckets();
+        let expected: Vec<_> = (1..50).map(|i| gen_entry(i * 2).key().to_vec()).collect();
+        assert_eq!(buckets, expected);
+
+        // No buckets by default.
+        let mut checker = Checker::new(150, 100, 0, None);
+        for i in 0..100 {
+            checker.on_kv(&mut ctx, &gen_entry(i));
+        }
+        assert!(checker.buckets().is_empty());
+    }
+
+    #[test]
+    fn test_checker_min_keys_before_split() {
+        let region = Region::default();
+        let mut ctx = ObserverContext::new(&region);
+        // A huge value followed by small ones.
+        let mut entries = vec![KeyEntry::new(b"0000".to_vec(), 0, 1000, CF_WRITE)];
+        for i in 1..20 {
+            let key = format!("{:04}", i).into_bytes();
+            entries.push(KeyEntry::new(key, 0, 6, CF_WRITE));
+        }
+
+        // Split right after the huge value.
+        let mut checker = Checker::new(200, 100, 0, None);
+        for e in &entries {
+            checker.on_kv(&mut ctx, e);
+        }
+        assert_eq!(checker.split_keys(), vec![b"0001".to_vec()]);
+
+        let mut checker = Checker::new(200, 100, 0, None).with_min_keys_before_split(4);
+        for e in &entries {
+            checker.on_kv(&mut ctx, e);
+        }
+        assert_eq!(checker.split_keys(), vec![b"0004".to_vec()]);
+    }
+
+    #[test]
+    fn test_checker_max_split_keys() {
+        let region = Region::default();
+        let mut ctx = ObserverContext::new(&region);
+        // 1000 bytes, far more than the split size.
+        let entries: Vec<_> = (0..100)
+            .map(|i| KeyEntry::new(format!("{:04}", i).into_bytes(), 0, 6, CF_WRITE))
+            .collect();
+
+        let mut checker = Checker::new(100, 60, 0, None);
+        for e in &entries {
+            checker.on_kv(&mut ctx, e);
+        }
+        assert_eq!(checker.split_keys().len(), 15);
+
+        // The scan stops once the segment after the last split key is too
+        // large to be merged back, the rest is left to the following checks.
+        let mut checker = Checker::new(100, 60, 0, None).with_max_split_keys(3);
+        let stopped = entries.iter().position(|e| checker.on_kv(&mut ctx, e));
+        assert_eq!(stopped, Some(22));
+        let expected: Vec<_> = (1..4)
+            .map(|i| format!("{:04}", i * 6).into_bytes())
+            .collect();
+        assert_eq!(checker.split_keys(), expected);
+
+        // Buckets need the whole region.
+        let mut checker = Checker::new(100, 60, 0, None)
+            .with_max_split_keys(3)
+            .with_bucket_count(2);
+        assert!(entries.iter().all(|e| !checker.on_kv(&mut ctx, e)));
+        assert_eq!(checker.split_keys(), expected);
+    }
+
+    #[test]
+    fn test_checker_count_value_only() {
+        let region = Region::default();
+        let mut ctx = ObserverContext::new(&region);
+        // Entries of 4 bytes key and 6 bytes value.
+        let entries: Vec<_> = (0..20)
+            .map(|i| KeyEntry::new(format!("{:04}", i).into_bytes(), 0, 6, CF_WRITE))
+            .collect();
+
+        let mut checker = Checker::new(150, 100, 0, None);
+        for e in &entries {
+            checker.on_kv(&mut ctx, e);
+        }
+        assert_eq!(checker.split_keys(), vec![b"0010".to_vec()]);
+
+        // Every entry only counts 6 bytes.
+        let mut checker = Checker::new(100, 60, 0, None).with_count_value_only(true);
+        for e in &entries {
+            checker.on_kv(&mut ctx, e);
+        }
+        assert_eq!(checker.split_keys(), vec![b"0010".to_vec()]);
+
+        let mut checker = Checker::new(150, 100, 0, None).with_count_value_only(true);
+        for e in &entries {
+            checker.on_kv(&mut ctx, e);
+        }
+        assert!(checker.split_keys().is_empty());
+    }
+
+    #[test]
+    fn test_checker_compression_ratios() {
+        let region = Region::default();
+        let mut ctx = ObserverContext::new(&region);
+        let entries: Vec<_> = (0..40)
+            .map(|i| KeyEntry::new(format!("{:04}", i).into_bytes(), 0, 6, CF_WRITE))
+            .collect();
+
+        let mut checker = Checker::new(150, 100, 0, None);
+        for e in &entries {
+            checker.on_kv(&mut ctx, e);
+        }
+        assert_eq!(
+            checker.split_keys(),
+            vec![b"0010".to_vec(), b"0020".to_vec(), b"0030".to_vec()]
+        );
+
+        // Twice the logical bytes are needed if the CF is compressed by half.
+        let mut ratios = HashMap::default();
+        ratios.insert(CF_WRITE, 0.5);
+        let mut checker = Checker::new(150, 100, 0, None).with_compression_ratios(ratios.clone());
+        for e in &entries {
+            checker.on_kv(&mut ctx, e);
+        }
+        assert_eq!(checker.split_keys(), vec![b"0020".to_vec()]);
+
+        // Other CFs are not scaled.
+        let mut checker = Checker::new(150, 100, 0, None).with_compression_ratios(ratios);
+        for e in &entries {
+            let e = KeyEntry::new(e.key().to_vec(), 0, 6, CF_DEFAULT);
+            checker.on_kv(&mut ctx, &e);
+        }
+        assert_eq!(checker.split_keys().len(), 3);
+    }
+
+    #[test]
+    fn test_host_checkers() {
+        let mut host = Host::new(true);
+        assert!(host.checkers().is_empty());
+        host.add_checker(Box::new(Checker::new(150, 100, 0, None)));
+        let infos = host.checkers();
+        assert_eq!(infos.len(), 1);
+        assert_eq!(infos[0].name, "size");
+        assert_eq!(infos[0].max_size, Some(150));
+        assert_eq!(infos[0].split_size, Some(100));
+        assert_eq!(infos[0].max_keys, None);
+    }
+
+    #[test]
+    fn test_checker_with_same_max_and_split_size() {
+        let region = Region::default();
+        let mut ctx = ObserverContext::new(&region);
+        for max_split_keys in 0..2 {
+            let mut checker = Checker::new(24, 24, 0, None).with_max_split_keys(max_split_keys);
+            // Entries of 8 bytes, the first 3 ones reach but don't exceed 24.
+            for i in 0..3 {
+                let data = KeyEntry::new(format!("{:04}", i).into_bytes(), 0, 4, CF_WRITE);
+                assert!(!checker.on_kv(&mut ctx, &data));
+            }
+            assert_eq!(checker.pending_split_key(), None);
+            assert_eq!(checker.current_size(), 24);
+
+            // The 4th entry crosses both the split size and the max size, it's
+            // the split key and starts the next segment. The scan stops at it
+            // if only one split key is needed.
+            let data = KeyEntry::new(b"0003".to_vec(), 0, 4, CF_WRITE);
+            assert_eq!(checker.on_kv(&mut ctx, &data), max_split_keys == 1);
+            assert_eq!(checker.pending_split_key(), Some(&b"0003"[..]));
+            assert_eq!(checker.current_size(), 8);
+            // The tail is kept although it's much smaller than the max size.
+            assert_eq!(checker.split_key(), Some(b"0003".to_vec()));
+        }
+    }
+
+    #[test]
+    fn test_checker_current_size() {
+        let mut checker = Checker::new(150, 100, 0, None);
+        let region = Region::default();
+        let mut ctx = ObserverContext::new(&region);
+        let mut split_keys = 0;
+        for i in 0..100 {
+            let key = format!("{:04}", i).into_bytes();
+            let last_size = checker.current_size();
+            checker.on_kv(&mut ctx, &KeyEntry::new(key.clone(), 0, 6, CF_WRITE));
+            // The size grows with every entry, until the entry becomes a
+            // split key and starts a new segment.
+            if checker.pending_split_key() == Some(key.as_slice()) {
+                assert!(last_size + 10 > 100, "{} {}", i, last_size);
+                assert_eq!(checker.current_size(), 10);
+                split_keys += 1;
+            } else {
+                assert_eq!(checker.current_size(), last_size + 10);
+                assert!(checker.current_size() <= 100);
+            }
+        }
+        // 1000 bytes, a split key every 100 bytes.
+        assert_eq!(split_keys, 9);
+    }
+
+    #[test]
+    fn test_checker_with_key_prefix() {
+        let region = Region::default();
+        let mut ctx = ObserverContext::new(&region);
+        let mut entries = vec![];
+        for i in 0..10 {
+            let row = format!("row_{:02}", i).into_bytes();
+            // Newer versions come first.
+            for ts in (1..4).rev() {
+                let key = keys::data_key(Key::from_raw(&row).append_ts(ts).encoded());
+                entries.push(KeyEntry::new(key, 0, 10, CF_WRITE));
+            }
+        }
+        let entry_size = entries[0].entry_size() as u64;
+
+        // Without the prefix, a row can be split into two regions.
+        let mut checker = Checker::new(entry_size * 6, entry_size * 4, 0, None);
+        for e in &entries {
+            checker.on_kv(&mut ctx, e);
+        }
+        let split_keys = checker.split_keys();
+        assert!(split_keys.iter().any(|k| Key::decode_ts_from(k).unwrap() != 3));
+
+        let mut checker = Checker::new(
+            entry_size * 6,
+            entry_size * 4,
+            0,
+            Some(Box::new(user_key_prefix)),
+        );
+        for e in &entries {
+            checker.on_kv(&mut ctx, e);
+        }
+        let split_keys = checker.split_keys();
+        assert!(!split_keys.is_empty());
+        for k in split_keys {
+            // Always split before the newest version of a row.
+            assert_eq!(Key::decode_ts_from(&k).unwrap(), 3);
+        }
+    }
+
+    #[test]
+    fn test_checker_split_keys() {
+        let region = Region::default();
+        let mut ctx = ObserverContext::new(&region);
+        let gen_entry = |i: u64| KeyEntry::new(format!("{:04}", i).into_bytes(), 0, 6, CF_WRITE);
+
+        // 26 entries of 10 bytes, split every 100 bytes.
+        let mut checker = Checker::new(150, 100, 0, None);
+        for i in 0..26 {
+            checker.on_kv(&mut ctx, &gen_entry(i));
+        }
+        assert_eq!(checker.split_keys(), vec![b"0010".to_vec(), b"0020".to_vec()]);
+
+        // The tail of 4 entries is too small to be a region.
+        let mut checker = Checker::new(150, 100, 0, None);
+        for i in 0..24 {
+            checker.on_kv(&mut ctx, &gen_entry(i));
+        }
+        assert_eq!(checker.split_keys(), vec![b"0010".to_vec()]);
+
+        // Not exceed the max size.
+        let mut checker = Checker::new(150, 100, 0, None);
+        for i in 0..15 {
+            checker.on_kv(&mut ctx, &gen_entry(i));
+        }
+        assert!(checker.split_keys().is_empty());
+    }
+
+    #[test]
+    fn test_checker_with_max_cf_size() {
+        let region = Region::default();
+        let mut ctx = ObserverContext::new(&region);
+        let gen_entry = |i: u64| KeyEntry::new(format!("{:04}", i).into_bytes(), 0, 6, CF_WRITE);
+
+        // 20 entries of 10 bytes in write cf.
+        let mut checker = Checker::new(300, 200, 0, None);
+        for i in 0..20 {
+            checker.on_kv(&mut ctx, &gen_entry(i));
+            let e = KeyEntry::new(format!("{:04}", i).into_bytes(), 0, 0, CF_DEFAULT);
+            checker.on_kv(&mut ctx, &e);
+        }
+        assert!(checker.split_keys().is_empty());
+
+        // The write cf will be split every 100 bytes.
+        let mut checker = Checker::new(300, 200, 0, None).with_max_cf_size(150);
+        for i in 0..20 {
+            checker.on_kv(&mut ctx, &gen_entry(i));
+            let e = KeyEntry::new(format!("{:04}", i).into_bytes(), 0, 0, CF_DEFAULT);
+            checker.on_kv(&mut ctx, &e);
+        }
+        assert_eq!(checker.split_keys(), vec![b"0010".to_vec()]);
+    }
+
+    #[test]
+    fn test_checker_with_min_split_region_size() {
+        let region = Region::default();
+        let mut ctx = ObserverContext::new(&region);
+        let gen_entry = |i: u64| KeyEntry::new(format!("{:04}", i).into_bytes(), 0, 6, CF_WRITE);
+
+        // The max size is barely exceeded, the tail of 6 entries is kept.
+        let mut checker = Checker::new(150, 100, 0, None);
+        for i in 0..16 {
+            checker.on_kv(&mut ctx, &gen_entry(i));
+        }
+        assert_eq!(checker.split_keys(), vec![b"0010".to_vec()]);
+
+        // The tail is smaller than the min split size.
+        let mut checker = Checker::new(150, 100, 80, None);
+        for i in 0..16 {
+            checker.on_kv(&mut ctx, &gen_entry(i));
+        }
+        assert!(checker.split_keys().is_empty());
+
+        // Only the tiny tail is discarded.
+        let mut checker = Checker::new(150, 100, 80, None);
+        for i in 0..26 {
+            checker.on_kv(&mut ctx, &gen_entry(i));
+        }
+        assert_eq!(checker.split_keys(), vec![b"0010".to_vec()]);
+    }
+
+    #[test]
+    fn test_checker_with_protected_ranges() {
+        let region = Region::default();
+        let mut ctx = ObserverContext::new(&region);
+        let gen_entry = |i: u64| KeyEntry::new(format!("{:04}", i).into_bytes(), 0, 6, CF_WRITE);
+        let check = |ranges: Vec<(&[u8], &[u8])>| {
+            let ranges = ranges
+                .into_iter()
+                .map(|(s, e)| (s.to_vec(), e.to_vec()))
+                .collect();
+            let mut checker = Checker::new(150, 100, 0, None).with_protected_ranges(ranges);
+            for i in 0..16 {
+                checker.on_kv(&mut ctx, &gen_entry(i));
+            }
+            checker.split_keys()
+        };
+
+        // The split is deferred to the end of the range.
+        assert_eq!(check(vec![(b"0008", b"0012")]), vec![b"0012".to_vec()]);
+        // A region can start at the start key of the range.
+        assert_eq!(check(vec![(b"0010", b"0012")]), vec![b"0010".to_vec()]);
+        // Ranges not covering the split key don't matter.
+        assert_eq!(check(vec![(b"0001", b"0003")]), vec![b"0010".to_vec()]);
+        // Never split if the range covers the rest of the region.
+        assert!(check(vec![(b"0008", b"")]).is_empty());
+    }
+
+    #[test]
+    fn test_retry_transient() {
+        let run = |results: Vec<StdResult<u64, ApproximateSizeError>>| {
+            let mut results = results.into_iter();
+            let mut calls = 0;
+            let res = retry_transient(1, || {
+                calls += 1;
+                results.next().unwrap()
+            });
+            (res, calls)
+        };
+        let io_err = || Err(ApproximateSizeError::Io("disk hiccup".to_owned()));
+
+        // Transient errors are retried once.
+        let (res, calls) = run(vec![io_err(), Ok(10)]);
+        assert_eq!((res.unwrap(), calls), (10, 2));
+        let (res, calls) = run(vec![io_err(), io_err()]);
+        assert!(res.is_err());
+        assert_eq!(calls, 2);
 
-        assert!(checker.split_key().is_some());
+        // The region is scanned without retry.
+        let (res, calls) = run(vec![Err(ApproximateSizeError::NoProperties)]);
+        assert!(res.is_err());
+        assert_eq!(calls, 1);
+        let (res, calls) = run(vec![Err(ApproximateSizeError::Decode(
+            codec::Error::KeyLength,
+        ))]);
+        assert!(res.is_err());
+        assert_eq!(calls, 1);
     }
 }
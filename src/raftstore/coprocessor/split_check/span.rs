@@ -0,0 +1,217 @@
+// Copyright 2018 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::cmp;
+use std::mem;
+
+use raftstore::store::keys;
+use rocksdb::DB;
+
+use super::super::{
+    CheckerInfo, Coprocessor, KeyEntry, ObserverContext, SplitCheckObserver, SplitChecker,
+};
+use super::Host;
+
+// Keys are compared by the number made of their first 8 bytes.
+pub const SPAN_KEY_WIDTH: usize = 8;
+
+/// Gets the big-endian number of the first `width` bytes of the key, the
+/// key is padded with 0 if it's shorter.
+pub fn key_to_number(key: &[u8], width: usize) -> u64 {
+    let width = cmp::min(width, SPAN_KEY_WIDTH);
+    let mut number = 0;
+    for i in 0..width {
+        number = (number << 8) | u64::from(key.get(i).cloned().unwrap_or(0));
+    }
+    number
+}
+
+/// Gets the key of the first `width` bytes of the big-endian `number`.
+pub fn number_to_key(number: u64, width: usize) -> Vec<u8> {
+    (0..width)
+        .map(|i| (number >> ((width - 1 - i) * 8)) as u8)
+        .collect()
+}
+
+/// Splits the region once its keys span more than `max_span`, so a sparse
+/// region doesn't cover a huge key range. Keys are compared as fixed-width
+/// numbers, which suits binary keys like encoded integers.
+pub struct Checker {
+    width: usize,
+    max_span: u64,
+    // The number of the first key of the current segment.
+    segment_start: Option<u64>,
+    first_key: bool,
+    split_keys: Vec<Vec<u8>>,
+}
+
+impl Checker {
+    /// `start_key` is the start key of the region, an empty one means the
+    /// span starts from the first scanned key.
+    pub fn new(start_key: &[u8], width: usize, max_span: u64) -> Checker {
+        let segment_start = if start_key.is_empty() {
+            None
+        } else {
+            Some(key_to_number(start_key, width))
+        };
+        Checker {
+            width,
+            max_span,
+            segment_start,
+            first_key: true,
+            split_keys: vec![],
+        }
+    }
+}
+
+impl SplitChecker for Checker {
+    fn on_kv(&mut self, _: &mut ObserverContext, entry: &KeyEntry) -> bool {
+        let number = key_to_number(keys::origin_key(entry.key()), self.width);
+        match self.segment_start {
+            // Never split at the first key, otherwise the left region would
+            // be empty.
+            Some(start) if !self.first_key && number.saturating_sub(start) > self.max_span => {
+                self.split_keys.push(entry.key().to_vec());
+                self.segment_start = Some(number);
+            }
+            Some(_) => {}
+            None => self.segment_start = Some(number),
+        }
+        self.first_key = false;
+        false
+    }
+
+    fn split_key(&mut self) -> Option<Vec<u8>> {
+        self.split_keys().into_iter().next()
+    }
+
+    fn split_keys(&mut self) -> Vec<Vec<u8>> {
+        mem::replace(&mut self.split_keys, vec![])
+    }
+
+    fn describe(&self) -> CheckerInfo {
+        CheckerInfo::new("span")
+    }
+}
+
+pub struct SpanCheckObserver {
+    max_span: u64,
+}
+
+impl SpanCheckObserver {
+    pub fn new(max_span: u64) -> SpanCheckObserver {
+        SpanCheckObserver { max_span }
+    }
+}
+
+impl Coprocessor for SpanCheckObserver {}
+
+impl SplitCheckObserver for SpanCheckObserver {
+    fn add_checker(&self, ctx: &mut ObserverContext, host: &mut Host, _: &DB) {
+        let region = ctx.region();
+        let (start_key, end_key) = (region.get_start_key(), region.get_end_key());
+        // An empty end key means the end of the key space.
+        if !end_key.is_empty() {
+            let span = key_to_number(end_key, SPAN_KEY_WIDTH)
+                .saturating_sub(key_to_number(start_key, SPAN_KEY_WIDTH));
+            if span <= self.max_span {
+                // Region is narrow enough, skip for saving IO.
+                return;
+            }
+        }
+        host.add_checker(Box::new(Checker::new(
+            start_key,
+            SPAN_KEY_WIDTH,
+            self.max_span,
+        )));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use kvproto::metapb::Region;
+    use tempdir::TempDir;
+
+    use storage::{ALL_CFS, CF_WRITE};
+    use util::rocksdb::new_engine;
+
+    use super::*;
+
+    fn number_key(n: u64) -> Vec<u8> {
+        (0..8).map(|i| (n >> (56 - i * 8)) as u8).collect()
+    }
+
+    fn check(checker: &mut Checker, numbers: &[u64]) -> Vec<Vec<u8>> {
+        let region = Region::default();
+        let mut ctx = ObserverContext::new(&region);
+        for n in numbers {
+            let entry = KeyEntry::new(keys::data_key(&number_key(*n)), 0, 10, CF_WRITE);
+            checker.on_kv(&mut ctx, &entry);
+        }
+        checker.split_keys()
+    }
+
+    #[test]
+    fn test_key_to_number() {
+        assert_eq!(key_to_number(b"", 8), 0);
+        assert_eq!(key_to_number(&number_key(1 << 40), 8), 1 << 40);
+        // Short keys are padded.
+        assert_eq!(key_to_number(&[1], 8), 1 << 56);
+        // Only the first bytes count.
+        assert_eq!(key_to_number(&[0, 1, 2], 2), 1);
+    }
+
+    #[test]
+    fn test_span_checker() {
+        // Dense keys are not split.
+        let mut checker = Checker::new(b"", 8, 1000);
+        let numbers: Vec<_> = (0..100).collect();
+        assert!(check(&mut checker, &numbers).is_empty());
+
+        // Split once the span from the start of the segment exceeds 1000.
+        let mut checker = Checker::new(b"", 8, 1000);
+        let numbers = [0, 10, 500, 1000, 1001, 1500, 2000, 2002, 5000];
+        let expected: Vec<_> = [1001, 2002, 5000]
+            .iter()
+            .map(|n| keys::data_key(&number_key(*n)))
+            .collect();
+        assert_eq!(check(&mut checker, &numbers), expected);
+
+        // The span starts from the start key of the region.
+        let mut checker = Checker::new(&number_key(100), 8, 1000);
+        let expected = vec![keys::data_key(&number_key(1101))];
+        assert_eq!(check(&mut checker, &[1100, 1101]), expected);
+    }
+
+    #[test]
+    fn test_span_check_observer() {
+        let path = TempDir::new("test-span-check-observer").unwrap();
+        let engine = new_engine(path.path().to_str().unwrap(), ALL_CFS, None).unwrap();
+        let observer = SpanCheckObserver::new(1000);
+        let check = |start_key: &[u8], end_key: &[u8]| {
+            let mut region = Region::default();
+            region.set_start_key(start_key.to_vec());
+            region.set_end_key(end_key.to_vec());
+            let mut ctx = ObserverContext::new(&region);
+            let mut host = Host::new(true);
+            observer.add_checker(&mut ctx, &mut host, &engine);
+            host.skip()
+        };
+
+        // The narrow region is skipped.
+        assert!(check(&number_key(100), &number_key(1100)));
+        assert!(!check(&number_key(100), &number_key(1101)));
+        assert!(!check(&number_key(100), b""));
+        assert!(!check(b"", &number_key(1001)));
+    }
+}
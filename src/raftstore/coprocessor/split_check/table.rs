@@ -24,7 +24,8 @@ use storage::CF_WRITE;
 use util::escape;
 
 use super::super::{
-    Coprocessor, KeyEntry, ObserverContext, Result, SplitCheckObserver, SplitChecker,
+    CheckerInfo, Coprocessor, KeyEntry, ObserverContext, Result, SplitCheckObserver,
+    SplitChecker,
 };
 use super::Host;
 
@@ -69,16 +70,95 @@ impl SplitChecker for Checker {
         let key = self.split_key.take()?;
         Some(keys::data_key(&key))
     }
+
+    fn describe(&self) -> CheckerInfo {
+        CheckerInfo::new("table")
+    }
+}
+
+/// Finds the first key where the record or index prefix changes, i.e. the
+/// table id or the index id changes.
+pub struct IndexChecker {
+    last_prefix: Option<Vec<u8>>,
+    // Size of the scanned keys before the current prefix.
+    current_size: u64,
+    min_split_size: u64,
+    split_key: Option<Vec<u8>>,
+}
+
+impl IndexChecker {
+    pub fn new(start_key: &[u8], min_split_size: u64) -> IndexChecker {
+        IndexChecker {
+            last_prefix: to_raw_index_prefix(start_key),
+            current_size: 0,
+            min_split_size,
+            split_key: None,
+        }
+    }
+}
+
+impl SplitChecker for IndexChecker {
+    fn on_kv(&mut self, _: &mut ObserverContext, entry: &KeyEntry) -> bool {
+        if self.split_key.is_some() {
+            return true;
+        }
+
+        let prefix = to_raw_index_prefix(keys::origin_key(entry.key()));
+        if prefix.is_some() && prefix != self.last_prefix {
+            // Don't split out a region that is too small, the keys before
+            // are merged into the region of the next prefix.
+            if self.current_size >= self.min_split_size {
+                self.split_key = prefix.as_ref().map(|p| Key::from_raw(p).take_encoded());
+            }
+            self.last_prefix = prefix;
+        }
+        self.current_size += entry.entry_size() as u64;
+        self.split_key.is_some()
+    }
+
+    fn split_key(&mut self) -> Option<Vec<u8>> {
+        let key = self.split_key.take()?;
+        Some(keys::data_key(&key))
+    }
+
+    fn describe(&self) -> CheckerInfo {
+        CheckerInfo::new("index")
+    }
 }
 
 #[derive(Default)]
-pub struct TableCheckObserver;
+pub struct TableCheckObserver {
+    split_on_index: bool,
+    min_split_size: u64,
+}
+
+impl TableCheckObserver {
+    /// Also split the region where the index changes if `split_on_index`,
+    /// unless the region before is smaller than `min_split_size`.
+    pub fn new(split_on_index: bool, min_split_size: u64) -> TableCheckObserver {
+        TableCheckObserver {
+            split_on_index,
+            min_split_size,
+        }
+    }
+}
 
 impl Coprocessor for TableCheckObserver {}
 
 impl SplitCheckObserver for TableCheckObserver {
     fn add_checker(&self, ctx: &mut ObserverContext, host: &mut Host, engine: &DB) {
         let region = ctx.region();
+        if self.split_on_index {
+            let start_prefix = to_raw_index_prefix(region.get_start_key());
+            if start_prefix.is_some() && start_prefix == to_raw_index_prefix(region.get_end_key())
+            {
+                // Region is inside a record or index range, skip for saving IO.
+                return;
+            }
+            let checker = IndexChecker::new(region.get_start_key(), self.min_split_size);
+            host.add_checker(Box::new(checker));
+            return;
+        }
         if is_same_table(region.get_start_key(), region.get_end_key()) {
             // Region is inside a table, skip for saving IO.
             return;
@@ -160,6 +240,15 @@ impl SplitCheckObserver for TableCheckObserver {
     }
 }
 
+/// Whether the region covers a whole table, i.e. it starts at the prefix of
+/// a table and hasn't been split since the table was split out.
+pub fn is_whole_table_region(region: &Region) -> bool {
+    let start_key = region.get_start_key();
+    is_table_key(start_key)
+        && to_encoded_table_prefix(start_key).map_or(false, |prefix| prefix == start_key)
+        && !is_same_table(start_key, region.get_end_key())
+}
+
 fn last_key_of_region(db: &DB, region: &Region) -> Result<Option<Vec<u8>>> {
     let start_key = keys::enc_start_key(region);
     let end_key = keys::enc_end_key(region);
@@ -190,6 +279,29 @@ fn to_encoded_table_prefix(encoded_key: &[u8]) -> Option<Vec<u8>> {
     }
 }
 
+/// Get the raw record prefix `t{table_id}_r` or index prefix
+/// `t{table_id}_i{index_id}` of the encoded key.
+fn to_raw_index_prefix(encoded_key: &[u8]) -> Option<Vec<u8>> {
+    let raw_key = match Key::from_encoded_slice(encoded_key).raw() {
+        Ok(raw_key) => raw_key,
+        Err(_) => return None,
+    };
+    if !raw_key.starts_with(table_codec::TABLE_PREFIX) || raw_key.len() < table_codec::PREFIX_LEN
+    {
+        return None;
+    }
+    let sep = &raw_key[table_codec::TABLE_PREFIX_KEY_LEN..table_codec::PREFIX_LEN];
+    if sep == table_codec::RECORD_PREFIX_SEP {
+        Some(raw_key[..table_codec::PREFIX_LEN].to_vec())
+    } else if sep == table_codec::INDEX_PREFIX_SEP
+        && raw_key.len() >= table_codec::PREFIX_LEN + table_codec::ID_LEN
+    {
+        Some(raw_key[..table_codec::PREFIX_LEN + table_codec::ID_LEN].to_vec())
+    } else {
+        None
+    }
+}
+
 // Encode a key like `t{i64}` will append some unnecessary bytes to the output,
 // The first 10 bytes are enough to find out which table this key belongs to.
 const ENCODED_TABLE_TABLE_PREFIX: usize = table_codec::TABLE_PREFIX_KEY_LEN + 1;
@@ -312,7 +424,7 @@ mod test {
 
         // Try to "disable" size split.
         cfg.region_max_size = ReadableSize::gb(2);
-        cfg.region_split_size = ReadableSize::gb(1);
+        cfg.region_split_size = Some(ReadableSize::gb(1));
         // Try to "disable" keys split
         cfg.region_max_keys = 2000000000;
         cfg.region_split_keys = 1000000000;
@@ -327,17 +439,28 @@ mod test {
                 region.set_start_key(encoded_start_key.unwrap_or_else(Vec::new));
                 region.set_end_key(encoded_end_key.unwrap_or_else(Vec::new));
                 runnable.run(SplitCheckTask::new(region.clone(), true, CheckPolicy::SCAN));
+                let mut res = rx.try_recv();
+                // Skip the statistics of the scan.
+                loop {
+                    match res {
+                        Ok(Msg::SplitCheckResult { .. })
+                        | Ok(Msg::RegionMaxEntry { .. })
+                        | Ok(Msg::SplitCheckCfStats { .. }) => {}
+                        _ => break,
+                    }
+                    res = rx.try_recv();
+                }
 
                 if let Some(id) = table_id {
                     let key = Key::from_raw(&gen_table_prefix(id));
-                    match rx.try_recv() {
-                        Ok(Msg::SplitRegion { split_key, .. }) => {
-                            assert_eq!(&split_key, key.encoded());
+                    match res {
+                        Ok(Msg::SplitRegion { split_keys, .. }) => {
+                            assert_eq!(split_keys, vec![key.encoded().clone()]);
                         }
                         others => panic!("expect {:?}, but got {:?}", key, others),
                     }
                 } else {
-                    match rx.try_recv() {
+                    match res {
                         Err(mpsc::TryRecvError::Empty) => (),
                         others => panic!("expect empty, but got {:?}", others),
                     }
@@ -435,4 +558,54 @@ mod test {
             (Some(gen_encoded_table_prefix(1)), None, Some(3)),
         ]);
     }
+
+    #[test]
+    fn test_index_checker() {
+        let gen_record_key = |table_id, handle| {
+            let key = table_codec::encode_row_key(table_id, handle);
+            keys::data_key(Key::from_raw(&key).append_ts(1).encoded())
+        };
+        let gen_index_key = |table_id, index_id| {
+            let key = table_codec::encode_index_seek_key(table_id, index_id, b"abc");
+            keys::data_key(Key::from_raw(&key).append_ts(1).encoded())
+        };
+        // t1_r, t1_i1, t1_i2, t2_r
+        let mut entries = vec![];
+        for handle in 0..5 {
+            entries.push(gen_record_key(1, handle));
+        }
+        entries.push(gen_index_key(1, 1));
+        entries.push(gen_index_key(1, 2));
+        entries.push(gen_record_key(2, 0));
+        let entries: Vec<_> = entries
+            .into_iter()
+            .map(|k| KeyEntry::new(k, 0, 10, CF_WRITE))
+            .collect();
+        let prefix_of = |key: &[u8]| {
+            let prefix = to_raw_index_prefix(keys::origin_key(key)).unwrap();
+            keys::data_key(Key::from_raw(&prefix).encoded())
+        };
+
+        let region = Region::new();
+        let mut ctx = ObserverContext::new(&region);
+        let mut check = |start_key: &[u8], min_split_size| {
+            let mut checker = IndexChecker::new(start_key, min_split_size);
+            for e in &entries {
+                if checker.on_kv(&mut ctx, e) {
+                    break;
+                }
+            }
+            checker.split_key()
+        };
+
+        // Starts from the first record.
+        let start_key = keys::origin_key(entries[0].key()).to_vec();
+        assert_eq!(check(&start_key, 0), Some(prefix_of(entries[5].key())));
+        // The records are too small to be a region.
+        let size = entries[0].entry_size() as u64;
+        assert_eq!(check(&start_key, size * 5 + 1), Some(prefix_of(entries[6].key())));
+        // Starts from a non-table key.
+        assert_eq!(check(b"", 0), Some(prefix_of(entries[0].key())));
+        assert_eq!(check(b"", size * 100), None);
+    }
 }
@@ -0,0 +1,160 @@
+// Copyright 2018 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use raftstore::store::util::TSO_PHYSICAL_SHIFT_BITS;
+use storage::types::Key;
+use storage::{CF_DEFAULT, CF_WRITE};
+
+use super::super::{CheckerInfo, KeyEntry, ObserverContext, SplitChecker};
+
+/// Gets the weight of an entry by the timestamp in its key.
+pub type WeightFn = Box<Fn(u64) -> f64>;
+
+/// Gets a weight from 1 to 2, which is halved towards 1 every
+/// `half_life_secs` by the age of the timestamp relative to `now_ts`.
+pub fn decay_weight(now_ts: u64, half_life_secs: u64) -> WeightFn {
+    let now_ms = now_ts >> TSO_PHYSICAL_SHIFT_BITS;
+    let half_life_ms = (half_life_secs * 1000) as f64;
+    Box::new(move |ts| {
+        let age_ms = now_ms.saturating_sub(ts >> TSO_PHYSICAL_SHIFT_BITS) as f64;
+        1.0 + (-age_ms / half_life_ms).exp2()
+    })
+}
+
+/// Experimental. Splits the region by size like `size::Checker`, but the
+/// size of each entry is scaled by the weight of its timestamp, which is
+/// the commit ts in the write CF. When recent writes weigh more, the split
+/// point moves towards them, so the new regions share the future writes
+/// more evenly.
+pub struct Checker {
+    max_size: u64,
+    split_size: u64,
+    weight_fn: WeightFn,
+    current_size: f64,
+    split_key: Option<Vec<u8>>,
+}
+
+impl Checker {
+    pub fn new(max_size: u64, split_size: u64, weight_fn: WeightFn) -> Checker {
+        Checker {
+            max_size,
+            split_size,
+            weight_fn,
+            current_size: 0.0,
+            split_key: None,
+        }
+    }
+
+    fn weight(&self, entry: &KeyEntry) -> f64 {
+        // Only the keys of these CFs have timestamps.
+        if entry.cf() != CF_WRITE && entry.cf() != CF_DEFAULT {
+            return 1.0;
+        }
+        match Key::split_on_ts_for(entry.key()) {
+            Ok((_, ts)) => (self.weight_fn)(ts),
+            Err(_) => 1.0,
+        }
+    }
+}
+
+impl SplitChecker for Checker {
+    fn on_kv(&mut self, _: &mut ObserverContext, entry: &KeyEntry) -> bool {
+        let size = entry.entry_size() as f64 * self.weight(entry);
+        self.current_size += size;
+        // Never split at the first key, otherwise the left region would be
+        // empty.
+        if self.split_key.is_none()
+            && self.current_size > self.split_size as f64
+            && self.current_size > size
+        {
+            self.split_key = Some(entry.key().to_vec());
+        }
+        self.current_size > self.max_size as f64
+    }
+
+    fn split_key(&mut self) -> Option<Vec<u8>> {
+        if self.current_size > self.max_size as f64 {
+            self.split_key.take()
+        } else {
+            None
+        }
+    }
+
+    fn describe(&self) -> CheckerInfo {
+        CheckerInfo {
+            max_size: Some(self.max_size),
+            split_size: Some(self.split_size),
+            ..CheckerInfo::new("weighted")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use kvproto::metapb::Region;
+
+    use raftstore::store::keys;
+
+    use super::*;
+
+    fn version_key(i: u64, ts: u64) -> Vec<u8> {
+        keys::data_key(
+            Key::from_raw(format!("k{:04}", i).as_bytes())
+                .append_ts(ts)
+                .encoded(),
+        )
+    }
+
+    // Keys before `recent` are written at ts 1, the others at ts 100.
+    fn check(weight_fn: WeightFn, recent: u64) -> Option<Vec<u8>> {
+        let region = Region::default();
+        let mut ctx = ObserverContext::new(&region);
+        let mut checker = Checker::new(2500, 2000, weight_fn);
+        for i in 0..100 {
+            let ts = if i < recent { 1 } else { 100 };
+            let entry = KeyEntry::new(version_key(i, ts), 0, 12, CF_WRITE);
+            if checker.on_kv(&mut ctx, &entry) {
+                break;
+            }
+        }
+        checker.split_key()
+    }
+
+    #[test]
+    fn test_weighted_checker() {
+        // Every entry is 30 bytes, the unweighted split is at the 67th key.
+        let unweighted = check(Box::new(|_| 1.0), 0);
+        assert_eq!(unweighted, Some(version_key(66, 100)));
+        // The recent keys at the front weigh more, the split moves earlier.
+        let weighted = check(Box::new(|ts| if ts >= 100 { 2.0 } else { 1.0 }), 0);
+        assert_eq!(weighted, Some(version_key(33, 100)));
+        // The recent keys are at the back, the split point is the same as
+        // unweighted as it's still among the old keys.
+        let weighted = check(Box::new(|ts| if ts >= 100 { 2.0 } else { 1.0 }), 80);
+        assert_eq!(weighted, Some(version_key(66, 1)));
+        // It's not split if the weighted size doesn't exceed the max size.
+        assert_eq!(check(Box::new(|_| 0.5), 0), None);
+    }
+
+    #[test]
+    fn test_decay_weight() {
+        let ts = |secs: u64| (secs * 1000) << TSO_PHYSICAL_SHIFT_BITS;
+        let weight = decay_weight(ts(1000), 100);
+        assert!((weight(ts(1000)) - 2.0).abs() < 1e-9);
+        assert!((weight(ts(900)) - 1.5).abs() < 1e-9);
+        assert!((weight(ts(800)) - 1.25).abs() < 1e-9);
+        // A timestamp in the future is as recent as now.
+        assert!((weight(ts(1100)) - 2.0).abs() < 1e-9);
+        assert!(weight(ts(0)) < 1.001);
+    }
+}
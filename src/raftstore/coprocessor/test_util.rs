@@ -0,0 +1,41 @@
+// Copyright 2018 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Fixtures shared by the tests of coprocessors and the split check worker.
+
+use std::sync::{Arc, Mutex};
+
+use storage::CfName;
+
+use super::{KeyEntry, ObserverContext, SplitChecker};
+
+/// A checker recording the CFs of the entries it's fed.
+pub struct CfChecker {
+    pub cfs: &'static [CfName],
+    pub fed: Arc<Mutex<Vec<CfName>>>,
+}
+
+impl SplitChecker for CfChecker {
+    fn on_kv(&mut self, _: &mut ObserverContext, entry: &KeyEntry) -> bool {
+        self.fed.lock().unwrap().push(entry.cf());
+        false
+    }
+
+    fn split_key(&mut self) -> Option<Vec<u8>> {
+        None
+    }
+
+    fn interested_cfs(&self) -> &[CfName] {
+        self.cfs
+    }
+}
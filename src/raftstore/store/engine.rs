@@ -180,6 +180,7 @@ pub struct IterOption {
     prefix_same_as_start: bool,
     fill_cache: bool,
     seek_mode: SeekMode,
+    readahead_size: usize,
 }
 
 impl IterOption {
@@ -194,6 +195,7 @@ impl IterOption {
             prefix_same_as_start: false,
             fill_cache,
             seek_mode: SeekMode::TotalOrder,
+            readahead_size: 0,
         }
     }
 
@@ -234,6 +236,19 @@ impl IterOption {
         self
     }
 
+    #[inline]
+    pub fn readahead_size(&self) -> usize {
+        self.readahead_size
+    }
+
+    /// Sets the size of read-ahead for the iterator, 0 means RocksDB's
+    /// default.
+    #[inline]
+    pub fn set_readahead_size(mut self, size: usize) -> IterOption {
+        self.readahead_size = size;
+        self
+    }
+
     pub fn build_read_opts(&self) -> ReadOptions {
         let mut opts = ReadOptions::new();
         opts.fill_cache(self.fill_cache);
@@ -248,6 +263,9 @@ impl IterOption {
         if let Some(ref key) = self.upper_bound {
             opts.set_iterate_upper_bound(key);
         }
+        if self.readahead_size > 0 {
+            opts.set_readahead_size(self.readahead_size);
+        }
         opts
     }
 }
@@ -260,6 +278,7 @@ impl Default for IterOption {
             prefix_same_as_start: false,
             fill_cache: true,
             seek_mode: SeekMode::TotalOrder,
+            readahead_size: 0,
         }
     }
 }
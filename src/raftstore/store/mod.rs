@@ -37,7 +37,8 @@ pub use self::config::Config;
 pub use self::engine::{Iterable, Mutable, Peekable};
 pub use self::msg::{
     BatchReadCallback, Callback, Msg, ReadCallback, ReadResponse, SeekRegionCallback,
-    SeekRegionFilter, SeekRegionResult, SignificantMsg, Tick, WriteCallback, WriteResponse,
+    SeekRegionFilter, SeekRegionResult, SignificantMsg, SplitCheckResult, Tick, WriteCallback,
+    WriteResponse,
 };
 pub use self::peer::{
     Peer, PeerStat, ProposalContext, ReadExecutor, RequestInspector, RequestPolicy,
@@ -55,7 +56,10 @@ pub use self::snap::{
 pub use self::store::{create_event_loop, new_compaction_listener, Store, StoreChannel, StoreStat};
 pub use self::transport::Transport;
 pub use self::util::Engines;
-pub use self::worker::{KeyEntry, ReadTask};
+pub use self::worker::{
+    split_keys_of_entries, EntryIterator, KeyEntry, ReadTask, SplitCheckReason, SplitEvent,
+    SplitEventSink, SplitHistory, VecEntryIterator,
+};
 
 // Only used in tests
 #[cfg(test)]
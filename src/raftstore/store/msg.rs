@@ -23,10 +23,11 @@ use kvproto::raft_cmdpb::{RaftCmdRequest, RaftCmdResponse};
 use kvproto::raft_serverpb::RaftMessage;
 
 use raft::SnapshotStatus;
+use util::collections::HashMap;
 use util::escape;
 use util::rocksdb::CompactedEvent;
 
-use super::{Peer, RegionSnapshot};
+use super::{Peer, RegionSnapshot, SplitCheckReason};
 
 #[derive(Debug, Clone)]
 pub struct ReadResponse {
@@ -51,6 +52,16 @@ pub enum SeekRegionResult {
     Ended,
 }
 
+/// Statistics of a split check scan of a region.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SplitCheckResult {
+    pub region_id: u64,
+    pub scanned_size: u64,
+    pub scanned_keys: u64,
+    pub found_split: bool,
+    pub estimated_splits: u64,
+}
+
 pub type ReadCallback = Box<FnBox(ReadResponse) + Send>;
 pub type WriteCallback = Box<FnBox(WriteResponse) + Send>;
 pub type BatchReadCallback = Box<FnBox(Vec<Option<ReadResponse>>) + Send>;
@@ -172,7 +183,8 @@ pub enum Msg {
         region_epoch: RegionEpoch,
         // It's an encoded key.
         // TODO: support meta key.
-        split_key: Vec<u8>,
+        split_keys: Vec<Vec<u8>>,
+        reason: SplitCheckReason,
         callback: Callback,
     },
 
@@ -192,12 +204,69 @@ pub enum Msg {
         size: u64,
     },
 
+    // For the size of each CF of a region, keyed by the CF names
+    RegionApproximateSizePerCf {
+        region_id: u64,
+        sizes: HashMap<String, u64>,
+    },
+
     // For region keys
     RegionApproximateKeys {
         region_id: u64,
         keys: u64,
     },
 
+    // Statistics of a split check scan
+    SplitCheckResult {
+        region_id: u64,
+        scanned_size: u64,
+        scanned_keys: u64,
+        found_split: bool,
+        estimated_splits: u64,
+    },
+
+    // Statistics of the split check scans of a batch of regions
+    BatchSplitCheckResult(Vec<SplitCheckResult>),
+
+    // The largest key and value seen by a split check scan
+    RegionMaxEntry {
+        region_id: u64,
+        max_key_len: u64,
+        max_value_len: u64,
+    },
+
+    // Number of entries of each CF scanned by a split check, the CFs not
+    // scanned are absent
+    SplitCheckCfStats {
+        region_id: u64,
+        cf_entries: HashMap<&'static str, u64>,
+    },
+
+    // Evenly spaced boundaries of the region found by a split check scan
+    RegionBuckets {
+        region_id: u64,
+        keys: Vec<Vec<u8>>,
+    },
+
+    // Most data of the region is in memtables, flush them so the size
+    // properties of the region become accurate
+    FlushRegion {
+        region_id: u64,
+    },
+
+    // Most versions of the region are garbage, compact it instead of
+    // splitting it
+    CompactRegion {
+        region_id: u64,
+    },
+
+    // Whether the split of the region asked by split check is applied, the
+    // duplicated split keys are not suppressed any more if it's not
+    SplitAck {
+        region_id: u64,
+        applied: bool,
+    },
+
     // Compaction finished event
     CompactedEvent(CompactedEvent),
     HalfSplitRegion {
@@ -242,19 +311,84 @@ impl fmt::Debug for Msg {
             ),
             Msg::SplitRegion {
                 ref region_id,
-                ref split_key,
+                ref split_keys,
+                ref reason,
                 ..
-            } => write!(fmt, "Split region {} at key {:?}", region_id, split_key),
+            } => write!(
+                fmt,
+                "Split region {} at keys {:?}, reason {:?}",
+                region_id, split_keys, reason
+            ),
             Msg::RegionApproximateSize { region_id, size } => write!(
                 fmt,
                 "Region's approximate size [region_id: {}, size: {:?}]",
                 region_id, size
             ),
+            Msg::RegionApproximateSizePerCf {
+                region_id,
+                ref sizes,
+            } => write!(
+                fmt,
+                "Region's approximate size per cf [region_id: {}, sizes: {:?}]",
+                region_id, sizes
+            ),
             Msg::RegionApproximateKeys { region_id, keys } => write!(
                 fmt,
                 "Region's approximate keys [region_id: {}, keys: {:?}]",
                 region_id, keys
             ),
+            Msg::SplitCheckResult {
+                region_id,
+                scanned_size,
+                scanned_keys,
+                found_split,
+                estimated_splits,
+            } => write!(
+                fmt,
+                "SplitCheckResult [region_id: {}, scanned_size: {}, scanned_keys: {}, \
+                 found_split: {}, estimated_splits: {}]",
+                region_id, scanned_size, scanned_keys, found_split, estimated_splits
+            ),
+            Msg::BatchSplitCheckResult(ref results) => write!(
+                fmt,
+                "BatchSplitCheckResult [regions count: {}]",
+                results.len()
+            ),
+            Msg::RegionMaxEntry {
+                region_id,
+                max_key_len,
+                max_value_len,
+            } => write!(
+                fmt,
+                "RegionMaxEntry [region_id: {}, max_key_len: {}, max_value_len: {}]",
+                region_id, max_key_len, max_value_len
+            ),
+            Msg::SplitCheckCfStats {
+                region_id,
+                ref cf_entries,
+            } => write!(
+                fmt,
+                "SplitCheckCfStats [region_id: {}, cf_entries: {:?}]",
+                region_id, cf_entries
+            ),
+            Msg::RegionBuckets {
+                region_id,
+                ref keys,
+            } => write!(
+                fmt,
+                "RegionBuckets [region_id: {}, keys count: {}]",
+                region_id,
+                keys.len()
+            ),
+            Msg::FlushRegion { region_id } => write!(fmt, "FlushRegion region_id {}", region_id),
+            Msg::CompactRegion { region_id } => {
+                write!(fmt, "CompactRegion region_id {}", region_id)
+            }
+            Msg::SplitAck { region_id, applied } => write!(
+                fmt,
+                "SplitAck [region_id: {}, applied: {}]",
+                region_id, applied
+            ),
             Msg::CompactedEvent(ref event) => write!(fmt, "CompactedEvent cf {}", event.cf),
             Msg::HalfSplitRegion { ref region_id, .. } => {
                 write!(fmt, "Half Split region {}", region_id)
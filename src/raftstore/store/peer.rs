@@ -36,13 +36,15 @@ use raft::{
     self, Progress, ProgressState, RawNode, Ready, SnapshotStatus, StateRole, INVALID_INDEX,
     NO_LIMIT,
 };
-use raftstore::coprocessor::CoprocessorHost;
+use raftstore::coprocessor::{CoprocessorHost, RegionState};
 use raftstore::store::engine::{Peekable, Snapshot};
 use raftstore::store::worker::apply::ApplyMetrics;
 use raftstore::store::worker::{
     apply, Apply, ApplyTask, Proposal, ReadProgress, ReadTask, RegionProposal,
 };
-use raftstore::store::{keys, Callback, Config, Engines, ReadResponse, RegionSnapshot};
+use raftstore::store::{
+    keys, Callback, Config, Engines, ReadResponse, RegionSnapshot, SplitCheckResult,
+};
 use raftstore::{Error, Result};
 use util::collections::{HashMap, HashSet};
 use util::time::{duration_to_sec, monotonic_raw_now};
@@ -243,6 +245,18 @@ pub struct Peer {
     pub approximate_size: Option<u64>,
     /// approximate keys of the region.
     pub approximate_keys: Option<u64>,
+    /// the statistics of the last split check scan of the region.
+    pub last_split_check: Option<SplitCheckResult>,
+    /// the bucket boundaries of the region found by the last split check.
+    pub buckets: Vec<Vec<u8>>,
+    /// the number of entries of each CF scanned by the last split check
+    /// of the region.
+    pub scanned_cf_entries: Option<HashMap<&'static str, u64>>,
+    /// the lengths of the largest key and value seen by the last split
+    /// check scan of the region.
+    pub max_entry_len: Option<(u64, u64)>,
+    /// approximate size of each CF of the region.
+    pub approximate_cf_sizes: Option<HashMap<String, u64>>,
     pub compaction_declined_bytes: u64,
 
     pub consistency_state: ConsistencyState,
@@ -384,6 +398,11 @@ impl Peer {
             delete_keys_hint: 0,
             approximate_size: None,
             approximate_keys: None,
+            last_split_check: None,
+            buckets: vec![],
+            scanned_cf_entries: None,
+            max_entry_len: None,
+            approximate_cf_sizes: None,
             compaction_declined_bytes: 0,
             apply_scheduler: store.apply_scheduler(),
             read_scheduler: store.read_scheduler(),
@@ -854,6 +873,17 @@ impl Peer {
             || self.pending_merge_state.is_some()
     }
 
+    /// Returns the state hint of the region for coprocessor observers.
+    pub fn region_state(&self) -> RegionState {
+        if self.is_merging() {
+            RegionState::Merging
+        } else if self.is_splitting() {
+            RegionState::Splitting
+        } else {
+            RegionState::Normal
+        }
+    }
+
     pub fn take_apply_proposals(&mut self) -> Option<RegionProposal> {
         if self.apply_proposals.is_empty() {
             return None;
@@ -1158,6 +1188,12 @@ impl Peer {
         // Reset delete_keys_hint and size_diff_hint.
         self.delete_keys_hint = 0;
         self.size_diff_hint = 0;
+        // The statistics of the region before split are stale.
+        self.last_split_check = None;
+        self.buckets.clear();
+        self.scanned_cf_entries = None;
+        self.max_entry_len = None;
+        self.approximate_cf_sizes = None;
     }
 
     /// Try to renew leader lease.
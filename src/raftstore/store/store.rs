@@ -16,6 +16,7 @@ use std::cell::RefCell;
 use std::collections::BTreeMap;
 use std::collections::Bound::{Excluded, Included, Unbounded};
 use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::mpsc::{self, Receiver as StdReceiver, TryRecvError};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
@@ -37,12 +38,13 @@ use kvproto::raft_serverpb::{
 };
 use raft::eraftpb::{ConfChangeType, MessageType};
 use raft::{self, SnapshotStatus, INVALID_INDEX, NO_LIMIT};
+use rand::{self, Rng};
 
 use pd::{PdClient, PdRunner, PdTask};
 use raftstore::coprocessor::split_observer::SplitObserver;
 use raftstore::coprocessor::CoprocessorHost;
 use raftstore::{Error, Result};
-use storage::{CF_DEFAULT, CF_LOCK, CF_RAFT, CF_WRITE};
+use storage::{CF_DEFAULT, CF_LOCK, CF_RAFT, CF_WRITE, LARGE_CFS};
 use util::collections::{HashMap, HashSet};
 use util::rocksdb::{CompactedEvent, CompactionListener};
 use util::sys as util_sys;
@@ -61,7 +63,7 @@ use super::keys::{
 };
 use super::local_metrics::RaftMetrics;
 use super::metrics::*;
-use super::msg::{Callback, ReadResponse};
+use super::msg::{Callback, ReadResponse, SplitCheckResult};
 use super::peer::{ConsistencyState, Peer, ReadyContext, StaleState};
 use super::peer_storage::{self, ApplySnapResult, CacheQueryStats};
 use super::transport::Transport;
@@ -69,8 +71,8 @@ use super::worker::apply::{ApplyMetrics, ApplyRes, ChangePeer, ExecResult};
 use super::worker::{
     ApplyRunner, ApplyTask, ApplyTaskRes, CleanupSSTRunner, CleanupSSTTask, CompactRunner,
     CompactTask, ConsistencyCheckRunner, ConsistencyCheckTask, LocalReader, RaftlogGcRunner,
-    RaftlogGcTask, ReadTask, RegionRunner, RegionTask, SplitCheckRunner, SplitCheckTask,
-    STALE_PEER_CHECK_INTERVAL,
+    RaftlogGcTask, ReadTask, RegionRunner, RegionTask, SplitCheckReason, SplitCheckRunner,
+    SplitCheckTask, DEFERRED_SPLIT_CHECK_INTERVAL, STALE_PEER_CHECK_INTERVAL,
 };
 use super::{
     util, Engines, Msg, SeekRegionCallback, SeekRegionFilter, SeekRegionResult, SignificantMsg,
@@ -82,6 +84,8 @@ type Key = Vec<u8>;
 
 const MIO_TICK_RATIO: u64 = 10;
 const PENDING_VOTES_CAP: usize = 20;
+// The pending split keys not sent in so many split check ticks are dropped.
+const PENDING_SPLIT_EXPIRE_TICKS: u32 = 6;
 
 // A helper structure to bundle all channels for messages to `Store`.
 pub struct StoreChannel {
@@ -124,6 +128,19 @@ pub struct StoreInfo {
     pub capacity: u64,
 }
 
+// The split keys left by a split check which found more than one key, they
+// are sent one by one after the previous split is applied. Once they are
+// stale, they are dropped and the region is checked again instead.
+struct PendingSplit {
+    split_keys: Vec<Vec<u8>>,
+    // The epoch of the region after the previous split is applied, the keys
+    // are dropped if the region is changed since then.
+    epoch: Option<metapb::RegionEpoch>,
+    // The keys are dropped if the previous split is not applied, or they
+    // are not sent, within PENDING_SPLIT_EXPIRE_TICKS since then.
+    queued_time: Instant,
+}
+
 pub struct Store<T, C: 'static> {
     cfg: Rc<Config>,
     engines: Engines,
@@ -145,7 +162,13 @@ pub struct Store<T, C: 'static> {
     // It assumes that when a peer is going to accept snapshot, it can never
     // captch up by normal log replication.
     pending_cross_snap: HashMap<u64, metapb::RegionEpoch>,
+    // region_id -> the split keys to be sent in the following ticks.
+    pending_splits: HashMap<u64, PendingSplit>,
     split_check_worker: Worker<SplitCheckTask>,
+    // Set to abort the running split check scan when stopping.
+    split_check_stopped: Arc<AtomicBool>,
+    // The number of split check tasks deferred by the runner.
+    split_check_deferred: Arc<AtomicUsize>,
     raftlog_gc_worker: Worker<RaftlogGcTask>,
     region_worker: Worker<RegionTask>,
     compact_worker: Worker<CompactTask>,
@@ -230,6 +253,8 @@ impl<T: Transport, C: PdClient> Store<T, C> {
             merging_regions: Some(vec![]),
             pending_raft_groups: HashSet::default(),
             split_check_worker: Worker::new("split-check"),
+            split_check_stopped: Arc::new(AtomicBool::new(false)),
+            split_check_deferred: Arc::new(AtomicUsize::new(0)),
             region_worker: Worker::new("snapshot-worker"),
             raftlog_gc_worker: Worker::new("raft-gc-worker"),
             compact_worker: Worker::new("compact-worker"),
@@ -243,6 +268,7 @@ impl<T: Transport, C: PdClient> Store<T, C> {
             region_ranges: BTreeMap::new(),
             pending_snapshot_regions: vec![],
             pending_cross_snap: HashMap::default(),
+            pending_splits: HashMap::default(),
             trans,
             pd_client,
             coprocessor_host: Arc::new(coprocessor_host),
@@ -540,9 +566,12 @@ impl<T: Transport, C: PdClient> Store<T, C> {
             Arc::clone(&self.engines.kv),
             self.sendch.clone(),
             Arc::clone(&self.coprocessor_host),
-        );
-
-        box_try!(self.split_check_worker.start(split_check_runner));
+        ).with_scheduler(self.split_check_worker.scheduler());
+        self.split_check_stopped = split_check_runner.stop_flag();
+        self.split_check_deferred = split_check_runner.deferred_count();
+        let mut timer = Timer::new(1);
+        timer.add_task(Duration::from_millis(DEFERRED_SPLIT_CHECK_INTERVAL), ());
+        box_try!(self.split_check_worker.start_with_timer(split_check_runner, timer));
 
         let region_runner = RegionRunner::new(
             self.engines.clone(),
@@ -610,6 +639,7 @@ impl<T: Transport, C: PdClient> Store<T, C> {
 
         // Wait all workers finish.
         let mut handles: Vec<Option<thread::JoinHandle<()>>> = vec![];
+        self.split_check_stopped.store(true, Ordering::SeqCst);
         handles.push(self.split_check_worker.stop());
         handles.push(self.region_worker.stop());
         handles.push(self.raftlog_gc_worker.stop());
@@ -1419,6 +1449,7 @@ impl<T: Transport, C: PdClient> Store<T, C> {
         // We can't destroy a peer which is applying snapshot.
         assert!(!p.is_applying_snapshot());
         self.pending_cross_snap.remove(&region_id);
+        self.pending_splits.remove(&region_id);
         let task = PdTask::DestroyPeer { region_id };
         if let Err(e) = self.pd_worker.schedule(task) {
             error!("{} failed to notify pd: {}", self.tag, e);
@@ -1564,6 +1595,19 @@ impl<T: Transport, C: PdClient> Store<T, C> {
             peer.set_region(origin_region);
             peer.post_split();
         }
+        if let Some(mut pending) = self.pending_splits.remove(&region_id) {
+            // The pending keys are after the split key, so they are in the
+            // right region.
+            let start_key = right.get_start_key();
+            pending
+                .split_keys
+                .retain(|k| k[..] > *start_key && util::check_key_in_region(k, &right).is_ok());
+            if !pending.split_keys.is_empty() {
+                pending.epoch = Some(right.get_region_epoch().clone());
+                pending.queued_time = Instant::now();
+                self.pending_splits.insert(right.get_id(), pending);
+            }
+        }
         let new_region_id = new_region.get_id();
         if let Some(peer) = self.region_peers.get(&new_region_id) {
             // If the store received a raft msg with the new region raft group
@@ -1640,6 +1684,10 @@ impl<T: Transport, C: PdClient> Store<T, C> {
             }
         }
 
+        if self.region_peers[&region_id].is_leader() {
+            self.on_split_ack(region_id, true);
+        }
+
         if !campaigned {
             if let Some(msg) = self
                 .pending_votes
@@ -2321,21 +2369,39 @@ impl<T: Transport, C: PdClient> Store<T, C> {
         for (region_id, declined_bytes) in region_declined_bytes.drain(..) {
             if let Some(peer) = self.region_peers.get_mut(&region_id) {
                 peer.compaction_declined_bytes += declined_bytes;
-                if peer.compaction_declined_bytes >= self.cfg.region_split_check_diff.0 {
-                    UPDATE_REGION_SIZE_BY_COMPACTION_COUNTER.inc();
+                if peer.compaction_declined_bytes < self.cfg.region_split_check_diff.0 {
+                    continue;
+                }
+                UPDATE_REGION_SIZE_BY_COMPACTION_COUNTER.inc();
+                if !peer.is_leader() {
+                    continue;
+                }
+                // Check the region now instead of waiting for the next split
+                // check tick, its size may have changed a lot.
+                let task = SplitCheckTask::post_compaction(peer.region().clone(), CheckPolicy::SCAN)
+                    .with_region_state(peer.region_state())
+                    .with_leader(peer.is_leader());
+                if let Err(e) = self.split_check_worker.schedule(task.into_pending()) {
+                    error!("{} failed to schedule split check: {}", self.tag, e);
+                    continue;
                 }
+                peer.compaction_declined_bytes = 0;
             }
         }
     }
 
     fn on_split_region_check_tick(&mut self, event_loop: &mut EventLoop<Self>) {
+        self.send_pending_splits();
         // To avoid frequent scan, we only add new scan tasks if all previous tasks
-        // have finished.
+        // have finished, including the deferred ones.
         // TODO: check whether a gc progress has been started.
-        if self.split_check_worker.is_busy() {
+        if self.split_check_worker.is_busy()
+            || self.split_check_deferred.load(Ordering::SeqCst) > 0
+        {
             self.register_split_region_check_tick(event_loop);
             return;
         }
+        let jitter_ms = self.coprocessor_host.cfg.split_check_jitter.as_millis();
         for peer in self.region_peers.values_mut() {
             if !peer.is_leader() {
                 continue;
@@ -2352,8 +2418,20 @@ impl<T: Transport, C: PdClient> Store<T, C> {
             {
                 continue;
             }
-            let task = SplitCheckTask::new(peer.region().clone(), true, CheckPolicy::SCAN);
-            if let Err(e) = self.split_check_worker.schedule(task) {
+            let reason = if peer.compaction_declined_bytes >= self.cfg.region_split_check_diff.0 {
+                SplitCheckReason::PostCompaction
+            } else {
+                SplitCheckReason::Size
+            };
+            let mut task = SplitCheckTask::new(peer.region().clone(), true, CheckPolicy::SCAN)
+                .with_region_state(peer.region_state())
+                .with_leader(peer.is_leader())
+                .with_reason(reason);
+            if jitter_ms > 0 {
+                let delay = rand::thread_rng().gen_range(0, jitter_ms);
+                task = task.with_delay(Duration::from_millis(delay));
+            }
+            if let Err(e) = self.split_check_worker.schedule(task.into_pending()) {
                 error!("{} failed to schedule split check: {}", self.tag, e);
             }
             peer.size_diff_hint = 0;
@@ -2430,15 +2508,36 @@ impl<T: Transport, C: PdClient> Store<T, C> {
         &mut self,
         region_id: u64,
         region_epoch: metapb::RegionEpoch,
-        split_key: Vec<u8>, // `split_key` is a encoded key.
+        split_keys: Vec<Vec<u8>>, // `split_keys` are encoded keys.
         cb: Callback,
     ) {
-        if let Err(e) = self.validate_split_region(region_id, &region_epoch, &split_key) {
+        if let Err(e) = self.validate_split_region(region_id, &region_epoch, &split_keys) {
             cb.invoke_with_response(new_error(e));
             return;
         }
         let peer = &self.region_peers[&region_id];
         let region = peer.region();
+        // PD allocates ids for one split at a time, so the region is split at
+        // the first key, the rest are sent after the split is applied.
+        let mut split_keys = split_keys.into_iter();
+        let split_key = split_keys.next().unwrap();
+        let split_keys: Vec<_> = split_keys.collect();
+        if split_keys.is_empty() {
+            self.pending_splits.remove(&region_id);
+        } else {
+            info!(
+                "{} split at {}, {} keys are pending",
+                peer.tag,
+                escape(&split_key),
+                split_keys.len()
+            );
+            let pending = PendingSplit {
+                split_keys,
+                epoch: None,
+                queued_time: Instant::now(),
+            };
+            self.pending_splits.insert(region_id, pending);
+        }
         let task = PdTask::AskSplit {
             region: region.clone(),
             split_key,
@@ -2461,14 +2560,20 @@ impl<T: Transport, C: PdClient> Store<T, C> {
         &mut self,
         region_id: u64,
         epoch: &metapb::RegionEpoch,
-        split_key: &[u8], // `split_key` is a encoded key.
+        split_keys: &[Vec<u8>], // `split_keys` are encoded keys.
     ) -> Result<()> {
-        if split_key.is_empty() {
-            error!("[region {}] split key should not be empty!!!", region_id);
-            return Err(box_err!(
-                "[region {}] split key should not be empty",
-                region_id
-            ));
+        if split_keys.is_empty() {
+            error!("[region {}] no split key is specified.", region_id);
+            return Err(box_err!("[region {}] no split key is specified.", region_id));
+        }
+        for key in split_keys {
+            if key.is_empty() {
+                error!("[region {}] split key should not be empty!!!", region_id);
+                return Err(box_err!(
+                    "[region {}] split key should not be empty",
+                    region_id
+                ));
+            }
         }
         let peer = match self.region_peers.get(&region_id) {
             None => {
@@ -2531,6 +2636,17 @@ impl<T: Transport, C: PdClient> Store<T, C> {
         peer.approximate_size = Some(size);
     }
 
+    fn on_approximate_region_cf_sizes(&mut self, region_id: u64, sizes: HashMap<String, u64>) {
+        let peer = match self.region_peers.get_mut(&region_id) {
+            Some(peer) => peer,
+            None => {
+                warn!("[region {}] receive stale approximate cf sizes", region_id);
+                return;
+            }
+        };
+        peer.approximate_cf_sizes = Some(sizes);
+    }
+
     fn on_approximate_region_keys(&mut self, region_id: u64, keys: u64) {
         let peer = match self.region_peers.get_mut(&region_id) {
             Some(peer) => peer,
@@ -2545,6 +2661,149 @@ impl<T: Transport, C: PdClient> Store<T, C> {
         peer.approximate_keys = Some(keys);
     }
 
+    fn on_split_check_result(&mut self, result: SplitCheckResult) {
+        let peer = match self.region_peers.get_mut(&result.region_id) {
+            Some(peer) => peer,
+            None => {
+                warn!("[region {}] receive stale split check result", result.region_id);
+                return;
+            }
+        };
+        debug!(
+            "{} split check result {:?}, approximate size {:?}, keys {:?}",
+            peer.tag, result, peer.approximate_size, peer.approximate_keys
+        );
+        peer.last_split_check = Some(result);
+    }
+
+    fn on_region_max_entry(&mut self, region_id: u64, max_key_len: u64, max_value_len: u64) {
+        let peer = match self.region_peers.get_mut(&region_id) {
+            Some(peer) => peer,
+            None => {
+                warn!("[region {}] receive stale max entry", region_id);
+                return;
+            }
+        };
+        peer.max_entry_len = Some((max_key_len, max_value_len));
+    }
+
+    fn on_split_check_cf_stats(&mut self, region_id: u64, cf_entries: HashMap<&'static str, u64>) {
+        let peer = match self.region_peers.get_mut(&region_id) {
+            Some(peer) => peer,
+            None => {
+                warn!("[region {}] receive stale split check cf stats", region_id);
+                return;
+            }
+        };
+        peer.scanned_cf_entries = Some(cf_entries);
+    }
+
+    fn on_region_buckets(&mut self, region_id: u64, keys: Vec<Vec<u8>>) {
+        let peer = match self.region_peers.get_mut(&region_id) {
+            Some(peer) => peer,
+            None => {
+                warn!("[region {}] receive stale buckets", region_id);
+                return;
+            }
+        };
+        peer.buckets = keys;
+    }
+
+    fn on_flush_region(&mut self, region_id: u64) {
+        if !self.region_peers.contains_key(&region_id) {
+            warn!("[region {}] receive stale flush request", region_id);
+            return;
+        }
+        info!("[region {}] flush memtables for split check", region_id);
+        // Memtables are shared by all the regions, don't wait for the flush.
+        for cf in LARGE_CFS {
+            let handle = rocksdb::get_cf_handle(&self.engines.kv, cf).unwrap();
+            if let Err(e) = self.engines.kv.flush_cf(handle, false) {
+                error!("[region {}] failed to flush cf {}: {}", region_id, cf, e);
+            }
+        }
+    }
+
+    fn on_compact_region(&mut self, region_id: u64) {
+        let region = match self.region_peers.get(&region_id) {
+            Some(peer) => peer.region().clone(),
+            None => {
+                warn!("[region {}] receive stale compact request", region_id);
+                return;
+            }
+        };
+        info!("[region {}] compact garbage found by split check", region_id);
+        let (start_key, end_key) = (enc_start_key(&region), enc_end_key(&region));
+        for cf in LARGE_CFS {
+            let task = CompactTask::Compact {
+                cf_name: String::from(*cf),
+                start_key: Some(start_key.clone()),
+                end_key: Some(end_key.clone()),
+            };
+            if let Err(e) = self.compact_worker.schedule(task) {
+                error!(
+                    "[region {}] failed to schedule compact cf {}: {:?}",
+                    region_id, cf, e
+                );
+            }
+        }
+    }
+
+    fn send_pending_splits(&mut self) {
+        let expire = self.cfg.split_region_check_tick_interval.0 * PENDING_SPLIT_EXPIRE_TICKS;
+        let (mut ready, mut stale) = (vec![], vec![]);
+        for (region_id, pending) in &self.pending_splits {
+            let peer = match self.region_peers.get(region_id) {
+                Some(peer) => peer,
+                None => {
+                    stale.push(*region_id);
+                    continue;
+                }
+            };
+            let epoch_changed = pending
+                .epoch
+                .as_ref()
+                .map_or(false, |epoch| epoch != peer.region().get_region_epoch());
+            if epoch_changed || pending.queued_time.elapsed() >= expire {
+                stale.push(*region_id);
+            } else if pending.epoch.is_some() && peer.is_leader() {
+                ready.push(*region_id);
+            }
+        }
+        for region_id in stale {
+            self.pending_splits.remove(&region_id);
+            // The keys were found in the region before it changed, check it
+            // again by the following tick instead of trusting them.
+            if let Some(peer) = self.region_peers.get_mut(&region_id) {
+                info!("{} drop stale pending split keys", peer.tag);
+                peer.size_diff_hint = self.cfg.region_split_check_diff.0;
+            }
+        }
+        for region_id in ready {
+            let pending = self.pending_splits.remove(&region_id).unwrap();
+            let epoch = pending.epoch.unwrap();
+            self.on_prepare_split_region(region_id, epoch, pending.split_keys, Callback::None);
+        }
+    }
+
+    fn on_split_ack(&mut self, region_id: u64, applied: bool) {
+        if !applied {
+            // The following splits depend on the failed one.
+            self.pending_splits.remove(&region_id);
+        }
+        let region = match self.region_peers.get(&region_id) {
+            Some(peer) => peer.region().clone(),
+            None => {
+                warn!("[region {}] receive stale split ack", region_id);
+                return;
+            }
+        };
+        let task = SplitCheckTask::split_ack(region, applied);
+        if let Err(e) = self.split_check_worker.schedule(task) {
+            error!("{} failed to schedule split ack: {}", self.tag, e);
+        }
+    }
+
     fn on_schedule_half_split_region(
         &mut self,
         region_id: u64,
@@ -2575,7 +2834,10 @@ impl<T: Transport, C: PdClient> Store<T, C> {
             return;
         }
 
-        let task = SplitCheckTask::new(region.clone(), false, policy);
+        let task = SplitCheckTask::new(region.clone(), false, policy)
+            .with_region_state(peer.region_state())
+            .with_leader(peer.is_leader())
+            .with_reason(SplitCheckReason::Manual);
         if let Err(e) = self.split_check_worker.schedule(task) {
             error!("{} failed to schedule split check: {}", self.tag, e);
         }
@@ -3305,22 +3567,56 @@ impl<T: Transport, C: PdClient> mio::Handler for Store<T, C> {
             Msg::SplitRegion {
                 region_id,
                 region_epoch,
-                split_key,
+                split_keys,
+                reason,
                 callback,
             } => {
                 info!(
-                    "[region {}] on split region at key {}.",
+                    "[region {}] on split region at keys {:?}, reason {:?}.",
                     region_id,
-                    escape(&split_key)
+                    split_keys.iter().map(|k| escape(k)).collect::<Vec<_>>(),
+                    reason
                 );
-                self.on_prepare_split_region(region_id, region_epoch, split_key, callback);
+                self.on_prepare_split_region(region_id, region_epoch, split_keys, callback);
             }
             Msg::RegionApproximateSize { region_id, size } => {
                 self.on_approximate_region_size(region_id, size)
             }
+            Msg::RegionApproximateSizePerCf { region_id, sizes } => {
+                self.on_approximate_region_cf_sizes(region_id, sizes)
+            }
             Msg::RegionApproximateKeys { region_id, keys } => {
                 self.on_approximate_region_keys(region_id, keys)
             }
+            Msg::SplitCheckResult {
+                region_id,
+                scanned_size,
+                scanned_keys,
+                found_split,
+                estimated_splits,
+            } => self.on_split_check_result(SplitCheckResult {
+                region_id,
+                scanned_size,
+                scanned_keys,
+                found_split,
+                estimated_splits,
+            }),
+            Msg::BatchSplitCheckResult(results) => for r in results {
+                self.on_split_check_result(r);
+            },
+            Msg::RegionMaxEntry {
+                region_id,
+                max_key_len,
+                max_value_len,
+            } => self.on_region_max_entry(region_id, max_key_len, max_value_len),
+            Msg::SplitCheckCfStats {
+                region_id,
+                cf_entries,
+            } => self.on_split_check_cf_stats(region_id, cf_entries),
+            Msg::RegionBuckets { region_id, keys } => self.on_region_buckets(region_id, keys),
+            Msg::FlushRegion { region_id } => self.on_flush_region(region_id),
+            Msg::CompactRegion { region_id } => self.on_compact_region(region_id),
+            Msg::SplitAck { region_id, applied } => self.on_split_ack(region_id, applied),
             Msg::CompactedEvent(event) => self.on_compaction_finished(event),
             Msg::HalfSplitRegion {
                 region_id,
@@ -11,8 +11,9 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::collections::Bound::Excluded;
+use std::collections::Bound::{Excluded, Included, Unbounded};
 use std::option::Option;
+use std::result::Result as StdResult;
 use std::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
 use std::sync::Arc;
 use std::{fmt, u64};
@@ -24,13 +25,14 @@ use protobuf::{self, Message};
 use raft::eraftpb::{self, ConfChangeType, ConfState, MessageType};
 use raftstore::store::keys;
 use raftstore::{Error, Result};
-use rocksdb::{Range, TablePropertiesCollection, Writable, WriteBatch, DB};
+use rocksdb::{CFHandle, Range, SeekKey, TablePropertiesCollection, Writable, WriteBatch, DB};
 use time::{Duration, Timespec};
 
 use storage::{Key, CF_DEFAULT, CF_LOCK, CF_RAFT, CF_WRITE, LARGE_CFS};
-use util::properties::RangeProperties;
+use util::codec;
+use util::properties::{MvccProperties, RangeProperties};
 use util::rocksdb::stats::get_range_entries_and_versions;
-use util::time::monotonic_raw_now;
+use util::time::{monotonic_raw_now, time_now_sec};
 use util::{rocksdb as rocksdb_util, Either};
 
 use super::engine::{IterOption, Iterable};
@@ -304,32 +306,267 @@ pub fn get_region_properties_cf(
     cfname: &str,
     region: &metapb::Region,
 ) -> Result<TablePropertiesCollection> {
-    let cf = rocksdb_util::get_cf_handle(db, cfname)?;
     let start = keys::enc_start_key(region);
     let end = keys::enc_end_key(region);
-    let range = Range::new(&start, &end);
+    get_range_properties_cf(db, cfname, &start, &end)
+}
+
+/// Get the properties of the SST files of the CF that overlap with the
+/// range of data keys `[start_key, end_key)`.
+pub fn get_range_properties_cf(
+    db: &DB,
+    cfname: &str,
+    start_key: &[u8],
+    end_key: &[u8],
+) -> Result<TablePropertiesCollection> {
+    let cf = rocksdb_util::get_cf_handle(db, cfname)?;
+    let range = Range::new(start_key, end_key);
     db.get_properties_of_tables_in_range(cf, &[range])
         .map_err(|e| e.into())
 }
 
+quick_error! {
+    /// The reason why the approximate size of a region can't be got.
+    #[derive(Debug)]
+    pub enum ApproximateSizeError {
+        /// Some SST files have no size properties, they may be written
+        /// without `RangePropertiesCollector`.
+        NoProperties {
+            description("no size properties")
+        }
+        /// The column family doesn't exist in the engine.
+        CfNotFound(cf: String) {
+            description("column family not found")
+            display("CfNotFound {}", cf)
+        }
+        /// Reading the engine fails, it may succeed if tried again.
+        Io(msg: String) {
+            description("failed to read engine")
+            display("Io {}", msg)
+        }
+        /// The size properties are corrupted.
+        Decode(err: codec::Error) {
+            cause(err)
+            description("corrupted size properties")
+            display("Decode {}", err)
+        }
+    }
+}
+
+impl ApproximateSizeError {
+    /// Whether it may succeed to get the size again.
+    pub fn is_transient(&self) -> bool {
+        match *self {
+            ApproximateSizeError::Io(_) => true,
+            ApproximateSizeError::NoProperties
+            | ApproximateSizeError::CfNotFound(_)
+            | ApproximateSizeError::Decode(_) => false,
+        }
+    }
+}
+
+impl From<codec::Error> for ApproximateSizeError {
+    fn from(e: codec::Error) -> ApproximateSizeError {
+        match e {
+            codec::Error::KeyNotFound => ApproximateSizeError::NoProperties,
+            e => ApproximateSizeError::Decode(e),
+        }
+    }
+}
+
+impl From<ApproximateSizeError> for Error {
+    fn from(e: ApproximateSizeError) -> Error {
+        Error::Other(Box::new(e))
+    }
+}
+
+fn get_cf_handle_for_size<'a>(
+    db: &'a DB,
+    cfname: &str,
+) -> StdResult<&'a CFHandle, ApproximateSizeError> {
+    db.cf_handle(cfname).ok_or_else(|| ApproximateSizeError::CfNotFound(cfname.to_owned()))
+}
+
 pub fn get_region_approximate_size_cf(
     db: &DB,
     cfname: &str,
     region: &metapb::Region,
 ) -> Result<u64> {
-    let cf = rocksdb_util::get_cf_handle(db, cfname)?;
+    let start = keys::enc_start_key(region);
+    let end = keys::enc_end_key(region);
+    get_range_approximate_size_cf(db, cfname, &start, &end)
+}
+
+/// Get the approximate size of the range of data keys `[start_key, end_key)`
+/// in the CF.
+pub fn get_range_approximate_size_cf(
+    db: &DB,
+    cfname: &str,
+    start_key: &[u8],
+    end_key: &[u8],
+) -> Result<u64> {
+    let size = get_range_approximate_memtable_size_cf(db, cfname, start_key, end_key)?;
+    Ok(size + get_range_approximate_sst_size_cf(db, cfname, start_key, end_key)?)
+}
+
+/// Get the approximate size of the region in the memtables of the CF.
+pub fn get_region_approximate_memtable_size_cf(
+    db: &DB,
+    cfname: &str,
+    region: &metapb::Region,
+) -> StdResult<u64, ApproximateSizeError> {
+    let start = keys::enc_start_key(region);
+    let end = keys::enc_end_key(region);
+    get_range_approximate_memtable_size_cf(db, cfname, &start, &end)
+}
+
+/// Get the approximate size of the range of data keys in the memtables of the CF.
+pub fn get_range_approximate_memtable_size_cf(
+    db: &DB,
+    cfname: &str,
+    start_key: &[u8],
+    end_key: &[u8],
+) -> StdResult<u64, ApproximateSizeError> {
+    let cf = get_cf_handle_for_size(db, cfname)?;
+    let (_, size) = db.get_approximate_memtable_stats_cf(cf, &Range::new(start_key, end_key));
+    Ok(size)
+}
+
+/// Get the approximate size of the region in the SST files of the CF, it reads
+/// the size offsets recorded by `RangePropertiesCollector`.
+pub fn get_region_approximate_sst_size_cf(
+    db: &DB,
+    cfname: &str,
+    region: &metapb::Region,
+) -> StdResult<u64, ApproximateSizeError> {
+    let start = keys::enc_start_key(region);
+    let end = keys::enc_end_key(region);
+    get_range_approximate_sst_size_cf(db, cfname, &start, &end)
+}
+
+/// Get the approximate size of the range of data keys in the SST files of the CF.
+pub fn get_range_approximate_sst_size_cf(
+    db: &DB,
+    cfname: &str,
+    start_key: &[u8],
+    end_key: &[u8],
+) -> StdResult<u64, ApproximateSizeError> {
+    let cf = get_cf_handle_for_size(db, cfname)?;
+    let range = Range::new(start_key, end_key);
+    let collection = db
+        .get_properties_of_tables_in_range(cf, &[range])
+        .map_err(ApproximateSizeError::Io)?;
+    let mut size = 0;
+    for (_, v) in &*collection {
+        let props = RangeProperties::decode(v.user_collected_properties())?;
+        size += props.get_approximate_size_in_range(start_key, end_key);
+    }
+    Ok(size)
+}
+
+/// Get the approximate size of the region in each SST file of the CF, the files
+/// are identified by their paths.
+pub fn get_region_approximate_sst_sizes_cf(
+    db: &DB,
+    cfname: &str,
+    region: &metapb::Region,
+) -> StdResult<Vec<(String, u64)>, ApproximateSizeError> {
+    let cf = get_cf_handle_for_size(db, cfname)?;
+    let start = keys::enc_start_key(region);
+    let end = keys::enc_end_key(region);
+    let range = Range::new(&start, &end);
+    let collection = db
+        .get_properties_of_tables_in_range(cf, &[range])
+        .map_err(ApproximateSizeError::Io)?;
+    let mut sizes = vec![];
+    for (file, v) in &*collection {
+        let props = RangeProperties::decode(v.user_collected_properties())?;
+        sizes.push((
+            file.to_owned(),
+            props.get_approximate_size_in_range(&start, &end),
+        ));
+    }
+    Ok(sizes)
+}
+
+/// Get the approximate size of the region in the CF without reading any table
+/// properties. The size in SST files is estimated from the offsets of the
+/// index entries within the region, which is much faster when thousands of
+/// SST files overlap with the region, but it's the size after compression.
+pub fn get_region_approximate_size_fast_cf(
+    db: &DB,
+    cfname: &str,
+    region: &metapb::Region,
+) -> StdResult<u64, ApproximateSizeError> {
+    let cf = get_cf_handle_for_size(db, cfname)?;
+    let start = keys::enc_start_key(region);
+    let end = keys::enc_end_key(region);
+    let range = Range::new(&start, &end);
+    let (_, memtable_size) = db.get_approximate_memtable_stats_cf(cf, &range);
+    let sst_size = db.get_approximate_sizes_cf(cf, &[range])[0];
+    Ok(memtable_size + sst_size)
+}
+
+/// Get the approximate size of the region in the CF between its first and
+/// last live keys. The keys covered by range tombstones are skipped by the
+/// iterator, so the data deleted by `DeleteRange` at the edges of the region
+/// isn't counted even if it hasn't been compacted yet.
+pub fn get_region_approximate_live_size_cf(
+    db: &DB,
+    cfname: &str,
+    region: &metapb::Region,
+) -> Result<u64> {
+    let start = keys::enc_start_key(region);
+    let end = keys::enc_end_key(region);
+    let iter_opt = IterOption::new(Some(start.clone()), Some(end), false);
+    let mut it = db.new_iterator_cf(cfname, iter_opt)?;
+    if !it.seek(start.as_slice().into()) {
+        return Ok(0);
+    }
+    let first_key = it.key().to_vec();
+    it.seek(SeekKey::End);
+    // Make the range include the last key.
+    let mut last_key = it.key().to_vec();
+    last_key.push(0);
+    get_range_approximate_size_cf(db, cfname, &first_key, &last_key)
+}
+
+/// The physical part of a TSO timestamp is the milliseconds in its high bits.
+pub const TSO_PHYSICAL_SHIFT_BITS: u64 = 18;
+// The data written in the duration is recent.
+const RECENT_WRITE_DURATION_SECS: u64 = 600;
+
+/// Get the ratio of the recently written data in the region, it reads the max
+/// timestamps of the blocks recorded by `RangePropertiesCollector` in write cf.
+/// A block is recent if any key in it is committed in the last
+/// `RECENT_WRITE_DURATION_SECS`, and the data in memtables is always recent.
+pub fn get_region_recent_write_ratio(db: &DB, region: &metapb::Region) -> Result<f64> {
+    let since_ms = time_now_sec().saturating_sub(RECENT_WRITE_DURATION_SECS) * 1000;
+    get_region_write_ratio_since(db, region, since_ms << TSO_PHYSICAL_SHIFT_BITS)
+}
+
+fn get_region_write_ratio_since(db: &DB, region: &metapb::Region, ts: u64) -> Result<f64> {
+    let cf = rocksdb_util::get_cf_handle(db, CF_WRITE)?;
     let start = keys::enc_start_key(region);
     let end = keys::enc_end_key(region);
     let range = Range::new(&start, &end);
-    let (_, mut size) = db.get_approximate_memtable_stats_cf(cf, &range);
+    let (_, memtable_size) = db.get_approximate_memtable_stats_cf(cf, &range);
+    let (mut recent_size, mut total_size) = (memtable_size, memtable_size);
     let collection = db.get_properties_of_tables_in_range(cf, &[range])?;
     for (_, v) in &*collection {
         let props = RangeProperties::decode(v.user_collected_properties())?;
-        size += props.get_approximate_size_in_range(&start, &end);
+        let (recent, total) = props.get_approximate_recent_size_in_range(&start, &end, ts);
+        recent_size += recent;
+        total_size += total;
     }
-    Ok(size)
+    if total_size == 0 {
+        return Ok(0.0);
+    }
+    Ok(recent_size as f64 / total_size as f64)
 }
 
+/// Get the approximate number of keys of the region in the CF, it reads the key
+/// offsets recorded by `RangePropertiesCollector`.
 pub fn get_region_approximate_keys_cf(
     db: &DB,
     cfname: &str,
@@ -385,10 +622,85 @@ pub fn get_region_approximate_middle_cf(
     Ok(Some(keys.swap_remove(middle)))
 }
 
-pub fn get_region_approximate_size(db: &DB, region: &metapb::Region) -> Result<u64> {
+/// Get the approximate split key of the region, so that the size of data
+/// before it is close to `split_size`. It interpolates the size offsets
+/// recorded in `RangeProperties` of the large CFs, so the result is only
+/// accurate to a property block.
+///
+/// The returned key is always inside the region and must start with "z".
+pub fn get_region_approximate_split_key(
+    db: &DB,
+    region: &metapb::Region,
+    split_size: u64,
+) -> Result<Option<Vec<u8>>> {
+    let start = keys::enc_start_key(region);
+    let end = keys::enc_end_key(region);
+
+    // Collect the size of every property block inside the region.
+    let mut blocks = Vec::new();
+    for cfname in LARGE_CFS {
+        let collection = get_region_properties_cf(db, cfname, region)?;
+        for (_, v) in &*collection {
+            let props = RangeProperties::decode(v.user_collected_properties())?;
+            let mut last_offset = props
+                .offsets
+                .range::<[u8], _>((Unbounded, Included(start.as_slice())))
+                .last()
+                .map_or(0, |(_, o)| o.size);
+            for (k, o) in props
+                .offsets
+                .range::<[u8], _>((Excluded(start.as_slice()), Excluded(end.as_slice())))
+            {
+                blocks.push((k.to_owned(), o.size - last_offset));
+                last_offset = o.size;
+            }
+        }
+    }
+    blocks.sort();
+
+    let mut split_key = None;
+    let (mut current_size, mut min_diff) = (0, u64::MAX);
+    for (key, size) in blocks {
+        current_size += size;
+        let diff = if current_size > split_size {
+            current_size - split_size
+        } else {
+            split_size - current_size
+        };
+        // `current_size` only grows, so the diff won't be smaller any more.
+        if diff >= min_diff {
+            break;
+        }
+        min_diff = diff;
+        split_key = Some(key);
+    }
+    Ok(split_key)
+}
+
+/// Get the approximate size of the region in all the large CFs, including
+/// the data in memtables, so a region written just now is not empty.
+pub fn get_region_approximate_size(
+    db: &DB,
+    region: &metapb::Region,
+) -> StdResult<u64, ApproximateSizeError> {
+    let start = keys::enc_start_key(region);
+    let end = keys::enc_end_key(region);
+    let mut size = 0;
+    for cfname in LARGE_CFS {
+        let cf = get_cf_handle_for_size(db, cfname)?;
+        let range = Range::new(&start, &end);
+        let (_, memtable_size) = db.get_approximate_memtable_stats_cf(cf, &range);
+        size += memtable_size + get_range_approximate_sst_size_cf(db, cfname, &start, &end)?;
+    }
+    Ok(size)
+}
+
+/// Get the approximate size of the range of data keys `[start_key, end_key)`,
+/// so the size of a range can be known without constructing a region.
+pub fn get_range_approximate_size(db: &DB, start_key: &[u8], end_key: &[u8]) -> Result<u64> {
     let mut size = 0;
     for cfname in LARGE_CFS {
-        size += get_region_approximate_size_cf(db, cfname, region)?
+        size += get_range_approximate_size_cf(db, cfname, start_key, end_key)?
     }
     Ok(size)
 }
@@ -413,6 +725,23 @@ pub fn get_region_approximate_keys(db: &DB, region: &metapb::Region) -> Result<u
     Ok(keys)
 }
 
+/// Get the approximate number of user keys and MVCC versions of the region,
+/// it reads the properties recorded by `MvccPropertiesCollector` in write cf.
+/// SST files partially overlapping with the region are counted entirely.
+pub fn get_region_approximate_versions(db: &DB, region: &metapb::Region) -> Result<(u64, u64)> {
+    let cf = rocksdb_util::get_cf_handle(db, CF_WRITE)?;
+    let start = keys::enc_start_key(region);
+    let end = keys::enc_end_key(region);
+    let range = Range::new(&start, &end);
+    let collection = db.get_properties_of_tables_in_range(cf, &[range])?;
+    let mut props = MvccProperties::new();
+    for (_, v) in &*collection {
+        let mvcc = MvccProperties::decode(v.user_collected_properties())?;
+        props.add(&mvcc);
+    }
+    Ok((props.num_rows, props.num_versions))
+}
+
 /// Get region approximate middle key based on default and write cf size.
 pub fn get_region_approximate_middle(db: &DB, region: &metapb::Region) -> Result<Option<Vec<u8>>> {
     let get_cf_size = |cf: &str| get_region_approximate_size_cf(db, cf, &region);
@@ -747,6 +1076,7 @@ mod tests {
     use kvproto::raft_cmdpb::AdminRequest;
     use kvproto::raft_serverpb::RaftMessage;
     use raft::eraftpb::{ConfChangeType, Message, MessageType};
+    use rand::{thread_rng, Rng};
     use rocksdb::{ColumnFamilyOptions, DBOptions, SeekKey, Writable, WriteBatch, DB};
     use tempdir::TempDir;
     use time::Duration as TimeDuration;
@@ -1050,6 +1380,120 @@ mod tests {
         assert_eq!(region_keys, cases.len() as u64);
     }
 
+    #[test]
+    fn test_region_approximate_versions() {
+        let path = TempDir::new("_test_region_approximate_versions").expect("");
+        let path_str = path.path().to_str().unwrap();
+        let db_opts = DBOptions::new();
+        let mut cf_opts = ColumnFamilyOptions::new();
+        cf_opts.set_level_zero_file_num_compaction_trigger(10);
+        let f = Box::new(MvccPropertiesCollectorFactory::default());
+        cf_opts.add_table_properties_collector_factory("tikv.mvcc-properties-collector", f);
+        let cfs_opts = LARGE_CFS
+            .iter()
+            .map(|cf| CFOptions::new(cf, cf_opts.clone()))
+            .collect();
+        let db = rocksdb_util::new_engine_opt(path_str, db_opts, cfs_opts).unwrap();
+
+        let write_cf = db.cf_handle(CF_WRITE).unwrap();
+        let cases = [("a", 1), ("b", 4), ("c", 10)];
+        for &(key, versions) in &cases {
+            for ts in 0..versions {
+                let key = keys::data_key(Key::from_raw(key.as_bytes()).append_ts(ts).encoded());
+                let write_v = Write::new(WriteType::Put, ts, None).to_bytes();
+                db.put_cf(write_cf, &key, &write_v).unwrap();
+            }
+        }
+        db.flush_cf(write_cf, true).unwrap();
+
+        let region = make_region(1, vec![], vec![]);
+        let res = get_region_approximate_versions(&db, &region).unwrap();
+        assert_eq!(res, (3, 15));
+
+        let region = make_region(1, vec![], Key::from_raw(b"b").take_encoded());
+        let res = get_region_approximate_versions(&db, &region).unwrap();
+        // The whole SST file is counted.
+        assert_eq!(res, (3, 15));
+    }
+
+    #[test]
+    fn test_region_approximate_keys_by_range_properties() {
+        let path = TempDir::new("_test_region_approximate_keys_by_range").expect("");
+        let path_str = path.path().to_str().unwrap();
+        let db_opts = DBOptions::new();
+        let mut cf_opts = ColumnFamilyOptions::new();
+        cf_opts.set_level_zero_file_num_compaction_trigger(10);
+        let f = Box::new(RangePropertiesCollectorFactory::default());
+        cf_opts.add_table_properties_collector_factory("tikv.range-collector", f);
+        let cfs_opts = LARGE_CFS
+            .iter()
+            .map(|cf| CFOptions::new(cf, cf_opts.clone()))
+            .collect();
+        let db = rocksdb_util::new_engine_opt(path_str, db_opts, cfs_opts).unwrap();
+
+        let write_cf = db.cf_handle(CF_WRITE).unwrap();
+        for i in 0..10 {
+            let key = format!("k{}", i);
+            let key = keys::data_key(Key::from_raw(key.as_bytes()).append_ts(2).encoded());
+            let write_v = Write::new(WriteType::Put, 0, None).to_bytes();
+            db.put_cf(write_cf, &key, &write_v).unwrap();
+        }
+        db.flush_cf(write_cf, true).unwrap();
+
+        let region = make_region(1, vec![], vec![]);
+        let region_keys = get_region_approximate_keys_cf(&db, CF_WRITE, &region).unwrap();
+        assert_eq!(region_keys, 10);
+        let region_keys = get_region_approximate_keys(&db, &region).unwrap();
+        assert_eq!(region_keys, 10);
+    }
+
+    #[test]
+    fn test_region_recent_write_ratio() {
+        let path = TempDir::new("_test_region_recent_write_ratio").expect("");
+        let path_str = path.path().to_str().unwrap();
+        let db_opts = DBOptions::new();
+        let mut cf_opts = ColumnFamilyOptions::new();
+        cf_opts.set_level_zero_file_num_compaction_trigger(10);
+        let f = Box::new(RangePropertiesCollectorFactory::default());
+        cf_opts.add_table_properties_collector_factory("tikv.range-collector", f);
+        let cfs_opts = LARGE_CFS
+            .iter()
+            .map(|cf| CFOptions::new(cf, cf_opts.clone()))
+            .collect();
+        let db = rocksdb_util::new_engine_opt(path_str, db_opts, cfs_opts).unwrap();
+
+        // Every key is in a block of its own, committed at increasing time.
+        let write_cf = db.cf_handle(CF_WRITE).unwrap();
+        let value = vec![0; 4 * 1024 * 1024];
+        let now_ms = time_now_sec() * 1000;
+        for i in 0..10 {
+            let commit_ms = now_ms - (10 - i) * 3600 * 1000;
+            let commit_ts = commit_ms << TSO_PHYSICAL_SHIFT_BITS;
+            let key = Key::from_raw(format!("k{}", i).as_bytes()).append_ts(commit_ts);
+            db.put_cf(write_cf, &keys::data_key(key.encoded()), &value)
+                .unwrap();
+        }
+        db.flush_cf(write_cf, true).unwrap();
+
+        let region = make_region(1, vec![], vec![]);
+        // None of the keys is written recently.
+        let ratio = get_region_recent_write_ratio(&db, &region).unwrap();
+        assert!(ratio.abs() < 0.01, "{}", ratio);
+        let since_ts = |hours: u64| (now_ms - hours * 3600 * 1000) << TSO_PHYSICAL_SHIFT_BITS;
+        let ratio = get_region_write_ratio_since(&db, &region, since_ts(5)).unwrap();
+        assert!((ratio - 0.5).abs() < 0.01, "{}", ratio);
+
+        // The data in memtables is recent.
+        let key = Key::from_raw(b"k10").append_ts(now_ms << TSO_PHYSICAL_SHIFT_BITS);
+        db.put_cf(write_cf, &keys::data_key(key.encoded()), &value)
+            .unwrap();
+        let ratio = get_region_recent_write_ratio(&db, &region).unwrap();
+        assert!(ratio > 0.0 && ratio < 0.5, "{}", ratio);
+        db.flush_cf(write_cf, true).unwrap();
+        let ratio = get_region_recent_write_ratio(&db, &region).unwrap();
+        assert!((ratio - 1.0 / 11.0).abs() < 0.01, "{}", ratio);
+    }
+
     #[test]
     fn test_region_approximate_size() {
         let path = TempDir::new("_test_raftstore_region_approximate_size").expect("");
@@ -1085,6 +1529,133 @@ mod tests {
             let size = get_region_approximate_size_cf(&db, cfname, &region).unwrap();
             assert_eq!(size, cf_size);
         }
+
+        // The whole region is the same as the range of its data keys.
+        let (start, end) = (keys::enc_start_key(&region), keys::enc_end_key(&region));
+        let size = get_range_approximate_size(&db, &start, &end).unwrap();
+        assert_eq!(size, cf_size * LARGE_CFS.len() as u64);
+
+        // The sub range only contains "b".
+        let (start, end) = (keys::data_key(b"a0"), keys::data_key(b"b0"));
+        let size = get_range_approximate_size(&db, &start, &end).unwrap();
+        assert_eq!(size, (2 + 2048) * LARGE_CFS.len() as u64);
+        for cfname in LARGE_CFS {
+            let size = get_range_approximate_size_cf(&db, cfname, &start, &end).unwrap();
+            assert_eq!(size, 2 + 2048);
+        }
+    }
+
+    #[test]
+    fn test_region_approximate_size_in_memtable() {
+        let path = TempDir::new("_test_raftstore_region_approximate_size_in_memtable").expect("");
+        let path_str = path.path().to_str().unwrap();
+        let db = rocksdb_util::new_engine(path_str, LARGE_CFS, None).unwrap();
+        let region = make_region(1, vec![], vec![]);
+        assert_eq!(get_region_approximate_size(&db, &region).unwrap(), 0);
+
+        // No SST file is written without flush.
+        for i in 0..100 {
+            let k = keys::data_key(format!("k{:03}", i).as_bytes());
+            db.put(&k, &[0; 1024]).unwrap();
+        }
+        let sst_size = get_region_approximate_sst_size_cf(&db, CF_DEFAULT, &region).unwrap();
+        assert_eq!(sst_size, 0);
+        let memtable_size =
+            get_region_approximate_memtable_size_cf(&db, CF_DEFAULT, &region).unwrap();
+        assert!(memtable_size > 0);
+        let size = get_region_approximate_size(&db, &region).unwrap();
+        assert_eq!(size, memtable_size);
+    }
+
+    #[test]
+    fn test_region_approximate_size_errors() {
+        let path = TempDir::new("_test_raftstore_region_approximate_size_errors").expect("");
+        let path_str = path.path().to_str().unwrap();
+        let region = make_region(1, vec![], vec![]);
+
+        // The SST files are written without size properties.
+        let db = rocksdb_util::new_engine(path_str, LARGE_CFS, None).unwrap();
+        let k = keys::data_key(b"k1");
+        db.put(&k, &[0; 1024]).unwrap();
+        db.flush(true).unwrap();
+        match get_region_approximate_size(&db, &region) {
+            Err(e @ ApproximateSizeError::NoProperties) => assert!(!e.is_transient()),
+            res => panic!("expect no properties, got {:?}", res),
+        }
+        drop(db);
+
+        // The CF is missing.
+        let path = TempDir::new("_test_raftstore_region_approximate_size_errors_cf").expect("");
+        let path_str = path.path().to_str().unwrap();
+        let db = rocksdb_util::new_engine(path_str, &[CF_DEFAULT], None).unwrap();
+        match get_region_approximate_size(&db, &region) {
+            Err(e @ ApproximateSizeError::CfNotFound(_)) => assert!(!e.is_transient()),
+            res => panic!("expect cf not found, got {:?}", res),
+        }
+        assert!(ApproximateSizeError::Io("disk hiccup".to_owned()).is_transient());
+
+        // Only missing properties are distinguished from corrupted ones.
+        let e = ApproximateSizeError::from(codec::Error::KeyNotFound);
+        assert!(match e {
+            ApproximateSizeError::NoProperties => true,
+            _ => false,
+        });
+        let e = ApproximateSizeError::from(codec::Error::KeyLength);
+        assert!(!e.is_transient());
+        assert!(match e {
+            ApproximateSizeError::Decode(_) => true,
+            _ => false,
+        });
+    }
+
+    #[test]
+    fn test_region_approximate_size_fast() {
+        let path = TempDir::new("_test_raftstore_region_approximate_size_fast").expect("");
+        let path_str = path.path().to_str().unwrap();
+        let db_opts = DBOptions::new();
+        let mut cf_opts = ColumnFamilyOptions::new();
+        let f = Box::new(RangePropertiesCollectorFactory::default());
+        cf_opts.add_table_properties_collector_factory("tikv.range-collector", f);
+        let cfs_opts = LARGE_CFS
+            .iter()
+            .map(|cf| CFOptions::new(cf, cf_opts.clone()))
+            .collect();
+        let db = rocksdb_util::new_engine_opt(path_str, db_opts, cfs_opts).unwrap();
+
+        // Random values can't be compressed, so the sizes on disk are close
+        // to the logical sizes.
+        let mut rng = thread_rng();
+        for cfname in LARGE_CFS {
+            let cf = db.cf_handle(cfname).unwrap();
+            for i in 0..1000 {
+                let k = keys::data_key(format!("{:04}", i).as_bytes());
+                let mut v = vec![0; 1024];
+                rng.fill_bytes(&mut v);
+                db.put_cf(cf, &k, &v).unwrap();
+            }
+            db.flush_cf(cf, true).unwrap();
+        }
+
+        let region = make_region(1, vec![], vec![]);
+        for cfname in LARGE_CFS {
+            let exact = get_region_approximate_size_cf(&db, cfname, &region).unwrap();
+            let fast = get_region_approximate_size_fast_cf(&db, cfname, &region).unwrap();
+            assert!(
+                fast > exact / 2 && fast < exact * 2,
+                "fast {} exact {}",
+                fast,
+                exact
+            );
+        }
+
+        // The range without any key is empty in both ways.
+        let region = make_region(1, b"a".to_vec(), b"b".to_vec());
+        for cfname in LARGE_CFS {
+            let exact = get_region_approximate_size_cf(&db, cfname, &region).unwrap();
+            let fast = get_region_approximate_size_fast_cf(&db, cfname, &region).unwrap();
+            assert_eq!(exact, 0);
+            assert!(fast < 1024, "fast {}", fast);
+        }
     }
 
     fn check_data(db: &DB, cfs: &[&str], expected: &[(&[u8], &[u8])]) {
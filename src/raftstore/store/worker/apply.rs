@@ -1794,6 +1794,10 @@ impl ApplyDelegate {
             return Err(e);
         }
 
+        // Don't split the region before the file is ingested.
+        let range = sst.get_range();
+        ctx.host
+            .register_ingest_range(range.get_start(), range.get_end());
         ctx.importer
             .ingest(sst, &self.engines.kv)
             .unwrap_or_else(|e| {
@@ -1801,6 +1805,8 @@ impl ApplyDelegate {
                 // is wrong with the engine, but we can do nothing about that.
                 panic!("{} ingest {:?}: {:?}", self.tag, sst, e);
             });
+        ctx.host
+            .unregister_ingest_range(range.get_start(), range.get_end());
 
         ssts.push(sst.clone());
         Ok(Response::new())
@@ -11,7 +11,9 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use prometheus::{exponential_buckets, Gauge, Histogram, HistogramVec, IntCounterVec};
+use prometheus::{
+    exponential_buckets, Gauge, Histogram, HistogramVec, IntCounter, IntCounterVec, IntGauge,
+};
 
 lazy_static! {
     pub static ref SNAP_COUNTER_VEC: IntCounterVec = register_int_counter_vec!(
@@ -35,6 +37,45 @@ lazy_static! {
         "Bucketed histogram of raftstore split check duration",
         exponential_buckets(0.0005, 2.0, 20).unwrap()
     ).unwrap();
+    pub static ref SPLIT_CHECK_DURATION_HISTOGRAM: HistogramVec = register_histogram_vec!(
+        "tikv_raftstore_split_check_stage_duration_seconds",
+        "Bucketed histogram of raftstore split check duration of each stage",
+        &["type", "reason"],
+        exponential_buckets(0.0005, 2.0, 20).unwrap()
+    ).unwrap();
+    pub static ref CHECK_SPILT_THROTTLE_COUNTER: IntCounter = register_int_counter!(
+        "tikv_raftstore_check_split_throttle_total",
+        "Total number of raftstore split check scans paused by the IO limiter."
+    ).unwrap();
+    pub static ref UNSPLITTABLE_REGION_COUNTER: IntCounter = register_int_counter!(
+        "tikv_raftstore_check_split_unsplittable_region_total",
+        "Total number of regions found by raftstore split check that contain only one large key."
+    ).unwrap();
+    pub static ref INVALID_SPLIT_KEY_COUNTER: IntCounter = register_int_counter!(
+        "tikv_raftstore_check_split_invalid_key_total",
+        "Total number of split keys out of the region found by raftstore split check."
+    ).unwrap();
+    pub static ref SPLIT_CHECK_SCANNED_BYTES_TOTAL: IntCounter = register_int_counter!(
+        "tikv_raftstore_check_split_scanned_bytes_total",
+        "Total bytes of kvs scanned by raftstore split check."
+    ).unwrap();
+    pub static ref SPLIT_CHECK_SCAN_ERROR_COUNTER: IntCounter = register_int_counter!(
+        "tikv_raftstore_check_split_scan_error_total",
+        "Total number of raftstore split check scans aborted by engine errors."
+    ).unwrap();
+    pub static ref SPLIT_CHECK_TIMEOUT_COUNTER: IntCounter = register_int_counter!(
+        "tikv_raftstore_check_split_timeout_total",
+        "Total number of raftstore split check scans aborted by the deadline."
+    ).unwrap();
+    pub static ref SPLIT_CHECK_SIZE_RATIO_HISTOGRAM: Histogram = register_histogram!(
+        "tikv_raftstore_check_split_size_ratio",
+        "Bucketed histogram of the scanned size of regions divided by their approximate size",
+        exponential_buckets(0.01, 2.0, 16).unwrap()
+    ).unwrap();
+    pub static ref SPLIT_CHECK_PENDING_TASKS: IntGauge = register_int_gauge!(
+        "tikv_raftstore_check_split_pending_tasks",
+        "Number of raftstore split check tasks scheduled but not finished yet."
+    ).unwrap();
     pub static ref COMPACT_RANGE_CF: HistogramVec = register_histogram_vec!(
         "tikv_compact_range_cf_duration_seconds",
         "Bucketed histogram of compact range for cf execution",
@@ -64,4 +64,8 @@ pub use self::consistency_check::{Runner as ConsistencyCheckRunner, Task as Cons
 pub use self::raftlog_gc::{Runner as RaftlogGcRunner, Task as RaftlogGcTask};
 pub use self::read::{LocalReader, Progress as ReadProgress, Task as ReadTask};
 pub use self::region::{Runner as RegionRunner, Task as RegionTask, STALE_PEER_CHECK_INTERVAL};
-pub use self::split_check::{KeyEntry, Runner as SplitCheckRunner, Task as SplitCheckTask};
+pub use self::split_check::{
+    split_keys_of_entries, EntryIterator, KeyEntry, Runner as SplitCheckRunner, SplitCheckReason,
+    SplitEvent, SplitEventSink, SplitHistory, Task as SplitCheckTask, VecEntryIterator,
+    DEFERRED_SPLIT_CHECK_INTERVAL,
+};
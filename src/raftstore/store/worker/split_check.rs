@@ -11,28 +11,322 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::cmp::Ordering;
-use std::collections::BinaryHeap;
+use std::cmp::{self, Ordering};
+use std::collections::{BinaryHeap, VecDeque};
 use std::fmt::{self, Display, Formatter};
 use std::mem;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering as AtomicOrdering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use std::vec;
 
 use kvproto::metapb::Region;
 use kvproto::metapb::RegionEpoch;
 use kvproto::pdpb::CheckPolicy;
-use rocksdb::{DBIterator, DB};
+use prometheus::IntGauge;
+use rocksdb::{DBIterator, SeekKey, DB};
 
-use raftstore::coprocessor::CoprocessorHost;
+use raftstore::coprocessor::{
+    CoprocessorHost, QuantileChecker, RegionState, SplitCheckContext, SplitCheckerHost,
+};
 use raftstore::store::engine::{IterOption, Iterable};
-use raftstore::store::{keys, Callback, Msg};
-use raftstore::Result;
-use storage::{CfName, CF_WRITE, LARGE_CFS};
+use raftstore::store::{keys, util, Callback, Msg, SplitCheckResult};
+use raftstore::{Error, Result};
+use storage::{CfName, Key, CF_WRITE, LARGE_CFS};
+use util::collections::HashMap;
 use util::escape;
+use util::io_limiter::IOLimiter;
+use util::time::{duration_to_ms, duration_to_sec};
+use util::timer::Timer;
 use util::transport::{RetryableSendCh, Sender};
-use util::worker::Runnable;
+use util::worker::{Runnable, RunnableWithTimer, Scheduler};
 
 use super::metrics::*;
 
+// Requesting IO from the limiter takes longer than this means it's throttled.
+const THROTTLE_THRESHOLD_MS: u64 = 1;
+// Retry policy of sending the split keys when the channel is full.
+const SPLIT_SEND_MAX_RETRIES: usize = 10;
+const SPLIT_SEND_BACKOFF_MS: u64 = 100;
+// Outdated check times are cleaned up once this many regions are recorded.
+const LAST_CHECK_TIME_CAPACITY: usize = 4096;
+// The same split keys of a region are not sent again within this duration.
+const DUPLICATED_SPLIT_WINDOW_SECS: u64 = 10;
+// Max number of split keys kept in the split history.
+const SPLIT_HISTORY_CAPACITY: usize = 256;
+// The weight of the latest duration in the moving average of durations.
+const RUN_DURATION_EWMA_ALPHA: f64 = 0.2;
+
+pub const DEFERRED_SPLIT_CHECK_INTERVAL: u64 = 100; // milliseconds
+
+// The split keys sent for a region last time.
+struct LastSplit {
+    region: Region,
+    split_keys: Vec<Vec<u8>>,
+    time: Instant,
+}
+
+/// The split keys recently sent by the runner, the oldest ones are dropped
+/// once it's full. It's only kept in memory for debugging.
+#[derive(Clone, Default)]
+pub struct SplitHistory {
+    records: Arc<Mutex<VecDeque<(u64, Instant, Vec<u8>)>>>,
+}
+
+impl SplitHistory {
+    fn record(&self, region_id: u64, time: Instant, split_keys: &[Vec<u8>]) {
+        let mut records = self.records.lock().unwrap();
+        for key in split_keys {
+            if records.len() >= SPLIT_HISTORY_CAPACITY {
+                records.pop_front();
+            }
+            records.push_back((region_id, time, keys::origin_key(key).to_vec()));
+        }
+    }
+
+    /// Get the `(region_id, time, split_key)` of the recent splits from the
+    /// oldest to the newest. The keys are in the same form as region keys.
+    pub fn records(&self) -> Vec<(u64, Instant, Vec<u8>)> {
+        self.records.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+// The statistics of a split check scan.
+struct ScanStats {
+    scanned_size: u64,
+    scanned_keys: u64,
+    max_key_len: u64,
+    max_value_len: u64,
+    // Whether all the keys in the range are scanned.
+    exhausted: bool,
+    // The last scanned key if the scan stops at the budget of a chunk, and
+    // the CFs of its entries scanned.
+    last_key: Option<Vec<u8>>,
+    last_key_cfs: Vec<CfName>,
+    // Set if all the scanned entries belong to one user key.
+    single_user_key: Option<Vec<u8>>,
+    // Whether the checkers found any split key, vetoed ones included.
+    found_split: bool,
+    estimated_splits: u64,
+    buckets: Vec<Vec<u8>>,
+    cfs: Vec<CfName>,
+    // Number of scanned entries of each CF.
+    cf_entries: HashMap<CfName, u64>,
+}
+
+struct FoundSplitKeys {
+    split_keys: Vec<Vec<u8>>,
+    // Only set by `CheckPolicy::SCAN`.
+    scan: Option<ScanStats>,
+}
+
+// Decides how the entries of a scan are fed to the checkers, and when the
+// scan stops early.
+struct ScanControl<'a> {
+    batch_size: usize,
+    // The scan stops after chunk_size bytes unless it's 0.
+    chunk_size: u64,
+    limiter: Option<&'a IOLimiter>,
+    stopped: Option<&'a AtomicBool>,
+    // The source of the time of the deadline and the throttling.
+    clock: &'a Clock,
+    deadline: Option<Instant>,
+    // The entries are fed to the checkers scanning in reverse order.
+    reversed: bool,
+    // The entries of the key in the CFs are skipped, they are fed to the
+    // checkers by the previous chunk.
+    skip: Option<(&'a [u8], &'a [CfName])>,
+}
+
+impl<'a> ScanControl<'a> {
+    fn new(batch_size: usize) -> ScanControl<'a> {
+        ScanControl {
+            batch_size: cmp::max(batch_size, 1),
+            chunk_size: 0,
+            limiter: None,
+            stopped: None,
+            clock: &MonotonicClock,
+            deadline: None,
+            reversed: false,
+            skip: None,
+        }
+    }
+
+    fn is_stopped(&self) -> bool {
+        self.stopped
+            .map_or(false, |stopped| stopped.load(AtomicOrdering::Relaxed))
+    }
+
+    fn is_timed_out(&self) -> bool {
+        self.deadline.map_or(false, |deadline| self.clock.now() >= deadline)
+    }
+}
+
+// The progress of feeding the entries of a scan to the checkers.
+#[derive(Default)]
+struct ScanProgress {
+    scanned_size: u64,
+    scanned_keys: u64,
+    max_key_len: u64,
+    max_value_len: u64,
+    // Only need to know whether there is more than one user key.
+    first_user_key: Vec<u8>,
+    distinct_user_keys: u64,
+    cf_entries: HashMap<CfName, u64>,
+    exhausted: bool,
+    stopped: bool,
+    timed_out: bool,
+    // The engine error hit in the middle of the scan, along with the last
+    // key fed to the checkers before it.
+    scan_error: Option<(Error, Option<Vec<u8>>)>,
+    // The last key of the chunk is kept if the budget is used up, along with
+    // the CFs of its entries fed to the checkers.
+    last_key: Option<Vec<u8>>,
+    last_key_cfs: Vec<CfName>,
+}
+
+// Feeds the entries of `iter` to the checkers in batches, until any of the
+// checkers aborts the scan or the entries are exhausted.
+fn scan_entries(
+    iter: &mut EntryIterator,
+    host: &mut SplitCheckerHost,
+    region: &Region,
+    ctl: &ScanControl,
+) -> ScanProgress {
+    let mut progress = ScanProgress::default();
+    let mut pending_bytes = 0;
+    let mut batch = Vec::with_capacity(ctl.batch_size);
+    let mut skip = ctl.skip;
+    // The entries of a key may span batches, so the last key of the previous
+    // batch is kept.
+    let (mut prev_key, mut key_cfs): (Vec<u8>, Vec<CfName>) = (vec![], vec![]);
+    loop {
+        while batch.len() < ctl.batch_size {
+            let e = match iter.next() {
+                Ok(Some(e)) => e,
+                Ok(None) => break,
+                Err(e) => {
+                    let last_key = batch.last().map(|e: &KeyEntry| e.key().to_vec());
+                    progress.scan_error = Some((e, last_key));
+                    return progress;
+                }
+            };
+            if let Some((key, cfs)) = skip {
+                if e.key() != key {
+                    skip = None;
+                } else if cfs.contains(&e.cf()) {
+                    continue;
+                }
+            }
+            if batch.last().map_or(prev_key.as_slice(), |e: &KeyEntry| e.key()) != e.key() {
+                key_cfs.clear();
+            }
+            key_cfs.push(e.cf());
+            let size = e.entry_size() as u64;
+            progress.scanned_size += size;
+            progress.scanned_keys += 1;
+            *progress.cf_entries.entry(e.cf()).or_insert(0) += 1;
+            progress.max_key_len = cmp::max(progress.max_key_len, e.key().len() as u64);
+            progress.max_value_len = cmp::max(progress.max_value_len, e.value_size() as u64);
+            if progress.distinct_user_keys < 2 {
+                let user_key = Key::truncate_ts_for(e.key()).unwrap_or(e.key());
+                if progress.distinct_user_keys == 0 {
+                    progress.first_user_key = user_key.to_vec();
+                    progress.distinct_user_keys = 1;
+                } else if user_key != progress.first_user_key.as_slice() {
+                    progress.distinct_user_keys = 2;
+                }
+            }
+            if let Some(limiter) = ctl.limiter {
+                pending_bytes += size;
+                request_io(limiter, ctl.clock, &mut pending_bytes, false);
+            }
+            batch.push(e);
+        }
+        if batch.is_empty() {
+            progress.exhausted = true;
+            break;
+        }
+        if ctl.is_stopped() {
+            progress.stopped = true;
+            return progress;
+        }
+        if ctl.is_timed_out() {
+            progress.timed_out = true;
+            return progress;
+        }
+        let abort = if ctl.reversed {
+            host.on_kvs_reversed(region, &batch)
+        } else {
+            host.on_kvs(region, &batch)
+        };
+        if abort {
+            break;
+        }
+        // A partial batch means the iterator is exhausted.
+        if batch.len() < ctl.batch_size {
+            progress.exhausted = true;
+            break;
+        }
+        if ctl.chunk_size > 0 && progress.scanned_size >= ctl.chunk_size {
+            progress.last_key = batch.pop().map(|e| e.key);
+            progress.last_key_cfs = key_cfs;
+            break;
+        }
+        prev_key = batch.pop().unwrap().key;
+        batch.clear();
+    }
+    if let Some(limiter) = ctl.limiter {
+        request_io(limiter, ctl.clock, &mut pending_bytes, true);
+    }
+    progress
+}
+
+// Logs why the scan is interrupted if it is. Splitting on a partial view of
+// the region may produce bad split keys, so the caller should give up and
+// wait for the next check.
+fn is_scan_interrupted(region_id: u64, progress: &ScanProgress) -> bool {
+    if let Some((ref e, ref last_key)) = progress.scan_error {
+        SPLIT_CHECK_SCAN_ERROR_COUNTER.inc();
+        error!(
+            "[region {}] failed to scan split key after {}: {}",
+            region_id,
+            last_key.as_ref().map_or_else(|| "none".to_owned(), |k| escape(k)),
+            e
+        );
+        return true;
+    }
+    if progress.timed_out {
+        SPLIT_CHECK_TIMEOUT_COUNTER.inc();
+        warn!(
+            "[region {}] split check times out after scanning {} bytes",
+            region_id, progress.scanned_size
+        );
+        return true;
+    }
+    if progress.stopped {
+        info!("[region {}] split check is stopped", region_id);
+        return true;
+    }
+    false
+}
+
+/// Feed the entries of `iter` in ascending order to the checkers of `host`
+/// in batches of `batch_size` like split check does, and get the split keys
+/// of the first checker that has found any. No storage engine is needed.
+pub fn split_keys_of_entries(
+    host: &mut SplitCheckerHost,
+    region: &Region,
+    iter: &mut EntryIterator,
+    batch_size: usize,
+) -> Result<Vec<Vec<u8>>> {
+    let progress = scan_entries(iter, host, region, &ScanControl::new(batch_size));
+    if let Some((e, _)) = progress.scan_error {
+        return Err(e);
+    }
+    Ok(host.split_keys())
+}
+
 #[derive(PartialEq, Eq)]
 pub struct KeyEntry {
     key: Vec<u8>,
@@ -55,6 +349,14 @@ impl KeyEntry {
         self.key.as_ref()
     }
 
+    pub fn cf(&self) -> CfName {
+        self.cf
+    }
+
+    pub fn value_size(&self) -> usize {
+        self.value_size
+    }
+
     pub fn is_commit_version(&self) -> bool {
         self.cf == CF_WRITE
     }
@@ -67,7 +369,9 @@ impl KeyEntry {
 impl PartialOrd for KeyEntry {
     fn partial_cmp(&self, rhs: &KeyEntry) -> Option<Ordering> {
         // BinaryHeap is max heap, so we have to reverse order to get a min heap.
-        Some(self.key.cmp(&rhs.key).reverse())
+        // Entries of the same key are ordered by the positions of their CFs,
+        // so the order doesn't depend on the state of the heap.
+        Some(self.key.cmp(&rhs.key).then(self.pos.cmp(&rhs.pos)).reverse())
     }
 }
 
@@ -77,6 +381,47 @@ impl Ord for KeyEntry {
     }
 }
 
+/// A source of the entries scanned by split check, in ascending order of
+/// their data keys unless the scan is reversed. It decouples the checkers
+/// from the storage engine.
+pub trait EntryIterator {
+    /// Get the next entry, None if there are no more.
+    fn next(&mut self) -> Result<Option<KeyEntry>>;
+}
+
+/// Yields the entries of a vector in order, so the checkers can be run
+/// without a storage engine.
+pub struct VecEntryIterator {
+    entries: vec::IntoIter<KeyEntry>,
+}
+
+impl VecEntryIterator {
+    pub fn new(entries: Vec<KeyEntry>) -> VecEntryIterator {
+        VecEntryIterator {
+            entries: entries.into_iter(),
+        }
+    }
+}
+
+impl EntryIterator for VecEntryIterator {
+    fn next(&mut self) -> Result<Option<KeyEntry>> {
+        Ok(self.entries.next())
+    }
+}
+
+// Gets the options of the iterators scanning `[start_key, end_key)`.
+fn scan_iter_opt(
+    start_key: &[u8],
+    end_key: &[u8],
+    fill_cache: bool,
+    readahead_size: usize,
+) -> IterOption {
+    IterOption::new(Some(start_key.to_vec()), Some(end_key.to_vec()), fill_cache)
+        .set_readahead_size(readahead_size)
+}
+
+// Merges the iterators of CFs, the entries are yielded in ascending order of
+// their data keys no matter which CFs they come from.
 struct MergedIterator<'a> {
     iters: Vec<(CfName, DBIterator<&'a DB>)>,
     heap: BinaryHeap<KeyEntry>,
@@ -89,12 +434,12 @@ impl<'a> MergedIterator<'a> {
         start_key: &[u8],
         end_key: &[u8],
         fill_cache: bool,
+        readahead_size: usize,
     ) -> Result<MergedIterator<'a>> {
         let mut iters = Vec::with_capacity(cfs.len());
         let mut heap = BinaryHeap::with_capacity(cfs.len());
         for (pos, cf) in cfs.into_iter().enumerate() {
-            let iter_opt =
-                IterOption::new(Some(start_key.to_vec()), Some(end_key.to_vec()), fill_cache);
+            let iter_opt = scan_iter_opt(start_key, end_key, fill_cache, readahead_size);
             let mut iter = db.new_iterator_cf(cf, iter_opt)?;
             if iter.seek(start_key.into()) {
                 heap.push(KeyEntry::new(
@@ -108,10 +453,13 @@ impl<'a> MergedIterator<'a> {
         }
         Ok(MergedIterator { iters, heap })
     }
+}
 
-    fn next(&mut self) -> Option<KeyEntry> {
+impl<'a> EntryIterator for MergedIterator<'a> {
+    fn next(&mut self) -> Result<Option<KeyEntry>> {
+        fail_point!("split_check_scan_error", |_| Err(box_err!("injected scan error")));
         let pos = match self.heap.peek() {
-            None => return None,
+            None => return Ok(None),
             Some(e) => e.pos,
         };
         let (cf, iter) = &mut self.iters[pos];
@@ -120,18 +468,186 @@ impl<'a> MergedIterator<'a> {
             let mut e = KeyEntry::new(iter.key().to_vec(), pos, iter.value().len(), cf);
             let mut front = self.heap.peek_mut().unwrap();
             mem::swap(&mut e, &mut front);
-            Some(e)
+            Ok(Some(e))
+        } else {
+            // The iterator becomes invalid on errors too.
+            if let Err(e) = iter.status() {
+                return Err(box_err!("failed to iterate cf {}: {}", cf, e));
+            }
+            Ok(self.heap.pop())
+        }
+    }
+}
+
+// Like `MergedIterator`, but yields the keys in descending order.
+struct ReversedMergedIterator<'a> {
+    iters: Vec<(CfName, DBIterator<&'a DB>)>,
+    heap: BinaryHeap<cmp::Reverse<KeyEntry>>,
+}
+
+impl<'a> ReversedMergedIterator<'a> {
+    fn new(
+        db: &'a DB,
+        cfs: &[CfName],
+        start_key: &[u8],
+        end_key: &[u8],
+        fill_cache: bool,
+        readahead_size: usize,
+    ) -> Result<ReversedMergedIterator<'a>> {
+        let mut iters = Vec::with_capacity(cfs.len());
+        let mut heap = BinaryHeap::with_capacity(cfs.len());
+        for (pos, cf) in cfs.into_iter().enumerate() {
+            let iter_opt = scan_iter_opt(start_key, end_key, fill_cache, readahead_size);
+            let mut iter = db.new_iterator_cf(cf, iter_opt)?;
+            if iter.seek(SeekKey::End) {
+                heap.push(cmp::Reverse(KeyEntry::new(
+                    iter.key().to_vec(),
+                    pos,
+                    iter.value().len(),
+                    *cf,
+                )));
+            }
+            iters.push((*cf, iter));
+        }
+        Ok(ReversedMergedIterator { iters, heap })
+    }
+}
+
+impl<'a> EntryIterator for ReversedMergedIterator<'a> {
+    fn next(&mut self) -> Result<Option<KeyEntry>> {
+        let pos = match self.heap.peek() {
+            None => return Ok(None),
+            Some(e) => e.0.pos,
+        };
+        let (cf, iter) = &mut self.iters[pos];
+        if iter.prev() {
+            let mut e = KeyEntry::new(iter.key().to_vec(), pos, iter.value().len(), cf);
+            let mut front = self.heap.peek_mut().unwrap();
+            mem::swap(&mut e, &mut front.0);
+            Ok(Some(e))
         } else {
-            self.heap.pop()
+            if let Err(e) = iter.status() {
+                return Err(box_err!("failed to iterate cf {}: {}", cf, e));
+            }
+            Ok(self.heap.pop().map(|e| e.0))
+        }
+    }
+}
+
+/// Why a split check is triggered.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SplitCheckReason {
+    /// The region's size may exceed the threshold.
+    Size,
+    /// The region's keys may exceed the threshold.
+    Keys,
+    /// Requested explicitly, by PD or an operator.
+    Manual,
+    /// The region's size may be changed by a compaction.
+    PostCompaction,
+}
+
+impl SplitCheckReason {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            SplitCheckReason::Size => "size",
+            SplitCheckReason::Keys => "keys",
+            SplitCheckReason::Manual => "manual",
+            SplitCheckReason::PostCompaction => "post_compaction",
         }
     }
 }
 
+/// A split decision of the runner, it's sent to the event sink of the runner
+/// after the split keys are sent to raftstore.
+#[derive(Clone, Debug)]
+pub struct SplitEvent {
+    pub region_id: u64,
+    pub epoch: RegionEpoch,
+    pub reason: SplitCheckReason,
+    /// The split keys in the same form as the region's keys.
+    pub split_keys: Vec<Vec<u8>>,
+    /// The size scanned to find the split keys, 0 if the region isn't
+    /// scanned.
+    pub scanned_size: u64,
+    /// How long it takes to find the split keys.
+    pub duration: Duration,
+}
+
+/// Receives the split events of the runner, it's called by the thread of the
+/// runner so it should return quickly.
+pub type SplitEventSink = Box<Fn(SplitEvent) + Send>;
+
+/// The source of time of the runner, all the timing of split checks goes
+/// through it so tests can control it.
+pub trait Clock: Send {
+    fn now(&self) -> Instant;
+}
+
+/// The monotonic clock of the system.
+#[derive(Clone, Copy, Default)]
+pub struct MonotonicClock;
+
+impl Clock for MonotonicClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+// Counts a task in the gauge until the task is dropped.
+struct PendingGuard(IntGauge);
+
+impl PendingGuard {
+    fn new(gauge: IntGauge) -> PendingGuard {
+        gauge.inc();
+        PendingGuard(gauge)
+    }
+}
+
+impl Drop for PendingGuard {
+    fn drop(&mut self) {
+        self.0.dec();
+    }
+}
+
 /// Split checking task.
 pub struct Task {
     region: Region,
     auto_split: bool,
     policy: CheckPolicy,
+    // The `[start, end)` key range to check, only used by `CheckPolicy::SCAN`.
+    range: Option<(Vec<u8>, Vec<u8>)>,
+    // Only log the split keys instead of splitting the region.
+    dry_run: bool,
+    region_state: RegionState,
+    // Whether the local peer is the leader when the task is scheduled.
+    is_leader: bool,
+    reason: SplitCheckReason,
+    // Tasks of other regions run with the task, their results are sent
+    // together.
+    batch: Vec<Task>,
+    // The data key to resume the scan from, the CFs whose entries of the key
+    // are scanned, and the size scanned before it.
+    resume_from: Option<Vec<u8>>,
+    resumed_cfs: Vec<CfName>,
+    resumed_size: u64,
+    // The task is deferred until this long after the runner receives it.
+    delay: Option<Duration>,
+    // The task is deferred if it's run before this time.
+    not_before: Option<Instant>,
+    // The scan is aborted if it's still running at this time.
+    deadline: Option<Instant>,
+    // A policy defined by observers, it's passed to them in the context.
+    custom_policy: Option<u32>,
+    // Split the region into this many parts of equal size instead of
+    // running the checkers of observers.
+    split_count: Option<u64>,
+    pending: Option<PendingGuard>,
+    // Split keys given by operators, the region is split at them directly.
+    split_keys: Option<Vec<Vec<u8>>>,
+    // Whether the last split sent for the region is applied, no check is
+    // run for the task if it's set.
+    split_ack: Option<bool>,
 }
 
 impl Task {
@@ -140,17 +656,179 @@ impl Task {
             region,
             auto_split,
             policy,
+            range: None,
+            dry_run: false,
+            region_state: RegionState::Normal,
+            is_leader: true,
+            reason: SplitCheckReason::Size,
+            batch: vec![],
+            resume_from: None,
+            resumed_cfs: vec![],
+            resumed_size: 0,
+            delay: None,
+            not_before: None,
+            deadline: None,
+            custom_policy: None,
+            split_count: None,
+            pending: None,
+            split_keys: None,
+            split_ack: None,
         }
     }
+
+    /// Create a task to split the region at `split_keys` without scanning it.
+    /// The keys are in the same form as the region's keys, they must be in
+    /// the region and in ascending order.
+    pub fn manual(region: Region, split_keys: Vec<Vec<u8>>) -> Task {
+        let mut task = Task::new(region, false, CheckPolicy::SCAN)
+            .with_reason(SplitCheckReason::Manual);
+        task.split_keys = Some(split_keys);
+        task
+    }
+
+    /// Create a task to tell the runner whether the last split sent for the
+    /// region is applied, see `Runner::on_split_ack`.
+    pub fn split_ack(region: Region, applied: bool) -> Task {
+        let mut task = Task::new(region, false, CheckPolicy::SCAN);
+        task.split_ack = Some(applied);
+        task
+    }
+
+    /// Create a task to run the checks of a batch of regions, every region
+    /// is checked as its own task, but the statistics of the scans are sent
+    /// in one `Msg::BatchSplitCheckResult` instead of one message for each
+    /// region.
+    pub fn batch(mut tasks: Vec<Task>) -> Task {
+        assert!(!tasks.is_empty(), "a batch split check without tasks");
+        let mut task = tasks.remove(0);
+        task.batch = tasks;
+        task
+    }
+
+    /// Create a task to check the region whose size may be changed a lot by
+    /// a compaction.
+    pub fn post_compaction(region: Region, policy: CheckPolicy) -> Task {
+        Task::new(region, true, policy).with_reason(SplitCheckReason::PostCompaction)
+    }
+
+    /// Set why the task is scheduled, it's `SplitCheckReason::Size` by
+    /// default.
+    pub fn with_reason(mut self, reason: SplitCheckReason) -> Task {
+        self.reason = reason;
+        self
+    }
+
+    /// Set the state of the region known by raftstore, observers may skip
+    /// checking the region according to it.
+    pub fn with_region_state(mut self, region_state: RegionState) -> Task {
+        self.region_state = region_state;
+        self
+    }
+
+    /// Set whether the local peer is the leader of the region, it's true by
+    /// default. Observers may skip checking the regions of followers.
+    pub fn with_leader(mut self, is_leader: bool) -> Task {
+        self.is_leader = is_leader;
+        self
+    }
+
+    /// Only scan the keys in `[start_key, end_key)` of the region. The keys
+    /// are in the same form as the region's keys, and an empty `end_key`
+    /// means the end of the region.
+    pub fn with_range(mut self, start_key: Vec<u8>, end_key: Vec<u8>) -> Task {
+        self.range = Some((start_key, end_key));
+        self
+    }
+
+    /// Find the split keys without splitting the region, the keys are logged.
+    pub fn with_dry_run(mut self) -> Task {
+        self.dry_run = true;
+        self
+    }
+
+    /// Don't run the task until `delay` after the runner receives it, the
+    /// time is read from the clock of the runner.
+    pub fn with_delay(mut self, delay: Duration) -> Task {
+        self.delay = Some(delay);
+        self
+    }
+
+    /// Abort the scan if it doesn't finish before `deadline`, no split is
+    /// sent then. The deadline is checked once a scan batch.
+    pub fn with_deadline(mut self, deadline: Instant) -> Task {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Check the region with a policy defined by observers, e.g. to split a
+    /// region with special rules. Observers find `code` in
+    /// `ObserverContext::custom_policy` when adding checkers, the ones that
+    /// don't know it work as usual. The region is scanned if `policy` of the
+    /// task is `CheckPolicy::SCAN`.
+    pub fn with_custom_policy(mut self, code: u32) -> Task {
+        self.custom_policy = Some(code);
+        self
+    }
+
+    /// Split the region into `count` parts of roughly equal size, `count - 1`
+    /// split keys are found in a single scan. The checkers of observers are
+    /// not run.
+    pub fn with_split_count(mut self, count: u64) -> Task {
+        self.split_count = Some(count);
+        self
+    }
+
+    /// Continue a check that has scanned `resumed_size` bytes of the region
+    /// without finding any split key, from the data key `key`. The entries
+    /// of `key` in `cfs` are scanned already.
+    pub fn with_resume_from(mut self, key: Vec<u8>, cfs: Vec<CfName>, resumed_size: u64) -> Task {
+        self.resume_from = Some(key);
+        self.resumed_cfs = cfs;
+        self.resumed_size = resumed_size;
+        self
+    }
+
+    /// Count the task in `SPLIT_CHECK_PENDING_TASKS` until it's run, it
+    /// should be called when the task is scheduled.
+    pub fn into_pending(self) -> Task {
+        self.counted_by(SPLIT_CHECK_PENDING_TASKS.clone())
+    }
+
+    fn counted_by(mut self, gauge: IntGauge) -> Task {
+        self.pending = Some(PendingGuard::new(gauge));
+        self
+    }
+
+    /// Create the task to check the rest of the region from `last_key`, the
+    /// entries of it in `cfs` are skipped.
+    fn resume(&self, last_key: &[u8], cfs: &[CfName], scanned_size: u64) -> Task {
+        // Other CFs may have entries of `last_key` not scanned yet.
+        let mut task = Task::new(self.region.clone(), self.auto_split, self.policy)
+            .with_reason(self.reason)
+            .with_region_state(self.region_state)
+            .with_leader(self.is_leader)
+            .with_resume_from(last_key.to_vec(), cfs.to_vec(), self.resumed_size + scanned_size);
+        task.range = self.range.clone();
+        task.dry_run = self.dry_run;
+        task.custom_policy = self.custom_policy;
+        task.split_count = self.split_count;
+        task
+    }
 }
 
 impl Display for Task {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         write!(
             f,
-            "Split Check Task for {}, auto_split: {:?}",
+            "Split Check Task for {}, batch: {}, auto_split: {:?}, dry_run: {:?}, reason: {:?}, \
+             custom_policy: {:?}, resume_from: {:?}",
             self.region.get_id(),
-            self.auto_split
+            self.batch.len(),
+            self.auto_split,
+            self.dry_run,
+            self.reason,
+            self.custom_policy,
+            self.resume_from.as_ref().map(|k| escape(k))
         )
     }
 }
@@ -159,6 +837,29 @@ pub struct Runner<C> {
     engine: Arc<DB>,
     ch: RetryableSendCh<Msg, C>,
     coprocessor: Arc<CoprocessorHost>,
+    // Shared by all the split check tasks.
+    limiter: Option<Arc<IOLimiter>>,
+    stopped: Arc<AtomicBool>,
+    check_interval: Duration,
+    scan_batch_size: usize,
+    readahead_size: usize,
+    // Automatic checks are resumed by another task with the scheduler after
+    // scanning chunk_size bytes.
+    chunk_size: u64,
+    scheduler: Option<Scheduler<Task>>,
+    // The last time each region was checked automatically.
+    last_check_time: HashMap<u64, Instant>,
+    last_splits: HashMap<u64, LastSplit>,
+    // Tasks scheduled before their time, they are checked on timeout.
+    deferred: Vec<Task>,
+    deferred_count: Arc<AtomicUsize>,
+    clock: Box<Clock>,
+    split_history: SplitHistory,
+    event_sink: Option<SplitEventSink>,
+    // Automatic checks don't scan regions while the moving average of the
+    // durations of checks exceeds it, in seconds.
+    overload_duration: f64,
+    run_duration_ewma: f64,
 }
 
 impl<C: Sender<Msg>> Runner<C> {
@@ -167,107 +868,2459 @@ impl<C: Sender<Msg>> Runner<C> {
         ch: RetryableSendCh<Msg, C>,
         coprocessor: Arc<CoprocessorHost>,
     ) -> Runner<C> {
+        let limiter = match coprocessor.cfg.split_check_bytes_per_sec.0 {
+            0 => None,
+            bytes_per_sec => Some(Arc::new(IOLimiter::new(bytes_per_sec))),
+        };
+        let check_interval = coprocessor.cfg.split_check_interval.0;
+        let scan_batch_size = cmp::max(coprocessor.cfg.split_check_scan_batch_size, 1);
+        let readahead_size = coprocessor.cfg.split_check_readahead_size.0 as usize;
+        let chunk_size = coprocessor.cfg.split_check_chunk_size.0;
+        let overload_duration = duration_to_sec(coprocessor.cfg.split_check_overload_duration.0);
+        // Size reports are best-effort, only split keys are retried.
+        let backoff = Duration::from_millis(SPLIT_SEND_BACKOFF_MS);
+        let ch = ch.with_retry(SPLIT_SEND_MAX_RETRIES, backoff);
         Runner {
             engine,
             ch,
             coprocessor,
+            limiter,
+            stopped: Arc::new(AtomicBool::new(false)),
+            check_interval,
+            scan_batch_size,
+            readahead_size,
+            chunk_size,
+            scheduler: None,
+            last_check_time: HashMap::default(),
+            last_splits: HashMap::default(),
+            deferred: vec![],
+            deferred_count: Arc::new(AtomicUsize::new(0)),
+            clock: Box::new(MonotonicClock),
+            split_history: SplitHistory::default(),
+            event_sink: None,
+            overload_duration,
+            run_duration_ewma: 0.0,
         }
     }
 
-    fn check_split(&mut self, task: Task) {
-        let region = &task.region;
-        let region_id = region.get_id();
-        let start_key = keys::enc_start_key(region);
-        let end_key = keys::enc_end_key(region);
-        debug!(
-            "[region {}] executing task {} {}",
-            region_id,
-            escape(&start_key),
-            escape(&end_key)
-        );
-        CHECK_SPILT_COUNTER_VEC.with_label_values(&["all"]).inc();
+    /// Replace the clock of the runner, it's the monotonic clock by default.
+    pub fn with_clock(mut self, clock: Box<Clock>) -> Runner<C> {
+        self.clock = clock;
+        self
+    }
 
-        let mut host =
-            self.coprocessor
-                .new_split_checker_host(region, &self.engine, task.auto_split);
-        if host.skip() {
-            debug!("[region {}] skip split check", region.get_id());
-            return;
-        }
+    /// Send a `SplitEvent` to `sink` for every split sent by the runner, it's
+    /// independent of the messages sent to raftstore.
+    pub fn with_event_sink(mut self, sink: SplitEventSink) -> Runner<C> {
+        self.event_sink = Some(sink);
+        self
+    }
 
-        let split_key = match task.policy {
-            CheckPolicy::SCAN => {
-                let timer = CHECK_SPILT_HISTOGRAM.start_coarse_timer();
-                let res = MergedIterator::new(
-                    self.engine.as_ref(),
-                    LARGE_CFS,
-                    &start_key,
-                    &end_key,
-                    false,
-                ).map(|mut iter| {
-                    while let Some(e) = iter.next() {
-                        if host.on_kv(region, &e) {
-                            break;
-                        }
-                    }
-                });
-                timer.observe_duration();
+    /// Set the scheduler of the worker running the runner, it's required to
+    /// scan a large region in chunks.
+    pub fn with_scheduler(mut self, scheduler: Scheduler<Task>) -> Runner<C> {
+        self.scheduler = Some(scheduler);
+        self
+    }
 
-                if let Err(e) = res {
-                    error!("[region {}] failed to scan split key: {}", region_id, e);
-                    return;
-                }
+    /// Get the flag to abort the running scan, the runner can't be used
+    /// any more once it's set.
+    pub fn stop_flag(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.stopped)
+    }
 
-                host.split_key()
-            }
-            CheckPolicy::APPROXIMATE => {
-                let res = host.approximate_split_key(region, &self.engine);
-                if let Err(e) = res {
-                    error!(
-                        "[region {}] failed to get approxiamte split key: {}",
-                        region_id, e
-                    );
-                    return;
-                }
-                res.unwrap()
+    /// Get the number of the tasks deferred by the runner, the worker should
+    /// be considered busy until they are run. It can still be read after the
+    /// runner is moved to the worker.
+    pub fn deferred_count(&self) -> Arc<AtomicUsize> {
+        Arc::clone(&self.deferred_count)
+    }
+
+    /// Get the history of the split keys sent by the runner, it can still be
+    /// read after the runner is moved to the worker.
+    pub fn split_history(&self) -> SplitHistory {
+        self.split_history.clone()
+    }
+
+    /// Whether the region was checked automatically just now, the check
+    /// time is updated if not.
+    fn checked_recently(&mut self, region_id: u64) -> bool {
+        if self.check_interval == Duration::from_secs(0) {
+            return false;
+        }
+        let now = self.clock.now();
+        if let Some(t) = self.last_check_time.get(&region_id) {
+            if now.duration_since(*t) < self.check_interval {
+                return true;
             }
-        };
+        }
+        if self.last_check_time.len() >= LAST_CHECK_TIME_CAPACITY {
+            let interval = self.check_interval;
+            self.last_check_time.retain(|_, t| now.duration_since(*t) < interval);
+        }
+        self.last_check_time.insert(region_id, now);
+        false
+    }
 
-        if let Some(key) = split_key {
-            let region_epoch = region.get_region_epoch().clone();
-            let res = self
-                .ch
-                .try_send(new_split_region(region_id, region_epoch, key));
-            if let Err(e) = res {
-                warn!("[region {}] failed to send check result: {}", region_id, e);
+    fn update_run_duration(&mut self, elapsed: Duration) {
+        self.run_duration_ewma = RUN_DURATION_EWMA_ALPHA * duration_to_sec(elapsed)
+            + (1.0 - RUN_DURATION_EWMA_ALPHA) * self.run_duration_ewma;
+    }
+
+    /// Whether the checks take so long that the automatic ones shouldn't
+    /// scan regions.
+    fn is_overloaded(&self) -> bool {
+        self.overload_duration > 0.0 && self.run_duration_ewma > self.overload_duration
+    }
+
+    /// Whether the same split keys were sent for the region with the same epoch
+    /// and range just now, the split may not be applied yet.
+    fn is_duplicated_split(&self, region: &Region, split_keys: &[Vec<u8>]) -> bool {
+        match self.last_splits.get(&region.get_id()) {
+            Some(last) => {
+                last.region.get_region_epoch() == region.get_region_epoch()
+                    && last.region.get_start_key() == region.get_start_key()
+                    && last.region.get_end_key() == region.get_end_key()
+                    && last.split_keys.as_slice() == split_keys
+                    && self.clock.now().duration_since(last.time)
+                        < Duration::from_secs(DUPLICATED_SPLIT_WINDOW_SECS)
             }
+            None => false,
+        }
+    }
 
-            CHECK_SPILT_COUNTER_VEC
-                .with_label_values(&["success"])
-                .inc();
-        } else {
-            debug!(
-                "[region {}] no need to send, split key not found",
-                region_id,
-            );
+    fn record_split(&mut self, region: &Region, split_keys: Vec<Vec<u8>>) {
+        let window = Duration::from_secs(DUPLICATED_SPLIT_WINDOW_SECS);
+        let now = self.clock.now();
+        self.split_history.record(region.get_id(), now, &split_keys);
+        if self.last_splits.len() >= LAST_CHECK_TIME_CAPACITY {
+            self.last_splits.retain(|_, s| now.duration_since(s.time) < window);
+        }
+        let last = LastSplit {
+            region: region.clone(),
+            split_keys,
+            time: now,
+        };
+        self.last_splits.insert(region.get_id(), last);
+    }
 
-            CHECK_SPILT_COUNTER_VEC.with_label_values(&["ignore"]).inc();
+    fn emit_split_event(
+        &self,
+        task: &Task,
+        split_keys: &[Vec<u8>],
+        scanned_size: u64,
+        start: Instant,
+    ) {
+        let sink = match self.event_sink {
+            Some(ref sink) => sink,
+            None => return,
+        };
+        sink(SplitEvent {
+            region_id: task.region.get_id(),
+            epoch: task.region.get_region_epoch().clone(),
+            reason: task.reason,
+            split_keys: split_keys
+                .iter()
+                .map(|k| keys::origin_key(k).to_vec())
+                .collect(),
+            scanned_size,
+            duration: self.clock.now().duration_since(start),
+        });
+    }
+
+    /// Handle the acknowledgement of the last split sent for the region. If
+    /// the split is not applied, e.g. PD rejects it, the same split keys are
+    /// not suppressed any more so the next check can retry. Otherwise the
+    /// suppression is refreshed as the region will change soon.
+    pub fn on_split_ack(&mut self, region_id: u64, applied: bool) {
+        if !applied {
+            if self.last_splits.remove(&region_id).is_some() {
+                info!(
+                    "[region {}] last split is not applied, stop suppressing it",
+                    region_id
+                );
+            }
+            return;
+        }
+        let now = self.clock.now();
+        if let Some(last) = self.last_splits.get_mut(&region_id) {
+            last.time = now;
         }
     }
-}
 
-impl<C: Sender<Msg>> Runnable<Task> for Runner<C> {
-    fn run(&mut self, task: Task) {
-        self.check_split(task);
+    /// Schedule a task to check the rest of the region from `last_key`.
+    fn resume_check(&self, task: &Task, last_key: &[u8], cfs: &[CfName], scanned_size: u64) {
+        let region_id = task.region.get_id();
+        let task = task.resume(last_key, cfs, scanned_size);
+        debug!(
+            "[region {}] no split key in {} bytes, resume from {}",
+            region_id,
+            task.resumed_size,
+            escape(last_key)
+        );
+        CHECK_SPILT_COUNTER_VEC
+            .with_label_values(&["resumed"])
+            .inc();
+        if let Err(e) = self.scheduler.as_ref().unwrap().schedule(task.into_pending()) {
+            warn!("[region {}] failed to resume split check: {}", region_id, e);
+        }
     }
-}
 
-fn new_split_region(region_id: u64, region_epoch: RegionEpoch, key: Vec<u8>) -> Msg {
-    let split_key = keys::origin_key(key.as_slice()).to_vec();
-    Msg::SplitRegion {
-        region_id,
-        region_epoch,
-        split_key,
-        callback: Callback::None,
+    /// Report the scanned size of the region if its approximate size diverges
+    /// too much from it, which usually means the table properties are stale.
+    /// The ratio of the sizes is always recorded.
+    fn check_size_divergence(&self, region: &Region, cfs: &[CfName], scanned_size: u64) {
+        let region_id = region.get_id();
+        let mut approximate_size = 0;
+        for cf in cfs.iter().filter(|cf| LARGE_CFS.contains(cf)) {
+            match util::get_region_approximate_size_cf(&self.engine, cf, region) {
+                Ok(size) => approximate_size += size,
+                Err(e) => {
+                    debug!(
+                        "[region {}] failed to get approximate size of cf {}: {}",
+                        region_id, cf, e
+                    );
+                    return;
+                }
+            }
+        }
+        observe_size_ratio(scanned_size, approximate_size);
+        let ratio = self.coprocessor.cfg.region_size_divergence_ratio;
+        if ratio == 0.0 {
+            return;
+        }
+        let (min_size, max_size) = if approximate_size < scanned_size {
+            (approximate_size, scanned_size)
+        } else {
+            (scanned_size, approximate_size)
+        };
+        if max_size as f64 <= min_size as f64 * ratio {
+            return;
+        }
+        warn!(
+            "[region {}] approximate size {} diverges from scanned size {}, maybe stale",
+            region_id, approximate_size, scanned_size
+        );
+        CHECK_SPILT_COUNTER_VEC
+            .with_label_values(&["size_diverged"])
+            .inc();
+        if !self.coprocessor.cfg.report_region_size {
+            return;
+        }
+        let res = Msg::RegionApproximateSize {
+            region_id,
+            size: scanned_size,
+        };
+        if let Err(e) = self.ch.try_send(res) {
+            warn!(
+                "[region {}] failed to send scanned region size: {}",
+                region_id, e
+            );
+        }
+    }
+
+    /// Ask to compact the region instead of splitting it if most of its MVCC
+    /// versions are garbage, it may not need to be split after compaction.
+    /// True is returned if the compaction is asked.
+    fn recommend_compaction(&self, region: &Region) -> bool {
+        let threshold = self.coprocessor.cfg.region_compact_garbage_ratio;
+        if threshold == 0.0 {
+            return false;
+        }
+        let region_id = region.get_id();
+        let (rows, versions) = match util::get_region_approximate_versions(&self.engine, region) {
+            Ok((_, 0)) => return false,
+            Ok(res) => res,
+            Err(e) => {
+                debug!(
+                    "[region {}] failed to get approximate versions: {}",
+                    region_id, e
+                );
+                return false;
+            }
+        };
+        let ratio = 1.0 - rows as f64 / versions as f64;
+        if ratio <= threshold {
+            return false;
+        }
+        info!(
+            "[region {}] {} of {} versions are garbage, compact instead of split",
+            region_id,
+            versions.saturating_sub(rows),
+            versions
+        );
+        match self.ch.try_send(Msg::CompactRegion { region_id }) {
+            Ok(()) => true,
+            Err(e) => {
+                warn!("[region {}] failed to send compact region: {}", region_id, e);
+                false
+            }
+        }
+    }
+
+    /// Get the split keys of the region by running the checkers, nothing is
+    /// sent to raftstore except the approximate statistics reported by the
+    /// observers when adding checkers.
+    pub fn compute_split_keys(
+        &mut self,
+        region: &Region,
+        policy: CheckPolicy,
+    ) -> Result<Vec<Vec<u8>>> {
+        let task = Task::new(region.clone(), false, policy);
+        let found = self.find_split_keys(&task, 0)?;
+        Ok(found.map_or_else(Vec::new, |found| found.split_keys))
+    }
+
+    /// Get the `count - 1` split keys dividing the region into `count` parts
+    /// of roughly equal size by scanning it, nothing is sent to raftstore.
+    pub fn compute_quantile_split_keys(
+        &mut self,
+        region: &Region,
+        count: u64,
+    ) -> Result<Vec<Vec<u8>>> {
+        let task = Task::new(region.clone(), false, CheckPolicy::SCAN).with_split_count(count);
+        let found = self.find_split_keys(&task, 0)?;
+        Ok(found.map_or_else(Vec::new, |found| found.split_keys))
+    }
+
+    /// Run the checkers of the task, None is returned if the check is
+    /// skipped. A scan stops after `chunk_size` bytes unless it's 0.
+    fn find_split_keys(
+        &mut self,
+        task: &Task,
+        chunk_size: u64,
+    ) -> Result<Option<FoundSplitKeys>> {
+        let region = &task.region;
+        let region_id = region.get_id();
+        let mut start_key = keys::enc_start_key(region);
+        let mut end_key = keys::enc_end_key(region);
+        if let Some((ref start, ref end)) = task.range {
+            start_key = cmp::max(start_key, keys::data_key(start));
+            end_key = cmp::min(end_key, keys::data_end_key(end));
+            if start_key >= end_key {
+                debug!(
+                    "[region {}] skip split check, range [{}, {}) is out of region",
+                    region_id,
+                    escape(start),
+                    escape(end)
+                );
+                return Ok(None);
+            }
+        }
+        if let Some(ref key) = task.resume_from {
+            start_key = cmp::max(start_key, key.clone());
+            if start_key >= end_key {
+                return Ok(None);
+            }
+        }
+        let reason = task.reason.as_str();
+        debug!(
+            "[region {}] executing task {} {}, reason {}",
+            region_id,
+            escape(&start_key),
+            escape(&end_key),
+            reason
+        );
+        CHECK_SPILT_COUNTER_VEC.with_label_values(&["all"]).inc();
+
+        // Observers read the approximate size and keys of the region when
+        // adding checkers.
+        let timer = SPLIT_CHECK_DURATION_HISTOGRAM
+            .with_label_values(&["approximate", reason])
+            .start_coarse_timer();
+        let mut host = match task.split_count {
+            Some(count) => {
+                let mut host = SplitCheckerHost::new(task.auto_split);
+                let size_limit = self.coprocessor.cfg.region_max_size.0;
+                host.add_checker(Box::new(QuantileChecker::new(count, size_limit)));
+                host
+            }
+            None => {
+                let ctx = SplitCheckContext {
+                    region_state: task.region_state,
+                    is_leader: task.is_leader,
+                    resumed_size: task.resumed_size,
+                    custom_policy: task.custom_policy,
+                    ..Default::default()
+                };
+                self.coprocessor.new_split_checker_host(region, &self.engine, task.auto_split, ctx)
+            }
+        };
+        timer.observe_duration();
+        if host.skip() {
+            debug!("[region {}] skip split check", region.get_id());
+            return Ok(None);
+        }
+
+        let (split_keys, scan) = match task.policy {
+            CheckPolicy::SCAN => {
+                let timer = CHECK_SPILT_HISTOGRAM.start_coarse_timer();
+                // All the checkers share the scan, so it can't be told apart.
+                let stage_timer = SPLIT_CHECK_DURATION_HISTOGRAM
+                    .with_label_values(&["scan", reason])
+                    .start_coarse_timer();
+                let cfs = host.interested_cfs();
+                let mut ctl = ScanControl {
+                    batch_size: self.scan_batch_size,
+                    chunk_size,
+                    limiter: self.limiter.as_ref().map(|limiter| limiter.as_ref()),
+                    stopped: Some(self.stopped.as_ref()),
+                    clock: self.clock.as_ref(),
+                    deadline: task.deadline,
+                    reversed: false,
+                    skip: task
+                        .resume_from
+                        .as_ref()
+                        .map(|key| (key.as_slice(), task.resumed_cfs.as_slice())),
+                };
+                let res = MergedIterator::new(
+                    self.engine.as_ref(),
+                    &cfs,
+                    &start_key,
+                    &end_key,
+                    false,
+                    self.readahead_size,
+                ).map(|mut iter| scan_entries(&mut iter, &mut host, region, &ctl));
+                timer.observe_duration();
+                stage_timer.observe_duration();
+
+                let progress = match res {
+                    Ok(progress) => progress,
+                    Err(e) => return Err(box_err!("failed to scan split key: {}", e)),
+                };
+                let scanned_size = progress.scanned_size;
+                SPLIT_CHECK_SCANNED_BYTES_TOTAL.inc_by(scanned_size as i64);
+                if is_scan_interrupted(region_id, &progress) {
+                    return Ok(None);
+                }
+
+                // The reversed scan covers the rest of the region at once, so
+                // it's only done along with the first chunk.
+                let reversed_cfs = host.reversed_cfs();
+                if !reversed_cfs.is_empty() && task.resume_from.is_none() {
+                    let timer = SPLIT_CHECK_DURATION_HISTOGRAM
+                        .with_label_values(&["reversed_scan", reason])
+                        .start_coarse_timer();
+                    ctl.chunk_size = 0;
+                    ctl.reversed = true;
+                    ctl.skip = None;
+                    let res = ReversedMergedIterator::new(
+                        self.engine.as_ref(),
+                        &reversed_cfs,
+                        &start_key,
+                        &end_key,
+                        false,
+                        self.readahead_size,
+                    ).map(|mut iter| scan_entries(&mut iter, &mut host, region, &ctl));
+                    timer.observe_duration();
+                    let reversed = match res {
+                        Ok(progress) => progress,
+                        Err(e) => return Err(box_err!("failed to scan split key: {}", e)),
+                    };
+                    SPLIT_CHECK_SCANNED_BYTES_TOTAL.inc_by(reversed.scanned_size as i64);
+                    if is_scan_interrupted(region_id, &reversed) {
+                        return Ok(None);
+                    }
+                }
+
+                let split_keys = host.split_keys();
+                let stats = ScanStats {
+                    scanned_size,
+                    scanned_keys: progress.scanned_keys,
+                    max_key_len: progress.max_key_len,
+                    max_value_len: progress.max_value_len,
+                    exhausted: progress.exhausted,
+                    last_key: progress.last_key,
+                    last_key_cfs: progress.last_key_cfs,
+                    single_user_key: if progress.distinct_user_keys == 1 {
+                        Some(progress.first_user_key)
+                    } else {
+                        None
+                    },
+                    found_split: !split_keys.is_empty(),
+                    estimated_splits: host.estimated_splits(),
+                    buckets: host.buckets(),
+                    cfs,
+                    cf_entries: progress.cf_entries,
+                };
+                (split_keys, Some(stats))
+            }
+            CheckPolicy::APPROXIMATE => {
+                let timer = SPLIT_CHECK_DURATION_HISTOGRAM
+                    .with_label_values(&["approximate_split", reason])
+                    .start_coarse_timer();
+                let res = host.approximate_split_key(region, &self.engine);
+                timer.observe_duration();
+                match res {
+                    Ok(key) => (key.into_iter().collect(), None),
+                    Err(e) => return Err(box_err!("failed to get approxiamte split key: {}", e)),
+                }
+            }
+        };
+
+        let mut split_keys: Vec<_> = split_keys
+            .into_iter()
+            .filter_map(|key| match host.adjust_split_key(region, key.clone()) {
+                Some(adjusted) => {
+                    if adjusted != key {
+                        debug!(
+                            "[region {}] split key {} is moved to {}",
+                            region_id,
+                            escape(&key),
+                            escape(&adjusted)
+                        );
+                    }
+                    Some(adjusted)
+                }
+                None => {
+                    info!(
+                        "[region {}] split at key {} is vetoed",
+                        region_id,
+                        escape(&key)
+                    );
+                    None
+                }
+            })
+            .collect();
+        // Adjusted split keys may be the same.
+        split_keys.dedup();
+        let data_start_key = keys::enc_start_key(region);
+        let data_end_key = keys::enc_end_key(region);
+        let split_keys = split_keys
+            .into_iter()
+            .filter(|key| {
+                // A key without the data prefix is a bug of the checker, it
+                // can't be converted to a key of the region.
+                if !keys::validate_data_key(key) {
+                    error!(
+                        "[region {}] ignore split key {} which is not a data key",
+                        region_id,
+                        escape(key)
+                    );
+                    INVALID_SPLIT_KEY_COUNTER.inc();
+                    return false;
+                }
+                // Splitting at the start key makes an empty region, and a key
+                // out of the region may be found with a stale region.
+                if *key <= data_start_key || *key >= data_end_key {
+                    warn!(
+                        "[region {}] ignore split key {} out of range [{}, {})",
+                        region_id,
+                        escape(key),
+                        escape(&data_start_key),
+                        escape(&data_end_key)
+                    );
+                    INVALID_SPLIT_KEY_COUNTER.inc();
+                    return false;
+                }
+                let approved = host.approve_split(region, key);
+                if !approved {
+                    info!(
+                        "[region {}] split at key {} is vetoed",
+                        region_id,
+                        escape(key)
+                    );
+                }
+                approved
+            })
+            .collect();
+        Ok(Some(FoundSplitKeys { split_keys, scan }))
+    }
+
+    /// Split the region at the keys given by operators without scanning it.
+    fn split_manually(&self, task: Task, split_keys: &[Vec<u8>]) -> Result<()> {
+        let start = self.clock.now();
+        let region = &task.region;
+        let region_id = region.get_id();
+        let split_keys = check_manual_split_keys(region, split_keys)?;
+        if task.dry_run {
+            info!(
+                "[region {}] dry run, would split at keys {:?}",
+                region_id,
+                split_keys.iter().map(|k| escape(k)).collect::<Vec<_>>()
+            );
+            return Ok(());
+        }
+        let region_epoch = region.get_region_epoch().clone();
+        let msg = new_split_region(region_id, region_epoch, &split_keys, task.reason);
+        if let Err(e) = self.ch.send_with_retry(msg) {
+            return Err(box_err!("failed to send split keys: {}", e));
+        }
+        self.coprocessor.on_split_emitted(region, &split_keys);
+        self.split_history
+            .record(region_id, self.clock.now(), &split_keys);
+        self.emit_split_event(&task, &split_keys, 0, start);
+        CHECK_SPILT_COUNTER_VEC
+            .with_label_values(&["manual"])
+            .inc();
+        Ok(())
+    }
+
+    /// Check whether the region of the task should be split. If `results` is
+    /// set, the statistics of the scan are pushed to it instead of being sent.
+    fn check_split(&mut self, mut task: Task, results: Option<&mut Vec<SplitCheckResult>>) {
+        let start = self.clock.now();
+        let region_id = task.region.get_id();
+        // Split checks requested explicitly or resumed are never skipped.
+        if task.auto_split && task.resume_from.is_none() && self.checked_recently(region_id) {
+            debug!(
+                "[region {}] skip split check, checked within {:?}",
+                region_id, self.check_interval
+            );
+            CHECK_SPILT_COUNTER_VEC
+                .with_label_values(&["too_frequent"])
+                .inc();
+            return;
+        }
+        // A resumed scan is finished anyway.
+        if task.auto_split
+            && task.policy == CheckPolicy::SCAN
+            && task.resume_from.is_none()
+            && self.is_overloaded()
+        {
+            debug!(
+                "[region {}] split checks take {:.3}s on average, don't scan the region",
+                region_id, self.run_duration_ewma
+            );
+            CHECK_SPILT_COUNTER_VEC
+                .with_label_values(&["downgraded"])
+                .inc();
+            task.policy = CheckPolicy::APPROXIMATE;
+        }
+        // Only automatic checks are split into chunks.
+        let chunk_size = match self.scheduler {
+            Some(_) if task.auto_split && results.is_none() => self.chunk_size,
+            _ => 0,
+        };
+        let found = match self.find_split_keys(&task, chunk_size) {
+            Ok(Some(found)) => found,
+            Ok(None) => return,
+            Err(e) => {
+                error!("[region {}] failed to check split: {}", region_id, e);
+                return;
+            }
+        };
+
+        let region = &task.region;
+        let split_keys = found.split_keys;
+        let scanned_size = found
+            .scan
+            .as_ref()
+            .map_or(0, |stats| task.resumed_size + stats.scanned_size);
+        if let Some(stats) = found.scan {
+            // The budget of the chunk is used up, leave the rest of the
+            // region to another task unless a split key is found.
+            if let Some(ref last_key) = stats.last_key {
+                if !stats.found_split {
+                    let cfs = &stats.last_key_cfs;
+                    self.resume_check(&task, last_key, cfs, stats.scanned_size);
+                    return;
+                }
+            }
+
+            // Only the size of the whole region can be compared.
+            if stats.exhausted && task.range.is_none() && task.resume_from.is_none() {
+                self.check_size_divergence(region, &stats.cfs, stats.scanned_size);
+                if let Some(ref user_key) = stats.single_user_key {
+                    if stats.scanned_size > self.coprocessor.cfg.region_max_size.0 {
+                        warn!(
+                            "[region {}] is a single large key {}, cannot split, range [{}, {})",
+                            region_id,
+                            escape(user_key),
+                            escape(region.get_start_key()),
+                            escape(region.get_end_key())
+                        );
+                        UNSPLITTABLE_REGION_COUNTER.inc();
+                    }
+                }
+            }
+
+            self.coprocessor.on_split_check_scan_complete(
+                region,
+                stats.scanned_size,
+                stats.found_split,
+            );
+            let result = SplitCheckResult {
+                region_id,
+                scanned_size: stats.scanned_size,
+                scanned_keys: stats.scanned_keys,
+                found_split: stats.found_split,
+                estimated_splits: stats.estimated_splits,
+            };
+            if self.coprocessor.cfg.report_split_check_result {
+                match results {
+                    Some(results) => results.push(result),
+                    None => {
+                        let res = Msg::SplitCheckResult {
+                            region_id,
+                            scanned_size: result.scanned_size,
+                            scanned_keys: result.scanned_keys,
+                            found_split: result.found_split,
+                            estimated_splits: result.estimated_splits,
+                        };
+                        if let Err(e) = self.ch.try_send(res) {
+                            warn!(
+                                "[region {}] failed to send split check result: {}",
+                                region_id, e
+                            );
+                        }
+                    }
+                }
+            }
+            if self.coprocessor.cfg.report_region_max_entry {
+                let res = Msg::RegionMaxEntry {
+                    region_id,
+                    max_key_len: stats.max_key_len,
+                    max_value_len: stats.max_value_len,
+                };
+                if let Err(e) = self.ch.try_send(res) {
+                    warn!("[region {}] failed to send max entry: {}", region_id, e);
+                }
+            }
+            if self.coprocessor.cfg.report_region_cf_entries {
+                let res = Msg::SplitCheckCfStats {
+                    region_id,
+                    cf_entries: stats.cf_entries,
+                };
+                if let Err(e) = self.ch.try_send(res) {
+                    warn!("[region {}] failed to send cf stats: {}", region_id, e);
+                }
+            }
+            if !stats.buckets.is_empty() {
+                let res = Msg::RegionBuckets {
+                    region_id,
+                    keys: stats
+                        .buckets
+                        .iter()
+                        .map(|k| keys::origin_key(k).to_vec())
+                        .collect(),
+                };
+                if let Err(e) = self.ch.try_send(res) {
+                    warn!("[region {}] failed to send buckets: {}", region_id, e);
+                }
+            }
+        }
+
+        if !split_keys.is_empty() && task.dry_run {
+            info!(
+                "[region {}] dry run, would split at keys {:?}",
+                region_id,
+                split_keys.iter().map(|k| escape(k)).collect::<Vec<_>>()
+            );
+            CHECK_SPILT_COUNTER_VEC
+                .with_label_values(&["dry_run"])
+                .inc();
+        } else if !split_keys.is_empty() && task.auto_split && self.recommend_compaction(region) {
+            CHECK_SPILT_COUNTER_VEC
+                .with_label_values(&["compact"])
+                .inc();
+        } else if !split_keys.is_empty()
+            && task.auto_split
+            && self.is_duplicated_split(region, &split_keys)
+        {
+            info!(
+                "[region {}] split keys {:?} are sent just now, skip",
+                region_id,
+                split_keys.iter().map(|k| escape(k)).collect::<Vec<_>>()
+            );
+            CHECK_SPILT_COUNTER_VEC
+                .with_label_values(&["duplicated"])
+                .inc();
+        } else if !split_keys.is_empty() {
+            let region_epoch = region.get_region_epoch().clone();
+            let msg = new_split_region(region_id, region_epoch, &split_keys, task.reason);
+            match self.ch.send_with_retry(msg) {
+                Ok(()) => {
+                    self.coprocessor.on_split_emitted(region, &split_keys);
+                    self.emit_split_event(&task, &split_keys, scanned_size, start);
+                    self.record_split(region, split_keys);
+                }
+                Err(e) => warn!("[region {}] failed to send check result: {}", region_id, e),
+            }
+
+            CHECK_SPILT_COUNTER_VEC
+                .with_label_values(&["success"])
+                .inc();
+        } else {
+            debug!(
+                "[region {}] no need to send, split key not found",
+                region_id,
+            );
+
+            CHECK_SPILT_COUNTER_VEC.with_label_values(&["ignore"]).inc();
+        }
+    }
+}
+
+impl<C: Sender<Msg>> Runnable<Task> for Runner<C> {
+    fn run(&mut self, mut task: Task) {
+        if let Some(delay) = task.delay.take() {
+            task.not_before = Some(self.clock.now() + delay);
+        }
+        if let Some(not_before) = task.not_before {
+            if not_before > self.clock.now() {
+                CHECK_SPILT_COUNTER_VEC
+                    .with_label_values(&["deferred"])
+                    .inc();
+                self.deferred.push(task);
+                self.deferred_count.store(self.deferred.len(), AtomicOrdering::SeqCst);
+                return;
+            }
+        }
+        if let Some(applied) = task.split_ack {
+            self.on_split_ack(task.region.get_id(), applied);
+            return;
+        }
+        if let Some(split_keys) = task.split_keys.take() {
+            let region_id = task.region.get_id();
+            if let Err(e) = self.split_manually(task, &split_keys) {
+                error!(
+                    "[region {}] failed to split at keys {:?}: {}",
+                    region_id,
+                    split_keys.iter().map(|k| escape(k)).collect::<Vec<_>>(),
+                    e
+                );
+            }
+            return;
+        }
+        if task.batch.is_empty() {
+            let start = self.clock.now();
+            self.check_split(task, None);
+            let elapsed = self.clock.now().duration_since(start);
+            self.update_run_duration(elapsed);
+            return;
+        }
+
+        // Regions that fail to be checked are not included in the results.
+        let batch = mem::replace(&mut task.batch, vec![]);
+        let mut results = Vec::with_capacity(batch.len() + 1);
+        self.check_split(task, Some(&mut results));
+        for task in batch {
+            self.check_split(task, Some(&mut results));
+        }
+        if results.is_empty() {
+            return;
+        }
+        if let Err(e) = self.ch.try_send(Msg::BatchSplitCheckResult(results)) {
+            warn!("failed to send batch split check result: {}", e);
+        }
+    }
+
+    fn shutdown(&mut self) {
+        self.stopped.store(true, AtomicOrdering::SeqCst);
+    }
+}
+
+impl<C: Sender<Msg>> RunnableWithTimer<Task, ()> for Runner<C> {
+    fn on_timeout(&mut self, timer: &mut Timer<()>, _: ()) {
+        if !self.deferred.is_empty() {
+            let now = self.clock.now();
+            let (due, deferred): (Vec<_>, Vec<_>) = mem::replace(&mut self.deferred, vec![])
+                .into_iter()
+                .partition(|t| t.not_before.map_or(true, |t| t <= now));
+            self.deferred = deferred;
+            self.deferred_count.store(self.deferred.len(), AtomicOrdering::SeqCst);
+            for task in due {
+                self.run(task);
+            }
+        }
+        timer.add_task(Duration::from_millis(DEFERRED_SPLIT_CHECK_INTERVAL), ());
+    }
+}
+
+/// Record the ratio of the scanned size of a region to its approximate size,
+/// nothing is recorded if the approximate size is 0.
+fn observe_size_ratio(scanned_size: u64, approximate_size: u64) -> Option<f64> {
+    if approximate_size == 0 {
+        return None;
+    }
+    let ratio = scanned_size as f64 / approximate_size as f64;
+    SPLIT_CHECK_SIZE_RATIO_HISTOGRAM.observe(ratio);
+    Some(ratio)
+}
+
+/// Requests IO quota for the scanned bytes from the limiter, one single
+/// burst at a time, the rest is left in `pending_bytes` unless `flush`.
+fn request_io(limiter: &IOLimiter, clock: &Clock, pending_bytes: &mut u64, flush: bool) {
+    let single = cmp::max(limiter.get_max_bytes_per_time(), 1) as u64;
+    while *pending_bytes >= single || (flush && *pending_bytes > 0) {
+        let bytes = cmp::min(*pending_bytes, single);
+        let now = clock.now();
+        limiter.request(bytes as i64);
+        if duration_to_ms(clock.now().duration_since(now)) >= THROTTLE_THRESHOLD_MS {
+            CHECK_SPILT_THROTTLE_COUNTER.inc();
+        }
+        *pending_bytes -= bytes;
+    }
+}
+
+/// Check the split keys given by operators are inside the region and in
+/// ascending order, the data keys of them are returned.
+fn check_manual_split_keys(region: &Region, split_keys: &[Vec<u8>]) -> Result<Vec<Vec<u8>>> {
+    if split_keys.is_empty() {
+        return Err(box_err!("no split key is given"));
+    }
+    let mut last_key: Option<&[u8]> = None;
+    for key in split_keys {
+        // Splitting at the start key makes an empty region.
+        if key.as_slice() == region.get_start_key() {
+            return Err(box_err!("split key {} is the start key", escape(key)));
+        }
+        util::check_key_in_region(key, region)?;
+        if let Some(last_key) = last_key {
+            if last_key >= key.as_slice() {
+                return Err(box_err!(
+                    "split key {} is not after {}",
+                    escape(key),
+                    escape(last_key)
+                ));
+            }
+        }
+        last_key = Some(key);
+    }
+    Ok(split_keys.iter().map(|k| keys::data_key(k)).collect())
+}
+
+fn new_split_region(
+    region_id: u64,
+    region_epoch: RegionEpoch,
+    split_keys: &[Vec<u8>],
+    reason: SplitCheckReason,
+) -> Msg {
+    debug_assert!(
+        split_keys.iter().all(|k| keys::validate_data_key(k)),
+        "[region {}] split keys must be data keys",
+        region_id
+    );
+    let split_keys = split_keys
+        .iter()
+        .map(|k| keys::origin_key(k).to_vec())
+        .collect();
+    Msg::SplitRegion {
+        region_id,
+        region_epoch,
+        split_keys,
+        reason,
+        callback: Callback::None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{mpsc, Mutex};
+
+    use rocksdb::{ColumnFamilyOptions, Writable};
+    use tempdir::TempDir;
+
+    use raftstore::coprocessor::dispatcher::BoxSplitCheckObserver;
+    use raftstore::coprocessor::test_util::CfChecker;
+    use raftstore::coprocessor::{
+        Config, Coprocessor, ObserverContext, SplitCheckObserver, SplitChecker, SplitCheckerHost,
+    };
+    use storage::{ALL_CFS, CF_DEFAULT, CF_LOCK, DATA_CFS};
+    use util::config::{ReadableDuration, ReadableSize};
+    use util::properties::{MvccPropertiesCollectorFactory, RangePropertiesCollectorFactory};
+    use util::rocksdb::{new_engine, CFOptions};
+    use util::worker::Worker;
+
+    use super::*;
+
+    /// A clock that only moves when it's advanced explicitly.
+    #[derive(Clone)]
+    struct MockClock(Arc<Mutex<Instant>>);
+
+    impl MockClock {
+        fn new() -> MockClock {
+            MockClock(Arc::new(Mutex::new(Instant::now())))
+        }
+
+        fn advance(&self, d: Duration) {
+            *self.0.lock().unwrap() += d;
+        }
+    }
+
+    impl Clock for MockClock {
+        fn now(&self) -> Instant {
+            *self.0.lock().unwrap()
+        }
+    }
+
+    // Creates an engine with `count` kvs of 10 bytes, the keys and values are
+    // both the data keys of "0000", "0001", ...
+    fn new_engine_with_kvs(path: &TempDir, count: usize) -> Arc<DB> {
+        let engine = Arc::new(new_engine(path.path().to_str().unwrap(), ALL_CFS, None).unwrap());
+        for i in 0..count {
+            let s = keys::data_key(format!("{:04}", i).as_bytes());
+            engine.put(&s, &s).unwrap();
+        }
+        engine
+    }
+
+    // Creates a runner on `engine`, `observers` are registered with the
+    // priorities 1, 2, ... in order.
+    fn new_runner(
+        cfg: Config,
+        engine: &Arc<DB>,
+        observers: Vec<BoxSplitCheckObserver>,
+    ) -> (Runner<mpsc::SyncSender<Msg>>, mpsc::Receiver<Msg>) {
+        let (tx, rx) = mpsc::sync_channel(100);
+        let ch = RetryableSendCh::new(tx, "test-split");
+        let mut coprocessor = CoprocessorHost::new(cfg, ch.clone());
+        for (i, observer) in observers.into_iter().enumerate() {
+            coprocessor
+                .registry
+                .register_split_check_observer(i as u32 + 1, observer);
+        }
+        (Runner::new(Arc::clone(engine), ch, Arc::new(coprocessor)), rx)
+    }
+
+    #[derive(Default)]
+    struct SentMsgs {
+        // The split keys and reasons of `SplitRegion`.
+        splits: Vec<(Vec<Vec<u8>>, SplitCheckReason)>,
+        // The scanned sizes and whether a split is found of `SplitCheckResult`.
+        results: Vec<(u64, bool)>,
+        // The sizes of `RegionApproximateSize`.
+        sizes: Vec<u64>,
+        others: Vec<Msg>,
+    }
+
+    // Receives all the messages sent by the runner so far.
+    fn recv_result(rx: &mpsc::Receiver<Msg>) -> SentMsgs {
+        let mut msgs = SentMsgs::default();
+        while let Ok(msg) = rx.try_recv() {
+            match msg {
+                Msg::SplitRegion {
+                    split_keys,
+                    reason,
+                    ..
+                } => msgs.splits.push((split_keys, reason)),
+                Msg::SplitCheckResult {
+                    scanned_size,
+                    found_split,
+                    ..
+                } => msgs.results.push((scanned_size, found_split)),
+                Msg::RegionApproximateSize { size, .. } => msgs.sizes.push(size),
+                msg => msgs.others.push(msg),
+            }
+        }
+        msgs
+    }
+
+    #[test]
+    fn test_split_check_with_io_limiter() {
+        let path = TempDir::new("test-split-check-io-limiter").unwrap();
+        let engine = new_engine_with_kvs(&path, 20);
+
+        let mut region = Region::new();
+        region.set_id(1);
+
+        let mut cfg = Config::default();
+        cfg.split_check_bytes_per_sec = ReadableSize::kb(1);
+        let (mut runnable, rx) = new_runner(cfg, &engine, vec![]);
+
+        // Half split check always scans the region.
+        runnable.run(Task::new(region, false, CheckPolicy::SCAN));
+        let (scanned_size, _) = recv_result(&rx).results[0];
+        // All the scanned bytes should be requested from the limiter.
+        let limiter = runnable.limiter.as_ref().unwrap();
+        assert_eq!(limiter.get_total_bytes_through() as u64, scanned_size);
+    }
+
+    struct FixedChecker {
+        split_key: Vec<u8>,
+        veto: bool,
+    }
+
+    impl SplitChecker for FixedChecker {
+        fn split_key(&mut self) -> Option<Vec<u8>> {
+            Some(self.split_key.clone())
+        }
+
+        fn approve_split(&self, _: &ObserverContext, key: &[u8]) -> bool {
+            !self.veto || key != self.split_key.as_slice()
+        }
+    }
+
+    struct FixedObserver {
+        veto: bool,
+    }
+
+    impl Coprocessor for FixedObserver {}
+
+    impl SplitCheckObserver for FixedObserver {
+        fn add_checker(&self, _: &mut ObserverContext, host: &mut SplitCheckerHost, _: &DB) {
+            host.add_checker(Box::new(FixedChecker {
+                split_key: keys::data_key(b"0005"),
+                veto: self.veto,
+            }));
+        }
+    }
+
+    #[derive(Default)]
+    struct CfObserver {
+        fed: Arc<Mutex<Vec<CfName>>>,
+    }
+
+    impl Coprocessor for CfObserver {}
+
+    impl SplitCheckObserver for CfObserver {
+        fn add_checker(&self, _: &mut ObserverContext, host: &mut SplitCheckerHost, _: &DB) {
+            host.add_checker(Box::new(CfChecker {
+                cfs: &[CF_WRITE],
+                fed: Arc::clone(&self.fed),
+            }));
+        }
+    }
+
+    #[test]
+    fn test_split_check_scanned_bytes() {
+        let path = TempDir::new("test-split-check-scanned-bytes").unwrap();
+        let engine = new_engine_with_kvs(&path, 10);
+
+        let mut region = Region::new();
+        region.set_id(1);
+
+        let (mut runnable, rx) = new_runner(Config::default(), &engine, vec![]);
+
+        // Other tests may scan at the same time.
+        let scanned_bytes = SPLIT_CHECK_SCANNED_BYTES_TOTAL.get();
+        // Half split check always scans the region.
+        runnable.run(Task::new(region, false, CheckPolicy::SCAN));
+        // 10 kvs of 10 bytes.
+        assert_eq!(recv_result(&rx).results[0].0, 100);
+        assert!(SPLIT_CHECK_SCANNED_BYTES_TOTAL.get() >= scanned_bytes + 100);
+    }
+
+    #[test]
+    fn test_split_check_result_disabled() {
+        let path = TempDir::new("test-split-check-result-disabled").unwrap();
+        let engine = new_engine_with_kvs(&path, 10);
+
+        let mut region = Region::new();
+        region.set_id(1);
+
+        let mut cfg = Config::default();
+        cfg.report_split_check_result = false;
+        let (mut runnable, rx) = new_runner(cfg, &engine, vec![]);
+
+        // Half split check always scans the region.
+        runnable.run(Task::new(region, false, CheckPolicy::SCAN));
+        assert!(recv_result(&rx).results.is_empty());
+    }
+
+    #[test]
+    fn test_split_check_cf_stats() {
+        let path = TempDir::new("test-split-check-cf-stats").unwrap();
+        let engine = new_engine_with_kvs(&path, 0);
+        for &(cf, count) in &[(CF_DEFAULT, 6), (CF_WRITE, 4), (CF_LOCK, 3)] {
+            let handle = engine.cf_handle(cf).unwrap();
+            for i in 0..count {
+                let s = keys::data_key(format!("{:04}", i).as_bytes());
+                engine.put_cf(handle, &s, &s).unwrap();
+            }
+        }
+
+        let mut region = Region::new();
+        region.set_id(1);
+
+        let mut cfg = Config::default();
+        cfg.report_region_cf_entries = true;
+        let (mut runnable, rx) = new_runner(cfg, &engine, vec![Box::new(CfObserver::default())]);
+
+        // Half split check always scans the region, no checker is interested
+        // in the lock cf.
+        runnable.run(Task::new(region, false, CheckPolicy::SCAN));
+        match recv_result(&rx).others.pop() {
+            Some(Msg::SplitCheckCfStats {
+                region_id,
+                cf_entries,
+            }) => {
+                assert_eq!(region_id, 1);
+                assert_eq!(cf_entries.len(), 2);
+                assert_eq!(cf_entries[CF_DEFAULT], 6);
+                assert_eq!(cf_entries[CF_WRITE], 4);
+            }
+            others => panic!("expect split check cf stats, but got {:?}", others),
+        }
+    }
+
+    #[test]
+    fn test_split_check_interested_cfs() {
+        let path = TempDir::new("test-split-check-interested-cfs").unwrap();
+        let engine = new_engine_with_kvs(&path, 0);
+        for cf in ALL_CFS {
+            let handle = engine.cf_handle(cf).unwrap();
+            for i in 0..10 {
+                let s = keys::data_key(format!("{:04}", i).as_bytes());
+                engine.put_cf(handle, &s, &s).unwrap();
+            }
+        }
+
+        let mut region = Region::new();
+        region.set_id(1);
+
+        let mut cfg = Config::default();
+        cfg.split_region_on_table = false;
+        let observer = CfObserver::default();
+        let fed = Arc::clone(&observer.fed);
+        let (mut runnable, _rx) = new_runner(cfg, &engine, vec![Box::new(observer)]);
+
+        runnable.run(Task::new(region, true, CheckPolicy::SCAN));
+        assert_eq!(*fed.lock().unwrap(), vec![CF_WRITE; 10]);
+    }
+
+    // Records the scanned entries and splits at the `n`th one.
+    struct NthChecker {
+        n: usize,
+        entries: Arc<Mutex<Vec<(Vec<u8>, CfName)>>>,
+    }
+
+    impl SplitChecker for NthChecker {
+        fn on_kv(&mut self, _: &mut ObserverContext, entry: &KeyEntry) -> bool {
+            let mut entries = self.entries.lock().unwrap();
+            entries.push((entry.key().to_vec(), entry.cf()));
+            false
+        }
+
+        fn split_key(&mut self) -> Option<Vec<u8>> {
+            let entries = self.entries.lock().unwrap();
+            entries.get(self.n).map(|e| e.0.clone())
+        }
+
+        fn interested_cfs(&self) -> &[CfName] {
+            DATA_CFS
+        }
+    }
+
+    struct NthObserver {
+        n: usize,
+        entries: Arc<Mutex<Vec<(Vec<u8>, CfName)>>>,
+    }
+
+    impl Coprocessor for NthObserver {}
+
+    impl SplitCheckObserver for NthObserver {
+        fn add_checker(&self, _: &mut ObserverContext, host: &mut SplitCheckerHost, _: &DB) {
+            host.add_checker(Box::new(NthChecker {
+                n: self.n,
+                entries: Arc::clone(&self.entries),
+            }));
+        }
+    }
+
+    #[test]
+    fn test_split_check_ordered_across_cfs() {
+        let path = TempDir::new("test-split-check-ordered-across-cfs").unwrap();
+        let engine = new_engine_with_kvs(&path, 0);
+        // The keys of the CFs overlap with each other.
+        let cf_keys: &[(CfName, &[usize])] = &[
+            (CF_DEFAULT, &[0, 2, 4, 6, 8, 10]),
+            (CF_LOCK, &[3, 4, 9]),
+            (CF_WRITE, &[1, 2, 5, 6, 7, 11]),
+        ];
+        let mut expected = vec![];
+        for &(cf, ids) in cf_keys {
+            let handle = engine.cf_handle(cf).unwrap();
+            for i in ids {
+                let s = keys::data_key(format!("{:04}", i).as_bytes());
+                engine.put_cf(handle, &s, &s).unwrap();
+                expected.push((s, cf));
+            }
+        }
+        // Entries of the same key are in the order of DATA_CFS.
+        expected.sort_by_key(|&(ref k, cf)| (k.clone(), DATA_CFS.iter().position(|c| *c == cf)));
+
+        let mut region = Region::new();
+        region.set_id(1);
+
+        let mut cfg = Config::default();
+        cfg.split_region_on_table = false;
+        cfg.split_check_scan_batch_size = 4;
+        let entries = Arc::new(Mutex::new(vec![]));
+        let observer = NthObserver {
+            n: 7,
+            entries: Arc::clone(&entries),
+        };
+        let (mut runnable, rx) = new_runner(cfg, &engine, vec![Box::new(observer)]);
+
+        runnable.run(Task::new(region, true, CheckPolicy::SCAN));
+        assert_eq!(*entries.lock().unwrap(), expected);
+        let split_keys = vec![keys::origin_key(&expected[7].0).to_vec()];
+        assert_eq!(recv_result(&rx).splits[0].0, split_keys);
+    }
+
+    #[derive(Default)]
+    struct RecordObserver {
+        scans: Arc<Mutex<Vec<(u64, u64, bool)>>>,
+        splits: Arc<Mutex<Vec<(u64, Vec<Vec<u8>>)>>>,
+    }
+
+    impl Coprocessor for RecordObserver {}
+
+    impl SplitCheckObserver for RecordObserver {
+        fn add_checker(&self, _: &mut ObserverContext, _: &mut SplitCheckerHost, _: &DB) {}
+
+        fn on_scan_complete(&self, ctx: &ObserverContext, scanned_size: u64, found_split: bool) {
+            let region_id = ctx.region().get_id();
+            self.scans
+                .lock()
+                .unwrap()
+                .push((region_id, scanned_size, found_split));
+        }
+
+        fn on_split_emitted(&self, ctx: &ObserverContext, split_keys: &[Vec<u8>]) {
+            let region_id = ctx.region().get_id();
+            self.splits
+                .lock()
+                .unwrap()
+                .push((region_id, split_keys.to_vec()));
+        }
+    }
+
+    #[test]
+    fn test_split_check_scan_complete() {
+        let path = TempDir::new("test-split-check-scan-complete").unwrap();
+        let engine = new_engine_with_kvs(&path, 10);
+
+        let mut region = Region::new();
+        region.set_id(1);
+
+        let mut cfg = Config::default();
+        cfg.split_region_on_table = false;
+        let observer = RecordObserver::default();
+        let scans = Arc::clone(&observer.scans);
+        let (mut runnable, _rx) = new_runner(cfg, &engine, vec![Box::new(observer)]);
+
+        // Half split check always scans the region.
+        runnable.run(Task::new(region.clone(), false, CheckPolicy::SCAN));
+        // 10 kvs of 10 bytes, the region is too small to be split.
+        assert_eq!(*scans.lock().unwrap(), vec![(1, 100, false)]);
+
+        // Not called if the region is not scanned.
+        runnable.run(Task::new(region, false, CheckPolicy::APPROXIMATE));
+        assert_eq!(scans.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_split_check_deadline() {
+        let path = TempDir::new("test-split-check-deadline").unwrap();
+        let engine = new_engine_with_kvs(&path, 10);
+
+        let mut region = Region::new();
+        region.set_id(1);
+
+        let mut cfg = Config::default();
+        cfg.split_region_on_table = false;
+        let clock = MockClock::new();
+        let (runnable, rx) =
+            new_runner(cfg, &engine, vec![Box::new(FixedObserver { veto: false })]);
+        let mut runnable = runnable.with_clock(Box::new(clock.clone()));
+
+        // Other tests may time out at the same time.
+        let timeouts = SPLIT_CHECK_TIMEOUT_COUNTER.get();
+        let deadline = clock.now();
+        clock.advance(Duration::from_millis(10));
+        runnable.run(Task::new(region.clone(), true, CheckPolicy::SCAN).with_deadline(deadline));
+        let msgs = recv_result(&rx);
+        assert!(msgs.splits.is_empty(), "split after the deadline");
+        // The approximate size is still sent before the scan.
+        assert!(!msgs.sizes.is_empty());
+        assert!(SPLIT_CHECK_TIMEOUT_COUNTER.get() > timeouts);
+
+        // Split as usual if the deadline is not reached.
+        let deadline = clock.now() + Duration::from_secs(60);
+        runnable.run(Task::new(region, true, CheckPolicy::SCAN).with_deadline(deadline));
+        assert_eq!(recv_result(&rx).splits[0].0, vec![b"0005".to_vec()]);
+    }
+
+    // Splits the region at "0005" only for the custom policy `code`.
+    struct CustomPolicyObserver {
+        code: u32,
+    }
+
+    impl Coprocessor for CustomPolicyObserver {}
+
+    impl SplitCheckObserver for CustomPolicyObserver {
+        fn add_checker(&self, ctx: &mut ObserverContext, host: &mut SplitCheckerHost, _: &DB) {
+            if ctx.custom_policy == Some(self.code) {
+                host.add_checker(Box::new(FixedChecker {
+                    split_key: keys::data_key(b"0005"),
+                    veto: false,
+                }));
+            }
+        }
+    }
+
+    #[test]
+    fn test_split_check_custom_policy() {
+        let path = TempDir::new("test-split-check-custom-policy").unwrap();
+        let engine = new_engine_with_kvs(&path, 10);
+
+        let mut region = Region::new();
+        region.set_id(1);
+
+        let mut cfg = Config::default();
+        cfg.split_region_on_table = false;
+        let (mut runnable, rx) =
+            new_runner(cfg, &engine, vec![Box::new(CustomPolicyObserver { code: 7 })]);
+
+        let mut split_keys = |task| {
+            runnable.run(task);
+            recv_result(&rx).splits.pop().map(|(keys, _)| keys)
+        };
+        // The region is too small to be split by the built-in checkers.
+        assert_eq!(split_keys(Task::new(region.clone(), true, CheckPolicy::SCAN)), None);
+        let task = Task::new(region.clone(), true, CheckPolicy::SCAN).with_custom_policy(8);
+        assert_eq!(split_keys(task), None);
+        let task = Task::new(region, true, CheckPolicy::SCAN).with_custom_policy(7);
+        assert_eq!(split_keys(task), Some(vec![b"0005".to_vec()]));
+    }
+
+    #[test]
+    fn test_split_check_split_emitted() {
+        let path = TempDir::new("test-split-check-split-emitted").unwrap();
+        let engine = new_engine_with_kvs(&path, 10);
+
+        let mut region = Region::new();
+        region.set_id(1);
+
+        let mut cfg = Config::default();
+        cfg.split_region_on_table = false;
+        let observer = RecordObserver::default();
+        let splits = Arc::clone(&observer.splits);
+        let (mut runnable, _rx) = new_runner(
+            cfg,
+            &engine,
+            vec![Box::new(FixedObserver { veto: false }), Box::new(observer)],
+        );
+
+        // Not called in dry run.
+        runnable.run(Task::new(region.clone(), true, CheckPolicy::SCAN).with_dry_run());
+        assert!(splits.lock().unwrap().is_empty());
+
+        runnable.run(Task::new(region, true, CheckPolicy::SCAN));
+        assert_eq!(
+            *splits.lock().unwrap(),
+            vec![(1, vec![keys::data_key(b"0005")])]
+        );
+    }
+
+    #[test]
+    fn test_split_check_with_veto() {
+        let path = TempDir::new("test-split-check-veto").unwrap();
+        let engine = new_engine_with_kvs(&path, 10);
+
+        let mut region = Region::new();
+        region.set_id(1);
+
+        let check = |veto: bool| {
+            let mut cfg = Config::default();
+            cfg.split_region_on_table = false;
+            let (mut runnable, rx) =
+                new_runner(cfg, &engine, vec![Box::new(FixedObserver { veto })]);
+            runnable.run(Task::new(region.clone(), true, CheckPolicy::SCAN));
+            recv_result(&rx).splits.pop().map(|(keys, _)| keys)
+        };
+
+        assert_eq!(check(false), Some(vec![b"0005".to_vec()]));
+        assert_eq!(check(true), None);
+    }
+
+    #[test]
+    fn test_split_check_max_entry() {
+        let path = TempDir::new("test-split-check-max-entry").unwrap();
+        let engine = new_engine_with_kvs(&path, 10);
+        let big_key = keys::data_key(b"0005_big_key");
+        engine.put(&big_key, b"v").unwrap();
+        engine.put(&keys::data_key(b"0006"), &[0; 100]).unwrap();
+
+        let mut region = Region::new();
+        region.set_id(1);
+
+        let mut cfg = Config::default();
+        cfg.report_region_max_entry = true;
+        let (mut runnable, rx) = new_runner(cfg, &engine, vec![]);
+
+        runnable.run(Task::new(region, false, CheckPolicy::SCAN));
+        match recv_result(&rx).others.pop() {
+            Some(Msg::RegionMaxEntry {
+                region_id,
+                max_key_len,
+                max_value_len,
+            }) => {
+                assert_eq!(region_id, 1);
+                assert_eq!(max_key_len, big_key.len() as u64);
+                assert_eq!(max_value_len, 100);
+            }
+            others => panic!("expect max entry, but got {:?}", others),
+        }
+    }
+
+    #[test]
+    fn test_split_check_interval() {
+        let path = TempDir::new("test-split-check-interval").unwrap();
+        let engine = new_engine_with_kvs(&path, 0);
+        let mut region = Region::new();
+        region.set_id(1);
+
+        let mut cfg = Config::default();
+        cfg.split_check_interval = ReadableDuration::secs(60);
+        let (mut runnable, rx) = new_runner(cfg, &engine, vec![]);
+
+        let mut check = |auto_split| {
+            runnable.run(Task::new(region.clone(), auto_split, CheckPolicy::SCAN));
+            let mut count = 0;
+            while rx.try_recv().is_ok() {
+                count += 1;
+            }
+            count
+        };
+        assert!(check(true) > 0);
+        // The second check is skipped as it's too frequent.
+        assert_eq!(check(true), 0);
+        // But the one requested explicitly is not.
+        assert!(check(false) > 0);
+    }
+
+    #[test]
+    fn test_split_check_interval_with_mock_clock() {
+        let path = TempDir::new("test-split-check-interval-mock-clock").unwrap();
+        let engine = new_engine_with_kvs(&path, 0);
+        let mut region = Region::new();
+        region.set_id(1);
+
+        let mut cfg = Config::default();
+        cfg.split_check_interval = ReadableDuration::secs(60);
+        let clock = MockClock::new();
+        let (runnable, rx) = new_runner(cfg, &engine, vec![]);
+        let mut runnable = runnable.with_clock(Box::new(clock.clone()));
+
+        let mut check = || {
+            runnable.run(Task::new(region.clone(), true, CheckPolicy::SCAN));
+            let mut count = 0;
+            while rx.try_recv().is_ok() {
+                count += 1;
+            }
+            count
+        };
+        assert!(check() > 0);
+        clock.advance(Duration::from_secs(59));
+        assert_eq!(check(), 0);
+        // The region can be checked again once the interval passes.
+        clock.advance(Duration::from_secs(1));
+        assert!(check() > 0);
+    }
+
+    #[test]
+    fn test_split_check_dry_run() {
+        let path = TempDir::new("test-split-check-dry-run").unwrap();
+        let engine = new_engine_with_kvs(&path, 10);
+
+        let mut region = Region::new();
+        region.set_id(1);
+
+        let mut cfg = Config::default();
+        cfg.split_region_on_table = false;
+        let (mut runnable, rx) =
+            new_runner(cfg, &engine, vec![Box::new(FixedObserver { veto: false })]);
+
+        let task = Task::new(region, true, CheckPolicy::SCAN).with_dry_run();
+        runnable.run(task);
+        let msgs = recv_result(&rx);
+        assert!(msgs.splits.is_empty(), "should not split in dry run");
+        assert!(msgs.results.last().unwrap().1);
+    }
+
+    #[test]
+    fn test_split_check_at_start_key() {
+        let path = TempDir::new("test-split-check-start-key").unwrap();
+        let engine = new_engine_with_kvs(&path, 10);
+
+        // The fixed checker splits at the start key of the region.
+        let mut region = Region::new();
+        region.set_id(1);
+        region.set_start_key(b"0005".to_vec());
+
+        let mut cfg = Config::default();
+        cfg.split_region_on_table = false;
+        let (mut runnable, rx) =
+            new_runner(cfg, &engine, vec![Box::new(FixedObserver { veto: false })]);
+
+        runnable.run(Task::new(region, true, CheckPolicy::SCAN));
+        assert!(recv_result(&rx).splits.is_empty(), "should not split at start key");
+    }
+
+    #[test]
+    fn test_split_check_post_compaction() {
+        let path = TempDir::new("test-split-check-post-compaction").unwrap();
+        let engine = new_engine_with_kvs(&path, 10);
+
+        let mut region = Region::new();
+        region.set_id(1);
+
+        let mut cfg = Config::default();
+        cfg.split_region_on_table = false;
+        let (mut runnable, rx) =
+            new_runner(cfg, &engine, vec![Box::new(FixedObserver { veto: false })]);
+
+        // The task scheduled after a compaction is an automatic one.
+        let task = Task::post_compaction(region, CheckPolicy::SCAN);
+        assert!(task.auto_split);
+        assert_eq!(task.reason, SplitCheckReason::PostCompaction);
+        runnable.run(task);
+        assert_eq!(recv_result(&rx).splits[0].1, SplitCheckReason::PostCompaction);
+    }
+
+    #[test]
+    fn test_split_check_duplicated() {
+        let path = TempDir::new("test-split-check-duplicated").unwrap();
+        let engine = new_engine_with_kvs(&path, 10);
+
+        let mut region = Region::new();
+        region.set_id(1);
+
+        let mut cfg = Config::default();
+        cfg.split_region_on_table = false;
+        let (mut runnable, rx) =
+            new_runner(cfg, &engine, vec![Box::new(FixedObserver { veto: false })]);
+
+        let mut split_count = |region: &Region, auto_split: bool| {
+            runnable.run(Task::new(region.clone(), auto_split, CheckPolicy::SCAN));
+            recv_result(&rx).splits.len()
+        };
+
+        assert_eq!(split_count(&region, true), 1);
+        // The same split keys are not sent again.
+        assert_eq!(split_count(&region, true), 0);
+        // Unless the split is requested explicitly.
+        assert_eq!(split_count(&region, false), 1);
+        // Or the epoch changes.
+        region.mut_region_epoch().set_version(2);
+        assert_eq!(split_count(&region, true), 1);
+        assert_eq!(split_count(&region, true), 0);
+    }
+
+    // Makes every check take `cost` by advancing the clock.
+    struct SlowObserver {
+        clock: MockClock,
+        cost: Arc<Mutex<Duration>>,
+    }
+
+    impl Coprocessor for SlowObserver {}
+
+    impl SplitCheckObserver for SlowObserver {
+        fn add_checker(&self, _: &mut ObserverContext, _: &mut SplitCheckerHost, _: &DB) {
+            self.clock.advance(*self.cost.lock().unwrap());
+        }
+    }
+
+    #[test]
+    fn test_split_check_downgrade_when_overloaded() {
+        let path = TempDir::new("test-split-check-downgrade").unwrap();
+        let engine = new_engine_with_kvs(&path, 10);
+
+        let mut cfg = Config::default();
+        cfg.split_region_on_table = false;
+        cfg.split_check_overload_duration = ReadableDuration::secs(1);
+        let clock = MockClock::new();
+        let cost = Arc::new(Mutex::new(Duration::from_secs(4)));
+        let observer = SlowObserver {
+            clock: clock.clone(),
+            cost: Arc::clone(&cost),
+        };
+        // It only splits the regions that are scanned.
+        let (runnable, rx) = new_runner(
+            cfg,
+            &engine,
+            vec![Box::new(observer), Box::new(FixedObserver { veto: false })],
+        );
+        let mut runnable = runnable.with_clock(Box::new(clock));
+
+        let mut region = Region::new();
+        region.set_id(1);
+        let mut split_count = |auto_split: bool| {
+            // A new epoch every time, so the split is never duplicated.
+            let version = region.get_region_epoch().get_version();
+            region.mut_region_epoch().set_version(version + 1);
+            runnable.run(Task::new(region.clone(), auto_split, CheckPolicy::SCAN));
+            recv_result(&rx).splits.len()
+        };
+
+        // The average duration is 0.8s and 1.44s after the checks.
+        assert_eq!(split_count(true), 1);
+        assert_eq!(split_count(true), 1);
+        // Overloaded, the region is not scanned.
+        assert_eq!(split_count(true), 0);
+        // Unless the split is requested explicitly.
+        assert_eq!(split_count(false), 1);
+
+        // The checks are fast again, the average drops below 1s after four
+        // more checks.
+        *cost.lock().unwrap() = Duration::from_secs(0);
+        for _ in 0..4 {
+            assert_eq!(split_count(true), 0);
+        }
+        assert_eq!(split_count(true), 1);
+    }
+
+    #[test]
+    fn test_split_check_split_ack() {
+        let path = TempDir::new("test-split-check-split-ack").unwrap();
+        let engine = new_engine_with_kvs(&path, 10);
+
+        let mut region = Region::new();
+        region.set_id(1);
+
+        let mut cfg = Config::default();
+        cfg.split_region_on_table = false;
+        let (mut runnable, rx) =
+            new_runner(cfg, &engine, vec![Box::new(FixedObserver { veto: false })]);
+
+        let mut split_count = |task: Task| {
+            runnable.run(task);
+            recv_result(&rx).splits.len()
+        };
+        let check = || Task::new(region.clone(), true, CheckPolicy::SCAN);
+
+        assert_eq!(split_count(check()), 1);
+        assert_eq!(split_count(check()), 0);
+        // An applied split keeps suppressing the same split keys.
+        assert_eq!(split_count(Task::split_ack(region.clone(), true)), 0);
+        assert_eq!(split_count(check()), 0);
+        // A split that is not applied can be sent again.
+        assert_eq!(split_count(Task::split_ack(region.clone(), false)), 0);
+        assert_eq!(split_count(check()), 1);
+        assert_eq!(split_count(check()), 0);
+    }
+
+    #[test]
+    fn test_split_check_batch() {
+        let path = TempDir::new("test-split-check-batch").unwrap();
+        let engine = new_engine_with_kvs(&path, 10);
+
+        let bounds: &[(&[u8], &[u8])] = &[(b"", b"0003"), (b"0003", b"0006"), (b"0006", b"")];
+        let mut tasks = vec![];
+        for (i, &(start, end)) in bounds.iter().enumerate() {
+            let mut region = Region::new();
+            region.set_id(i as u64 + 1);
+            region.set_start_key(start.to_vec());
+            region.set_end_key(end.to_vec());
+            tasks.push(Task::new(region, false, CheckPolicy::SCAN));
+        }
+        // The context of every task is kept.
+        let last = tasks.pop().unwrap().with_range(b"0006".to_vec(), b"0008".to_vec());
+        tasks.push(last);
+
+        let mut cfg = Config::default();
+        cfg.split_region_on_table = false;
+        let (mut runnable, rx) = new_runner(cfg, &engine, vec![]);
+
+        runnable.run(Task::batch(tasks));
+        let mut msgs = recv_result(&rx);
+        assert!(msgs.results.is_empty(), "results should be sent together");
+        assert_eq!(msgs.others.len(), 1);
+        let results = match msgs.others.pop() {
+            Some(Msg::BatchSplitCheckResult(results)) => results,
+            others => panic!("expect batch split check result, but got {:?}", others),
+        };
+        // Every kv is 10 bytes.
+        let stats: Vec<_> = results
+            .iter()
+            .map(|r| (r.region_id, r.scanned_size, r.scanned_keys))
+            .collect();
+        assert_eq!(stats, vec![(1, 30, 3), (2, 30, 3), (3, 20, 2)]);
+    }
+
+    #[test]
+    fn test_split_check_single_large_key() {
+        let path = TempDir::new("test-split-check-single-large-key").unwrap();
+        let engine = new_engine_with_kvs(&path, 0);
+        let default_cf = engine.cf_handle(CF_DEFAULT).unwrap();
+        let write_cf = engine.cf_handle(CF_WRITE).unwrap();
+        // Many versions of a single user key.
+        for ts in 0..10 {
+            let key = keys::data_key(Key::from_raw(b"k").append_ts(ts).encoded());
+            engine.put_cf(default_cf, &key, &[0; 1024]).unwrap();
+            engine.put_cf(write_cf, &key, b"v").unwrap();
+        }
+
+        let mut region = Region::new();
+        region.set_id(1);
+
+        let mut cfg = Config::default();
+        cfg.split_region_on_table = false;
+        cfg.region_max_size = ReadableSize::kb(4);
+        cfg.region_split_size = Some(ReadableSize::kb(2));
+        let (mut runnable, _rx) = new_runner(cfg, &engine, vec![]);
+
+        // Half split check always scans the region.
+        let count = UNSPLITTABLE_REGION_COUNTER.get();
+        runnable.run(Task::new(region, false, CheckPolicy::SCAN));
+        assert!(UNSPLITTABLE_REGION_COUNTER.get() > count);
+    }
+
+    #[test]
+    fn test_split_check_size_divergence() {
+        let path = TempDir::new("test-split-check-size-divergence").unwrap();
+        let mut cf_opts = ColumnFamilyOptions::new();
+        let f = Box::new(RangePropertiesCollectorFactory::default());
+        cf_opts.add_table_properties_collector_factory("tikv.range-collector", f);
+        let cfs_opts = ALL_CFS
+            .iter()
+            .map(|cf| CFOptions::new(cf, cf_opts.clone()))
+            .collect();
+        let path_str = path.path().to_str().unwrap();
+        let engine = Arc::new(new_engine(path_str, ALL_CFS, Some(cfs_opts)).unwrap());
+        for i in 0..10 {
+            let s = keys::data_key(format!("{:04}", i).as_bytes());
+            engine.put(&s, &[0; 1024]).unwrap();
+        }
+        engine.flush(true).unwrap();
+        // The properties of the flushed values become stale after overwriting.
+        for i in 0..10 {
+            let s = keys::data_key(format!("{:04}", i).as_bytes());
+            engine.put(&s, b"v").unwrap();
+        }
+
+        let mut region = Region::new();
+        region.set_id(1);
+        let check = |ratio: f64| {
+            let mut cfg = Config::default();
+            cfg.region_size_divergence_ratio = ratio;
+            let (mut runnable, rx) = new_runner(cfg, &engine, vec![]);
+
+            // Half split check always scans the region.
+            runnable.run(Task::new(region.clone(), false, CheckPolicy::SCAN));
+            let msgs = recv_result(&rx);
+            (msgs.sizes, msgs.results.last().map_or(0, |r| r.0))
+        };
+
+        // The scanned size is reported after the approximate size.
+        let (sizes, scanned) = check(2.0);
+        assert_eq!(sizes.len(), 2);
+        assert!(sizes[0] > scanned * 2);
+        assert_eq!(sizes[1], scanned);
+
+        let (sizes, scanned) = check(0.0);
+        assert_eq!(sizes.len(), 1);
+        assert!(scanned > 0);
+    }
+
+    #[test]
+    fn test_split_check_compact_garbage() {
+        let path = TempDir::new("test-split-check-compact-garbage").unwrap();
+        let mut cf_opts = ColumnFamilyOptions::new();
+        let f = Box::new(MvccPropertiesCollectorFactory::default());
+        cf_opts.add_table_properties_collector_factory("tikv.mvcc-properties-collector", f);
+        let cfs_opts = ALL_CFS
+            .iter()
+            .map(|cf| CFOptions::new(cf, cf_opts.clone()))
+            .collect();
+        let path_str = path.path().to_str().unwrap();
+        let engine = Arc::new(new_engine(path_str, ALL_CFS, Some(cfs_opts)).unwrap());
+        // 10 keys with 20 versions each, 95% of the versions are garbage.
+        let write_cf = engine.cf_handle(CF_WRITE).unwrap();
+        for i in 0..10 {
+            for ts in 0..20 {
+                let k = Key::from_raw(format!("k{:04}", i).as_bytes()).append_ts(ts);
+                engine
+                    .put_cf(write_cf, &keys::data_key(k.encoded()), &[0; 20])
+                    .unwrap();
+            }
+        }
+        engine.flush_cf(write_cf, true).unwrap();
+
+        let mut region = Region::new();
+        region.set_id(1);
+        let check = |ratio: f64| {
+            let mut cfg = Config::default();
+            cfg.split_region_on_table = false;
+            cfg.region_max_size = ReadableSize(1024);
+            cfg.region_split_size = Some(ReadableSize(512));
+            cfg.region_compact_garbage_ratio = ratio;
+            let (mut runnable, rx) = new_runner(cfg, &engine, vec![]);
+
+            runnable.run(Task::new(region.clone(), true, CheckPolicy::SCAN));
+            let mut msgs = recv_result(&rx);
+            let compacted = match msgs.others.pop() {
+                Some(Msg::CompactRegion { region_id }) => {
+                    assert_eq!(region_id, 1);
+                    true
+                }
+                None => false,
+                others => panic!("expect compact region, but got {:?}", others),
+            };
+            (compacted, !msgs.splits.is_empty())
+        };
+
+        assert_eq!(check(0.9), (true, false));
+        // Not enough garbage, or the compaction is disabled.
+        assert_eq!(check(0.99), (false, true));
+        assert_eq!(check(0.0), (false, true));
+
+        // Splits requested explicitly are never replaced by compactions.
+        let mut cfg = Config::default();
+        cfg.split_region_on_table = false;
+        cfg.region_compact_garbage_ratio = 0.9;
+        let (mut runnable, rx) = new_runner(cfg, &engine, vec![]);
+        runnable.run(Task::new(region, false, CheckPolicy::SCAN));
+        let msgs = recv_result(&rx);
+        assert!(msgs.others.is_empty(), "should not compact");
+        assert_eq!(msgs.splits.len(), 1);
+    }
+
+    #[test]
+    fn test_split_check_out_of_range() {
+        let path = TempDir::new("test-split-check-out-of-range").unwrap();
+        let engine = new_engine_with_kvs(&path, 10);
+
+        // The fixed checker splits at a key after the end key of the region.
+        let mut region = Region::new();
+        region.set_id(1);
+        region.set_end_key(b"0003".to_vec());
+
+        let mut cfg = Config::default();
+        cfg.split_region_on_table = false;
+        let (mut runnable, rx) =
+            new_runner(cfg, &engine, vec![Box::new(FixedObserver { veto: false })]);
+
+        let invalid_count = INVALID_SPLIT_KEY_COUNTER.get();
+        runnable.run(Task::new(region, true, CheckPolicy::SCAN));
+        assert!(recv_result(&rx).splits.is_empty(), "should not split out of the region");
+        assert!(INVALID_SPLIT_KEY_COUNTER.get() > invalid_count);
+    }
+
+    #[test]
+    fn test_split_check_invalid_data_key() {
+        struct RawKeyObserver;
+
+        impl Coprocessor for RawKeyObserver {}
+
+        impl SplitCheckObserver for RawKeyObserver {
+            fn add_checker(&self, _: &mut ObserverContext, host: &mut SplitCheckerHost, _: &DB) {
+                host.add_checker(Box::new(FixedChecker {
+                    split_key: b"0005".to_vec(),
+                    veto: false,
+                }));
+            }
+        }
+
+        let path = TempDir::new("test-split-check-invalid-data-key").unwrap();
+        let engine = new_engine_with_kvs(&path, 10);
+
+        let mut region = Region::new();
+        region.set_id(1);
+
+        let mut cfg = Config::default();
+        cfg.split_region_on_table = false;
+        let (mut runnable, rx) = new_runner(cfg, &engine, vec![Box::new(RawKeyObserver)]);
+
+        // The split key lacks the data prefix, it's rejected instead of
+        // being sent.
+        let invalid_count = INVALID_SPLIT_KEY_COUNTER.get();
+        runnable.run(Task::new(region, true, CheckPolicy::SCAN));
+        assert!(recv_result(&rx).splits.is_empty(), "should not split at a raw key");
+        assert!(INVALID_SPLIT_KEY_COUNTER.get() > invalid_count);
+    }
+
+    #[test]
+    fn test_split_check_scan_batch_size() {
+        let path = TempDir::new("test-split-check-scan-batch-size").unwrap();
+        let engine = new_engine_with_kvs(&path, 100);
+
+        let mut region = Region::new();
+        region.set_id(1);
+
+        let split_keys = |batch_size: usize| {
+            let mut cfg = Config::default();
+            cfg.split_region_on_table = false;
+            cfg.region_max_size = ReadableSize(1024);
+            cfg.region_split_size = Some(ReadableSize(512));
+            cfg.split_check_scan_batch_size = batch_size;
+            let (mut runnable, rx) = new_runner(cfg, &engine, vec![]);
+
+            // Half split check always scans the region.
+            runnable.run(Task::new(region.clone(), false, CheckPolicy::SCAN));
+            recv_result(&rx).splits.remove(0).0
+        };
+
+        // The split keys don't depend on the batch size.
+        let expected = split_keys(1024);
+        for batch_size in &[1, 7, 100, 101] {
+            assert_eq!(split_keys(*batch_size), expected);
+        }
+    }
+
+    #[test]
+    fn test_split_check_readahead() {
+        let path = TempDir::new("test-split-check-readahead").unwrap();
+        let engine = new_engine_with_kvs(&path, 100);
+
+        let mut cfg = Config::default();
+        cfg.split_check_readahead_size = ReadableSize::mb(2);
+        let (runnable, _rx) = new_runner(cfg, &engine, vec![]);
+        assert_eq!(runnable.readahead_size, ReadableSize::mb(2).0 as usize);
+
+        let (start_key, end_key) = (keys::data_key(b""), keys::data_end_key(b""));
+        let iter_opt = scan_iter_opt(&start_key, &end_key, false, runnable.readahead_size);
+        assert_eq!(iter_opt.readahead_size(), ReadableSize::mb(2).0 as usize);
+        assert_eq!(iter_opt.lower_bound(), Some(start_key.as_slice()));
+        assert_eq!(iter_opt.upper_bound(), Some(end_key.as_slice()));
+
+        // Read-ahead doesn't change what is scanned.
+        let scan = |readahead_size| {
+            let mut iter = MergedIterator::new(
+                engine.as_ref(),
+                &[CF_DEFAULT, CF_WRITE],
+                &start_key,
+                &end_key,
+                false,
+                readahead_size,
+            ).unwrap();
+            let mut keys = vec![];
+            while let Some(e) = iter.next().unwrap() {
+                keys.push(e.key().to_vec());
+            }
+            keys
+        };
+        let expected = scan(0);
+        assert_eq!(expected.len(), 100);
+        assert_eq!(scan(runnable.readahead_size), expected);
+    }
+
+    #[test]
+    fn test_split_check_stopped() {
+        let path = TempDir::new("test-split-check-stopped").unwrap();
+        let engine = new_engine_with_kvs(&path, 0);
+        for i in 0..Config::default().split_check_scan_batch_size * 2 {
+            let s = keys::data_key(format!("{:08}", i).as_bytes());
+            engine.put(&s, &s).unwrap();
+        }
+
+        let mut region = Region::new();
+        region.set_id(1);
+
+        let mut cfg = Config::default();
+        cfg.split_region_on_table = false;
+        let (mut runnable, rx) =
+            new_runner(cfg, &engine, vec![Box::new(FixedObserver { veto: false })]);
+
+        runnable.stop_flag().store(true, AtomicOrdering::SeqCst);
+        runnable.run(Task::new(region, true, CheckPolicy::SCAN));
+        let msgs = recv_result(&rx);
+        assert!(msgs.splits.is_empty() && msgs.results.is_empty(), "the scan should be aborted");
+    }
+
+    // Forwards the scheduled tasks so they can be run by the test.
+    struct ForwardRunner(mpsc::Sender<Task>);
+
+    impl Runnable<Task> for ForwardRunner {
+        fn run(&mut self, task: Task) {
+            self.0.send(task).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_split_check_resume() {
+        let path = TempDir::new("test-split-check-resume").unwrap();
+        let mut cf_opts = ColumnFamilyOptions::new();
+        let f = Box::new(RangePropertiesCollectorFactory::default());
+        cf_opts.add_table_properties_collector_factory("tikv.range-collector", f);
+        let cfs_opts = ALL_CFS
+            .iter()
+            .map(|cf| CFOptions::new(cf, cf_opts.clone()))
+            .collect();
+        let path_str = path.path().to_str().unwrap();
+        let engine = Arc::new(new_engine(path_str, ALL_CFS, Some(cfs_opts)).unwrap());
+        // Every entry is 105 bytes.
+        for i in 0..20 {
+            let s = keys::data_key(format!("{:04}", i).as_bytes());
+            engine.put(&s, &[0; 100]).unwrap();
+        }
+        engine.flush(true).unwrap();
+
+        let mut region = Region::new();
+        region.set_id(1);
+
+        let mut worker = Worker::new("test-split-check-resume");
+        let (task_tx, task_rx) = mpsc::channel();
+        worker.start(ForwardRunner(task_tx)).unwrap();
+        let mut cfg = Config::default();
+        cfg.split_region_on_table = false;
+        cfg.region_max_size = ReadableSize(1000);
+        cfg.region_split_size = Some(ReadableSize(600));
+        cfg.split_check_scan_batch_size = 1;
+        cfg.split_check_chunk_size = ReadableSize(500);
+        let (runnable, rx) = new_runner(cfg, &engine, vec![]);
+        let mut runnable = runnable.with_scheduler(worker.scheduler());
+
+        // The first chunk stops after 5 entries without a split key.
+        runnable.run(Task::new(region, true, CheckPolicy::SCAN));
+        assert!(recv_result(&rx).splits.is_empty(), "the first chunk should not split");
+        let task = task_rx.recv_timeout(Duration::from_secs(3)).unwrap();
+        assert_eq!(task.resume_from, Some(keys::data_key(b"0004")));
+        assert_eq!(task.resumed_cfs, vec![CF_DEFAULT]);
+        assert_eq!(task.resumed_size, 525);
+        assert!(task.auto_split);
+
+        // The second chunk carries on the size of the first one.
+        runnable.run(task);
+        assert_eq!(recv_result(&rx).splits[0].0, vec![b"0005".to_vec()]);
+        assert!(task_rx.try_recv().is_err());
+        worker.stop().unwrap().join().unwrap();
+    }
+
+    #[test]
+    fn test_split_check_deferred() {
+        let path = TempDir::new("test-split-check-deferred").unwrap();
+        let engine = new_engine_with_kvs(&path, 10);
+
+        let mut region = Region::new();
+        region.set_id(1);
+
+        let mut cfg = Config::default();
+        cfg.split_region_on_table = false;
+        let clock = MockClock::new();
+        let (runnable, rx) =
+            new_runner(cfg, &engine, vec![Box::new(FixedObserver { veto: false })]);
+        let mut runnable = runnable.with_clock(Box::new(clock.clone()));
+        let deferred_count = runnable.deferred_count();
+
+        // The delay starts when the runner receives the task.
+        let delay = Duration::from_millis(100);
+        let task = Task::new(region, true, CheckPolicy::SCAN).with_delay(delay);
+        clock.advance(delay * 2);
+        runnable.run(task);
+        assert!(rx.try_recv().is_err());
+        assert_eq!(runnable.deferred.len(), 1);
+        assert_eq!(deferred_count.load(AtomicOrdering::SeqCst), 1);
+
+        // The task is kept until its time.
+        let mut timer = Timer::new(1);
+        runnable.on_timeout(&mut timer, ());
+        assert_eq!(runnable.deferred.len(), 1);
+        assert!(rx.try_recv().is_err());
+
+        clock.advance(delay);
+        runnable.on_timeout(&mut timer, ());
+        assert!(runnable.deferred.is_empty());
+        assert_eq!(deferred_count.load(AtomicOrdering::SeqCst), 0);
+        assert_eq!(recv_result(&rx).splits.len(), 1);
+    }
+
+    #[test]
+    fn test_split_history() {
+        let path = TempDir::new("test-split-history").unwrap();
+        let engine = new_engine_with_kvs(&path, 10);
+
+        let mut cfg = Config::default();
+        cfg.split_region_on_table = false;
+        let clock = MockClock::new();
+        let (runnable, _rx) =
+            new_runner(cfg, &engine, vec![Box::new(FixedObserver { veto: false })]);
+        let mut runnable = runnable.with_clock(Box::new(clock.clone()));
+        let history = runnable.split_history();
+
+        let mut region = Region::new();
+        region.set_id(1);
+        let t1 = clock.now();
+        runnable.run(Task::new(region.clone(), true, CheckPolicy::SCAN));
+        clock.advance(Duration::from_secs(1));
+        region.set_id(2);
+        let t2 = clock.now();
+        let split_keys = vec![b"0002".to_vec(), b"0007".to_vec()];
+        runnable.run(Task::manual(region, split_keys));
+
+        assert_eq!(
+            history.records(),
+            vec![
+                (1, t1, b"0005".to_vec()),
+                (2, t2, b"0002".to_vec()),
+                (2, t2, b"0007".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_split_check_event_sink() {
+        let path = TempDir::new("test-split-check-event-sink").unwrap();
+        let engine = new_engine_with_kvs(&path, 10);
+
+        let mut cfg = Config::default();
+        cfg.split_region_on_table = false;
+        let events = Arc::new(Mutex::new(vec![]));
+        let sink_events = Arc::clone(&events);
+        let (runnable, _rx) =
+            new_runner(cfg, &engine, vec![Box::new(FixedObserver { veto: false })]);
+        let mut runnable =
+            runnable.with_event_sink(Box::new(move |e| sink_events.lock().unwrap().push(e)));
+
+        let mut region = Region::new();
+        region.set_id(1);
+        region.mut_region_epoch().set_version(2);
+        runnable.run(Task::new(region.clone(), true, CheckPolicy::SCAN));
+        // No event if the split is not sent.
+        runnable.run(Task::new(region.clone(), true, CheckPolicy::SCAN).with_dry_run());
+        let split_keys = vec![b"0002".to_vec(), b"0007".to_vec()];
+        runnable.run(Task::manual(region.clone(), split_keys.clone()));
+
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].region_id, 1);
+        assert_eq!(&events[0].epoch, region.get_region_epoch());
+        assert_eq!(events[0].reason, SplitCheckReason::Size);
+        assert_eq!(events[0].split_keys, vec![b"0005".to_vec()]);
+        assert!(events[0].scanned_size > 0);
+        assert_eq!(events[1].reason, SplitCheckReason::Manual);
+        assert_eq!(events[1].split_keys, split_keys);
+        assert_eq!(events[1].scanned_size, 0);
+    }
+
+    #[test]
+    fn test_split_check_manual() {
+        let path = TempDir::new("test-split-check-manual").unwrap();
+        let engine = new_engine_with_kvs(&path, 0);
+
+        let mut region = Region::new();
+        region.set_id(1);
+        region.set_start_key(b"0001".to_vec());
+        region.set_end_key(b"0009".to_vec());
+        region.mut_region_epoch().set_version(3);
+
+        let observer = RecordObserver::default();
+        let splits = Arc::clone(&observer.splits);
+        let (mut runnable, rx) = new_runner(Config::default(), &engine, vec![Box::new(observer)]);
+
+        let split_keys = vec![b"0003".to_vec(), b"0005".to_vec()];
+        runnable.run(Task::manual(region.clone(), split_keys.clone()));
+        match rx.try_recv() {
+            Ok(Msg::SplitRegion {
+                region_id,
+                region_epoch,
+                split_keys: keys,
+                reason,
+                ..
+            }) => {
+                assert_eq!(region_id, 1);
+                assert_eq!(region_epoch.get_version(), 3);
+                assert_eq!(keys, split_keys);
+                assert_eq!(reason, SplitCheckReason::Manual);
+            }
+            others => panic!("expect split region, but got {:?}", others),
+        }
+        // The region is not scanned.
+        assert!(rx.try_recv().is_err());
+        // Observers are notified with the data keys.
+        let data_keys = split_keys.iter().map(|k| keys::data_key(k)).collect();
+        assert_eq!(*splits.lock().unwrap(), vec![(1, data_keys)]);
+
+        // Invalid keys are rejected.
+        let invalid_keys: Vec<Vec<Vec<u8>>> = vec![
+            vec![],
+            // Out of order.
+            vec![b"0005".to_vec(), b"0003".to_vec()],
+            vec![b"0003".to_vec(), b"0003".to_vec()],
+            // Out of the region.
+            vec![b"0000".to_vec()],
+            vec![b"0001".to_vec()],
+            vec![b"0003".to_vec(), b"0009".to_vec()],
+        ];
+        for keys in invalid_keys {
+            assert!(check_manual_split_keys(&region, &keys).is_err());
+            runnable.run(Task::manual(region.clone(), keys));
+            assert!(rx.try_recv().is_err());
+        }
+        assert_eq!(splits.lock().unwrap().len(), 1);
+    }
+
+    // Fails once the entries are used up.
+    struct FailingIterator(VecEntryIterator);
+
+    impl EntryIterator for FailingIterator {
+        fn next(&mut self) -> Result<Option<KeyEntry>> {
+            match self.0.next()? {
+                Some(e) => Ok(Some(e)),
+                None => Err(box_err!("injected error")),
+            }
+        }
+    }
+
+    #[test]
+    fn test_scan_entries() {
+        let region = Region::new();
+        // Every entry is 10 bytes.
+        let entries = |n: usize| -> Vec<KeyEntry> {
+            (0..n)
+                .map(|i| {
+                    let key = keys::data_key(format!("{:04}", i).as_bytes());
+                    KeyEntry::new(key, 0, 5, CF_DEFAULT)
+                })
+                .collect()
+        };
+        let mut host = SplitCheckerHost::new(true);
+        host.add_checker(Box::new(FixedChecker {
+            split_key: keys::data_key(b"0005"),
+            veto: false,
+        }));
+
+        let mut iter = VecEntryIterator::new(entries(10));
+        let progress = scan_entries(&mut iter, &mut host, &region, &ScanControl::new(3));
+        assert!(progress.exhausted);
+        assert_eq!(progress.scanned_size, 100);
+        assert_eq!(progress.scanned_keys, 10);
+        assert_eq!((progress.max_key_len, progress.max_value_len), (5, 5));
+        assert_eq!(progress.distinct_user_keys, 2);
+        assert!(progress.last_key.is_none());
+
+        // The last key of the batch exceeding the chunk size is left to the
+        // next chunk.
+        let mut ctl = ScanControl::new(3);
+        ctl.chunk_size = 25;
+        let mut iter = VecEntryIterator::new(entries(10));
+        let progress = scan_entries(&mut iter, &mut host, &region, &ctl);
+        assert!(!progress.exhausted);
+        assert_eq!(progress.last_key, Some(keys::data_key(b"0002")));
+        assert_eq!(progress.last_key_cfs, vec![CF_DEFAULT]);
+
+        // The entries of the last key in other CFs are left to the next
+        // chunk, even if the entries of the key span batches.
+        let entries_of_cfs = |start: usize| -> Vec<KeyEntry> {
+            let mut entries = vec![];
+            for i in start..4 {
+                let key = keys::data_key(format!("{:04}", i).as_bytes());
+                entries.push(KeyEntry::new(key.clone(), 0, 5, CF_DEFAULT));
+                entries.push(KeyEntry::new(key, 1, 5, CF_WRITE));
+            }
+            entries
+        };
+        let mut ctl = ScanControl::new(3);
+        ctl.chunk_size = 25;
+        let mut iter = VecEntryIterator::new(entries_of_cfs(0));
+        let progress = scan_entries(&mut iter, &mut host, &region, &ctl);
+        assert_eq!(progress.last_key, Some(keys::data_key(b"0001")));
+        assert_eq!(progress.last_key_cfs, vec![CF_DEFAULT]);
+        let mut ctl = ScanControl::new(1);
+        ctl.chunk_size = 20;
+        let mut iter = VecEntryIterator::new(entries_of_cfs(0));
+        let progress = scan_entries(&mut iter, &mut host, &region, &ctl);
+        assert_eq!(progress.last_key, Some(keys::data_key(b"0000")));
+        assert_eq!(progress.last_key_cfs, vec![CF_DEFAULT, CF_WRITE]);
+        // Resuming from the key skips the entries scanned already.
+        let (key, cfs) = (keys::data_key(b"0001"), [CF_DEFAULT]);
+        let mut ctl = ScanControl::new(3);
+        ctl.skip = Some((key.as_slice(), &cfs[..]));
+        let mut iter = VecEntryIterator::new(entries_of_cfs(1));
+        let progress = scan_entries(&mut iter, &mut host, &region, &ctl);
+        assert!(progress.exhausted);
+        assert_eq!(progress.scanned_keys, 5);
+
+        let stopped = AtomicBool::new(true);
+        let mut ctl = ScanControl::new(3);
+        ctl.stopped = Some(&stopped);
+        let mut iter = VecEntryIterator::new(entries(10));
+        assert!(scan_entries(&mut iter, &mut host, &region, &ctl).stopped);
+
+        let mut iter = FailingIterator(VecEntryIterator::new(entries(4)));
+        let progress = scan_entries(&mut iter, &mut host, &region, &ScanControl::new(3));
+        let (_, last_key) = progress.scan_error.unwrap();
+        assert_eq!(last_key, Some(keys::data_key(b"0003")));
+
+        let mut iter = VecEntryIterator::new(entries(10));
+        let split_keys = split_keys_of_entries(&mut host, &region, &mut iter, 3).unwrap();
+        assert_eq!(split_keys, vec![keys::data_key(b"0005")]);
+        let mut iter = FailingIterator(VecEntryIterator::new(entries(10)));
+        assert!(split_keys_of_entries(&mut host, &region, &mut iter, 3).is_err());
+    }
+
+    #[test]
+    fn test_compute_split_keys() {
+        let path = TempDir::new("test-compute-split-keys").unwrap();
+        let engine = new_engine_with_kvs(&path, 10);
+
+        let mut region = Region::new();
+        region.set_id(1);
+
+        let mut cfg = Config::default();
+        cfg.split_region_on_table = false;
+        let (mut runnable, rx) =
+            new_runner(cfg, &engine, vec![Box::new(FixedObserver { veto: false })]);
+
+        let split_keys = runnable.compute_split_keys(&region, CheckPolicy::SCAN).unwrap();
+        assert_eq!(split_keys, vec![keys::data_key(b"0005")]);
+        // The keys out of the region are filtered.
+        region.set_end_key(b"0003".to_vec());
+        let split_keys = runnable.compute_split_keys(&region, CheckPolicy::SCAN).unwrap();
+        assert!(split_keys.is_empty());
+
+        // The region is neither split nor reported.
+        let msgs = recv_result(&rx);
+        assert!(msgs.splits.is_empty() && msgs.results.is_empty(), "no result should be sent");
+    }
+
+    #[test]
+    fn test_compute_quantile_split_keys() {
+        let path = TempDir::new("test-compute-quantile-split-keys").unwrap();
+        // 100 kvs of the same size.
+        let engine = new_engine_with_kvs(&path, 100);
+
+        let mut region = Region::new();
+        region.set_id(1);
+
+        let mut cfg = Config::default();
+        cfg.split_region_on_table = false;
+        cfg.region_max_size = ReadableSize(1024);
+        cfg.region_split_size = Some(ReadableSize(1024));
+        let (mut runnable, _rx) = new_runner(cfg, &engine, vec![]);
+
+        let split_keys = runnable.compute_quantile_split_keys(&region, 4).unwrap();
+        assert_eq!(split_keys.len(), 3);
+        // The segments have roughly the same number of kvs.
+        let mut last = 0;
+        for key in split_keys.iter().chain(Some(&keys::data_key(b"0100"))) {
+            let i: i64 = String::from_utf8_lossy(keys::origin_key(key))
+                .parse()
+                .unwrap();
+            assert!((i - last - 25).abs() <= 1, "{:?}", split_keys);
+            last = i;
+        }
+    }
+
+    #[test]
+    fn test_split_check_pending_tasks() {
+        let path = TempDir::new("test-split-check-pending-tasks").unwrap();
+        let engine = new_engine_with_kvs(&path, 0);
+        let mut region = Region::new();
+        region.set_id(1);
+
+        let mut cfg = Config::default();
+        cfg.split_region_on_table = false;
+        let (runnable, _rx) = new_runner(cfg, &engine, vec![]);
+
+        // Tasks are pending until they are run.
+        let gauge = IntGauge::new("test_split_check_pending_tasks", "test").unwrap();
+        let mut worker = Worker::new("test-split-check-pending-tasks");
+        for _ in 0..3 {
+            let task = Task::new(region.clone(), true, CheckPolicy::SCAN);
+            worker.schedule(task.counted_by(gauge.clone())).unwrap();
+        }
+        assert_eq!(gauge.get(), 3);
+        worker.start(runnable).unwrap();
+        worker.stop().unwrap().join().unwrap();
+        assert_eq!(gauge.get(), 0);
+    }
+
+    #[test]
+    fn test_observe_size_ratio() {
+        let count = SPLIT_CHECK_SIZE_RATIO_HISTOGRAM.get_sample_count();
+        let ratio = observe_size_ratio(300, 100).unwrap();
+        assert!((ratio - 3.0).abs() < 1e-9);
+        let ratio = observe_size_ratio(50, 200).unwrap();
+        assert!((ratio - 0.25).abs() < 1e-9);
+        // Other tests may observe ratios at the same time.
+        assert!(SPLIT_CHECK_SIZE_RATIO_HISTOGRAM.get_sample_count() >= count + 2);
+
+        // The ratio is unknown without the approximate size.
+        assert_eq!(observe_size_ratio(100, 0), None);
     }
 }
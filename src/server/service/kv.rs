@@ -28,7 +28,7 @@ use std::iter::{self, FromIterator};
 
 use coprocessor::local_metrics::BasicLocalMetrics;
 use coprocessor::{err_resp, EndPointTask, RequestTask};
-use raftstore::store::{Callback, Msg as StoreMessage};
+use raftstore::store::{Callback, Msg as StoreMessage, SplitCheckReason};
 use server::metrics::*;
 use server::snap::Task as SnapTask;
 use server::transport::RaftStoreRouter;
@@ -1127,7 +1127,8 @@ impl<T: RaftStoreRouter + 'static, E: Engine> tikvpb_grpc::Tikv for Service<T, E
         let req = StoreMessage::SplitRegion {
             region_id: req.get_context().get_region_id(),
             region_epoch: req.take_context().take_region_epoch(),
-            split_key: Key::from_raw(req.get_split_key()).take_encoded(),
+            split_keys: vec![Key::from_raw(req.get_split_key()).take_encoded()],
+            reason: SplitCheckReason::Manual,
             callback: Callback::Write(cb),
         };
 
@@ -177,3 +177,76 @@ impl rocksdb::EventListener for CompactionListener {
         ));
     }
 }
+
+/// The SST files of a CF are changed by a flush, a compaction or an
+/// ingestion.
+pub struct SstChangedEvent {
+    pub cf: String,
+    /// The paths of the added SST files with their range properties, and the
+    /// paths of the deleted SST files. It's `None` if they are unknown.
+    pub files: Option<(Vec<(String, RangeProperties)>, Vec<String>)>,
+}
+
+/// Sends the changes of SST files to `ch`, so the size of key ranges can be
+/// updated without reading all the table properties again.
+pub struct SstChangeListener {
+    ch: Box<Fn(SstChangedEvent) + Send + Sync>,
+}
+
+impl SstChangeListener {
+    pub fn new(ch: Box<Fn(SstChangedEvent) + Send + Sync>) -> SstChangeListener {
+        SstChangeListener { ch }
+    }
+}
+
+fn flushed_files(info: &FlushJobInfo) -> Option<(Vec<(String, RangeProperties)>, Vec<String>)> {
+    let file = info.file_path().to_str()?.to_owned();
+    let prop = RangeProperties::decode(info.table_properties().user_collected_properties()).ok()?;
+    Some((vec![(file, prop)], vec![]))
+}
+
+fn compacted_files(
+    info: &CompactionJobInfo,
+) -> Option<(Vec<(String, RangeProperties)>, Vec<String>)> {
+    let mut input_files = Vec::with_capacity(info.input_file_count());
+    let mut output_files = HashSet::with_capacity(info.output_file_count());
+    for i in 0..info.input_file_count() {
+        input_files.push(info.input_file_at(i).to_str()?.to_owned());
+    }
+    for i in 0..info.output_file_count() {
+        output_files.insert(info.output_file_at(i).to_str()?.to_owned());
+    }
+    let mut added = Vec::with_capacity(info.output_file_count());
+    let iter = info.table_properties().into_iter();
+    for (file, properties) in iter {
+        if output_files.contains(file) {
+            let prop = RangeProperties::decode(properties.user_collected_properties()).ok()?;
+            added.push((file.to_owned(), prop));
+        }
+    }
+    Some((added, input_files))
+}
+
+impl rocksdb::EventListener for SstChangeListener {
+    fn on_flush_completed(&self, info: &FlushJobInfo) {
+        (self.ch)(SstChangedEvent {
+            cf: info.cf_name().to_owned(),
+            files: flushed_files(info),
+        });
+    }
+
+    fn on_compaction_completed(&self, info: &CompactionJobInfo) {
+        (self.ch)(SstChangedEvent {
+            cf: info.cf_name().to_owned(),
+            files: compacted_files(info),
+        });
+    }
+
+    fn on_external_file_ingested(&self, info: &IngestionInfo) {
+        // The ingested files may be built without range properties.
+        (self.ch)(SstChangedEvent {
+            cf: info.cf_name().to_owned(),
+            files: None,
+        });
+    }
+}
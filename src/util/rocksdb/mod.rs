@@ -17,7 +17,9 @@ pub mod metrics_flusher;
 pub mod properties;
 pub mod stats;
 
-pub use self::event_listener::{CompactedEvent, CompactionListener, EventListener};
+pub use self::event_listener::{
+    CompactedEvent, CompactionListener, EventListener, SstChangeListener, SstChangedEvent,
+};
 pub use self::metrics_flusher::MetricsFlusher;
 
 use std::cmp;
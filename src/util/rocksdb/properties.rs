@@ -39,6 +39,7 @@ const PROP_ROWS_INDEX_DISTANCE: u64 = 10000;
 const PROP_TOTAL_SIZE: &str = "tikv.total_size";
 const PROP_SIZE_INDEX: &str = "tikv.size_index";
 const PROP_RANGE_INDEX: &str = "tikv.range_index";
+const PROP_RANGE_TS_INDEX: &str = "tikv.range_ts_index";
 const PROP_SIZE_INDEX_DISTANCE: u64 = 4 * 1024 * 1024;
 const PROP_KEYS_INDEX_DISTANCE: u64 = 40 * 1024;
 
@@ -476,8 +477,8 @@ pub enum RangeOffsetKind {
 
 #[derive(Debug, Default, Clone)]
 pub struct RangeOffsets {
-    size: u64,
-    keys: u64,
+    pub size: u64,
+    pub keys: u64,
 }
 
 impl RangeOffsets {
@@ -492,6 +493,9 @@ impl RangeOffsets {
 #[derive(Debug, Default)]
 pub struct RangeProperties {
     pub offsets: BTreeMap<Vec<u8>, RangeOffsets>,
+    // The max timestamp of the keys after the previous offset key and not
+    // after the key, it's empty for old versions.
+    pub max_ts: BTreeMap<Vec<u8>, u64>,
 }
 
 impl RangeProperties {
@@ -505,6 +509,13 @@ impl RangeProperties {
         }
         let mut props = UserProperties::new();
         props.encode(PROP_RANGE_INDEX, buf);
+        let mut buf = Vec::with_capacity(1024);
+        for (k, ts) in &self.max_ts {
+            buf.encode_u64(k.len() as u64).unwrap();
+            buf.extend(k);
+            buf.encode_u64(*ts).unwrap();
+        }
+        props.encode(PROP_RANGE_TS_INDEX, buf);
         props
     }
 
@@ -528,6 +539,19 @@ impl RangeProperties {
             offsets.keys = number::decode_u64(&mut buf)?;
             res.offsets.insert(k, offsets);
         }
+        let mut buf = match props.decode(PROP_RANGE_TS_INDEX) {
+            Ok(buf) => buf,
+            // Written by an old version.
+            Err(Error::KeyNotFound) => return Ok(res),
+            Err(e) => return Err(e),
+        };
+        while !buf.is_empty() {
+            let klen = number::decode_u64(&mut buf)?;
+            let mut k = vec![0; klen as usize];
+            buf.read_exact(&mut k)?;
+            let ts = number::decode_u64(&mut buf)?;
+            res.max_ts.insert(k, ts);
+        }
         Ok(res)
     }
 
@@ -569,6 +593,34 @@ impl RangeProperties {
         end_offset - start_offset
     }
 
+    /// Get the size of the blocks overlapping with `[start, end)` whose max
+    /// timestamps are not less than `ts`, and the size of all the blocks
+    /// overlapping with it. Blocks without timestamps are never recent.
+    pub fn get_approximate_recent_size_in_range(
+        &self,
+        start: &[u8],
+        end: &[u8],
+        ts: u64,
+    ) -> (u64, u64) {
+        let (mut recent_size, mut total_size) = (0, 0);
+        let (mut last_key, mut last_size): (&[u8], u64) = (b"", 0);
+        for (k, offsets) in &self.offsets {
+            if last_key >= end {
+                break;
+            }
+            if k.as_slice() >= start {
+                let size = offsets.size - last_size;
+                total_size += size;
+                if self.max_ts.get(k).map_or(false, |t| *t >= ts) {
+                    recent_size += size;
+                }
+            }
+            last_key = k;
+            last_size = offsets.size;
+        }
+        (recent_size, total_size)
+    }
+
     pub fn smallest_key(&self) -> Option<Vec<u8>> {
         self.offsets.iter().next().map(|(key, _)| key.clone())
     }
@@ -596,6 +648,8 @@ pub struct RangePropertiesCollector {
     last_offsets: RangeOffsets,
     last_key: Vec<u8>,
     cur_offsets: RangeOffsets,
+    // The max timestamp of the keys added after the last offset key.
+    cur_max_ts: u64,
 }
 
 impl RangePropertiesCollector {
@@ -609,7 +663,9 @@ impl RangePropertiesCollector {
 
     fn insert_new_point(&mut self, key: Vec<u8>) {
         self.last_offsets = self.cur_offsets.clone();
+        self.props.max_ts.insert(key.clone(), self.cur_max_ts);
         self.props.offsets.insert(key, self.cur_offsets.clone());
+        self.cur_max_ts = 0;
     }
 }
 
@@ -624,6 +680,10 @@ impl TablePropertiesCollector for RangePropertiesCollector {
         // size
         let size = key.len() + value.len();
         self.cur_offsets.size += size as u64;
+        // The collector is only used by the CFs whose keys are timestamped.
+        if let Ok((_, ts)) = Key::split_on_ts_for(key) {
+            self.cur_max_ts = cmp::max(self.cur_max_ts, ts);
+        }
         // Add the start key for convenience.
         if self.last_key.is_empty()
             || self.size_in_last_range() >= PROP_SIZE_INDEX_DISTANCE
@@ -940,4 +1000,39 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_range_properties_max_ts() {
+        let mut collector = RangePropertiesCollector::default();
+        let v = vec![0; PROP_SIZE_INDEX_DISTANCE as usize];
+        let mut ks = vec![];
+        // Every key is in a block of its own.
+        for i in 0..10 {
+            let k = Key::from_raw(format!("k{}", i).as_bytes()).append_ts(i + 1);
+            let k = keys::data_key(k.encoded());
+            collector.add(&k, &v, DBEntryType::Put, 0, 0);
+            ks.push(k);
+        }
+        let result = UserProperties(collector.finish());
+
+        let props = RangeProperties::decode(&result).unwrap();
+        assert_eq!(props.max_ts.len(), ks.len());
+        for (i, k) in ks.iter().enumerate() {
+            assert_eq!(props.max_ts[k], i as u64 + 1);
+        }
+        let block_size = (ks[0].len() + v.len()) as u64;
+        assert_eq!(
+            props.get_approximate_recent_size_in_range(keys::DATA_MIN_KEY, keys::DATA_MAX_KEY, 6),
+            (5 * block_size, 10 * block_size)
+        );
+        // Only the blocks overlapping with the range are counted.
+        assert_eq!(
+            props.get_approximate_recent_size_in_range(&ks[2], &ks[8], 6),
+            (4 * block_size, 7 * block_size)
+        );
+        assert_eq!(
+            props.get_approximate_recent_size_in_range(&ks[2], &ks[8], 20),
+            (0, 7 * block_size)
+        );
+    }
 }
@@ -14,8 +14,8 @@
 use std::fmt::Debug;
 use std::marker::PhantomData;
 use std::sync::mpsc;
-use std::time::Duration;
-use std::{error, io, thread};
+use std::time::{Duration, Instant};
+use std::{cmp, error, io, thread};
 
 use mio;
 use prometheus::IntCounterVec;
@@ -26,9 +26,16 @@ lazy_static! {
         "Total number of channel full errors.",
         &["type"]
     ).unwrap();
+    pub static ref CHANNEL_SEND_RETRY_COUNTER_VEC: IntCounterVec = register_int_counter_vec!(
+        "tikv_channel_send_retry_total",
+        "Total number of messages retried or dropped when the channel is full.",
+        &["type", "result"]
+    ).unwrap();
 }
 
 const MAX_SEND_RETRY_CNT: usize = 5;
+// Interval to retry sending in `try_send_timeout`.
+const SEND_RETRY_INTERVAL_MS: u64 = 10;
 
 quick_error! {
     #[derive(Debug)]
@@ -97,6 +104,9 @@ impl<T> Sender<T> for mpsc::SyncSender<T> {
 pub struct RetryableSendCh<T, C> {
     ch: C,
     name: &'static str,
+    // The retry policy of `send_with_retry`.
+    max_retries: usize,
+    backoff: Duration,
 
     marker: PhantomData<T>,
 }
@@ -111,10 +121,21 @@ impl<T: Debug, C: Sender<T>> RetryableSendCh<T, C> {
         RetryableSendCh {
             ch,
             name,
+            max_retries: 0,
+            backoff: Duration::from_millis(SEND_RETRY_INTERVAL_MS),
             marker: Default::default(),
         }
     }
 
+    /// Set the retry policy of `send_with_retry`, the message is retried at
+    /// most `max_retries` times and waits `backoff` between retries if the
+    /// channel is full.
+    pub fn with_retry(mut self, max_retries: usize, backoff: Duration) -> RetryableSendCh<T, C> {
+        self.max_retries = max_retries;
+        self.backoff = backoff;
+        self
+    }
+
     /// Try send t with default try times.
     pub fn send(&self, t: T) -> Result<(), Error> {
         self.send_with_try_times(t, MAX_SEND_RETRY_CNT)
@@ -124,6 +145,80 @@ impl<T: Debug, C: Sender<T>> RetryableSendCh<T, C> {
         self.send_with_try_times(t, 1)
     }
 
+    /// Try send t, blocks at most `timeout` if the channel is full.
+    ///
+    /// `Error::Discard` is returned if the channel is still full after
+    /// `timeout`, so the caller can decide whether to retry later.
+    pub fn try_send_timeout(&self, mut t: T, timeout: Duration) -> Result<(), Error> {
+        let deadline = Instant::now() + timeout;
+        let mut retried = false;
+        loop {
+            t = match self.ch.send(t) {
+                Ok(_) => {
+                    if retried {
+                        CHANNEL_SEND_RETRY_COUNTER_VEC
+                            .with_label_values(&[self.name, "retried"])
+                            .inc();
+                    }
+                    return Ok(());
+                }
+                Err(NotifyError::Full(m)) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        CHANNEL_FULL_COUNTER_VEC
+                            .with_label_values(&[self.name])
+                            .inc();
+                        CHANNEL_SEND_RETRY_COUNTER_VEC
+                            .with_label_values(&[self.name, "dropped"])
+                            .inc();
+                        return Err(NotifyError::Full(m).into());
+                    }
+                    let interval = Duration::from_millis(SEND_RETRY_INTERVAL_MS);
+                    thread::sleep(cmp::min(deadline - now, interval));
+                    retried = true;
+                    m
+                }
+                Err(e) => return Err(e.into()),
+            };
+        }
+    }
+
+    /// Try send t with the retry policy set by `with_retry`.
+    ///
+    /// It's meant for important messages which shouldn't be dropped by a
+    /// transient full channel, `Error::Discard` is returned if the channel
+    /// is still full after all the retries.
+    pub fn send_with_retry(&self, mut t: T) -> Result<(), Error> {
+        let mut retries = 0;
+        loop {
+            t = match self.ch.send(t) {
+                Ok(_) => {
+                    if retries > 0 {
+                        CHANNEL_SEND_RETRY_COUNTER_VEC
+                            .with_label_values(&[self.name, "retried"])
+                            .inc();
+                    }
+                    return Ok(());
+                }
+                Err(NotifyError::Full(m)) => {
+                    if retries >= self.max_retries {
+                        CHANNEL_FULL_COUNTER_VEC
+                            .with_label_values(&[self.name])
+                            .inc();
+                        CHANNEL_SEND_RETRY_COUNTER_VEC
+                            .with_label_values(&[self.name, "dropped"])
+                            .inc();
+                        return Err(NotifyError::Full(m).into());
+                    }
+                    thread::sleep(self.backoff);
+                    retries += 1;
+                    m
+                }
+                Err(e) => return Err(e.into()),
+            };
+        }
+    }
+
     pub fn into_inner(self) -> C {
         self.ch
     }
@@ -157,6 +252,8 @@ impl<T, C: Sender<T>> Clone for RetryableSendCh<T, C> {
         RetryableSendCh {
             ch: self.ch.clone(),
             name: self.name,
+            max_retries: self.max_retries,
+            backoff: self.backoff,
             marker: Default::default(),
         }
     }
@@ -289,4 +386,48 @@ mod tests {
 
         h.join().unwrap();
     }
+
+    #[test]
+    fn test_sync_sendch_timeout() {
+        let (tx, rx) = mpsc::sync_channel(1);
+        let ch = SyncSendCh::new(tx, "test");
+        ch.try_send(Msg::Sleep(0)).unwrap();
+        match ch.try_send_timeout(Msg::Sleep(0), Duration::from_millis(50)) {
+            Err(Error::Discard(_)) => {}
+            res => panic!("expect discard error, but found: {:?}", res),
+        }
+
+        // The message is sent once the receiver catches up.
+        let h = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            while let Ok(Msg::Sleep(_)) = rx.recv() {}
+        });
+        ch.try_send_timeout(Msg::Sleep(0), Duration::from_secs(10)).unwrap();
+        ch.try_send_timeout(Msg::Quit, Duration::from_secs(10)).unwrap();
+
+        h.join().unwrap();
+    }
+
+    #[test]
+    fn test_sync_sendch_retry() {
+        let (tx, rx) = mpsc::sync_channel(1);
+        let ch = SyncSendCh::new(tx, "test").with_retry(1, Duration::from_millis(200));
+        ch.try_send(Msg::Sleep(0)).unwrap();
+
+        // The receiver drains the channel before the retry.
+        let h = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            rx.recv().unwrap();
+            rx
+        });
+        ch.send_with_retry(Msg::Sleep(0)).unwrap();
+        let _rx = h.join().unwrap();
+
+        // Give up if the channel is still full after all the retries.
+        let ch = ch.with_retry(2, Duration::from_millis(10));
+        match ch.send_with_retry(Msg::Quit) {
+            Err(Error::Discard(_)) => {}
+            res => panic!("expect discard error, but found: {:?}", res),
+        }
+    }
 }
@@ -408,10 +408,48 @@ fn test_serde_custom_tikv_config() {
     };
     value.coprocessor = CopConfig {
         split_region_on_table: true,
+        split_region_on_index: true,
+        split_region_on_user_key: true,
+        split_region_on_prefix_len: 8,
+        split_region_on_hash_prefix_len: 1,
+        region_hash_prefix_ranges: 16,
+        split_region_avoid_locks: true,
+        split_protected_ranges: vec![("t\\200\\000".to_owned(), "t\\200\\001".to_owned())],
         region_max_size: ReadableSize::mb(12),
-        region_split_size: ReadableSize::mb(12),
+        region_split_size: Some(ReadableSize::mb(12)),
+        region_initial_split_size: ReadableSize::mb(4),
+        region_hot_split_size: ReadableSize::mb(6),
+        region_min_split_size: ReadableSize::mb(1),
+        region_min_split_keys: 16,
+        region_split_on_value_size: true,
+        region_cf_compression_ratios: map!{ "write".to_owned() => 0.5 },
+        region_max_cf_size: ReadableSize::mb(10),
         region_max_keys: 100000,
         region_split_keys: 100000,
+        region_split_keys_reversed: true,
+        region_split_distinct_keys: 50000,
+        region_max_key_span: 1 << 40,
+        region_bucket_count: 4,
+        max_split_keys_per_check: 16,
+        report_region_size: false,
+        report_region_cf_sizes: true,
+        report_region_max_entry: true,
+        report_region_cf_entries: true,
+        report_split_check_result: false,
+        fast_size_estimate: true,
+        track_region_size: true,
+        skip_empty_region: true,
+        split_check_leader_only: false,
+        region_size_divergence_ratio: 3.0,
+        region_compact_garbage_ratio: 0.8,
+        split_check_bytes_per_sec: ReadableSize::mb(50),
+        split_check_interval: ReadableDuration::secs(30),
+        split_check_scan_batch_size: 256,
+        split_check_readahead_size: ReadableSize::mb(2),
+        split_check_flush_memtable: true,
+        split_check_chunk_size: ReadableSize::mb(64),
+        split_check_jitter: ReadableDuration::secs(5),
+        split_check_overload_duration: ReadableDuration::secs(3),
     };
     value.security = SecurityConfig {
         ca_path: "invalid path".to_owned(),
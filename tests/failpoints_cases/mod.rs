@@ -14,5 +14,6 @@
 mod test_merge;
 mod test_pending_peers;
 mod test_snap;
+mod test_split_check;
 mod test_stale_read;
 mod test_storage;
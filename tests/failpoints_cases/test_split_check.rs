@@ -0,0 +1,58 @@
+// Copyright 2018 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use fail;
+
+use raftstore::node::new_node_cluster;
+use tikv::storage::CF_DEFAULT;
+use tikv::util::config::{ReadableDuration, ReadableSize};
+
+#[test]
+fn test_split_check_scan_error() {
+    let _guard = ::setup();
+    let mut cluster = new_node_cluster(0, 1);
+    cluster.cfg.raft_store.split_region_check_tick_interval = ReadableDuration::millis(100);
+    cluster.cfg.raft_store.region_split_check_diff = ReadableSize(256);
+    cluster.cfg.coprocessor.region_max_size = ReadableSize(1024);
+    cluster.cfg.coprocessor.region_split_size = Some(ReadableSize(512));
+    cluster.run();
+    let pd_client = Arc::clone(&cluster.pd_client);
+
+    // The engine fails whenever the region is scanned, so it's never split.
+    let scan_error_fp = "split_check_scan_error";
+    fail::cfg(scan_error_fp, "return").unwrap();
+    for i in 0..20 {
+        cluster.must_put(format!("{:04}", i).as_bytes(), &[b'v'; 64]);
+    }
+    cluster.must_flush_cf(CF_DEFAULT, true);
+    thread::sleep(Duration::from_secs(1));
+    assert_eq!(pd_client.get_regions_number(), 1);
+
+    // The region is split once the engine recovers.
+    fail::remove(scan_error_fp);
+    for i in 20..40 {
+        cluster.must_put(format!("{:04}", i).as_bytes(), &[b'v'; 64]);
+    }
+    cluster.must_flush_cf(CF_DEFAULT, true);
+    for _ in 0..50 {
+        if pd_client.get_regions_number() > 1 {
+            return;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    panic!("the region isn't split after the engine recovers");
+}
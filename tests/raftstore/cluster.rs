@@ -806,11 +806,11 @@ impl<T: Simulator> Cluster<T> {
             .rl()
             .get_store_sendch(leader.get_store_id())
             .unwrap();
-        let split_key = split_key.to_vec();
         ch.try_send(Msg::SplitRegion {
             region_id: region.get_id(),
             region_epoch: region.get_region_epoch().clone(),
-            split_key: split_key.clone(),
+            split_keys: vec![split_key.to_vec()],
+            reason: SplitCheckReason::Manual,
             callback: cb,
         }).unwrap();
     }
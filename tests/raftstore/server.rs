@@ -170,7 +170,7 @@ impl Simulator for ServerCluster {
             server = Some(Server::new(
                 &server_cfg,
                 &security_mgr,
-                cfg.coprocessor.region_split_size.0 as usize,
+                cfg.coprocessor.region_split_size().0 as usize,
                 store.clone(),
                 cop_read_pool.clone(),
                 sim_router.clone(),